@@ -0,0 +1,40 @@
+-- Revert 002_money_cents: drop the sync triggers, then the cents columns
+
+DROP TRIGGER IF EXISTS trg_products_money_cents_insert;
+DROP TRIGGER IF EXISTS trg_products_money_cents_update;
+ALTER TABLE products DROP COLUMN price_cents;
+ALTER TABLE products DROP COLUMN partner_price_cents;
+ALTER TABLE products DROP COLUMN vip_price_cents;
+ALTER TABLE products DROP COLUMN cost_cents;
+
+DROP TRIGGER IF EXISTS trg_transactions_money_cents_insert;
+DROP TRIGGER IF EXISTS trg_transactions_money_cents_update;
+ALTER TABLE transactions DROP COLUMN subtotal_cents;
+ALTER TABLE transactions DROP COLUMN tax_amount_cents;
+ALTER TABLE transactions DROP COLUMN discount_amount_cents;
+ALTER TABLE transactions DROP COLUMN total_cents;
+
+DROP TRIGGER IF EXISTS trg_transaction_items_money_cents_insert;
+DROP TRIGGER IF EXISTS trg_transaction_items_money_cents_update;
+ALTER TABLE transaction_items DROP COLUMN unit_price_cents;
+ALTER TABLE transaction_items DROP COLUMN discount_amount_cents;
+ALTER TABLE transaction_items DROP COLUMN total_price_cents;
+
+DROP TRIGGER IF EXISTS trg_payments_money_cents_insert;
+DROP TRIGGER IF EXISTS trg_payments_money_cents_update;
+ALTER TABLE payments DROP COLUMN amount_cents;
+
+DROP TRIGGER IF EXISTS trg_cash_register_sessions_money_cents_insert;
+DROP TRIGGER IF EXISTS trg_cash_register_sessions_money_cents_update;
+ALTER TABLE cash_register_sessions DROP COLUMN opening_balance_cents;
+ALTER TABLE cash_register_sessions DROP COLUMN closing_balance_cents;
+ALTER TABLE cash_register_sessions DROP COLUMN expected_balance_cents;
+ALTER TABLE cash_register_sessions DROP COLUMN difference_cents;
+
+DROP TRIGGER IF EXISTS trg_cash_movements_money_cents_insert;
+DROP TRIGGER IF EXISTS trg_cash_movements_money_cents_update;
+ALTER TABLE cash_movements DROP COLUMN amount_cents;
+
+DROP TRIGGER IF EXISTS trg_stock_movements_money_cents_insert;
+DROP TRIGGER IF EXISTS trg_stock_movements_money_cents_update;
+ALTER TABLE stock_movements DROP COLUMN unit_cost_cents;
@@ -0,0 +1,183 @@
+-- Integer-cents money columns
+-- DECIMAL(10,2) columns are stored by SQLite as REAL, so sums of prices and
+-- IVA amounts drift by fractions of a cent. Each money column gets an INTEGER
+-- *_cents companion that the Rust layer reads through the Money type.
+-- The DECIMAL columns stay the write path for existing frontend services;
+-- triggers derive the cents value whenever they are inserted or updated.
+
+-- products
+ALTER TABLE products ADD COLUMN price_cents INTEGER;
+ALTER TABLE products ADD COLUMN partner_price_cents INTEGER;
+ALTER TABLE products ADD COLUMN vip_price_cents INTEGER;
+ALTER TABLE products ADD COLUMN cost_cents INTEGER;
+UPDATE products SET
+    price_cents = CAST(ROUND(price * 100) AS INTEGER),
+    partner_price_cents = CAST(ROUND(partner_price * 100) AS INTEGER),
+    vip_price_cents = CAST(ROUND(vip_price * 100) AS INTEGER),
+    cost_cents = CAST(ROUND(cost * 100) AS INTEGER);
+
+CREATE TRIGGER trg_products_money_cents_insert AFTER INSERT ON products
+BEGIN
+    UPDATE products SET
+        price_cents = CAST(ROUND(NEW.price * 100) AS INTEGER),
+        partner_price_cents = CAST(ROUND(NEW.partner_price * 100) AS INTEGER),
+        vip_price_cents = CAST(ROUND(NEW.vip_price * 100) AS INTEGER),
+        cost_cents = CAST(ROUND(NEW.cost * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_products_money_cents_update AFTER UPDATE OF price, partner_price, vip_price, cost ON products
+BEGIN
+    UPDATE products SET
+        price_cents = CAST(ROUND(NEW.price * 100) AS INTEGER),
+        partner_price_cents = CAST(ROUND(NEW.partner_price * 100) AS INTEGER),
+        vip_price_cents = CAST(ROUND(NEW.vip_price * 100) AS INTEGER),
+        cost_cents = CAST(ROUND(NEW.cost * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+-- transactions
+ALTER TABLE transactions ADD COLUMN subtotal_cents INTEGER;
+ALTER TABLE transactions ADD COLUMN tax_amount_cents INTEGER;
+ALTER TABLE transactions ADD COLUMN discount_amount_cents INTEGER;
+ALTER TABLE transactions ADD COLUMN total_cents INTEGER;
+UPDATE transactions SET
+    subtotal_cents = CAST(ROUND(subtotal * 100) AS INTEGER),
+    tax_amount_cents = CAST(ROUND(tax_amount * 100) AS INTEGER),
+    discount_amount_cents = CAST(ROUND(discount_amount * 100) AS INTEGER),
+    total_cents = CAST(ROUND(total * 100) AS INTEGER);
+
+CREATE TRIGGER trg_transactions_money_cents_insert AFTER INSERT ON transactions
+BEGIN
+    UPDATE transactions SET
+        subtotal_cents = CAST(ROUND(NEW.subtotal * 100) AS INTEGER),
+        tax_amount_cents = CAST(ROUND(NEW.tax_amount * 100) AS INTEGER),
+        discount_amount_cents = CAST(ROUND(NEW.discount_amount * 100) AS INTEGER),
+        total_cents = CAST(ROUND(NEW.total * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_transactions_money_cents_update AFTER UPDATE OF subtotal, tax_amount, discount_amount, total ON transactions
+BEGIN
+    UPDATE transactions SET
+        subtotal_cents = CAST(ROUND(NEW.subtotal * 100) AS INTEGER),
+        tax_amount_cents = CAST(ROUND(NEW.tax_amount * 100) AS INTEGER),
+        discount_amount_cents = CAST(ROUND(NEW.discount_amount * 100) AS INTEGER),
+        total_cents = CAST(ROUND(NEW.total * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+-- transaction_items
+ALTER TABLE transaction_items ADD COLUMN unit_price_cents INTEGER;
+ALTER TABLE transaction_items ADD COLUMN discount_amount_cents INTEGER;
+ALTER TABLE transaction_items ADD COLUMN total_price_cents INTEGER;
+UPDATE transaction_items SET
+    unit_price_cents = CAST(ROUND(unit_price * 100) AS INTEGER),
+    discount_amount_cents = CAST(ROUND(discount_amount * 100) AS INTEGER),
+    total_price_cents = CAST(ROUND(total_price * 100) AS INTEGER);
+
+CREATE TRIGGER trg_transaction_items_money_cents_insert AFTER INSERT ON transaction_items
+BEGIN
+    UPDATE transaction_items SET
+        unit_price_cents = CAST(ROUND(NEW.unit_price * 100) AS INTEGER),
+        discount_amount_cents = CAST(ROUND(NEW.discount_amount * 100) AS INTEGER),
+        total_price_cents = CAST(ROUND(NEW.total_price * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_transaction_items_money_cents_update AFTER UPDATE OF unit_price, discount_amount, total_price ON transaction_items
+BEGIN
+    UPDATE transaction_items SET
+        unit_price_cents = CAST(ROUND(NEW.unit_price * 100) AS INTEGER),
+        discount_amount_cents = CAST(ROUND(NEW.discount_amount * 100) AS INTEGER),
+        total_price_cents = CAST(ROUND(NEW.total_price * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+-- payments
+ALTER TABLE payments ADD COLUMN amount_cents INTEGER;
+UPDATE payments SET
+    amount_cents = CAST(ROUND(amount * 100) AS INTEGER);
+
+CREATE TRIGGER trg_payments_money_cents_insert AFTER INSERT ON payments
+BEGIN
+    UPDATE payments SET
+        amount_cents = CAST(ROUND(NEW.amount * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_payments_money_cents_update AFTER UPDATE OF amount ON payments
+BEGIN
+    UPDATE payments SET
+        amount_cents = CAST(ROUND(NEW.amount * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+-- cash_register_sessions
+ALTER TABLE cash_register_sessions ADD COLUMN opening_balance_cents INTEGER;
+ALTER TABLE cash_register_sessions ADD COLUMN closing_balance_cents INTEGER;
+ALTER TABLE cash_register_sessions ADD COLUMN expected_balance_cents INTEGER;
+ALTER TABLE cash_register_sessions ADD COLUMN difference_cents INTEGER;
+UPDATE cash_register_sessions SET
+    opening_balance_cents = CAST(ROUND(opening_balance * 100) AS INTEGER),
+    closing_balance_cents = CAST(ROUND(closing_balance * 100) AS INTEGER),
+    expected_balance_cents = CAST(ROUND(expected_balance * 100) AS INTEGER),
+    difference_cents = CAST(ROUND(difference * 100) AS INTEGER);
+
+CREATE TRIGGER trg_cash_register_sessions_money_cents_insert AFTER INSERT ON cash_register_sessions
+BEGIN
+    UPDATE cash_register_sessions SET
+        opening_balance_cents = CAST(ROUND(NEW.opening_balance * 100) AS INTEGER),
+        closing_balance_cents = CAST(ROUND(NEW.closing_balance * 100) AS INTEGER),
+        expected_balance_cents = CAST(ROUND(NEW.expected_balance * 100) AS INTEGER),
+        difference_cents = CAST(ROUND(NEW.difference * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_cash_register_sessions_money_cents_update AFTER UPDATE OF opening_balance, closing_balance, expected_balance, difference ON cash_register_sessions
+BEGIN
+    UPDATE cash_register_sessions SET
+        opening_balance_cents = CAST(ROUND(NEW.opening_balance * 100) AS INTEGER),
+        closing_balance_cents = CAST(ROUND(NEW.closing_balance * 100) AS INTEGER),
+        expected_balance_cents = CAST(ROUND(NEW.expected_balance * 100) AS INTEGER),
+        difference_cents = CAST(ROUND(NEW.difference * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+-- cash_movements
+ALTER TABLE cash_movements ADD COLUMN amount_cents INTEGER;
+UPDATE cash_movements SET
+    amount_cents = CAST(ROUND(amount * 100) AS INTEGER);
+
+CREATE TRIGGER trg_cash_movements_money_cents_insert AFTER INSERT ON cash_movements
+BEGIN
+    UPDATE cash_movements SET
+        amount_cents = CAST(ROUND(NEW.amount * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_cash_movements_money_cents_update AFTER UPDATE OF amount ON cash_movements
+BEGIN
+    UPDATE cash_movements SET
+        amount_cents = CAST(ROUND(NEW.amount * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+-- stock_movements
+ALTER TABLE stock_movements ADD COLUMN unit_cost_cents INTEGER;
+UPDATE stock_movements SET
+    unit_cost_cents = CAST(ROUND(unit_cost * 100) AS INTEGER);
+
+CREATE TRIGGER trg_stock_movements_money_cents_insert AFTER INSERT ON stock_movements
+BEGIN
+    UPDATE stock_movements SET
+        unit_cost_cents = CAST(ROUND(NEW.unit_cost * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_stock_movements_money_cents_update AFTER UPDATE OF unit_cost ON stock_movements
+BEGIN
+    UPDATE stock_movements SET
+        unit_cost_cents = CAST(ROUND(NEW.unit_cost * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
@@ -0,0 +1,8 @@
+-- Revert 003_product_search: drop the sync triggers, then the index tables
+
+DROP TRIGGER IF EXISTS trg_categories_fts_rename;
+DROP TRIGGER IF EXISTS trg_products_fts_delete;
+DROP TRIGGER IF EXISTS trg_products_fts_update;
+DROP TRIGGER IF EXISTS trg_products_fts_insert;
+DROP TABLE IF EXISTS products_fts_vocab;
+DROP TABLE IF EXISTS products_fts;
@@ -0,0 +1,55 @@
+-- Full-text product search
+-- products_fts mirrors the searchable product fields plus the category name.
+-- unicode61 with remove_diacritics folds case and accents, so "acetaminofen"
+-- matches "Acetaminofén"; prefix indexes keep short as-you-type prefixes fast.
+-- The rowid of each row is the product id.
+
+CREATE VIRTUAL TABLE products_fts USING fts5(
+    name,
+    barcode,
+    description,
+    category,
+    tokenize = 'unicode61 remove_diacritics 2',
+    prefix = '2 3'
+);
+
+-- Relevance for ORDER BY rank; name and barcode hits weigh most
+INSERT INTO products_fts (products_fts, rank) VALUES ('rank', 'bm25(10.0, 8.0, 1.0, 2.0)');
+
+-- Indexed terms, used to correct typos in search queries
+CREATE VIRTUAL TABLE products_fts_vocab USING fts5vocab(products_fts, 'row');
+
+INSERT INTO products_fts (rowid, name, barcode, description, category)
+SELECT p.id, p.name, p.barcode, p.description, c.name
+FROM products p
+LEFT JOIN categories c ON c.id = p.category_id;
+
+CREATE TRIGGER trg_products_fts_insert AFTER INSERT ON products
+BEGIN
+    INSERT INTO products_fts (rowid, name, barcode, description, category)
+    VALUES (
+        NEW.id, NEW.name, NEW.barcode, NEW.description,
+        (SELECT name FROM categories WHERE id = NEW.category_id)
+    );
+END;
+
+CREATE TRIGGER trg_products_fts_update AFTER UPDATE OF name, barcode, description, category_id ON products
+BEGIN
+    DELETE FROM products_fts WHERE rowid = OLD.id;
+    INSERT INTO products_fts (rowid, name, barcode, description, category)
+    VALUES (
+        NEW.id, NEW.name, NEW.barcode, NEW.description,
+        (SELECT name FROM categories WHERE id = NEW.category_id)
+    );
+END;
+
+CREATE TRIGGER trg_products_fts_delete AFTER DELETE ON products
+BEGIN
+    DELETE FROM products_fts WHERE rowid = OLD.id;
+END;
+
+CREATE TRIGGER trg_categories_fts_rename AFTER UPDATE OF name ON categories
+BEGIN
+    UPDATE products_fts SET category = NEW.name
+    WHERE rowid IN (SELECT id FROM products WHERE category_id = NEW.id);
+END;
@@ -0,0 +1,4 @@
+-- Revert 005_sync_attempts
+
+ALTER TABLE change_log DROP COLUMN last_error;
+ALTER TABLE change_log DROP COLUMN attempts;
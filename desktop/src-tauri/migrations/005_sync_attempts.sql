@@ -0,0 +1,7 @@
+-- Sync retry bookkeeping
+-- Changes the backend rejects keep their place in change_log with the
+-- reason, and stop being resent after a few attempts so they can't hold up
+-- the rest of the outbox.
+
+ALTER TABLE change_log ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE change_log ADD COLUMN last_error TEXT;
@@ -0,0 +1,3 @@
+-- Revert 007_pin_lockout
+
+DROP TABLE IF EXISTS pin_attempts;
@@ -0,0 +1,14 @@
+-- PIN brute-force protection
+-- Failed login attempts per user and per terminal. Each failure delays the
+-- next attempt a little longer; too many lock the user or terminal until
+-- the lockout expires or a manager unlocks it.
+
+CREATE TABLE pin_attempts (
+    scope TEXT NOT NULL CHECK (scope IN ('user', 'terminal')),
+    subject TEXT NOT NULL,  -- users.id or the terminal id
+    failed_count INTEGER NOT NULL DEFAULT 0,
+    last_failed_at TEXT,  -- RFC 3339, UTC
+    blocked_until TEXT,  -- RFC 3339, UTC
+    locked BOOLEAN NOT NULL DEFAULT 0,
+    PRIMARY KEY (scope, subject)
+);
@@ -0,0 +1,142 @@
+-- Revert 008_money_cents_write_path: restore the DECIMAL-driven triggers from 002
+
+DROP TRIGGER IF EXISTS trg_products_money_cents_insert;
+DROP TRIGGER IF EXISTS trg_products_money_cents_update;
+
+CREATE TRIGGER trg_products_money_cents_insert AFTER INSERT ON products
+BEGIN
+    UPDATE products SET
+        price_cents = CAST(ROUND(NEW.price * 100) AS INTEGER),
+        partner_price_cents = CAST(ROUND(NEW.partner_price * 100) AS INTEGER),
+        vip_price_cents = CAST(ROUND(NEW.vip_price * 100) AS INTEGER),
+        cost_cents = CAST(ROUND(NEW.cost * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_products_money_cents_update AFTER UPDATE OF price, partner_price, vip_price, cost ON products
+BEGIN
+    UPDATE products SET
+        price_cents = CAST(ROUND(NEW.price * 100) AS INTEGER),
+        partner_price_cents = CAST(ROUND(NEW.partner_price * 100) AS INTEGER),
+        vip_price_cents = CAST(ROUND(NEW.vip_price * 100) AS INTEGER),
+        cost_cents = CAST(ROUND(NEW.cost * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+DROP TRIGGER IF EXISTS trg_transactions_money_cents_insert;
+DROP TRIGGER IF EXISTS trg_transactions_money_cents_update;
+
+CREATE TRIGGER trg_transactions_money_cents_insert AFTER INSERT ON transactions
+BEGIN
+    UPDATE transactions SET
+        subtotal_cents = CAST(ROUND(NEW.subtotal * 100) AS INTEGER),
+        tax_amount_cents = CAST(ROUND(NEW.tax_amount * 100) AS INTEGER),
+        discount_amount_cents = CAST(ROUND(NEW.discount_amount * 100) AS INTEGER),
+        total_cents = CAST(ROUND(NEW.total * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_transactions_money_cents_update AFTER UPDATE OF subtotal, tax_amount, discount_amount, total ON transactions
+BEGIN
+    UPDATE transactions SET
+        subtotal_cents = CAST(ROUND(NEW.subtotal * 100) AS INTEGER),
+        tax_amount_cents = CAST(ROUND(NEW.tax_amount * 100) AS INTEGER),
+        discount_amount_cents = CAST(ROUND(NEW.discount_amount * 100) AS INTEGER),
+        total_cents = CAST(ROUND(NEW.total * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+DROP TRIGGER IF EXISTS trg_transaction_items_money_cents_insert;
+DROP TRIGGER IF EXISTS trg_transaction_items_money_cents_update;
+
+CREATE TRIGGER trg_transaction_items_money_cents_insert AFTER INSERT ON transaction_items
+BEGIN
+    UPDATE transaction_items SET
+        unit_price_cents = CAST(ROUND(NEW.unit_price * 100) AS INTEGER),
+        discount_amount_cents = CAST(ROUND(NEW.discount_amount * 100) AS INTEGER),
+        total_price_cents = CAST(ROUND(NEW.total_price * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_transaction_items_money_cents_update AFTER UPDATE OF unit_price, discount_amount, total_price ON transaction_items
+BEGIN
+    UPDATE transaction_items SET
+        unit_price_cents = CAST(ROUND(NEW.unit_price * 100) AS INTEGER),
+        discount_amount_cents = CAST(ROUND(NEW.discount_amount * 100) AS INTEGER),
+        total_price_cents = CAST(ROUND(NEW.total_price * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+DROP TRIGGER IF EXISTS trg_payments_money_cents_insert;
+DROP TRIGGER IF EXISTS trg_payments_money_cents_update;
+
+CREATE TRIGGER trg_payments_money_cents_insert AFTER INSERT ON payments
+BEGIN
+    UPDATE payments SET
+        amount_cents = CAST(ROUND(NEW.amount * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_payments_money_cents_update AFTER UPDATE OF amount ON payments
+BEGIN
+    UPDATE payments SET
+        amount_cents = CAST(ROUND(NEW.amount * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+DROP TRIGGER IF EXISTS trg_cash_register_sessions_money_cents_insert;
+DROP TRIGGER IF EXISTS trg_cash_register_sessions_money_cents_update;
+
+CREATE TRIGGER trg_cash_register_sessions_money_cents_insert AFTER INSERT ON cash_register_sessions
+BEGIN
+    UPDATE cash_register_sessions SET
+        opening_balance_cents = CAST(ROUND(NEW.opening_balance * 100) AS INTEGER),
+        closing_balance_cents = CAST(ROUND(NEW.closing_balance * 100) AS INTEGER),
+        expected_balance_cents = CAST(ROUND(NEW.expected_balance * 100) AS INTEGER),
+        difference_cents = CAST(ROUND(NEW.difference * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_cash_register_sessions_money_cents_update AFTER UPDATE OF opening_balance, closing_balance, expected_balance, difference ON cash_register_sessions
+BEGIN
+    UPDATE cash_register_sessions SET
+        opening_balance_cents = CAST(ROUND(NEW.opening_balance * 100) AS INTEGER),
+        closing_balance_cents = CAST(ROUND(NEW.closing_balance * 100) AS INTEGER),
+        expected_balance_cents = CAST(ROUND(NEW.expected_balance * 100) AS INTEGER),
+        difference_cents = CAST(ROUND(NEW.difference * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+DROP TRIGGER IF EXISTS trg_cash_movements_money_cents_insert;
+DROP TRIGGER IF EXISTS trg_cash_movements_money_cents_update;
+
+CREATE TRIGGER trg_cash_movements_money_cents_insert AFTER INSERT ON cash_movements
+BEGIN
+    UPDATE cash_movements SET
+        amount_cents = CAST(ROUND(NEW.amount * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_cash_movements_money_cents_update AFTER UPDATE OF amount ON cash_movements
+BEGIN
+    UPDATE cash_movements SET
+        amount_cents = CAST(ROUND(NEW.amount * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+DROP TRIGGER IF EXISTS trg_stock_movements_money_cents_insert;
+DROP TRIGGER IF EXISTS trg_stock_movements_money_cents_update;
+
+CREATE TRIGGER trg_stock_movements_money_cents_insert AFTER INSERT ON stock_movements
+BEGIN
+    UPDATE stock_movements SET
+        unit_cost_cents = CAST(ROUND(NEW.unit_cost * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_stock_movements_money_cents_update AFTER UPDATE OF unit_cost ON stock_movements
+BEGIN
+    UPDATE stock_movements SET
+        unit_cost_cents = CAST(ROUND(NEW.unit_cost * 100) AS INTEGER)
+    WHERE id = NEW.id;
+END;
@@ -0,0 +1,191 @@
+-- Integer cents become the write path for money
+-- 002 derived *_cents from the DECIMAL columns, so every amount still went
+-- through a REAL first. From here on a written *_cents value is
+-- authoritative and the DECIMAL column becomes a display mirror derived
+-- from it. Writers that only set the DECIMAL column keep working: their
+-- cents are still derived as before. When one UPDATE changes both, the
+-- cents win.
+
+-- products
+DROP TRIGGER IF EXISTS trg_products_money_cents_insert;
+DROP TRIGGER IF EXISTS trg_products_money_cents_update;
+
+CREATE TRIGGER trg_products_money_cents_insert AFTER INSERT ON products
+BEGIN
+    UPDATE products SET
+        price_cents = COALESCE(NEW.price_cents, CAST(ROUND(NEW.price * 100) AS INTEGER)),
+        price = CASE WHEN NEW.price_cents IS NULL THEN NEW.price ELSE NEW.price_cents / 100.0 END,
+        partner_price_cents = COALESCE(NEW.partner_price_cents, CAST(ROUND(NEW.partner_price * 100) AS INTEGER)),
+        partner_price = CASE WHEN NEW.partner_price_cents IS NULL THEN NEW.partner_price ELSE NEW.partner_price_cents / 100.0 END,
+        vip_price_cents = COALESCE(NEW.vip_price_cents, CAST(ROUND(NEW.vip_price * 100) AS INTEGER)),
+        vip_price = CASE WHEN NEW.vip_price_cents IS NULL THEN NEW.vip_price ELSE NEW.vip_price_cents / 100.0 END,
+        cost_cents = COALESCE(NEW.cost_cents, CAST(ROUND(NEW.cost * 100) AS INTEGER)),
+        cost = CASE WHEN NEW.cost_cents IS NULL THEN NEW.cost ELSE NEW.cost_cents / 100.0 END
+    WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_products_money_cents_update AFTER UPDATE OF price, partner_price, vip_price, cost, price_cents, partner_price_cents, vip_price_cents, cost_cents ON products
+BEGIN
+    UPDATE products SET
+        price_cents = CASE WHEN NEW.price_cents IS NOT OLD.price_cents THEN NEW.price_cents ELSE CAST(ROUND(NEW.price * 100) AS INTEGER) END,
+        price = CASE WHEN NEW.price_cents IS NOT OLD.price_cents THEN NEW.price_cents / 100.0 ELSE NEW.price END,
+        partner_price_cents = CASE WHEN NEW.partner_price_cents IS NOT OLD.partner_price_cents THEN NEW.partner_price_cents ELSE CAST(ROUND(NEW.partner_price * 100) AS INTEGER) END,
+        partner_price = CASE WHEN NEW.partner_price_cents IS NOT OLD.partner_price_cents THEN NEW.partner_price_cents / 100.0 ELSE NEW.partner_price END,
+        vip_price_cents = CASE WHEN NEW.vip_price_cents IS NOT OLD.vip_price_cents THEN NEW.vip_price_cents ELSE CAST(ROUND(NEW.vip_price * 100) AS INTEGER) END,
+        vip_price = CASE WHEN NEW.vip_price_cents IS NOT OLD.vip_price_cents THEN NEW.vip_price_cents / 100.0 ELSE NEW.vip_price END,
+        cost_cents = CASE WHEN NEW.cost_cents IS NOT OLD.cost_cents THEN NEW.cost_cents ELSE CAST(ROUND(NEW.cost * 100) AS INTEGER) END,
+        cost = CASE WHEN NEW.cost_cents IS NOT OLD.cost_cents THEN NEW.cost_cents / 100.0 ELSE NEW.cost END
+    WHERE id = NEW.id;
+END;
+
+-- transactions
+DROP TRIGGER IF EXISTS trg_transactions_money_cents_insert;
+DROP TRIGGER IF EXISTS trg_transactions_money_cents_update;
+
+CREATE TRIGGER trg_transactions_money_cents_insert AFTER INSERT ON transactions
+BEGIN
+    UPDATE transactions SET
+        subtotal_cents = COALESCE(NEW.subtotal_cents, CAST(ROUND(NEW.subtotal * 100) AS INTEGER)),
+        subtotal = CASE WHEN NEW.subtotal_cents IS NULL THEN NEW.subtotal ELSE NEW.subtotal_cents / 100.0 END,
+        tax_amount_cents = COALESCE(NEW.tax_amount_cents, CAST(ROUND(NEW.tax_amount * 100) AS INTEGER)),
+        tax_amount = CASE WHEN NEW.tax_amount_cents IS NULL THEN NEW.tax_amount ELSE NEW.tax_amount_cents / 100.0 END,
+        discount_amount_cents = COALESCE(NEW.discount_amount_cents, CAST(ROUND(NEW.discount_amount * 100) AS INTEGER)),
+        discount_amount = CASE WHEN NEW.discount_amount_cents IS NULL THEN NEW.discount_amount ELSE NEW.discount_amount_cents / 100.0 END,
+        total_cents = COALESCE(NEW.total_cents, CAST(ROUND(NEW.total * 100) AS INTEGER)),
+        total = CASE WHEN NEW.total_cents IS NULL THEN NEW.total ELSE NEW.total_cents / 100.0 END
+    WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_transactions_money_cents_update AFTER UPDATE OF subtotal, tax_amount, discount_amount, total, subtotal_cents, tax_amount_cents, discount_amount_cents, total_cents ON transactions
+BEGIN
+    UPDATE transactions SET
+        subtotal_cents = CASE WHEN NEW.subtotal_cents IS NOT OLD.subtotal_cents THEN NEW.subtotal_cents ELSE CAST(ROUND(NEW.subtotal * 100) AS INTEGER) END,
+        subtotal = CASE WHEN NEW.subtotal_cents IS NOT OLD.subtotal_cents THEN NEW.subtotal_cents / 100.0 ELSE NEW.subtotal END,
+        tax_amount_cents = CASE WHEN NEW.tax_amount_cents IS NOT OLD.tax_amount_cents THEN NEW.tax_amount_cents ELSE CAST(ROUND(NEW.tax_amount * 100) AS INTEGER) END,
+        tax_amount = CASE WHEN NEW.tax_amount_cents IS NOT OLD.tax_amount_cents THEN NEW.tax_amount_cents / 100.0 ELSE NEW.tax_amount END,
+        discount_amount_cents = CASE WHEN NEW.discount_amount_cents IS NOT OLD.discount_amount_cents THEN NEW.discount_amount_cents ELSE CAST(ROUND(NEW.discount_amount * 100) AS INTEGER) END,
+        discount_amount = CASE WHEN NEW.discount_amount_cents IS NOT OLD.discount_amount_cents THEN NEW.discount_amount_cents / 100.0 ELSE NEW.discount_amount END,
+        total_cents = CASE WHEN NEW.total_cents IS NOT OLD.total_cents THEN NEW.total_cents ELSE CAST(ROUND(NEW.total * 100) AS INTEGER) END,
+        total = CASE WHEN NEW.total_cents IS NOT OLD.total_cents THEN NEW.total_cents / 100.0 ELSE NEW.total END
+    WHERE id = NEW.id;
+END;
+
+-- transaction_items
+DROP TRIGGER IF EXISTS trg_transaction_items_money_cents_insert;
+DROP TRIGGER IF EXISTS trg_transaction_items_money_cents_update;
+
+CREATE TRIGGER trg_transaction_items_money_cents_insert AFTER INSERT ON transaction_items
+BEGIN
+    UPDATE transaction_items SET
+        unit_price_cents = COALESCE(NEW.unit_price_cents, CAST(ROUND(NEW.unit_price * 100) AS INTEGER)),
+        unit_price = CASE WHEN NEW.unit_price_cents IS NULL THEN NEW.unit_price ELSE NEW.unit_price_cents / 100.0 END,
+        discount_amount_cents = COALESCE(NEW.discount_amount_cents, CAST(ROUND(NEW.discount_amount * 100) AS INTEGER)),
+        discount_amount = CASE WHEN NEW.discount_amount_cents IS NULL THEN NEW.discount_amount ELSE NEW.discount_amount_cents / 100.0 END,
+        total_price_cents = COALESCE(NEW.total_price_cents, CAST(ROUND(NEW.total_price * 100) AS INTEGER)),
+        total_price = CASE WHEN NEW.total_price_cents IS NULL THEN NEW.total_price ELSE NEW.total_price_cents / 100.0 END
+    WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_transaction_items_money_cents_update AFTER UPDATE OF unit_price, discount_amount, total_price, unit_price_cents, discount_amount_cents, total_price_cents ON transaction_items
+BEGIN
+    UPDATE transaction_items SET
+        unit_price_cents = CASE WHEN NEW.unit_price_cents IS NOT OLD.unit_price_cents THEN NEW.unit_price_cents ELSE CAST(ROUND(NEW.unit_price * 100) AS INTEGER) END,
+        unit_price = CASE WHEN NEW.unit_price_cents IS NOT OLD.unit_price_cents THEN NEW.unit_price_cents / 100.0 ELSE NEW.unit_price END,
+        discount_amount_cents = CASE WHEN NEW.discount_amount_cents IS NOT OLD.discount_amount_cents THEN NEW.discount_amount_cents ELSE CAST(ROUND(NEW.discount_amount * 100) AS INTEGER) END,
+        discount_amount = CASE WHEN NEW.discount_amount_cents IS NOT OLD.discount_amount_cents THEN NEW.discount_amount_cents / 100.0 ELSE NEW.discount_amount END,
+        total_price_cents = CASE WHEN NEW.total_price_cents IS NOT OLD.total_price_cents THEN NEW.total_price_cents ELSE CAST(ROUND(NEW.total_price * 100) AS INTEGER) END,
+        total_price = CASE WHEN NEW.total_price_cents IS NOT OLD.total_price_cents THEN NEW.total_price_cents / 100.0 ELSE NEW.total_price END
+    WHERE id = NEW.id;
+END;
+
+-- payments
+DROP TRIGGER IF EXISTS trg_payments_money_cents_insert;
+DROP TRIGGER IF EXISTS trg_payments_money_cents_update;
+
+CREATE TRIGGER trg_payments_money_cents_insert AFTER INSERT ON payments
+BEGIN
+    UPDATE payments SET
+        amount_cents = COALESCE(NEW.amount_cents, CAST(ROUND(NEW.amount * 100) AS INTEGER)),
+        amount = CASE WHEN NEW.amount_cents IS NULL THEN NEW.amount ELSE NEW.amount_cents / 100.0 END
+    WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_payments_money_cents_update AFTER UPDATE OF amount, amount_cents ON payments
+BEGIN
+    UPDATE payments SET
+        amount_cents = CASE WHEN NEW.amount_cents IS NOT OLD.amount_cents THEN NEW.amount_cents ELSE CAST(ROUND(NEW.amount * 100) AS INTEGER) END,
+        amount = CASE WHEN NEW.amount_cents IS NOT OLD.amount_cents THEN NEW.amount_cents / 100.0 ELSE NEW.amount END
+    WHERE id = NEW.id;
+END;
+
+-- cash_register_sessions
+DROP TRIGGER IF EXISTS trg_cash_register_sessions_money_cents_insert;
+DROP TRIGGER IF EXISTS trg_cash_register_sessions_money_cents_update;
+
+CREATE TRIGGER trg_cash_register_sessions_money_cents_insert AFTER INSERT ON cash_register_sessions
+BEGIN
+    UPDATE cash_register_sessions SET
+        opening_balance_cents = COALESCE(NEW.opening_balance_cents, CAST(ROUND(NEW.opening_balance * 100) AS INTEGER)),
+        opening_balance = CASE WHEN NEW.opening_balance_cents IS NULL THEN NEW.opening_balance ELSE NEW.opening_balance_cents / 100.0 END,
+        closing_balance_cents = COALESCE(NEW.closing_balance_cents, CAST(ROUND(NEW.closing_balance * 100) AS INTEGER)),
+        closing_balance = CASE WHEN NEW.closing_balance_cents IS NULL THEN NEW.closing_balance ELSE NEW.closing_balance_cents / 100.0 END,
+        expected_balance_cents = COALESCE(NEW.expected_balance_cents, CAST(ROUND(NEW.expected_balance * 100) AS INTEGER)),
+        expected_balance = CASE WHEN NEW.expected_balance_cents IS NULL THEN NEW.expected_balance ELSE NEW.expected_balance_cents / 100.0 END,
+        difference_cents = COALESCE(NEW.difference_cents, CAST(ROUND(NEW.difference * 100) AS INTEGER)),
+        difference = CASE WHEN NEW.difference_cents IS NULL THEN NEW.difference ELSE NEW.difference_cents / 100.0 END
+    WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_cash_register_sessions_money_cents_update AFTER UPDATE OF opening_balance, closing_balance, expected_balance, difference, opening_balance_cents, closing_balance_cents, expected_balance_cents, difference_cents ON cash_register_sessions
+BEGIN
+    UPDATE cash_register_sessions SET
+        opening_balance_cents = CASE WHEN NEW.opening_balance_cents IS NOT OLD.opening_balance_cents THEN NEW.opening_balance_cents ELSE CAST(ROUND(NEW.opening_balance * 100) AS INTEGER) END,
+        opening_balance = CASE WHEN NEW.opening_balance_cents IS NOT OLD.opening_balance_cents THEN NEW.opening_balance_cents / 100.0 ELSE NEW.opening_balance END,
+        closing_balance_cents = CASE WHEN NEW.closing_balance_cents IS NOT OLD.closing_balance_cents THEN NEW.closing_balance_cents ELSE CAST(ROUND(NEW.closing_balance * 100) AS INTEGER) END,
+        closing_balance = CASE WHEN NEW.closing_balance_cents IS NOT OLD.closing_balance_cents THEN NEW.closing_balance_cents / 100.0 ELSE NEW.closing_balance END,
+        expected_balance_cents = CASE WHEN NEW.expected_balance_cents IS NOT OLD.expected_balance_cents THEN NEW.expected_balance_cents ELSE CAST(ROUND(NEW.expected_balance * 100) AS INTEGER) END,
+        expected_balance = CASE WHEN NEW.expected_balance_cents IS NOT OLD.expected_balance_cents THEN NEW.expected_balance_cents / 100.0 ELSE NEW.expected_balance END,
+        difference_cents = CASE WHEN NEW.difference_cents IS NOT OLD.difference_cents THEN NEW.difference_cents ELSE CAST(ROUND(NEW.difference * 100) AS INTEGER) END,
+        difference = CASE WHEN NEW.difference_cents IS NOT OLD.difference_cents THEN NEW.difference_cents / 100.0 ELSE NEW.difference END
+    WHERE id = NEW.id;
+END;
+
+-- cash_movements
+DROP TRIGGER IF EXISTS trg_cash_movements_money_cents_insert;
+DROP TRIGGER IF EXISTS trg_cash_movements_money_cents_update;
+
+CREATE TRIGGER trg_cash_movements_money_cents_insert AFTER INSERT ON cash_movements
+BEGIN
+    UPDATE cash_movements SET
+        amount_cents = COALESCE(NEW.amount_cents, CAST(ROUND(NEW.amount * 100) AS INTEGER)),
+        amount = CASE WHEN NEW.amount_cents IS NULL THEN NEW.amount ELSE NEW.amount_cents / 100.0 END
+    WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_cash_movements_money_cents_update AFTER UPDATE OF amount, amount_cents ON cash_movements
+BEGIN
+    UPDATE cash_movements SET
+        amount_cents = CASE WHEN NEW.amount_cents IS NOT OLD.amount_cents THEN NEW.amount_cents ELSE CAST(ROUND(NEW.amount * 100) AS INTEGER) END,
+        amount = CASE WHEN NEW.amount_cents IS NOT OLD.amount_cents THEN NEW.amount_cents / 100.0 ELSE NEW.amount END
+    WHERE id = NEW.id;
+END;
+
+-- stock_movements
+DROP TRIGGER IF EXISTS trg_stock_movements_money_cents_insert;
+DROP TRIGGER IF EXISTS trg_stock_movements_money_cents_update;
+
+CREATE TRIGGER trg_stock_movements_money_cents_insert AFTER INSERT ON stock_movements
+BEGIN
+    UPDATE stock_movements SET
+        unit_cost_cents = COALESCE(NEW.unit_cost_cents, CAST(ROUND(NEW.unit_cost * 100) AS INTEGER)),
+        unit_cost = CASE WHEN NEW.unit_cost_cents IS NULL THEN NEW.unit_cost ELSE NEW.unit_cost_cents / 100.0 END
+    WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_stock_movements_money_cents_update AFTER UPDATE OF unit_cost, unit_cost_cents ON stock_movements
+BEGIN
+    UPDATE stock_movements SET
+        unit_cost_cents = CASE WHEN NEW.unit_cost_cents IS NOT OLD.unit_cost_cents THEN NEW.unit_cost_cents ELSE CAST(ROUND(NEW.unit_cost * 100) AS INTEGER) END,
+        unit_cost = CASE WHEN NEW.unit_cost_cents IS NOT OLD.unit_cost_cents THEN NEW.unit_cost_cents / 100.0 ELSE NEW.unit_cost END
+    WHERE id = NEW.id;
+END;
@@ -0,0 +1,5 @@
+-- Revert 009_sync_parked_changes
+
+DROP TRIGGER IF EXISTS transactions_report_status_change;
+ALTER TABLE transactions DROP COLUMN reported_at;
+DROP TABLE IF EXISTS sync_parked_changes;
@@ -0,0 +1,29 @@
+-- Remote changes that failed to apply, and sales reported to the backend
+-- A pulled change that can't be written (e.g. it references a row that
+-- hasn't arrived yet) is parked here with the error instead of failing the
+-- whole pull, and retried on every poll until it applies or a newer
+-- version of the row replaces it.
+
+CREATE TABLE sync_parked_changes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    table_name TEXT NOT NULL,
+    backend_id TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    change TEXT NOT NULL,  -- the remote change as received, JSON
+    last_error TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 1,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_attempt_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (table_name, backend_id)
+);
+
+-- Set once a completed or voided sale has been sent to /sales/report
+ALTER TABLE transactions ADD COLUMN reported_at DATETIME;
+
+-- A sale voided after it was reported goes out again with its new status
+CREATE TRIGGER transactions_report_status_change
+AFTER UPDATE OF status ON transactions
+WHEN OLD.status <> NEW.status AND NEW.reported_at IS NOT NULL
+BEGIN
+    UPDATE transactions SET reported_at = NULL WHERE id = NEW.id;
+END;
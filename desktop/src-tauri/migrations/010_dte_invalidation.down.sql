@@ -0,0 +1,4 @@
+-- Revert 010_dte_invalidation
+
+DROP INDEX IF EXISTS idx_dte_invalidation_requested;
+ALTER TABLE dte DROP COLUMN invalidation_requested_at;
@@ -0,0 +1,8 @@
+-- DTEs of cancelled sales waiting for an invalidation event
+-- Voiding a sale sets this on its DTEs; the DTE flow sends the invalidation
+-- to Hacienda for accepted documents and clears it.
+
+ALTER TABLE dte ADD COLUMN invalidation_requested_at DATETIME;
+
+CREATE INDEX idx_dte_invalidation_requested
+    ON dte(invalidation_requested_at) WHERE invalidation_requested_at IS NOT NULL;
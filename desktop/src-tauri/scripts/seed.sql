@@ -13,7 +13,21 @@ INSERT OR IGNORE INTO system_settings (key, value, description, category, is_sys
 ('company_phone', '', 'Company phone number', 'company', 0),
 ('low_stock_alert', '10', 'Minimum stock level for alerts', 'inventory', 0),
 ('backup_frequency', '24', 'Backup frequency in hours', 'system', 1),
+('backup_directory', '', 'Backup directory (absolute path, empty for app data/backups)', 'system', 1),
+('backup_retention_count', '14', 'Number of backups to keep', 'system', 1),
+('backup_retention_days', '30', 'Days to keep backups', 'system', 1),
+('maintenance_interval_hours', '24', 'Hours between integrity checks and VACUUM, 0 disables', 'system', 1),
+('sync_api_url', '', 'Backend base URL for sync, empty disables sync', 'sync', 1),
 ('session_timeout', '480', 'Session timeout in minutes', 'security', 1),
+('pin_max_attempts', '5', 'Failed PIN attempts before a user is locked', 'security', 1),
+('pin_terminal_max_attempts', '20', 'Failed PIN attempts on this terminal, any user, before it is locked', 'security', 1),
+('pin_delay_base_seconds', '1', 'Wait after a failed PIN attempt, doubled after each further failure', 'security', 1),
+('pin_delay_max_seconds', '60', 'Longest wait between failed PIN attempts', 'security', 1),
+('pin_lockout_minutes', '15', 'Lockout duration after too many failed PIN attempts', 'security', 1),
+('pin_argon2_memory_kib', '19456', 'Argon2id memory cost for new PIN hashes (KiB); weaker hashes are upgraded on login', 'security', 1),
+('pin_argon2_iterations', '2', 'Argon2id time cost (passes) for new PIN hashes', 'security', 1),
+('pin_argon2_parallelism', '1', 'Argon2id lanes for new PIN hashes', 'security', 1),
+('auth_legacy_pin_hashes', 'true', 'Accept unsalted SHA-256 PIN hashes (upgraded on login); set to false once all users migrated', 'security', 1),
 ('default_customer_name', 'Consumidor Final', 'Default customer name for transactions', 'ui', 0),
 ('default_customer_type', 'general', 'Default customer type', 'ui', 0);
 
@@ -0,0 +1,188 @@
+#!/usr/bin/env python3
+"""Stand-in for the backend's /sync/changes and /sales/report endpoints,
+for testing the desktop sync client without the Go backend.
+
+Every pushed change is acknowledged and kept in memory, unless its
+`base_version` is older than the stub's copy of the row: that is rejected
+as a version conflict with the current row attached. Changes queued with
+--seed (a JSON file holding a list of change objects) or POSTed to
+/stub/changes count as edits made at HQ and are returned to terminals whose
+sequence is behind them. Reported sales are kept by id and listed at
+/stub/sales.
+
+    python3 scripts/sync_stub_server.py --port 8787 [--seed changes.json]
+
+Then set `sync_api_url` to http://127.0.0.1:8787. Options to exercise the
+client's error handling:
+
+    --reject TABLE   reject every change to TABLE
+    --fail-every N   answer every Nth /sync/changes call with 503
+"""
+
+import argparse
+import base64
+import json
+import sys
+import threading
+from http.server import BaseHTTPRequestHandler, ThreadingHTTPServer
+
+FORMAT = "pos-change-log/1"
+
+
+class Store:
+    def __init__(self, seed):
+        self.lock = threading.Lock()
+        self.log = []  # (sequence, change) visible to pulls
+        self.rows = {}  # (table, backend_id) -> change
+        self.sales = {}  # id -> latest report
+        self.calls = 0
+        for change in seed:
+            self.publish(change)
+
+    def publish(self, change):
+        self.log.append((len(self.log) + 1, change))
+        self.store(change)
+
+    def store(self, change):
+        key = (change["table"], change["backend_id"])
+        if change["operation"] == "delete":
+            self.rows.pop(key, None)
+        else:
+            self.rows[key] = change
+
+    def since(self, sequence):
+        return [c for s, c in self.log if s > sequence], len(self.log)
+
+
+class Handler(BaseHTTPRequestHandler):
+    store = None
+    options = None
+
+    def do_GET(self):
+        if self.path == "/health":
+            self.reply(200, {"status": "ok"})
+        elif self.path == "/stub/rows":
+            with self.store.lock:
+                rows = list(self.store.rows.values())
+            self.reply(200, rows)
+        elif self.path == "/stub/sales":
+            with self.store.lock:
+                sales = list(self.store.sales.values())
+            self.reply(200, sales)
+        else:
+            self.reply(404, {"error": "not found"})
+
+    def do_POST(self):
+        body = self.read_json()
+        if body is None:
+            return
+        if self.path == "/sync/changes":
+            self.sync_changes(body)
+        elif self.path == "/sales/report":
+            if not isinstance(body, list):
+                self.reply(400, {"error": "expected a list of sales"})
+                return
+            with self.store.lock:
+                for sale in body:
+                    self.store.sales[sale["id"]] = sale
+            print(f"{len(body)} sales reported", file=sys.stderr)
+            self.reply(200, {"reported": len(body)})
+        elif self.path == "/stub/changes":
+            with self.store.lock:
+                for change in body if isinstance(body, list) else [body]:
+                    self.store.publish(change)
+            self.reply(200, {"queued": True})
+        else:
+            self.reply(404, {"error": "not found"})
+
+    def sync_changes(self, body):
+        store, options = self.store, self.options
+        with store.lock:
+            store.calls += 1
+            if options.fail_every and store.calls % options.fail_every == 0:
+                self.reply(503, {"error": "stub outage"})
+                return
+
+            try:
+                change_set = json.loads(base64.b64decode(body["changes"]))
+            except (KeyError, ValueError) as e:
+                self.reply(400, {"error": f"invalid changes: {e}"})
+                return
+            if change_set.get("format") != FORMAT:
+                self.reply(400, {"error": f"unsupported format {change_set.get('format')}"})
+                return
+
+            acknowledged, rejected = [], []
+            for change in change_set.get("changes", []):
+                if change["table"] in options.reject:
+                    rejected.append({"change_id": change["change_id"], "reason": "rejected by stub"})
+                    continue
+                current = store.rows.get((change["table"], change["backend_id"]))
+                base = change.get("base_version")
+                if current and base is not None and base < current["version"]:
+                    rejected.append({
+                        "change_id": change["change_id"],
+                        "reason": f"version conflict: backend has {current['version']}",
+                        "current": current,
+                    })
+                    continue
+                store.store({k: v for k, v in change.items() if k not in ("change_id", "base_version")})
+                acknowledged.append({"change_id": change["change_id"]})
+
+            changes, next_sequence = store.since(int(body.get("sequence", 0)))
+
+        server_changes = {"acknowledged": acknowledged, "rejected": rejected, "changes": changes}
+        encoded = base64.b64encode(json.dumps(server_changes).encode()).decode()
+        print(
+            f"terminal {body.get('terminal_id')}: {len(acknowledged)} acked, "
+            f"{len(rejected)} rejected, {len(changes)} sent",
+            file=sys.stderr,
+        )
+        self.reply(200, {"accepted": True, "server_changes": encoded, "next_sequence": next_sequence})
+
+    def read_json(self):
+        length = int(self.headers.get("Content-Length", 0))
+        try:
+            return json.loads(self.rfile.read(length) or b"null")
+        except ValueError as e:
+            self.reply(400, {"error": f"invalid JSON: {e}"})
+            return None
+
+    def reply(self, status, payload):
+        data = json.dumps(payload).encode()
+        self.send_response(status)
+        self.send_header("Content-Type", "application/json")
+        self.send_header("Content-Length", str(len(data)))
+        self.end_headers()
+        self.wfile.write(data)
+
+    def log_message(self, format, *args):
+        pass
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("--host", default="127.0.0.1")
+    parser.add_argument("--port", type=int, default=8787)
+    parser.add_argument("--seed", help="JSON file with remote changes to serve")
+    parser.add_argument("--reject", action="append", default=[], metavar="TABLE")
+    parser.add_argument("--fail-every", type=int, default=0, metavar="N")
+    options = parser.parse_args()
+
+    seed = []
+    if options.seed:
+        with open(options.seed) as f:
+            seed = json.load(f)
+
+    Handler.store = Store(seed)
+    Handler.options = options
+    server = ThreadingHTTPServer((options.host, options.port), Handler)
+    print(f"Sync stub listening on http://{options.host}:{options.port}", file=sys.stderr)
+    try:
+        server.serve_forever()
+    except KeyboardInterrupt:
+        pass
+
+
+if __name__ == "__main__":
+    main()
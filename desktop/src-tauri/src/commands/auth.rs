@@ -1,50 +1,330 @@
-use argon2::{
-    Argon2,
-    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng},
-};
+use tauri::State;
+
+use std::time::Duration;
+
+use serde_json::json;
 
 use crate::error::AppError;
+use crate::services::approvals::{ApprovalAction, ApprovalManager, ApprovalToken};
+use crate::services::audit::{self, AuditEntry};
+use crate::services::auth_session::{self, SessionInfo, SessionManager};
+use crate::services::database::open_read_only_connection;
+use crate::services::db_writer::DbWriter;
+use crate::services::permissions::{self, Permission};
+use crate::services::pin_auth::{self, CheckedAttempt, LoginOutcome, LoginUser};
+use crate::services::pin_hash::{self, PinHashBenchmark, PinHashParams};
 
-/// Hash a PIN using Argon2id
-/// Returns the hash in PHC string format
+/// Hash a PIN using Argon2id with the configured parameters
+/// Returns the hash in PHC string format, for creating or updating a user.
+/// Only allowed from the main window, and needs `can_manage_users`.
 #[tauri::command]
-pub async fn hash_pin(pin: String) -> Result<String, AppError> {
+pub async fn hash_pin(
+    app_handle: tauri::AppHandle,
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
+    pin: String,
+) -> Result<String, AppError> {
+    if window.label() != "main" {
+        log::warn!(
+            "hash_pin rejected: called from non-main window '{}'",
+            window.label()
+        );
+        return Err(AppError::Validation(
+            "PINs can only be set from the main window".to_string(),
+        ));
+    }
+    sessions.authorize(&window, Permission::CanManageUsers)?;
+
     tokio::task::spawn_blocking(move || {
-        let salt = SaltString::generate(&mut OsRng);
-        let argon2 = Argon2::default();
+        let params = open_read_only_connection(&app_handle)
+            .and_then(|conn| PinHashParams::load(&conn))
+            .map_err(AppError::Database)?;
+        pin_hash::hash(&pin, &params).map_err(AppError::Auth)
+    })
+    .await
+    .map_err(|e| AppError::TaskJoin(e.to_string()))?
+}
+
+/// Time Argon2 on this machine and suggest the costliest parameters that
+/// verify within `target_ms` (250 by default). With `apply` they become the
+/// settings for new hashes, and existing hashes are upgraded as users log
+/// in. Needs `can_manage_settings`.
+#[tauri::command]
+pub async fn benchmark_pin_hash(
+    app_handle: tauri::AppHandle,
+    window: tauri::Window,
+    writer: State<'_, DbWriter>,
+    sessions: State<'_, SessionManager>,
+    target_ms: Option<u64>,
+    apply: Option<bool>,
+) -> Result<PinHashBenchmark, AppError> {
+    let session = sessions.authorize(&window, Permission::CanManageSettings)?;
+    let target = target_ms.map_or(pin_hash::DEFAULT_TARGET, Duration::from_millis);
+    if !(Duration::from_millis(50)..=Duration::from_secs(5)).contains(&target) {
+        return Err(AppError::Validation(
+            "Target time must be between 50 and 5000 ms".to_string(),
+        ));
+    }
+
+    let mut result = tokio::task::spawn_blocking(move || pin_hash::benchmark(target))
+        .await
+        .map_err(|e| AppError::TaskJoin(e.to_string()))?
+        .map_err(AppError::System)?;
+    if !apply.unwrap_or(false) {
+        return Ok(result);
+    }
 
-        argon2
-            .hash_password(pin.as_bytes(), &salt)
-            .map(|hash| hash.to_string())
-            .map_err(|e| AppError::Auth(format!("Failed to hash PIN: {}", e)))
+    let params = result.params;
+    let user_id = session.user.id;
+    writer
+        .execute(&app_handle, move |conn| {
+            conn.in_transaction(|conn| {
+                let previous = PinHashParams::load(conn)?;
+                params.save(conn)?;
+                audit::record(
+                    conn,
+                    AuditEntry {
+                        user_id: Some(user_id),
+                        action: "pin_hash_params_changed",
+                        table_name: Some("system_settings"),
+                        record_id: None,
+                        old_values: Some(json!({ "params": previous.describe() })),
+                        new_values: Some(json!({ "params": params.describe() })),
+                    },
+                )
+            })
+        })
+        .await
+        .map_err(AppError::Database)?;
+
+    log::info!("PIN hash parameters set to {}", params.describe());
+    result.applied = true;
+    Ok(result)
+}
+
+/// Load a PIN attempt and check the PIN on a blocking thread, so Argon2
+/// doesn't hold up the writer. `pin_auth::record_attempt` then counts it in a
+/// short write.
+async fn check_pin_attempt(
+    app_handle: tauri::AppHandle,
+    user_id: i64,
+    pin: String,
+    purpose: &'static str,
+    ignore_terminal_lock: bool,
+) -> Result<CheckedAttempt, AppError> {
+    tokio::task::spawn_blocking(move || {
+        let conn = open_read_only_connection(&app_handle)?;
+        pin_auth::load_attempt(&conn, user_id, purpose, ignore_terminal_lock)?.check(&pin)
     })
     .await
     .map_err(|e| AppError::TaskJoin(e.to_string()))?
+    .map_err(AppError::Database)
 }
 
-/// Verify a PIN against its Argon2 hash
-/// Supports both legacy SHA256 hashes and new Argon2 hashes
+/// Active users to pick from on the login screen. Needs no session; the
+/// list carries names and roles only.
 #[tauri::command]
-pub async fn verify_pin(pin: String, hash: String) -> Result<bool, AppError> {
+pub async fn list_login_users(app_handle: tauri::AppHandle) -> Result<Vec<LoginUser>, AppError> {
     tokio::task::spawn_blocking(move || {
-        // Try Argon2 verification first (new format)
-        if let Ok(parsed_hash) = PasswordHash::new(&hash) {
-            let argon2 = Argon2::default();
-            return argon2.verify_password(pin.as_bytes(), &parsed_hash).is_ok();
-        }
-
-        // Fallback to SHA256 for legacy hashes (64 hex characters)
-        // This allows existing users to continue logging in
-        if hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()) {
-            use sha2::{Digest, Sha256};
-            let mut hasher = Sha256::new();
-            hasher.update(pin.as_bytes());
-            let pin_hash = format!("{:x}", hasher.finalize());
-            return pin_hash == hash;
-        }
-
-        false
+        let conn = open_read_only_connection(&app_handle)?;
+        pin_auth::login_users(&conn)
     })
     .await
-    .map_err(|e| AppError::TaskJoin(e.to_string()))
+    .map_err(|e| AppError::TaskJoin(e.to_string()))?
+    .map_err(AppError::Database)
+}
+
+/// Log a user in with their PIN and start a session for the calling
+/// window. Failed attempts delay the next one and eventually lock the user
+/// or terminal; every attempt is audited. A matching legacy SHA256 hash is
+/// replaced with an Argon2id hash.
+#[tauri::command]
+pub async fn login_with_pin(
+    app_handle: tauri::AppHandle,
+    window: tauri::Window,
+    writer: State<'_, DbWriter>,
+    sessions: State<'_, SessionManager>,
+    user_id: i64,
+    pin: String,
+) -> Result<SessionInfo, AppError> {
+    let attempt = check_pin_attempt(app_handle.clone(), user_id, pin, "login", false).await?;
+    let (outcome, idle_timeout) = writer
+        .execute(&app_handle, move |conn| {
+            conn.in_transaction(|conn| {
+                let outcome = pin_auth::record_attempt(conn, attempt)?;
+                Ok((outcome, auth_session::idle_timeout(conn)?))
+            })
+        })
+        .await
+        .map_err(AppError::Database)?;
+
+    let user = outcome.into_result().map_err(AppError::Auth)?;
+    log::info!("User {} logged in on window '{}'", user.id, window.label());
+    Ok(sessions.start(window.label(), user, idle_timeout))
+}
+
+/// End the calling window's session
+#[tauri::command]
+pub async fn logout(
+    app_handle: tauri::AppHandle,
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
+) -> Result<(), AppError> {
+    let Some(session) = sessions.end(window.label()) else {
+        return Ok(());
+    };
+    log::info!(
+        "User {} logged out of window '{}'",
+        session.user.id,
+        window.label()
+    );
+    auth_session::audit_event(&app_handle, "logout", &session)
+        .await
+        .map_err(AppError::Database)
+}
+
+/// The calling window's session, `None` when logged out or expired
+#[tauri::command]
+pub async fn get_current_session(
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
+) -> Result<Option<SessionInfo>, AppError> {
+    Ok(sessions.current(window.label()).map(|s| s.info()))
+}
+
+/// Report user activity (input, scanning) that doesn't otherwise reach a
+/// command, so the session isn't ended as idle
+#[tauri::command]
+pub async fn touch_session(
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
+) -> Result<SessionInfo, AppError> {
+    sessions.require(&window).map(|s| s.info())
+}
+
+/// Clear a user's PIN lockout (or only the terminal's, without `user_id`)
+/// after a user with `can_manage_users` enters their PIN. Only allowed from
+/// the main window.
+#[tauri::command]
+pub async fn unlock_pin_lockout(
+    app_handle: tauri::AppHandle,
+    window: tauri::Window,
+    writer: State<'_, DbWriter>,
+    manager_id: i64,
+    manager_pin: String,
+    user_id: Option<i64>,
+) -> Result<(), AppError> {
+    if window.label() != "main" {
+        log::warn!(
+            "unlock_pin_lockout rejected: called from non-main window '{}'",
+            window.label()
+        );
+        return Err(AppError::Validation(
+            "PIN lockouts can only be cleared from the main window".to_string(),
+        ));
+    }
+
+    let manager =
+        check_pin_attempt(app_handle.clone(), manager_id, manager_pin, "unlock", true).await?;
+    writer
+        .execute(&app_handle, move |conn| {
+            conn.in_transaction(|conn| pin_auth::unlock(conn, manager, user_id))
+        })
+        .await
+        .map_err(AppError::Database)?
+        .map_err(AppError::Auth)
+}
+
+/// Let a supervisor approve one action on one record for the user logged in
+/// on this window, without logging them out. The approver's PIN goes
+/// through the usual lockout, and they need the permission the action
+/// requires. The returned token works once, within a minute.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn request_approval(
+    app_handle: tauri::AppHandle,
+    window: tauri::Window,
+    writer: State<'_, DbWriter>,
+    sessions: State<'_, SessionManager>,
+    approvals: State<'_, ApprovalManager>,
+    approver_id: i64,
+    approver_pin: String,
+    action: ApprovalAction,
+    record_id: i64,
+) -> Result<ApprovalToken, AppError> {
+    let session = sessions.require(&window)?;
+    if approver_id == session.user.id {
+        return Err(AppError::Validation(
+            "Approval must come from another user".to_string(),
+        ));
+    }
+
+    let requested_by = session.user.id;
+    let window_label = session.window_label.clone();
+    let attempt = check_pin_attempt(
+        app_handle.clone(),
+        approver_id,
+        approver_pin,
+        "approval",
+        false,
+    )
+    .await?;
+    let (outcome, granted) = writer
+        .execute(&app_handle, move |conn| {
+            conn.in_transaction(|conn| {
+                let outcome = pin_auth::record_attempt(conn, attempt)?;
+                let LoginOutcome::Success(approver) = &outcome else {
+                    return Ok((outcome, false));
+                };
+                let granted = permissions::resolve(&approver.role, &approver.permissions)
+                    .contains(&action.permission());
+                audit::record(
+                    conn,
+                    AuditEntry {
+                        user_id: Some(requested_by),
+                        action: if granted {
+                            "approval_granted"
+                        } else {
+                            "approval_denied"
+                        },
+                        table_name: None,
+                        record_id: Some(record_id),
+                        old_values: None,
+                        new_values: Some(json!({
+                            "approval_action": action.as_str(),
+                            "approved_by": approver_id,
+                            "requested_by": requested_by,
+                            "window_label": window_label,
+                        })),
+                    },
+                )?;
+                Ok((outcome, granted))
+            })
+        })
+        .await
+        .map_err(AppError::Database)?;
+
+    let approver = outcome.into_result().map_err(AppError::Auth)?;
+    if !granted {
+        log::warn!(
+            "User {} cannot approve {} (missing {})",
+            approver.id,
+            action.as_str(),
+            action.permission().as_str()
+        );
+        return Err(AppError::Forbidden(format!(
+            "{} is not allowed to approve {}",
+            approver.full_name,
+            action.as_str()
+        )));
+    }
+
+    log::info!(
+        "User {} approved {} on {} for user {}",
+        approver.id,
+        action.as_str(),
+        record_id,
+        requested_by
+    );
+    Ok(approvals.issue(&session, &approver, action, record_id))
 }
@@ -0,0 +1,81 @@
+use tauri::{Emitter, State};
+
+use crate::error::AppError;
+use crate::services::auth_session::SessionManager;
+use crate::services::backup::{self, BackupInfo, BackupReason, BackupSettings, RestoreResult};
+use crate::services::db_writer::DbWriter;
+use crate::services::permissions::Permission;
+
+/// Take a backup now, outside the schedule
+#[tauri::command]
+pub async fn create_backup(
+    app_handle: tauri::AppHandle,
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
+) -> Result<BackupInfo, AppError> {
+    sessions.authorize(&window, Permission::CanManageDatabase)?;
+    tokio::task::spawn_blocking(move || backup::create_backup(&app_handle, BackupReason::Manual))
+        .await
+        .map_err(|e| AppError::TaskJoin(e.to_string()))?
+        .map_err(AppError::Database)
+}
+
+/// Backups in the configured directory, newest first
+#[tauri::command]
+pub async fn list_backups(app_handle: tauri::AppHandle) -> Result<Vec<BackupInfo>, AppError> {
+    tokio::task::spawn_blocking(move || {
+        let settings = BackupSettings::load(&app_handle)?;
+        backup::list_backups(&settings)
+    })
+    .await
+    .map_err(|e| AppError::TaskJoin(e.to_string()))?
+    .map_err(AppError::Database)
+}
+
+/// Effective backup directory, frequency and retention
+#[tauri::command]
+pub async fn get_backup_settings(app_handle: tauri::AppHandle) -> Result<BackupSettings, AppError> {
+    tokio::task::spawn_blocking(move || BackupSettings::load(&app_handle))
+        .await
+        .map_err(|e| AppError::TaskJoin(e.to_string()))?
+        .map_err(AppError::Database)
+}
+
+/// Replace the database with a validated backup. Only allowed from the main
+/// window. Runs on the database writer, so queued writes wait for it; every
+/// window receives `database-restored` and should reload its data.
+#[tauri::command]
+pub async fn restore_backup(
+    app_handle: tauri::AppHandle,
+    window: tauri::Window,
+    writer: State<'_, DbWriter>,
+    sessions: State<'_, SessionManager>,
+    file_name: String,
+) -> Result<RestoreResult, AppError> {
+    if window.label() != "main" {
+        log::warn!(
+            "restore_backup rejected: called from non-main window '{}'",
+            window.label()
+        );
+        return Err(AppError::Validation(
+            "Restoring a backup is only available from the main window".to_string(),
+        ));
+    }
+    sessions.authorize(&window, Permission::CanManageDatabase)?;
+
+    let handle = app_handle.clone();
+    let result = writer
+        .execute(&app_handle, move |_conn| {
+            backup::restore_backup(&handle, &file_name)
+        })
+        .await
+        .map_err(AppError::Database)?;
+    // Later writes start from a connection opened on the restored database
+    writer.reset_sender();
+
+    if let Err(e) = app_handle.emit("database-restored", &result.restored) {
+        log::warn!("Failed to emit database-restored: {}", e);
+    }
+
+    Ok(result)
+}
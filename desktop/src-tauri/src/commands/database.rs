@@ -1,7 +1,7 @@
-use base64::Engine;
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
-use tauri::Manager;
 
 /// A single SQL statement to execute within a transaction.
 #[derive(Debug, Deserialize)]
@@ -10,6 +10,9 @@ pub struct TransactionStatement {
     pub params: Vec<JsonValue>,
     #[serde(default)]
     pub query: bool,
+    /// Name other statements can use to reference this result via `$ref:alias`
+    #[serde(default)]
+    pub alias: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -35,108 +38,674 @@ pub enum StatementResult {
     Query {
         rows: Vec<serde_json::Map<String, JsonValue>>,
     },
+    /// The statement failed, was skipped or was undone by a savepoint rollback
+    Discarded,
+}
+
+/// A batch entry: either a plain statement or a group of statements run
+/// under a named savepoint
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum TransactionStep {
+    Statement(TransactionStatement),
+    Savepoint(SavepointGroup),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavepointGroup {
+    pub savepoint: String,
+    #[serde(default)]
+    pub on_error: OnError,
+    pub statements: Vec<TransactionStatement>,
+}
+
+/// What to do when a statement inside a savepoint fails
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnError {
+    /// Undo the savepoint's statements and continue with the rest of the batch
+    RollbackSavepoint,
+    /// Roll back the whole transaction
+    #[default]
+    Abort,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OutcomeStatus {
+    /// Executed and committed
+    Ok,
+    /// This statement's error triggered a savepoint rollback
+    Failed,
+    /// Executed, then undone by a savepoint rollback
+    RolledBack,
+    /// Not executed because an earlier statement in its savepoint failed
+    Skipped,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatementOutcome {
+    pub index: usize,
+    pub savepoint: Option<String>,
+    pub status: OutcomeStatus,
+    pub error: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TransactionResult {
+    /// One entry per statement, in flattened batch order
     pub results: Vec<StatementResult>,
+    pub outcomes: Vec<StatementOutcome>,
     pub last_insert_id: i64,
 }
 
+use tauri::{Emitter, State};
+
 use crate::error::AppError;
+use crate::services::approvals::{self, ApprovalManager, ApprovalUse};
+use crate::services::audit::{self, AuditEntry};
+use crate::services::auth_session::{Session, SessionManager};
+use crate::services::backup::{self, BackupReason};
+use crate::services::database::{Setting, list_settings, open_read_only_connection};
+use crate::services::db_encryption::{self, EncryptionStatus};
+use crate::services::db_writer::{self, DbWriter, WriteConnection, WriterMetricsSnapshot};
+use crate::services::migrations::{self, SchemaInfo};
+use crate::services::permissions::{self, Permission};
+use crate::services::sql_value::{ResultEncoding, json_to_sql_values, result_columns, row_to_json};
+use crate::services::sync::SyncEngine;
 
+/// Execute a batch of statements atomically.
+/// Parameters may be plain JSON or tagged values (`{"type":"blob","base64":..}`,
+/// `{"type":"decimal","value":"12.34"}`, `{"type":"datetime","value":..}`).
+/// With `result_encoding: "typed"` query rows come back in the same tagged form.
+/// Optional steps can be wrapped in `{ savepoint, onError, statements }`;
+/// with `onError: "rollback_savepoint"` a failure there only undoes that group.
+/// Needs a session whose permissions cover every table the batch writes;
+/// otherwise nothing runs and the call fails with `AppError::Forbidden`.
+/// A discount above `discount_approval_threshold`, a price matching none of
+/// the product's tiers or a cash movement (drawer opening) needs
+/// `can_override_prices` / `can_open_drawer` or a supervisor approval for
+/// it in `approvals`, or the batch is rolled back with `Forbidden`. The
+/// approvals are used up and recorded in `audit_logs` only if the batch
+/// commits.
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn execute_transaction(
     app_handle: tauri::AppHandle,
-    statements: Vec<TransactionStatement>,
+    window: tauri::Window,
+    writer: State<'_, DbWriter>,
+    sync: State<'_, SyncEngine>,
+    sessions: State<'_, SessionManager>,
+    approval_manager: State<'_, ApprovalManager>,
+    statements: Vec<TransactionStep>,
+    result_encoding: Option<ResultEncoding>,
+    approvals: Option<Vec<ApprovalUse>>,
 ) -> Result<TransactionResult, AppError> {
-    tokio::task::spawn_blocking(move || execute_transaction_sync(&app_handle, statements))
+    let session = sessions.require(&window)?;
+    validate_steps(&statements).map_err(AppError::Validation)?;
+    let encoding = result_encoding.unwrap_or_default();
+    let uses = approvals.unwrap_or_default();
+    for used in &uses {
+        approval_manager.check(&session, used)?;
+    }
+
+    let approvals = approval_manager.inner().clone();
+    let result = writer
+        .execute(&app_handle, move |conn| {
+            if let Err(denied) = permissions::check_statements(
+                conn,
+                flatten(&statements).map(|stmt| stmt.sql.as_str()),
+                &session.permissions,
+            )? {
+                log::warn!(
+                    "execute_transaction denied for user {}: {}",
+                    session.user.id,
+                    denied.message()
+                );
+                return Ok(Err(AppError::Forbidden(denied.message())));
+            }
+            Ok(run_transaction(
+                conn,
+                &statements,
+                encoding,
+                &approvals,
+                &session,
+                &uses,
+            ))
+        })
+        .await
+        .map_err(AppError::Database)??;
+
+    // Sales and edits should reach the backend promptly
+    sync.wake();
+    Ok(result)
+}
+
+/// Queue wait and lock-retry figures for the shared database writer
+#[tauri::command]
+pub async fn get_db_writer_metrics(
+    writer: State<'_, DbWriter>,
+) -> Result<WriterMetricsSnapshot, AppError> {
+    Ok(writer.metrics())
+}
+
+/// Applied and pending schema migrations with their checksum status
+#[tauri::command]
+pub async fn get_schema_info(app_handle: tauri::AppHandle) -> Result<SchemaInfo, AppError> {
+    tokio::task::spawn_blocking(move || {
+        let conn = open_read_only_connection(&app_handle)?;
+        migrations::schema_info(&conn)
+    })
+    .await
+    .map_err(|e| AppError::TaskJoin(e.to_string()))?
+    .map_err(AppError::Database)
+}
+
+/// `system_settings` whose key starts with `prefix`, all when it's omitted.
+/// Needs no session, since the app reads its settings before anyone logs
+/// in; settings holding credentials are left out.
+#[tauri::command]
+pub async fn get_system_settings(
+    app_handle: tauri::AppHandle,
+    prefix: Option<String>,
+) -> Result<Vec<Setting>, AppError> {
+    tokio::task::spawn_blocking(move || {
+        let conn = open_read_only_connection(&app_handle)?;
+        list_settings(&conn, prefix.as_deref())
+    })
+    .await
+    .map_err(|e| AppError::TaskJoin(e.to_string()))?
+    .map_err(AppError::Database)
+}
+
+/// Whether the database is encrypted, and the SQLCipher version in use
+#[tauri::command]
+pub async fn get_database_encryption_status(
+    app_handle: tauri::AppHandle,
+) -> Result<EncryptionStatus, AppError> {
+    tokio::task::spawn_blocking(move || {
+        let conn = open_read_only_connection(&app_handle)?;
+        db_encryption::status(&conn)
+    })
+    .await
+    .map_err(|e| AppError::TaskJoin(e.to_string()))?
+    .map_err(AppError::Database)
+}
+
+/// Re-encrypt the database with a newly generated key. Only allowed from the
+/// main window. Every window receives `database-rekeyed`.
+#[tauri::command]
+pub async fn rekey_database(
+    app_handle: tauri::AppHandle,
+    window: tauri::Window,
+    writer: State<'_, DbWriter>,
+    sessions: State<'_, SessionManager>,
+) -> Result<(), AppError> {
+    if window.label() != "main" {
+        log::warn!(
+            "rekey_database rejected: called from non-main window '{}'",
+            window.label()
+        );
+        return Err(AppError::Validation(
+            "Database rekey is only available from the main window".to_string(),
+        ));
+    }
+    sessions.authorize(&window, Permission::CanManageDatabase)?;
+
+    let handle = app_handle.clone();
+    writer
+        .execute(&app_handle, move |conn| db_encryption::rekey(&handle, conn))
+        .await
+        .map_err(AppError::Database)?;
+    // Later writes open the database with the new key
+    writer.reset_sender();
+
+    if let Err(e) = app_handle.emit("database-rekeyed", ()) {
+        log::warn!("Failed to emit database-rekeyed: {}", e);
+    }
+    Ok(())
+}
+
+/// The database recovery key, for an admin to keep offline: with
+/// `POS_DB_RECOVERY_KEY` it rebuilds the key vault if the keyring loses its
+/// password, and opens backups on a new machine. Only allowed from the main
+/// window; every export is written to `audit_logs`.
+#[tauri::command]
+pub async fn export_database_recovery_key(
+    app_handle: tauri::AppHandle,
+    window: tauri::Window,
+    writer: State<'_, DbWriter>,
+    sessions: State<'_, SessionManager>,
+) -> Result<String, AppError> {
+    if window.label() != "main" {
+        log::warn!(
+            "export_database_recovery_key rejected: called from non-main window '{}'",
+            window.label()
+        );
+        return Err(AppError::Validation(
+            "The recovery key can only be exported from the main window".to_string(),
+        ));
+    }
+    let session = sessions.authorize(&window, Permission::CanManageDatabase)?;
+    let recovery_key = db_encryption::recovery_key().map_err(AppError::System)?;
+
+    let user_id = session.user.id;
+    writer
+        .execute(&app_handle, move |conn| {
+            audit::record(
+                conn,
+                AuditEntry {
+                    user_id: Some(user_id),
+                    action: "database_recovery_key_exported",
+                    ..AuditEntry::default()
+                },
+            )
+        })
+        .await
+        .map_err(AppError::Database)?;
+    log::warn!("Database recovery key exported by user {}", user_id);
+    Ok(recovery_key.to_string())
+}
+
+/// Revert schema migrations down to `target_version` for support staff
+/// preparing a downgrade. Only allowed from the main window, and only after a
+/// safety backup has been written. Returns the reverted versions; restarting
+/// this build re-applies them.
+#[tauri::command]
+pub async fn rollback_schema(
+    app_handle: tauri::AppHandle,
+    window: tauri::Window,
+    writer: State<'_, DbWriter>,
+    sessions: State<'_, SessionManager>,
+    target_version: i64,
+) -> Result<Vec<i64>, AppError> {
+    if window.label() != "main" {
+        log::warn!(
+            "rollback_schema rejected: called from non-main window '{}'",
+            window.label()
+        );
+        return Err(AppError::Validation(
+            "Schema rollback is only available from the main window".to_string(),
+        ));
+    }
+    sessions.authorize(&window, Permission::CanManageDatabase)?;
+
+    log::warn!("Schema rollback to version {} requested", target_version);
+    let handle = app_handle.clone();
+    writer
+        .execute(&app_handle, move |conn| {
+            let safety_backup = backup::create_backup(&handle, BackupReason::PreRollback)
+                .map_err(|e| format!("Refusing to roll back without a safety backup: {}", e))?;
+            log::info!(
+                "Safety backup {} taken before rollback",
+                safety_backup.file_name
+            );
+            migrations::rollback_to(conn, target_version)
+        })
         .await
-        .map_err(|e| AppError::TaskJoin(e.to_string()))?
         .map_err(AppError::Database)
 }
 
-fn execute_transaction_sync(
-    app_handle: &tauri::AppHandle,
-    statements: Vec<TransactionStatement>,
-) -> Result<TransactionResult, String> {
-    let app_data_dir = app_handle
-        .path()
-        .app_data_dir()
-        .map_err(|e| format!("Failed to get app data dir: {}", e))?;
-    let db_path = app_data_dir.join("pos_database.db");
+/// Running state of a batch, indexed by flattened statement position
+#[derive(Default)]
+struct BatchState<'a> {
+    results: Vec<StatementResult>,
+    outcomes: Vec<StatementOutcome>,
+    /// `None` for statements whose effects were discarded
+    insert_ids: Vec<Option<i64>>,
+    aliases: HashMap<&'a str, usize>,
+    last_insert_id: i64,
+}
 
-    let conn = rusqlite::Connection::open(&db_path)
-        .map_err(|e| format!("Failed to open database: {}", e))?;
+/// Run the batch in one transaction and check what it wrote against the
+/// session's permissions and the approvals it brought
+fn run_transaction(
+    conn: &WriteConnection<'_>,
+    steps: &[TransactionStep],
+    encoding: ResultEncoding,
+    approvals: &ApprovalManager,
+    session: &Session,
+    uses: &[ApprovalUse],
+) -> Result<TransactionResult, AppError> {
+    approvals::in_transaction(conn, approvals, session, uses, |conn, approved| {
+        approvals::track_changes(conn).map_err(AppError::Database)?;
+        let state = run_steps(conn, steps, encoding).map_err(AppError::Database)?;
+        approvals::check_required(conn, session, approved)?;
 
-    configure_pragmas(&conn)?;
+        Ok(TransactionResult {
+            results: state.results,
+            outcomes: state.outcomes,
+            last_insert_id: state.last_insert_id,
+        })
+    })
+}
 
-    conn.execute("BEGIN IMMEDIATE", [])
-        .map_err(|e| format!("Failed to begin transaction: {}", e))?;
+fn run_steps<'a>(
+    conn: &rusqlite::Connection,
+    steps: &'a [TransactionStep],
+    encoding: ResultEncoding,
+) -> Result<BatchState<'a>, String> {
+    let mut state = BatchState::default();
 
-    let mut results = Vec::with_capacity(statements.len());
-    let mut insert_ids: Vec<i64> = Vec::with_capacity(statements.len());
-    let mut last_insert_id: i64 = 0;
+    for step in steps {
+        match step {
+            TransactionStep::Statement(stmt) => {
+                let index = state.results.len();
+                run_statement(conn, &mut state, stmt, encoding)
+                    .map_err(|e| format!("Statement {} failed: {}", index, e))?;
+                state.outcomes.push(StatementOutcome {
+                    index,
+                    savepoint: None,
+                    status: OutcomeStatus::Ok,
+                    error: None,
+                });
+            }
+            TransactionStep::Savepoint(group) => {
+                run_savepoint(conn, &mut state, group, encoding)?;
+            }
+        }
+    }
 
-    for (i, stmt) in statements.iter().enumerate() {
-        let resolved_params = resolve_params(&stmt.params, &insert_ids, last_insert_id)?;
+    Ok(state)
+}
 
-        if stmt.query {
-            match execute_query(&conn, &stmt.sql, &resolved_params) {
-                Ok(rows) => {
-                    insert_ids.push(0);
-                    results.push(StatementResult::Query { rows });
-                }
-                Err(e) => {
-                    let _ = conn.execute("ROLLBACK", []);
-                    return Err(format!("Statement {} failed: {}", i, e));
-                }
+fn run_savepoint<'a>(
+    conn: &rusqlite::Connection,
+    state: &mut BatchState<'a>,
+    group: &'a SavepointGroup,
+    encoding: ResultEncoding,
+) -> Result<(), String> {
+    let name = &group.savepoint;
+    conn.execute_batch(&format!("SAVEPOINT \"{}\"", name))
+        .map_err(|e| format!("Failed to create savepoint '{}': {}", name, e))?;
+
+    let first_index = state.results.len();
+    let last_insert_id_before = state.last_insert_id;
+
+    for (offset, stmt) in group.statements.iter().enumerate() {
+        let index = first_index + offset;
+        let Err(e) = run_statement(conn, state, stmt, encoding) else {
+            continue;
+        };
+
+        if group.on_error == OnError::Abort {
+            return Err(format!(
+                "Statement {} in savepoint '{}' failed: {}",
+                index, name, e
+            ));
+        }
+
+        conn.execute_batch(&format!(
+            "ROLLBACK TO SAVEPOINT \"{}\"; RELEASE SAVEPOINT \"{}\"",
+            name, name
+        ))
+        .map_err(|e| format!("Failed to roll back savepoint '{}': {}", name, e))?;
+
+        log::warn!(
+            "Savepoint '{}' rolled back after statement {} failed: {}",
+            name,
+            index,
+            e
+        );
+
+        discard_group(state, group, first_index, index, &e);
+        state.last_insert_id = last_insert_id_before;
+        return Ok(());
+    }
+
+    conn.execute_batch(&format!("RELEASE SAVEPOINT \"{}\"", name))
+        .map_err(|e| format!("Failed to release savepoint '{}': {}", name, e))?;
+
+    for offset in 0..group.statements.len() {
+        state.outcomes.push(StatementOutcome {
+            index: first_index + offset,
+            savepoint: Some(name.clone()),
+            status: OutcomeStatus::Ok,
+            error: None,
+        });
+    }
+
+    Ok(())
+}
+
+/// Mark every statement of a rolled-back savepoint and forget its aliases
+fn discard_group(
+    state: &mut BatchState<'_>,
+    group: &SavepointGroup,
+    first_index: usize,
+    failed_index: usize,
+    error: &str,
+) {
+    state.results.truncate(first_index);
+    state.insert_ids.truncate(first_index);
+    state
+        .aliases
+        .retain(|_, statement_index| *statement_index < first_index);
+
+    for offset in 0..group.statements.len() {
+        let index = first_index + offset;
+        let (status, error) = match index.cmp(&failed_index) {
+            std::cmp::Ordering::Less => (OutcomeStatus::RolledBack, None),
+            std::cmp::Ordering::Equal => (OutcomeStatus::Failed, Some(error.to_string())),
+            std::cmp::Ordering::Greater => (OutcomeStatus::Skipped, None),
+        };
+
+        state.results.push(StatementResult::Discarded);
+        state.insert_ids.push(None);
+        state.outcomes.push(StatementOutcome {
+            index,
+            savepoint: Some(group.savepoint.clone()),
+            status,
+            error,
+        });
+    }
+}
+
+/// Resolve parameters and run one statement, recording its result
+fn run_statement<'a>(
+    conn: &rusqlite::Connection,
+    state: &mut BatchState<'a>,
+    stmt: &'a TransactionStatement,
+    encoding: ResultEncoding,
+) -> Result<(), String> {
+    let index = state.results.len();
+    let params = resolve_params(
+        &stmt.params,
+        &state.insert_ids,
+        state.last_insert_id,
+        &state.aliases,
+        &state.results,
+    )
+    .and_then(|params| json_to_sql_values(&params))?;
+
+    if stmt.query {
+        let rows = execute_query(conn, &stmt.sql, &params, encoding).map_err(|e| e.to_string())?;
+        state.insert_ids.push(Some(0));
+        state.results.push(StatementResult::Query { rows });
+    } else {
+        let rows_affected =
+            execute_statement(conn, &stmt.sql, &params).map_err(|e| e.to_string())?;
+        state.last_insert_id = conn.last_insert_rowid();
+        state.insert_ids.push(Some(state.last_insert_id));
+        state.results.push(StatementResult::Execute {
+            rows_affected,
+            last_insert_id: state.last_insert_id,
+        });
+    }
+
+    if let Some(alias) = &stmt.alias {
+        state.aliases.insert(alias, index);
+    }
+
+    Ok(())
+}
+
+const REF_PREFIX: &str = "$ref:";
+
+/// A `$ref:alias[.path]` parameter pointing at an earlier statement's result
+#[derive(Debug)]
+struct ResultRef<'a> {
+    alias: &'a str,
+    path: RefPath<'a>,
+}
+
+#[derive(Debug)]
+enum RefPath<'a> {
+    /// `$ref:alias` or `$ref:alias.lastInsertId`
+    LastInsertId,
+    /// `$ref:alias.rowsAffected`
+    RowsAffected,
+    /// `$ref:alias.rows[N].column`
+    Column { row: usize, column: &'a str },
+}
+
+/// Check savepoint names, aliases and `$ref:` parameters before anything
+/// touches the database
+fn validate_steps(steps: &[TransactionStep]) -> Result<(), String> {
+    let mut savepoints = std::collections::HashSet::new();
+
+    for step in steps {
+        if let TransactionStep::Savepoint(group) = step {
+            if !is_valid_alias(&group.savepoint) {
+                return Err(format!(
+                    "Invalid savepoint name '{}' (use letters, digits and underscores)",
+                    group.savepoint
+                ));
+            }
+            if !savepoints.insert(group.savepoint.as_str()) {
+                return Err(format!("Duplicate savepoint '{}'", group.savepoint));
             }
-        } else {
-            match execute_statement(&conn, &stmt.sql, &resolved_params) {
-                Ok(rows_affected) => {
-                    last_insert_id = conn.last_insert_rowid();
-                    insert_ids.push(last_insert_id);
-                    results.push(StatementResult::Execute {
-                        rows_affected,
-                        last_insert_id,
-                    });
+        }
+    }
+
+    validate_statements(flatten(steps))
+}
+
+/// Statements of a batch in flattened order
+fn flatten(steps: &[TransactionStep]) -> impl Iterator<Item = &TransactionStatement> {
+    steps.iter().flat_map(|step| match step {
+        TransactionStep::Statement(stmt) => std::slice::from_ref(stmt),
+        TransactionStep::Savepoint(group) => group.statements.as_slice(),
+    })
+}
+
+/// A reference must name an alias declared by an earlier statement.
+/// Statement indexes are positions in the flattened batch.
+fn validate_statements<'a>(
+    statements: impl Iterator<Item = &'a TransactionStatement>,
+) -> Result<(), String> {
+    // alias -> whether the aliased statement is a query
+    let mut declared: HashMap<&str, bool> = HashMap::new();
+
+    for (i, stmt) in statements.enumerate() {
+        for param in &stmt.params {
+            let s = match param {
+                JsonValue::String(s) => s,
+                JsonValue::Object(_) => {
+                    json_to_sql_values(std::slice::from_ref(param))
+                        .map_err(|e| format!("Statement {}: {}", i, e))?;
+                    continue;
                 }
-                Err(e) => {
-                    let _ = conn.execute("ROLLBACK", []);
-                    return Err(format!("Statement {} failed: {}", i, e));
+                _ => continue,
+            };
+            let Some(reference) =
+                parse_result_ref(s).map_err(|e| format!("Statement {}: {}", i, e))?
+            else {
+                continue;
+            };
+
+            let is_query = *declared.get(reference.alias).ok_or_else(|| {
+                format!(
+                    "Statement {}: unknown alias '{}' in {}",
+                    i, reference.alias, s
+                )
+            })?;
+
+            match (&reference.path, is_query) {
+                (RefPath::Column { .. }, false) => {
+                    return Err(format!(
+                        "Statement {}: {} reads rows but '{}' is not a query",
+                        i, s, reference.alias
+                    ));
                 }
+                (RefPath::LastInsertId | RefPath::RowsAffected, true) => {
+                    return Err(format!(
+                        "Statement {}: {} needs an execute result but '{}' is a query",
+                        i, s, reference.alias
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(alias) = &stmt.alias {
+            if !is_valid_alias(alias) {
+                return Err(format!(
+                    "Statement {}: invalid alias '{}' (use letters, digits and underscores)",
+                    i, alias
+                ));
+            }
+            if declared.insert(alias, stmt.query).is_some() {
+                return Err(format!("Statement {}: duplicate alias '{}'", i, alias));
             }
         }
     }
 
-    conn.execute("COMMIT", [])
-        .map_err(|e| format!("Failed to commit: {}", e))?;
+    Ok(())
+}
+
+fn parse_result_ref(s: &str) -> Result<Option<ResultRef<'_>>, String> {
+    let Some(reference) = s.strip_prefix(REF_PREFIX) else {
+        return Ok(None);
+    };
+
+    let (alias, path) = match reference.split_once('.') {
+        Some((alias, path)) => (alias, Some(path)),
+        None => (reference, None),
+    };
 
-    Ok(TransactionResult {
-        results,
-        last_insert_id,
+    if !is_valid_alias(alias) {
+        return Err(format!("Invalid alias in {}", s));
+    }
+
+    let path = match path {
+        None | Some("lastInsertId") => RefPath::LastInsertId,
+        Some("rowsAffected") => RefPath::RowsAffected,
+        Some(path) => {
+            parse_column_path(path).ok_or_else(|| format!("Invalid reference path in {}", s))?
+        }
+    };
+
+    Ok(Some(ResultRef { alias, path }))
+}
+
+/// Parse `rows[N].column`
+fn parse_column_path(path: &str) -> Option<RefPath<'_>> {
+    let rest = path.strip_prefix("rows[")?;
+    let (index, column) = rest.split_once(']')?;
+    let column = column.strip_prefix('.')?;
+    if column.is_empty() {
+        return None;
+    }
+    Some(RefPath::Column {
+        row: index.parse().ok()?,
+        column,
     })
 }
 
-fn configure_pragmas(conn: &rusqlite::Connection) -> Result<(), String> {
-    conn.execute_batch(
-        "PRAGMA journal_mode = WAL;
-         PRAGMA synchronous = NORMAL;
-         PRAGMA foreign_keys = ON;
-         PRAGMA cache_size = -64000;
-         PRAGMA temp_store = MEMORY;
-         PRAGMA busy_timeout = 5000;
-         PRAGMA defer_foreign_keys = ON;",
-    )
-    .map_err(|e| format!("Failed to configure PRAGMAs: {}", e))
+fn is_valid_alias(alias: &str) -> bool {
+    !alias.is_empty() && alias.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
 }
 
 fn resolve_params(
     params: &[JsonValue],
-    insert_ids: &[i64],
+    insert_ids: &[Option<i64>],
     last_insert_id: i64,
+    aliases: &HashMap<&str, usize>,
+    results: &[StatementResult],
 ) -> Result<Vec<JsonValue>, String> {
     params
         .iter()
@@ -151,85 +720,322 @@ fn resolve_params(
                     .map_err(|_| format!("Invalid statement index in {}", s))?;
                 let id = insert_ids
                     .get(idx)
-                    .ok_or_else(|| format!("Statement index {} not yet executed", idx))?;
-                Ok(JsonValue::Number((*id).into()))
+                    .ok_or_else(|| format!("Statement index {} not yet executed", idx))?
+                    .ok_or_else(|| format!("Statement index {} was rolled back", idx))?;
+                Ok(JsonValue::Number(id.into()))
+            }
+            JsonValue::String(s) if s.starts_with(REF_PREFIX) => {
+                resolve_result_ref(s, aliases, results)
             }
             other => Ok(other.clone()),
         })
         .collect()
 }
 
-fn json_to_rusqlite_params(params: &[JsonValue]) -> Vec<Box<dyn rusqlite::types::ToSql>> {
-    params
-        .iter()
-        .map(|p| -> Box<dyn rusqlite::types::ToSql> {
-            match p {
-                JsonValue::Null => Box::new(rusqlite::types::Null),
-                JsonValue::Bool(b) => Box::new(*b as i64),
-                JsonValue::Number(n) => {
-                    if let Some(i) = n.as_i64() {
-                        Box::new(i)
-                    } else if let Some(f) = n.as_f64() {
-                        Box::new(f)
-                    } else {
-                        Box::new(n.to_string())
-                    }
-                }
-                JsonValue::String(s) => Box::new(s.clone()),
-                other => Box::new(other.to_string()),
-            }
-        })
-        .collect()
+fn resolve_result_ref(
+    s: &str,
+    aliases: &HashMap<&str, usize>,
+    results: &[StatementResult],
+) -> Result<JsonValue, String> {
+    let reference = parse_result_ref(s)?.ok_or_else(|| format!("Invalid reference {}", s))?;
+    let result = aliases
+        .get(reference.alias)
+        .and_then(|idx| results.get(*idx))
+        .ok_or_else(|| {
+            format!(
+                "Unknown or rolled back alias '{}' in {}",
+                reference.alias, s
+            )
+        })?;
+
+    match (reference.path, result) {
+        (RefPath::LastInsertId, StatementResult::Execute { last_insert_id, .. }) => {
+            Ok(JsonValue::Number((*last_insert_id).into()))
+        }
+        (RefPath::RowsAffected, StatementResult::Execute { rows_affected, .. }) => {
+            Ok(JsonValue::Number((*rows_affected as u64).into()))
+        }
+        (RefPath::Column { row, column }, StatementResult::Query { rows }) => {
+            let row_values = rows.get(row).ok_or_else(|| {
+                format!(
+                    "{} refers to row {} but '{}' returned {} rows",
+                    s,
+                    row,
+                    reference.alias,
+                    rows.len()
+                )
+            })?;
+            row_values
+                .get(column)
+                .cloned()
+                .ok_or_else(|| format!("{} refers to unknown column '{}'", s, column))
+        }
+        _ => Err(format!(
+            "{} does not match the result type of '{}'",
+            s, reference.alias
+        )),
+    }
 }
 
 fn execute_statement(
     conn: &rusqlite::Connection,
     sql: &str,
-    params: &[JsonValue],
+    params: &[rusqlite::types::Value],
 ) -> Result<usize, rusqlite::Error> {
-    let rusqlite_params = json_to_rusqlite_params(params);
-    let param_refs: Vec<&dyn rusqlite::types::ToSql> =
-        rusqlite_params.iter().map(|p| p.as_ref()).collect();
-    conn.execute(sql, param_refs.as_slice())
+    db_writer::retry_on_busy(
+        "run statement",
+        || (),
+        || conn.execute(sql, rusqlite::params_from_iter(params)),
+    )
 }
 
 fn execute_query(
     conn: &rusqlite::Connection,
     sql: &str,
-    params: &[JsonValue],
+    params: &[rusqlite::types::Value],
+    encoding: ResultEncoding,
 ) -> Result<Vec<serde_json::Map<String, JsonValue>>, rusqlite::Error> {
-    let rusqlite_params = json_to_rusqlite_params(params);
-    let param_refs: Vec<&dyn rusqlite::types::ToSql> =
-        rusqlite_params.iter().map(|p| p.as_ref()).collect();
-
     let mut stmt = conn.prepare(sql)?;
-    let column_names: Vec<String> = stmt.column_names().iter().map(|s| s.to_string()).collect();
-
-    let rows = stmt.query_map(param_refs.as_slice(), |row| {
-        let mut map = serde_json::Map::new();
-        for (i, name) in column_names.iter().enumerate() {
-            let value = row.get_ref(i)?;
-            let json_val = rusqlite_value_to_json(value);
-            map.insert(name.clone(), json_val);
-        }
-        Ok(map)
-    })?;
+    let columns = result_columns(&stmt);
 
-    rows.collect::<Result<Vec<_>, _>>()
+    db_writer::retry_on_busy(
+        "run query",
+        || (),
+        || {
+            stmt.query_map(rusqlite::params_from_iter(params), |row| {
+                row_to_json(row, &columns, encoding)
+            })?
+            .collect::<Result<Vec<_>, _>>()
+        },
+    )
 }
 
-fn rusqlite_value_to_json(value: rusqlite::types::ValueRef<'_>) -> JsonValue {
-    match value {
-        rusqlite::types::ValueRef::Null => JsonValue::Null,
-        rusqlite::types::ValueRef::Integer(i) => JsonValue::Number(i.into()),
-        rusqlite::types::ValueRef::Real(f) => serde_json::Number::from_f64(f)
-            .map(JsonValue::Number)
-            .unwrap_or(JsonValue::Null),
-        rusqlite::types::ValueRef::Text(t) => {
-            JsonValue::String(String::from_utf8_lossy(t).to_string())
-        }
-        rusqlite::types::ValueRef::Blob(b) => {
-            JsonValue::String(base64::engine::general_purpose::STANDARD.encode(b))
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn steps(value: JsonValue) -> Vec<TransactionStep> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn conn() -> rusqlite::Connection {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE customers (id INTEGER PRIMARY KEY, name TEXT);
+             CREATE TABLE sales (id INTEGER PRIMARY KEY, customer_id INTEGER, note TEXT);",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn run<'a>(
+        conn: &rusqlite::Connection,
+        batch: &'a [TransactionStep],
+    ) -> Result<BatchState<'a>, String> {
+        validate_steps(batch)?;
+        run_steps(conn, batch, ResultEncoding::Plain)
+    }
+
+    #[test]
+    fn parses_references() {
+        assert!(matches!(
+            parse_result_ref("$ref:sale").unwrap().unwrap().path,
+            RefPath::LastInsertId
+        ));
+        assert!(matches!(
+            parse_result_ref("$ref:sale.rowsAffected")
+                .unwrap()
+                .unwrap()
+                .path,
+            RefPath::RowsAffected
+        ));
+        assert!(matches!(
+            parse_result_ref("$ref:found.rows[2].id")
+                .unwrap()
+                .unwrap()
+                .path,
+            RefPath::Column {
+                row: 2,
+                column: "id"
+            }
+        ));
+        assert!(parse_result_ref("plain text").unwrap().is_none());
+        assert!(parse_result_ref("$ref:bad-alias").is_err());
+        assert!(parse_result_ref("$ref:found.rows[x].id").is_err());
+        assert!(parse_result_ref("$ref:found.rows[0].").is_err());
+    }
+
+    #[test]
+    fn resolves_insert_ids_and_columns() {
+        let conn = conn();
+        let batch = steps(serde_json::json!([
+            { "sql": "INSERT INTO customers (name) VALUES (?)", "params": ["Ana"], "alias": "customer" },
+            { "sql": "SELECT id, name FROM customers WHERE id = ?", "params": ["$ref:customer"], "query": true, "alias": "found" },
+            { "sql": "INSERT INTO sales (customer_id, note) VALUES (?, ?)", "params": ["$ref:customer.lastInsertId", "$ref:found.rows[0].name"] },
+        ]));
+
+        run(&conn, &batch).unwrap();
+        let (customer_id, note): (i64, String) = conn
+            .query_row("SELECT customer_id, note FROM sales", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(customer_id, 1);
+        assert_eq!(note, "Ana");
+    }
+
+    #[test]
+    fn rejects_unknown_and_forward_aliases() {
+        let batch = steps(serde_json::json!([
+            { "sql": "INSERT INTO sales (customer_id) VALUES (?)", "params": ["$ref:customer"] },
+            { "sql": "INSERT INTO customers (name) VALUES ('Ana')", "params": [], "alias": "customer" },
+        ]));
+        let err = validate_steps(&batch).unwrap_err();
+        assert!(err.contains("unknown alias 'customer'"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_mismatched_result_types() {
+        let batch = steps(serde_json::json!([
+            { "sql": "INSERT INTO customers (name) VALUES ('Ana')", "params": [], "alias": "customer" },
+            { "sql": "SELECT ?", "params": ["$ref:customer.rows[0].id"], "query": true },
+        ]));
+        assert!(
+            validate_steps(&batch)
+                .unwrap_err()
+                .contains("is not a query")
+        );
+
+        let batch = steps(serde_json::json!([
+            { "sql": "SELECT 1 AS id", "params": [], "query": true, "alias": "found" },
+            { "sql": "SELECT ?", "params": ["$ref:found"], "query": true },
+        ]));
+        assert!(validate_steps(&batch).unwrap_err().contains("is a query"));
+    }
+
+    #[test]
+    fn rejects_duplicate_aliases() {
+        let batch = steps(serde_json::json!([
+            { "sql": "SELECT 1", "params": [], "query": true, "alias": "a" },
+            { "sql": "SELECT 2", "params": [], "query": true, "alias": "a" },
+        ]));
+        assert!(
+            validate_steps(&batch)
+                .unwrap_err()
+                .contains("duplicate alias")
+        );
+    }
+
+    #[test]
+    fn reports_missing_rows() {
+        let conn = conn();
+        let batch = steps(serde_json::json!([
+            { "sql": "SELECT id FROM customers", "params": [], "query": true, "alias": "found" },
+            { "sql": "INSERT INTO sales (customer_id) VALUES (?)", "params": ["$ref:found.rows[0].id"] },
+        ]));
+        let err = run(&conn, &batch).err().unwrap();
+        assert!(err.contains("returned 0 rows"), "{}", err);
+    }
+
+    fn statuses(state: &BatchState<'_>) -> Vec<OutcomeStatus> {
+        state.outcomes.iter().map(|o| o.status).collect()
+    }
+
+    fn count(conn: &rusqlite::Connection, table: &str) -> i64 {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |r| r.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn rolls_back_only_the_failed_savepoint() {
+        let conn = conn();
+        let batch = steps(serde_json::json!([
+            { "sql": "INSERT INTO customers (name) VALUES ('Ana')", "params": [], "alias": "customer" },
+            {
+                "savepoint": "loyalty",
+                "onError": "rollback_savepoint",
+                "statements": [
+                    { "sql": "INSERT INTO sales (customer_id) VALUES (?)", "params": ["$ref:customer"], "alias": "bonus" },
+                    { "sql": "INSERT INTO missing_table VALUES (1)", "params": [] },
+                    { "sql": "INSERT INTO sales (customer_id) VALUES (2)", "params": [] },
+                ]
+            },
+            { "sql": "INSERT INTO sales (customer_id, note) VALUES (?, 'kept')", "params": ["$ref:customer"] },
+        ]));
+
+        let state = run(&conn, &batch).unwrap();
+        assert_eq!(
+            statuses(&state),
+            [
+                OutcomeStatus::Ok,
+                OutcomeStatus::RolledBack,
+                OutcomeStatus::Failed,
+                OutcomeStatus::Skipped,
+                OutcomeStatus::Ok,
+            ]
+        );
+        assert!(state.outcomes[2].error.is_some());
+        assert!(matches!(state.results[1], StatementResult::Discarded));
+        assert!(!state.aliases.contains_key("bonus"));
+        assert_eq!(count(&conn, "customers"), 1);
+        assert_eq!(count(&conn, "sales"), 1);
+        // The rolled-back sale's rowid is reused by the kept one
+        assert_eq!(state.last_insert_id, 1);
+    }
+
+    #[test]
+    fn aborting_savepoint_fails_the_batch() {
+        let conn = conn();
+        let batch = steps(serde_json::json!([
+            {
+                "savepoint": "items",
+                "statements": [
+                    { "sql": "INSERT INTO customers (name) VALUES ('Ana')", "params": [] },
+                    { "sql": "INSERT INTO missing_table VALUES (1)", "params": [] },
+                ]
+            },
+        ]));
+        let err = run(&conn, &batch).err().unwrap();
+        assert!(err.contains("in savepoint 'items' failed"), "{}", err);
+    }
+
+    #[test]
+    fn rolled_back_insert_ids_are_unusable() {
+        let conn = conn();
+        let batch = steps(serde_json::json!([
+            {
+                "savepoint": "optional",
+                "onError": "rollback_savepoint",
+                "statements": [
+                    { "sql": "INSERT INTO customers (name) VALUES ('Ana')", "params": [] },
+                    { "sql": "INSERT INTO missing_table VALUES (1)", "params": [] },
+                ]
+            },
+            { "sql": "INSERT INTO sales (customer_id) VALUES (?)", "params": ["$INSERT_ID_0"] },
+        ]));
+        let err = run(&conn, &batch).err().unwrap();
+        assert!(err.contains("was rolled back"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_bad_savepoint_names() {
+        let batch = steps(serde_json::json!([
+            { "savepoint": "a\"; DROP TABLE sales; --", "statements": [] },
+        ]));
+        assert!(
+            validate_steps(&batch)
+                .unwrap_err()
+                .contains("Invalid savepoint name")
+        );
+
+        let batch = steps(serde_json::json!([
+            { "savepoint": "a", "statements": [] },
+            { "savepoint": "a", "statements": [] },
+        ]));
+        assert!(
+            validate_steps(&batch)
+                .unwrap_err()
+                .contains("Duplicate savepoint")
+        );
     }
 }
@@ -0,0 +1,45 @@
+use tauri::State;
+
+use crate::error::AppError;
+use crate::services::auth_session::SessionManager;
+use crate::services::db_writer::DbWriter;
+use crate::services::maintenance::{self, MaintenanceReport};
+use crate::services::permissions::Permission;
+
+/// Run integrity checks, checkpoint, ANALYZE and VACUUM now, e.g. before the
+/// Z cut. Only allowed from the main window. Unlike the scheduled run, this
+/// also does the full VACUUM that switches an old database to incremental
+/// auto-vacuum. Problems are reported in the result rather than as an error.
+#[tauri::command]
+pub async fn run_database_maintenance(
+    app_handle: tauri::AppHandle,
+    window: tauri::Window,
+    writer: State<'_, DbWriter>,
+    sessions: State<'_, SessionManager>,
+) -> Result<MaintenanceReport, AppError> {
+    if window.label() != "main" {
+        log::warn!(
+            "run_database_maintenance rejected: called from non-main window '{}'",
+            window.label()
+        );
+        return Err(AppError::Validation(
+            "Database maintenance is only available from the main window".to_string(),
+        ));
+    }
+    sessions.authorize(&window, Permission::CanManageDatabase)?;
+
+    maintenance::run_maintenance(&app_handle, &writer, true)
+        .await
+        .map_err(AppError::Database)
+}
+
+/// Report from the last manual or scheduled run
+#[tauri::command]
+pub async fn get_last_maintenance_report(
+    app_handle: tauri::AppHandle,
+) -> Result<Option<MaintenanceReport>, AppError> {
+    tokio::task::spawn_blocking(move || maintenance::last_report(&app_handle))
+        .await
+        .map_err(|e| AppError::TaskJoin(e.to_string()))?
+        .map_err(AppError::Database)
+}
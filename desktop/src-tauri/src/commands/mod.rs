@@ -1,10 +1,22 @@
 pub mod auth;
+pub mod backup;
 pub mod database;
+pub mod maintenance;
+pub mod products;
+pub mod query;
+pub mod sales;
 pub mod secure_storage;
+pub mod sync;
 pub mod system;
 
 // Re-export all commands for easy access
 pub use auth::*;
+pub use backup::*;
 pub use database::*;
+pub use maintenance::*;
+pub use products::*;
+pub use query::*;
+pub use sales::*;
 pub use secure_storage::*;
+pub use sync::*;
 pub use system::*;
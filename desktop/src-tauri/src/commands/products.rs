@@ -0,0 +1,35 @@
+use crate::error::AppError;
+use crate::services::database::open_read_only_connection;
+use crate::services::product_search::{self, ProductMatch, SearchFilters};
+
+const DEFAULT_SEARCH_LIMIT: usize = 20;
+const MAX_SEARCH_LIMIT: usize = 200;
+
+/// Search products by barcode, name, description or category name.
+/// Matching ignores case and accents, accepts word prefixes and falls back
+/// to close spellings when nothing matches. Inactive products are excluded
+/// unless `include_inactive` is set.
+#[tauri::command]
+pub async fn search_products(
+    app_handle: tauri::AppHandle,
+    query: String,
+    category_ids: Option<Vec<i64>>,
+    limit: Option<usize>,
+    include_inactive: Option<bool>,
+) -> Result<Vec<ProductMatch>, AppError> {
+    let filters = SearchFilters {
+        category_ids: category_ids.unwrap_or_default(),
+        include_inactive: include_inactive.unwrap_or(false),
+        limit: limit
+            .unwrap_or(DEFAULT_SEARCH_LIMIT)
+            .clamp(1, MAX_SEARCH_LIMIT),
+    };
+
+    tokio::task::spawn_blocking(move || {
+        let conn = open_read_only_connection(&app_handle)?;
+        product_search::search_products(&conn, &query, &filters)
+    })
+    .await
+    .map_err(|e| AppError::TaskJoin(e.to_string()))?
+    .map_err(AppError::Database)
+}
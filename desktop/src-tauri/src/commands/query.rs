@@ -0,0 +1,626 @@
+use base64::Engine;
+use rusqlite::types::Value;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
+use tauri::State;
+use tauri::ipc::Channel;
+
+use crate::error::AppError;
+use crate::services::auth_session::{Session, SessionManager};
+use crate::services::database::open_read_only_connection;
+use crate::services::permissions;
+use crate::services::sql_value::{ResultEncoding, json_to_sql_values, result_columns, row_to_json};
+
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+const DEFAULT_CHUNK_SIZE: usize = 500;
+/// Column pages are keyed on when the caller doesn't name one
+const DEFAULT_KEY_COLUMN: &str = "id";
+
+/// One page of a paginated read-only query
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryPage {
+    pub rows: Vec<serde_json::Map<String, JsonValue>>,
+    /// Opaque token to fetch the next page, `None` when there are no more rows
+    pub next_cursor: Option<String>,
+}
+
+/// Messages sent over the channel while streaming a query
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", content = "data", rename_all = "camelCase")]
+pub enum QueryStreamEvent {
+    Columns {
+        columns: Vec<String>,
+    },
+    Rows {
+        rows: Vec<serde_json::Map<String, JsonValue>>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Finished {
+        total_rows: usize,
+    },
+}
+
+/// A column to order pages by, ahead of the key column
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SortColumn {
+    pub column: String,
+    #[serde(default)]
+    pub descending: bool,
+}
+
+/// Continuation token contents: the `order_by` values and key of the last
+/// row returned. The fingerprint ties a cursor to the query that produced it
+/// so it can't be replayed against a different statement.
+#[derive(Debug, Serialize, Deserialize)]
+struct QueryCursor {
+    after: Vec<JsonValue>,
+    fingerprint: String,
+}
+
+/// Run a read-only SELECT with keyset pagination on `key_column` (default
+/// `id`), which the query must return and which must be unique. Rows come
+/// back ordered by the `order_by` columns, then by that key, so a page never
+/// skips or repeats rows when others are inserted in between. The query
+/// itself may not have an ORDER BY, and `order_by` columns must be returned
+/// and never null (wrap them in COALESCE).
+/// Uses a separate read-only connection so it never waits on sale writes.
+/// Needs a session, and the query may not read PIN hashes, lockout state or
+/// setting values (`AppError::Forbidden`).
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn execute_read_query(
+    app_handle: tauri::AppHandle,
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
+    sql: String,
+    params: Vec<JsonValue>,
+    limit: Option<usize>,
+    cursor: Option<String>,
+    key_column: Option<String>,
+    order_by: Option<Vec<SortColumn>>,
+    result_encoding: Option<ResultEncoding>,
+) -> Result<QueryPage, AppError> {
+    let session = sessions.require(&window)?;
+    let encoding = result_encoding.unwrap_or_default();
+    tokio::task::spawn_blocking(move || {
+        let conn = open_read_only_connection(&app_handle).map_err(AppError::Database)?;
+        authorize_read(&conn, &sql, &session)?;
+        read_page(
+            &conn,
+            &sql,
+            &params,
+            key_column.as_deref().unwrap_or(DEFAULT_KEY_COLUMN),
+            order_by.as_deref().unwrap_or_default(),
+            limit,
+            cursor.as_deref(),
+            encoding,
+        )
+        .map_err(AppError::Database)
+    })
+    .await
+    .map_err(|e| AppError::TaskJoin(e.to_string()))?
+}
+
+/// Run a read-only SELECT and stream the rows to the webview in chunks.
+/// Guarded like `execute_read_query`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn stream_read_query(
+    app_handle: tauri::AppHandle,
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
+    sql: String,
+    params: Vec<JsonValue>,
+    chunk_size: Option<usize>,
+    result_encoding: Option<ResultEncoding>,
+    on_event: Channel<QueryStreamEvent>,
+) -> Result<(), AppError> {
+    let session = sessions.require(&window)?;
+    let encoding = result_encoding.unwrap_or_default();
+    tokio::task::spawn_blocking(move || {
+        let conn = open_read_only_connection(&app_handle).map_err(AppError::Database)?;
+        authorize_read(&conn, &sql, &session)?;
+        stream_read_query_sync(&conn, &sql, &params, chunk_size, encoding, &on_event)
+            .map_err(AppError::Database)
+    })
+    .await
+    .map_err(|e| AppError::TaskJoin(e.to_string()))?
+}
+
+/// Refuse a query that reads secret columns or needs a permission the
+/// session lacks, e.g. a pragma
+fn authorize_read(
+    conn: &rusqlite::Connection,
+    sql: &str,
+    session: &Session,
+) -> Result<(), AppError> {
+    permissions::check_statements(conn, [trim_statement(sql)], &session.permissions)
+        .map_err(AppError::Database)?
+        .map_err(|denied| {
+            log::warn!(
+                "Read query denied for user {}: {}",
+                session.user.id,
+                denied.message()
+            );
+            AppError::Forbidden(denied.message())
+        })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn read_page(
+    conn: &rusqlite::Connection,
+    sql: &str,
+    params: &[JsonValue],
+    key_column: &str,
+    order_by: &[SortColumn],
+    limit: Option<usize>,
+    cursor: Option<&str>,
+    encoding: ResultEncoding,
+) -> Result<QueryPage, String> {
+    if !is_identifier(key_column) {
+        return Err(format!("Invalid key column '{}'", key_column));
+    }
+    if let Some(sort) = order_by.iter().find(|sort| !is_identifier(&sort.column)) {
+        return Err(format!("Invalid sort column '{}'", sort.column));
+    }
+    if has_top_level_order_by(sql) {
+        return Err("Query must not have its own ORDER BY; pass orderBy instead".to_string());
+    }
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let fingerprint = query_fingerprint(sql, params, key_column, order_by);
+
+    // The sort columns, then the key as a unique tiebreaker
+    let ordering: Vec<(&str, bool)> = order_by
+        .iter()
+        .map(|sort| (sort.column.as_str(), sort.descending))
+        .chain([(key_column, false)])
+        .collect();
+
+    let after = match cursor {
+        Some(token) => {
+            let cursor = decode_cursor(token)?;
+            if cursor.fingerprint != fingerprint || cursor.after.len() != ordering.len() {
+                return Err("Cursor does not belong to this query".to_string());
+            }
+            Some(cursor.after)
+        }
+        None => None,
+    };
+
+    // Placeholders are numbered after the caller's parameters. Fetch one
+    // extra row to know whether another page exists.
+    let mut paged_params = params.to_vec();
+    let first_cursor_param = params.len() + 1;
+    let filter = match after {
+        Some(after) => {
+            paged_params.extend(after);
+            format!(" WHERE {}", keyset_filter(&ordering, first_cursor_param))
+        }
+        None => String::new(),
+    };
+    paged_params.push(JsonValue::from(limit as i64 + 1));
+    let order = ordering
+        .iter()
+        .map(|(column, descending)| {
+            format!("\"{}\"{}", column, if *descending { " DESC" } else { "" })
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let paged_sql = format!(
+        // The newline ends a trailing `--` comment in the caller's SQL
+        "SELECT * FROM ({}\n){} ORDER BY {} LIMIT ?{}",
+        trim_statement(sql),
+        filter,
+        order,
+        paged_params.len()
+    );
+
+    let sql_params = json_to_sql_values(&paged_params)?;
+    let mut stmt = prepare_read_only(conn, &paged_sql)?;
+    let columns = result_columns(&stmt);
+    let ordering_indexes = ordering
+        .iter()
+        .map(|(column, _)| {
+            columns
+                .iter()
+                .position(|c| c.name == *column)
+                .ok_or_else(|| {
+                    let kind = if *column == key_column { "key" } else { "sort" };
+                    format!("Query must return the {} column '{}'", kind, column)
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut rows = stmt
+        .query_map(rusqlite::params_from_iter(&sql_params), |row| {
+            Ok((
+                ordering_indexes
+                    .iter()
+                    .map(|&i| row.get::<_, Value>(i))
+                    .collect::<Result<Vec<_>, _>>()?,
+                row_to_json(row, &columns, encoding)?,
+            ))
+        })
+        .map_err(|e| format!("Query failed: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Query failed: {}", e))?;
+
+    let next_cursor = if rows.len() > limit {
+        rows.truncate(limit);
+        let Some((last, _)) = rows.last() else {
+            return Err("Page is empty".to_string());
+        };
+        let after = last
+            .iter()
+            .zip(&ordering)
+            .map(|(value, (column, _))| match value {
+                Value::Integer(i) => Ok(JsonValue::from(*i)),
+                Value::Real(f) => Ok(JsonValue::from(*f)),
+                Value::Text(t) => Ok(JsonValue::from(t.as_str())),
+                _ => Err(format!(
+                    "Column '{}' must be a non-null number or text to page on",
+                    column
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Some(encode_cursor(&QueryCursor { after, fingerprint })?)
+    } else {
+        None
+    };
+
+    Ok(QueryPage {
+        rows: rows.into_iter().map(|(_, row)| row).collect(),
+        next_cursor,
+    })
+}
+
+/// Rows after the cursor in `ordering`: `a > ?1 OR (a = ?1 AND b > ?2) ..`,
+/// with `<` for descending columns. The cursor values are numbered from
+/// `first_param`.
+fn keyset_filter(ordering: &[(&str, bool)], first_param: usize) -> String {
+    (0..ordering.len())
+        .map(|i| {
+            let equal = ordering[..i]
+                .iter()
+                .enumerate()
+                .map(|(j, (column, _))| format!("\"{}\" = ?{}", column, first_param + j));
+            let (column, descending) = ordering[i];
+            let past = format!(
+                "\"{}\" {} ?{}",
+                column,
+                if descending { "<" } else { ">" },
+                first_param + i
+            );
+            let terms: Vec<String> = equal.chain([past]).collect();
+            format!("({})", terms.join(" AND "))
+        })
+        .collect::<Vec<_>>()
+        .join(" OR ")
+}
+
+fn stream_read_query_sync(
+    conn: &rusqlite::Connection,
+    sql: &str,
+    params: &[JsonValue],
+    chunk_size: Option<usize>,
+    encoding: ResultEncoding,
+    on_event: &Channel<QueryStreamEvent>,
+) -> Result<(), String> {
+    let chunk_size = chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE).max(1);
+    let sql_params = json_to_sql_values(params)?;
+    let mut stmt = prepare_read_only(conn, trim_statement(sql))?;
+    let columns = result_columns(&stmt);
+
+    send_event(
+        on_event,
+        QueryStreamEvent::Columns {
+            columns: columns.iter().map(|c| c.name.clone()).collect(),
+        },
+    )?;
+
+    let mut rows = stmt
+        .query(rusqlite::params_from_iter(&sql_params))
+        .map_err(|e| format!("Query failed: {}", e))?;
+
+    let mut buffer = Vec::with_capacity(chunk_size);
+    let mut total_rows = 0;
+
+    while let Some(row) = rows.next().map_err(|e| format!("Query failed: {}", e))? {
+        let json_row =
+            row_to_json(row, &columns, encoding).map_err(|e| format!("Query failed: {}", e))?;
+        buffer.push(json_row);
+        total_rows += 1;
+
+        if buffer.len() >= chunk_size {
+            let chunk = std::mem::replace(&mut buffer, Vec::with_capacity(chunk_size));
+            send_event(on_event, QueryStreamEvent::Rows { rows: chunk })?;
+        }
+    }
+
+    if !buffer.is_empty() {
+        send_event(on_event, QueryStreamEvent::Rows { rows: buffer })?;
+    }
+
+    send_event(on_event, QueryStreamEvent::Finished { total_rows })
+}
+
+/// Prepare a statement and reject anything that would modify the database
+fn prepare_read_only<'conn>(
+    conn: &'conn rusqlite::Connection,
+    sql: &str,
+) -> Result<rusqlite::Statement<'conn>, String> {
+    let stmt = conn
+        .prepare(sql)
+        .map_err(|e| format!("Failed to prepare query: {}", e))?;
+
+    if !stmt.readonly() {
+        return Err("Only read-only statements are allowed".to_string());
+    }
+
+    Ok(stmt)
+}
+
+fn send_event(channel: &Channel<QueryStreamEvent>, event: QueryStreamEvent) -> Result<(), String> {
+    channel
+        .send(event)
+        .map_err(|e| format!("Failed to send query results: {}", e))
+}
+
+fn trim_statement(sql: &str) -> &str {
+    sql.trim().trim_end_matches(';').trim_end()
+}
+
+/// Key and sort column names are spliced into the paging SQL, so only plain
+/// identifiers are accepted
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Whether the statement ends in its own ORDER BY, which the paging query
+/// would override. Ignores ORDER BY in subqueries, window definitions,
+/// strings and comments.
+fn has_top_level_order_by(sql: &str) -> bool {
+    let mut top_level = String::with_capacity(sql.len());
+    let mut depth = 0usize;
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // A doubled quote inside reads as two quoted runs, which is fine
+            '\'' | '"' | '`' | '[' => {
+                let close = if c == '[' { ']' } else { c };
+                chars.by_ref().find(|&next| next == close);
+                top_level.push(' ');
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                chars.by_ref().find(|&next| next == '\n');
+                top_level.push(' ');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                chars.by_ref().find(|&next| {
+                    let end = previous == '*' && next == '/';
+                    previous = next;
+                    end
+                });
+                top_level.push(' ');
+            }
+            '(' => {
+                depth += 1;
+                top_level.push(' ');
+            }
+            ')' => {
+                depth = depth.saturating_sub(1);
+                top_level.push(' ');
+            }
+            _ if depth == 0 => top_level.push(c.to_ascii_uppercase()),
+            _ => {}
+        }
+    }
+
+    top_level
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .windows(2)
+        .any(|words| words == ["ORDER", "BY"])
+}
+
+fn query_fingerprint(
+    sql: &str,
+    params: &[JsonValue],
+    key_column: &str,
+    order_by: &[SortColumn],
+) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(trim_statement(sql).as_bytes());
+    hasher.update(JsonValue::from(params.to_vec()).to_string().as_bytes());
+    hasher.update(key_column.as_bytes());
+    for sort in order_by {
+        hasher.update(format!("{} {}", sort.column, sort.descending).as_bytes());
+    }
+    format!("{:x}", hasher.finalize())[..16].to_string()
+}
+
+fn encode_cursor(cursor: &QueryCursor) -> Result<String, String> {
+    let json = serde_json::to_vec(cursor).map_err(|e| format!("Failed to encode cursor: {}", e))?;
+    Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json))
+}
+
+fn decode_cursor(token: &str) -> Result<QueryCursor, String> {
+    let json = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(token)
+        .map_err(|_| "Invalid cursor".to_string())?;
+    serde_json::from_slice(&json).map_err(|_| "Invalid cursor".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn products() -> rusqlite::Connection {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE products (id INTEGER PRIMARY KEY, name TEXT);
+             INSERT INTO products (id, name) VALUES (1, 'a'), (2, 'b'), (3, 'c'), (4, 'd'), (5, 'e');",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn ids(page: &QueryPage) -> Vec<i64> {
+        page.rows
+            .iter()
+            .map(|r| r["id"].as_i64().unwrap())
+            .collect()
+    }
+
+    fn page(
+        conn: &rusqlite::Connection,
+        sql: &str,
+        cursor: Option<&str>,
+    ) -> Result<QueryPage, String> {
+        read_page(
+            conn,
+            sql,
+            &[],
+            "id",
+            &[],
+            Some(2),
+            cursor,
+            ResultEncoding::Plain,
+        )
+    }
+
+    #[test]
+    fn cursor_round_trips() {
+        let cursor = QueryCursor {
+            after: vec![JsonValue::from("b"), JsonValue::from(42)],
+            fingerprint: "abc".to_string(),
+        };
+        let decoded = decode_cursor(&encode_cursor(&cursor).unwrap()).unwrap();
+        assert_eq!(decoded.after, cursor.after);
+        assert_eq!(decoded.fingerprint, "abc");
+    }
+
+    #[test]
+    fn rejects_malformed_cursor() {
+        assert_eq!(
+            decode_cursor("not a cursor!").unwrap_err(),
+            "Invalid cursor"
+        );
+        let not_json = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode("{");
+        assert_eq!(decode_cursor(&not_json).unwrap_err(), "Invalid cursor");
+    }
+
+    #[test]
+    fn pages_by_key() {
+        let conn = products();
+        let sql = "SELECT id, name FROM products";
+
+        let first = page(&conn, sql, None).unwrap();
+        assert_eq!(ids(&first), [1, 2]);
+
+        // A row inserted before the cursor doesn't shift the next page
+        conn.execute("INSERT INTO products (id, name) VALUES (0, 'z')", [])
+            .unwrap();
+        let second = page(&conn, sql, first.next_cursor.as_deref()).unwrap();
+        assert_eq!(ids(&second), [3, 4]);
+
+        let last = page(&conn, sql, second.next_cursor.as_deref()).unwrap();
+        assert_eq!(ids(&last), [5]);
+        assert!(last.next_cursor.is_none());
+    }
+
+    #[test]
+    fn pages_in_the_requested_order() {
+        let conn = products();
+        conn.execute_batch("UPDATE products SET name = 'b' WHERE id IN (1, 4, 5)")
+            .unwrap();
+        let order_by = [SortColumn {
+            column: "name".to_string(),
+            descending: true,
+        }];
+
+        let mut seen = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = read_page(
+                &conn,
+                "SELECT id, name FROM products WHERE id > ?",
+                &[JsonValue::from(0)],
+                "id",
+                &order_by,
+                Some(2),
+                cursor.as_deref(),
+                ResultEncoding::Plain,
+            )
+            .unwrap();
+            seen.extend(ids(&page));
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        // c, then the b's by id
+        assert_eq!(seen, [3, 1, 2, 4, 5]);
+    }
+
+    #[test]
+    fn rejects_its_own_order_by() {
+        let conn = products();
+        let err = page(&conn, "SELECT id, name FROM products ORDER BY name", None).unwrap_err();
+        assert!(err.contains("orderBy"), "{}", err);
+
+        for sql in [
+            "SELECT id, (SELECT name FROM products p ORDER BY id LIMIT 1) AS first FROM products",
+            "SELECT id, row_number() OVER (ORDER BY name) AS n FROM products",
+            "SELECT id, 'order by' AS label FROM products -- order by name",
+        ] {
+            assert_eq!(ids(&page(&conn, sql, None).unwrap()), [1, 2], "{}", sql);
+        }
+    }
+
+    #[test]
+    fn rejects_cursor_from_another_query() {
+        let conn = products();
+        let first = page(&conn, "SELECT id FROM products", None).unwrap();
+        let err = page(
+            &conn,
+            "SELECT id, name FROM products",
+            first.next_cursor.as_deref(),
+        )
+        .unwrap_err();
+        assert_eq!(err, "Cursor does not belong to this query");
+    }
+
+    #[test]
+    fn requires_key_column() {
+        let conn = products();
+        let err = page(&conn, "SELECT name FROM products", None).unwrap_err();
+        assert_eq!(err, "Query must return the key column 'id'");
+
+        let err = read_page(
+            &conn,
+            "SELECT id FROM products",
+            &[],
+            "id; DROP TABLE products",
+            &[],
+            None,
+            None,
+            ResultEncoding::Plain,
+        )
+        .unwrap_err();
+        assert!(err.starts_with("Invalid key column"));
+    }
+
+    #[test]
+    fn rejects_writes() {
+        let conn = products();
+        assert!(page(&conn, "DELETE FROM products RETURNING id", None).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+use tauri::State;
+
+use crate::error::AppError;
+use crate::money::Money;
+use crate::services::approvals::{self, ApprovalAction, ApprovalManager};
+use crate::services::auth_session::SessionManager;
+use crate::services::database::open_read_only_connection;
+use crate::services::db_writer::DbWriter;
+use crate::services::permissions::Permission;
+use crate::services::sale_totals::{self, SaleLineInput, SaleTotals, SalesSummary};
+use crate::services::sales;
+use crate::services::sync::SyncEngine;
+
+/// Price a cart in integer cents with the configured `tax_rate`. The sale
+/// screen writes these figures to the `*_cents` columns and the DTE takes
+/// its summary from them, so lines and totals always agree.
+#[tauri::command]
+pub async fn calculate_sale_totals(
+    app_handle: tauri::AppHandle,
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
+    items: Vec<SaleLineInput>,
+    discount_amount: Option<Money>,
+) -> Result<SaleTotals, AppError> {
+    sessions.authorize(&window, Permission::CanSell)?;
+
+    let tax_rate_bps = tokio::task::spawn_blocking(move || {
+        let conn = open_read_only_connection(&app_handle)?;
+        sale_totals::tax_rate_bps(&conn)
+    })
+    .await
+    .map_err(|e| AppError::TaskJoin(e.to_string()))?
+    .map_err(AppError::Database)?;
+
+    sale_totals::calculate(&items, discount_amount.unwrap_or_default(), tax_rate_bps)
+        .map_err(AppError::Validation)
+}
+
+/// Today's completed sales, for one cashier or the whole store. Cashiers
+/// may see their own; anything else needs `can_view_reports`.
+#[tauri::command]
+pub async fn get_sales_summary(
+    app_handle: tauri::AppHandle,
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
+    user_id: Option<i64>,
+) -> Result<SalesSummary, AppError> {
+    let session = sessions.require(&window)?;
+    let permission = match user_id {
+        Some(id) if id == session.user.id => Permission::CanSell,
+        _ => Permission::CanViewReports,
+    };
+    sessions.authorize(&window, permission)?;
+
+    tokio::task::spawn_blocking(move || {
+        let conn = open_read_only_connection(&app_handle)?;
+        sale_totals::today_summary(&conn, user_id)
+    })
+    .await
+    .map_err(|e| AppError::TaskJoin(e.to_string()))?
+    .map_err(AppError::Database)
+}
+
+/// Void a completed sale: it becomes `cancelled`, its sold units go back to
+/// stock and its DTEs are flagged for invalidation. Users without `can_void`
+/// need an `approval_token` from `request_approval` for `void_sale` on this
+/// sale, used up only if the void commits; the audit entry names both users.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn void_sale(
+    app_handle: tauri::AppHandle,
+    window: tauri::Window,
+    writer: State<'_, DbWriter>,
+    sync: State<'_, SyncEngine>,
+    sessions: State<'_, SessionManager>,
+    approvals: State<'_, ApprovalManager>,
+    sale_id: i64,
+    reason: Option<String>,
+    approval_token: Option<String>,
+) -> Result<(), AppError> {
+    let (session, used) = approvals::authorize(
+        &sessions,
+        &approvals,
+        &window,
+        ApprovalAction::VoidSale,
+        sale_id,
+        approval_token,
+    )?;
+
+    let user_id = session.user.id;
+    let approvals = approvals.inner().clone();
+    let outcome = writer
+        .execute(&app_handle, move |conn| {
+            Ok(approvals::in_transaction(
+                conn,
+                &approvals,
+                &session,
+                used.as_slice(),
+                |conn, approved| {
+                    let approved_by = approved.first().map(|a| a.approver_id);
+                    sales::void_sale(conn, sale_id, reason.as_deref(), user_id, approved_by)
+                        .map_err(AppError::Database)
+                },
+            ))
+        })
+        .await
+        .map_err(AppError::Database)??;
+
+    log::info!(
+        "Sale {} voided by user {} ({} stock movements reversed, {} DTEs flagged)",
+        sale_id,
+        user_id,
+        outcome.stock_reversed,
+        outcome.dte_flagged
+    );
+    sync.wake();
+    Ok(())
+}
@@ -1,6 +1,14 @@
-use crate::services::secure_storage::{SecureStorageError, SecureStorageManager};
+use crate::error::AppError;
+use crate::services::auth_session::SessionManager;
+use crate::services::permissions::Permission;
+use crate::services::secret_policy;
+use crate::services::secure_storage::{self, SecureStorageError, SecureStorageManager};
+use crate::services::vault_unlock::{VaultUnlock, VaultUnlockStatus};
 use serde::{Deserialize, Serialize};
-use tauri::{AppHandle, Manager, State};
+use tauri::{AppHandle, State};
+use zeroize::Zeroizing;
+
+const MIN_VAULT_PASSWORD_LEN: usize = 8;
 
 #[derive(Serialize, Deserialize)]
 pub struct SecretMetadata {
@@ -8,74 +16,212 @@ pub struct SecretMetadata {
     pub exists: bool,
 }
 
-/// Initialize the secure storage with a password hash
+/// Open the secure storage vault with its password, creating the vault on
+/// first use. Only allowed from the main window.
 #[tauri::command]
 pub async fn initialize_secure_storage(
-    password_hash: Vec<u8>,
+    password: String,
     app_handle: AppHandle,
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
     storage: State<'_, SecureStorageManager>,
-) -> Result<(), SecureStorageError> {
+) -> Result<(), AppError> {
+    let password = Zeroizing::new(password);
+    require_main_window(&window, "initialize_secure_storage")?;
+    sessions.authorize(&window, Permission::CanManageCertificates)?;
     log::info!("Initializing secure storage");
+    let vault_path = secure_storage::vault_path(&app_handle)?;
+    log::debug!("Vault path: {}", vault_path);
 
-    let app_data_dir = app_handle
-        .path()
-        .app_data_dir()
-        .map_err(|e| SecureStorageError::from(format!("Failed to get app data dir: {}", e)))?;
+    let result = storage.initialize(password, &vault_path).await;
+    match &result {
+        Ok(_) => log::info!("Secure storage initialized successfully"),
+        Err(SecureStorageError::WrongPassword) => {
+            log::warn!("Secure storage not opened: wrong vault password")
+        }
+        Err(e) => log::error!("Failed to initialize secure storage: {}", e),
+    }
 
-    log::debug!("App data directory: {:?}", app_data_dir);
+    Ok(result?)
+}
 
-    std::fs::create_dir_all(&app_data_dir)
-        .map_err(|e| SecureStorageError::from(format!("Failed to create app data dir: {}", e)))?;
+/// Re-encrypt the open vault under a new password. Only allowed from the
+/// main window.
+#[tauri::command]
+pub async fn change_vault_password(
+    current_password: String,
+    new_password: String,
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
+    storage: State<'_, SecureStorageManager>,
+) -> Result<(), AppError> {
+    let (current_password, new_password) = (
+        Zeroizing::new(current_password),
+        Zeroizing::new(new_password),
+    );
+    require_main_window(&window, "change_vault_password")?;
+    sessions.authorize(&window, Permission::CanManageCertificates)?;
+
+    if new_password.chars().count() < MIN_VAULT_PASSWORD_LEN {
+        return Err(AppError::Validation(format!(
+            "Vault password must be at least {} characters",
+            MIN_VAULT_PASSWORD_LEN
+        )));
+    }
+    if *new_password == *current_password {
+        return Err(AppError::Validation(
+            "New vault password must differ from the current one".to_string(),
+        ));
+    }
 
-    let vault_path = app_data_dir.join("secure-storage.stronghold");
-    let vault_path_str = vault_path
-        .to_str()
-        .ok_or_else(|| SecureStorageError::from("Invalid vault path"))?;
+    Ok(storage
+        .change_password(current_password, new_password)
+        .await?)
+}
 
-    log::debug!("Vault path: {}", vault_path_str);
+/// Whether a vault password is stored for unlocking at startup, and where
+#[tauri::command]
+pub async fn get_vault_unlock_status(
+    unlock: State<'_, VaultUnlock>,
+) -> Result<VaultUnlockStatus, AppError> {
+    unlock.status().map_err(AppError::System)
+}
 
-    let result = storage.initialize(password_hash, vault_path_str).await;
-    match &result {
-        Ok(_) => log::info!("Secure storage initialized successfully"),
-        Err(e) => log::error!("Failed to initialize secure storage: {}", e),
-    }
+/// Check the vault password, creating the vault on first use, and store it
+/// in the keyring so the vault opens at startup. Only allowed from the main
+/// window.
+#[tauri::command]
+pub async fn enroll_vault_password(
+    password: String,
+    app_handle: AppHandle,
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
+    storage: State<'_, SecureStorageManager>,
+    unlock: State<'_, VaultUnlock>,
+) -> Result<(), AppError> {
+    let password = Zeroizing::new(password);
+    require_main_window(&window, "enroll_vault_password")?;
+    sessions.authorize(&window, Permission::CanManageCertificates)?;
+
+    let vault_path = secure_storage::vault_path(&app_handle)?;
+    Ok(unlock.enroll(&storage, &vault_path, password).await?)
+}
+
+/// Re-key the vault with a new random password kept only in the keyring.
+/// Only allowed from the main window.
+#[tauri::command]
+pub async fn rotate_vault_password(
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
+    storage: State<'_, SecureStorageManager>,
+    unlock: State<'_, VaultUnlock>,
+) -> Result<(), AppError> {
+    require_main_window(&window, "rotate_vault_password")?;
+    sessions.authorize(&window, Permission::CanManageCertificates)?;
+    Ok(unlock.rotate(&storage).await?)
+}
 
-    result
+/// Remove the vault password from the keyring; the vault then needs
+/// `initialize_secure_storage` after a restart. Returns whether one was
+/// stored. Only allowed from the main window.
+#[tauri::command]
+pub async fn forget_vault_password(
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
+    unlock: State<'_, VaultUnlock>,
+) -> Result<bool, AppError> {
+    require_main_window(&window, "forget_vault_password")?;
+    sessions.authorize(&window, Permission::CanManageCertificates)?;
+    Ok(unlock.forget()?)
+}
+
+fn require_main_window(window: &tauri::Window, command: &str) -> Result<(), AppError> {
+    if window.label() != "main" {
+        log::warn!(
+            "{} rejected: called from non-main window '{}'",
+            command,
+            window.label()
+        );
+        return Err(AppError::Validation(
+            "Vault password can only be managed from the main window".to_string(),
+        ));
+    }
+    Ok(())
 }
 
-/// Store a secret in the secure storage
+/// Store a secret in the secure storage, if its policy lets the caller's
+/// window and session change it (`services/secret_policy.rs`)
 #[tauri::command]
 pub async fn store_secret(
     key: String,
     secret_data: Vec<u8>,
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
     storage: State<'_, SecureStorageManager>,
-) -> Result<(), SecureStorageError> {
-    storage.store_secret(&key, &secret_data).await
+) -> Result<(), AppError> {
+    let session = sessions.require(&window)?;
+    secret_policy::check_write(&key, &session)?;
+    Ok(storage.store_secret(&key, &secret_data).await?)
+}
+
+/// Read a secret the caller's window and session are allowed to see under
+/// its policy (`services/secret_policy.rs`); `None` if it isn't stored
+#[tauri::command]
+pub async fn get_secret(
+    key: String,
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
+    storage: State<'_, SecureStorageManager>,
+) -> Result<Option<Vec<u8>>, AppError> {
+    let session = sessions.require(&window)?;
+    secret_policy::check(&key, &session)?;
+    Ok(storage.get_secret(&key).await?)
 }
 
 /// Check if a secret exists in the secure storage
 #[tauri::command]
 pub async fn has_secret(
     key: String,
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
     storage: State<'_, SecureStorageManager>,
-) -> Result<bool, SecureStorageError> {
-    storage.has_secret(&key).await
+) -> Result<bool, AppError> {
+    sessions.authorize(&window, Permission::CanManageCertificates)?;
+    Ok(storage.has_secret(&key).await?)
+}
+
+/// List the keys of all secrets in the secure storage
+#[tauri::command]
+pub async fn list_secret_keys(
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
+    storage: State<'_, SecureStorageManager>,
+) -> Result<Vec<String>, AppError> {
+    sessions.authorize(&window, Permission::CanManageCertificates)?;
+    Ok(storage.list_keys().await?)
 }
 
-/// Remove a secret from the secure storage
+/// Remove a secret from the secure storage, guarded like `store_secret`
 #[tauri::command]
 pub async fn remove_secret(
     key: String,
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
     storage: State<'_, SecureStorageManager>,
-) -> Result<(), SecureStorageError> {
-    storage.remove_secret(&key).await
+) -> Result<(), AppError> {
+    let session = sessions.require(&window)?;
+    secret_policy::check_write(&key, &session)?;
+    Ok(storage.remove_secret(&key).await?)
 }
 
 /// Clear all secrets from the secure storage
 #[tauri::command]
 pub async fn clear_secure_storage(
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
     storage: State<'_, SecureStorageManager>,
-) -> Result<(), SecureStorageError> {
+) -> Result<(), AppError> {
+    sessions.authorize(&window, Permission::CanManageCertificates)?;
     storage.clear().await?;
     Ok(())
 }
@@ -92,8 +238,11 @@ pub async fn is_secure_storage_initialized(
 #[tauri::command]
 pub async fn get_secret_metadata(
     keys: Vec<String>,
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
     storage: State<'_, SecureStorageManager>,
-) -> Result<Vec<SecretMetadata>, SecureStorageError> {
+) -> Result<Vec<SecretMetadata>, AppError> {
+    sessions.authorize(&window, Permission::CanManageCertificates)?;
     let mut metadata = Vec::new();
 
     for key in keys {
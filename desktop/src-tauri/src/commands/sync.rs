@@ -0,0 +1,78 @@
+use tauri::State;
+
+use crate::error::AppError;
+use crate::services::auth_session::SessionManager;
+use crate::services::database::open_read_only_connection;
+use crate::services::db_writer::DbWriter;
+use crate::services::permissions::Permission;
+use crate::services::sync::{SyncEngine, SyncStatus};
+use crate::services::sync_conflicts::{self, Resolution, SyncConflict};
+
+/// Start a sync poll now instead of waiting for the next interval.
+/// The poll runs in the background; failures show up in the sync status.
+#[tauri::command]
+pub async fn sync_now(engine: State<'_, SyncEngine>) -> Result<(), AppError> {
+    engine.wake();
+    Ok(())
+}
+
+/// Connection state, outbound backlog per table, last push and pull, backoff
+/// and pending DTEs. The same payload is sent to the main window as the
+/// `sync-status` event.
+#[tauri::command]
+pub async fn get_sync_status(
+    app_handle: tauri::AppHandle,
+    engine: State<'_, SyncEngine>,
+) -> Result<SyncStatus, AppError> {
+    engine.status(&app_handle).await.map_err(AppError::Database)
+}
+
+/// Rows edited here and at the backend that need a decision, with both
+/// versions
+#[tauri::command]
+pub async fn get_sync_conflicts(
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<SyncConflict>, AppError> {
+    tokio::task::spawn_blocking(move || {
+        let conn = open_read_only_connection(&app_handle)?;
+        sync_conflicts::list_open(&conn)
+    })
+    .await
+    .map_err(|e| AppError::TaskJoin(e.to_string()))?
+    .map_err(AppError::Database)
+}
+
+/// Keep the local row (`keep_local`, pushed on the next poll) or the
+/// backend's (`keep_remote`, local edits are dropped). Only allowed from the
+/// main window.
+#[tauri::command]
+pub async fn resolve_conflict(
+    app_handle: tauri::AppHandle,
+    window: tauri::Window,
+    writer: State<'_, DbWriter>,
+    sessions: State<'_, SessionManager>,
+    engine: State<'_, SyncEngine>,
+    conflict_id: i64,
+    resolution: Resolution,
+) -> Result<(), AppError> {
+    if window.label() != "main" {
+        log::warn!(
+            "resolve_conflict rejected: called from non-main window '{}'",
+            window.label()
+        );
+        return Err(AppError::Validation(
+            "Sync conflicts can only be resolved from the main window".to_string(),
+        ));
+    }
+    sessions.authorize(&window, Permission::CanManageDatabase)?;
+
+    writer
+        .execute(&app_handle, move |conn| {
+            conn.in_transaction(|conn| sync_conflicts::resolve(conn, conflict_id, resolution))
+        })
+        .await
+        .map_err(AppError::Database)?;
+
+    engine.wake();
+    Ok(())
+}
@@ -4,6 +4,8 @@ use tauri::{AppHandle, Emitter, Manager, State};
 use tauri_plugin_shell::ShellExt;
 
 use crate::error::AppError;
+use crate::services::auth_session::SessionManager;
+use crate::services::permissions::Permission;
 
 /// Thread-safe counter for sale window numbering
 pub struct SaleWindowCounter(AtomicU32);
@@ -159,12 +161,16 @@ pub struct SaleWindowCreated {
     pub sale_number: u32,
 }
 
-/// Create a new sale window from the backend
+/// Create a new sale window from the backend.
+/// Needs `can_sell`; the new window shares the calling window's session.
 #[tauri::command]
 pub async fn create_sale_window(
     app_handle: AppHandle,
+    window: tauri::Window,
     counter: State<'_, SaleWindowCounter>,
+    sessions: State<'_, SessionManager>,
 ) -> Result<SaleWindowCreated, AppError> {
+    sessions.authorize(&window, Permission::CanSell)?;
     let sale_number = counter.next();
     let label = format!("pos-sale-{}", sale_number);
     let title = format!("POS Inteligente - Venta #{}", sale_number);
@@ -191,6 +197,14 @@ pub async fn create_sale_window(
         sale_number
     );
 
+    if let Some(session) = sessions.share(window.label(), &label) {
+        log::info!(
+            "Sale window {} shares the session of user {}",
+            label,
+            session.user.id
+        );
+    }
+
     // Emit event to main window to notify of new window creation
     let payload = SaleWindowCreated {
         label: label.clone(),
@@ -204,16 +218,27 @@ pub async fn create_sale_window(
 }
 
 /// Force close the application (bypassing close prevention).
-/// Only allowed from the main window.
+/// Only allowed from the main window, for users with `can_close_app`.
 #[tauri::command]
-pub async fn force_close_app(app_handle: AppHandle, window: tauri::Window) {
+pub async fn force_close_app(
+    app_handle: AppHandle,
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
+) -> Result<(), AppError> {
     if window.label() != "main" {
         log::warn!(
             "force_close_app rejected: called from non-main window '{}'",
             window.label()
         );
-        return;
+        return Err(AppError::Validation(
+            "The app can only be force closed from the main window".to_string(),
+        ));
     }
-    log::info!("Backend: Force closing application requested");
+    let session = sessions.authorize(&window, Permission::CanCloseApp)?;
+    log::info!(
+        "Backend: Force closing application requested by user {}",
+        session.user.id
+    );
     app_handle.exit(0);
+    Ok(())
 }
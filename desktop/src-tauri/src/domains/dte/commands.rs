@@ -4,6 +4,8 @@ use tauri::{State, command};
 
 use super::service::DteSignerService;
 use crate::error::AppError;
+use crate::services::auth_session::SessionManager;
+use crate::services::permissions::Permission;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SignDteInput {
@@ -22,8 +24,11 @@ pub struct SignDteResult {
 #[command]
 pub async fn sign_dte(
     payload: SignDteInput,
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
     signer_state: State<'_, Mutex<DteSignerService>>,
 ) -> Result<SignDteResult, AppError> {
+    sessions.authorize(&window, Permission::CanSell)?;
     let signer = signer_state
         .lock()
         .map_err(|e| AppError::Dte(format!("Failed to acquire signer lock: {}", e)))?;
@@ -49,8 +54,11 @@ pub async fn sign_dte(
 pub async fn load_certificate(
     path: String,
     password: String,
+    window: tauri::Window,
+    sessions: State<'_, SessionManager>,
     signer_state: State<'_, Mutex<DteSignerService>>,
 ) -> Result<bool, AppError> {
+    sessions.authorize(&window, Permission::CanManageCertificates)?;
     let mut signer = signer_state
         .lock()
         .map_err(|e| AppError::Dte(format!("Failed to acquire signer lock: {}", e)))?;
@@ -1,4 +1,5 @@
 // Domain modules organized by bounded context
-// Note: Most domain logic lives in TypeScript (frontend) using tauri-plugin-sql
+// Note: Most domain logic lives in TypeScript (frontend), which reaches the
+// database through the guarded commands in `commands::database` and `commands::query`
 // Only DTE remains in Rust due to cryptographic requirements
 pub mod dte;
@@ -9,6 +9,13 @@ pub enum AppError {
     #[error("{0}")]
     Database(String),
 
+    #[error("{0}")]
+    Validation(String),
+
+    /// The session's user lacks the permission the command needs
+    #[error("{0}")]
+    Forbidden(String),
+
     #[error("{0}")]
     System(String),
 
@@ -1,6 +1,7 @@
 mod commands;
 mod domains;
 pub mod error;
+pub mod money;
 mod plugins;
 mod services;
 
@@ -9,8 +10,12 @@ use std::sync::Mutex;
 use commands::system::SaleWindowCounter;
 use commands::*;
 use domains::dte::service::DteSignerService;
+use services::approvals::ApprovalManager;
+use services::auth_session::SessionManager;
+use services::db_writer::DbWriter;
 use services::secure_storage::SecureStorageManager;
-use tauri::Emitter;
+use services::sync::SyncEngine;
+use tauri::{Emitter, Manager};
 
 // Re-export DTE domain commands (DTE signing requires Rust crypto)
 use domains::dte::{is_certificate_loaded, load_certificate, sign_dte};
@@ -20,11 +25,48 @@ pub fn run() {
     plugins::configure_plugins(tauri::Builder::default())
         .manage(SecureStorageManager::new())
         .manage(SaleWindowCounter::new())
+        .manage(DbWriter::new())
+        .manage(SyncEngine::new())
+        .manage(SessionManager::new())
+        .manage(ApprovalManager::new())
         .manage(Mutex::new(DteSignerService::new()))
+        .setup(|app| {
+            // Keys every connection and encrypts a plaintext database first
+            services::db_encryption::initialize(app.handle()).map_err(|e| {
+                log::error!("Database encryption setup failed: {}", e);
+                e
+            })?;
+
+            // Refuse to start on a tampered or newer schema
+            services::database::run_migrations(app.handle()).map_err(|e| {
+                log::error!("Database migration failed: {}", e);
+                e
+            })?;
+
+            // Opens the secure storage vault with the password in the keyring
+            app.manage(services::vault_unlock::VaultUnlock::open(app.handle())?);
+            tauri::async_runtime::spawn(services::vault_unlock::unlock_at_startup(
+                app.handle().clone(),
+            ));
+
+            tauri::async_runtime::spawn(services::backup::run_scheduler(app.handle().clone()));
+            tauri::async_runtime::spawn(services::maintenance::run_scheduler(app.handle().clone()));
+            tauri::async_runtime::spawn(services::sync::run_scheduler(app.handle().clone()));
+            tauri::async_runtime::spawn(services::auth_session::run_expiry(app.handle().clone()));
+            Ok(())
+        })
         .on_window_event(|window, event| {
             if let tauri::WindowEvent::Destroyed = event {
-                // Emit event to main window when any window is destroyed
                 let label = window.label().to_string();
+                // A closed window's session ends with it
+                if let Some(session) = window.state::<SessionManager>().end(&label) {
+                    log::info!(
+                        "Session of user {} ended with window '{}'",
+                        session.user.id,
+                        label
+                    );
+                }
+                // Emit event to main window when any window is destroyed
                 if let Err(e) = window.emit_to("main", "window-destroyed", label.clone()) {
                     // If main window is closed, this might fail, which is expected
                     log::debug!("Failed to emit window-destroyed to main window: {}", e);
@@ -35,7 +77,14 @@ pub fn run() {
             // Secure Storage
             initialize_secure_storage,
             store_secret,
+            change_vault_password,
+            get_vault_unlock_status,
+            enroll_vault_password,
+            rotate_vault_password,
+            forget_vault_password,
+            get_secret,
             has_secret,
+            list_secret_keys,
             remove_secret,
             clear_secure_storage,
             is_secure_storage_initialized,
@@ -43,15 +92,47 @@ pub fn run() {
             // System Commands
             open_log_folder,
             generate_test_logs,
-            // Authentication (PIN hashing with Argon2)
+            // Authentication (PIN hashing with Argon2, sessions)
             hash_pin,
-            verify_pin,
+            benchmark_pin_hash,
+            list_login_users,
+            login_with_pin,
+            unlock_pin_lockout,
+            logout,
+            get_current_session,
+            touch_session,
+            request_approval,
             // Domain: DTE (requires Rust crypto)
             sign_dte,
             load_certificate,
             is_certificate_loaded,
+            // Sales
+            void_sale,
+            calculate_sale_totals,
+            get_sales_summary,
             // Database
             execute_transaction,
+            get_db_writer_metrics,
+            get_schema_info,
+            get_system_settings,
+            rollback_schema,
+            get_database_encryption_status,
+            rekey_database,
+            export_database_recovery_key,
+            // Backups
+            create_backup,
+            list_backups,
+            get_backup_settings,
+            restore_backup,
+            run_database_maintenance,
+            get_last_maintenance_report,
+            search_products,
+            sync_now,
+            get_sync_status,
+            get_sync_conflicts,
+            resolve_conflict,
+            execute_read_query,
+            stream_read_query,
             // System
             create_sale_window,
             force_close_app,
@@ -0,0 +1,491 @@
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// How to round when a calculation lands between two cents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundingMode {
+    /// Half away from zero (2.345 -> 2.35, -2.345 -> -2.35). Used for IVA.
+    #[default]
+    HalfUp,
+    /// Half to even, a.k.a. banker's rounding (2.345 -> 2.34, 2.355 -> 2.36)
+    HalfEven,
+    /// Toward zero (truncate)
+    Down,
+    /// Away from zero
+    Up,
+}
+
+/// Monetary amount in integer cents (USD).
+/// Serialized over IPC as a decimal string ("12.34") so it never passes
+/// through a JavaScript float.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Money(i64);
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum MoneyError {
+    #[error("Invalid amount '{0}'")]
+    Invalid(String),
+    #[error("Amount '{0}' has more than 2 decimal places")]
+    TooPrecise(String),
+    #[error("Amount out of range")]
+    Overflow,
+}
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+
+    pub const fn from_cents(cents: i64) -> Self {
+        Money(cents)
+    }
+
+    pub const fn cents(self) -> i64 {
+        self.0
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    pub fn abs(self) -> Self {
+        Money(self.0.abs())
+    }
+
+    /// Parse a decimal amount, rounding extra decimal places with `mode`
+    pub fn parse_rounded(value: &str, mode: RoundingMode) -> Result<Self, MoneyError> {
+        let (negative, digits) = match value.trim().strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, value.trim()),
+        };
+
+        let (int_part, frac_part) = digits.split_once('.').unwrap_or((digits, ""));
+        let valid = !int_part.is_empty()
+            && int_part.chars().all(|c| c.is_ascii_digit())
+            && frac_part.chars().all(|c| c.is_ascii_digit())
+            && !(digits.contains('.') && frac_part.is_empty());
+        if !valid {
+            return Err(MoneyError::Invalid(value.to_string()));
+        }
+
+        // Scale everything to an integer numerator over 10^decimals
+        let decimals = frac_part.len() as u32;
+        let numerator: i128 = format!("{}{}", int_part, frac_part)
+            .parse()
+            .map_err(|_| MoneyError::Overflow)?;
+        let numerator = if negative { -numerator } else { numerator };
+
+        let cents = if decimals <= 2 {
+            numerator * 10_i128.pow(2 - decimals)
+        } else {
+            let divisor = 10_i128
+                .checked_pow(decimals - 2)
+                .ok_or(MoneyError::Overflow)?;
+            div_round(numerator, divisor, mode)
+        };
+
+        i64::try_from(cents)
+            .map(Money)
+            .map_err(|_| MoneyError::Overflow)
+    }
+
+    /// Convert a legacy REAL value (DECIMAL columns) to cents.
+    /// Goes through the shortest decimal representation of the float, so
+    /// 2.675 becomes 268 cents instead of 267.
+    pub fn from_f64(value: f64, mode: RoundingMode) -> Result<Self, MoneyError> {
+        if !value.is_finite() {
+            return Err(MoneyError::Invalid(value.to_string()));
+        }
+        Self::parse_rounded(&format_plain(value), mode)
+    }
+
+    /// Read a legacy DECIMAL(10,2) column value (INTEGER, REAL or TEXT)
+    pub fn from_decimal_value(value: rusqlite::types::ValueRef<'_>) -> Result<Self, MoneyError> {
+        match value {
+            rusqlite::types::ValueRef::Integer(units) => units
+                .checked_mul(100)
+                .map(Money)
+                .ok_or(MoneyError::Overflow),
+            rusqlite::types::ValueRef::Real(f) => Self::from_f64(f, RoundingMode::HalfUp),
+            rusqlite::types::ValueRef::Text(t) => {
+                Self::parse_rounded(&String::from_utf8_lossy(t), RoundingMode::HalfUp)
+            }
+            _ => Err(MoneyError::Invalid("non-numeric column value".to_string())),
+        }
+    }
+
+    /// Value as a float, only for legacy DECIMAL columns, display and the
+    /// backend's numeric amounts
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / 100.0
+    }
+
+    /// Multiply by an integer quantity
+    pub fn times(self, quantity: i64) -> Result<Self, MoneyError> {
+        self.0
+            .checked_mul(quantity)
+            .map(Money)
+            .ok_or(MoneyError::Overflow)
+    }
+
+    /// Multiply by a decimal quantity such as "1.5" (weighed products)
+    pub fn times_decimal(self, quantity: &str, mode: RoundingMode) -> Result<Self, MoneyError> {
+        let (int_part, frac_part) = quantity.split_once('.').unwrap_or((quantity, ""));
+        let scale = 10_i128
+            .checked_pow(frac_part.len() as u32)
+            .ok_or(MoneyError::Overflow)?;
+        let numerator: i128 = format!("{}{}", int_part, frac_part)
+            .parse()
+            .map_err(|_| MoneyError::Invalid(quantity.to_string()))?;
+
+        let cents = div_round(self.0 as i128 * numerator, scale, mode);
+        i64::try_from(cents)
+            .map(Money)
+            .map_err(|_| MoneyError::Overflow)
+    }
+
+    /// Apply a rate expressed in basis points (13% IVA = 1300)
+    pub fn percent_bps(self, basis_points: i64, mode: RoundingMode) -> Self {
+        Money(div_round(self.0 as i128 * basis_points as i128, 10_000, mode) as i64)
+    }
+
+    /// Divide by a count, e.g. the average ticket of a day's sales
+    pub fn divide(self, divisor: i64, mode: RoundingMode) -> Self {
+        Money(div_round(self.0 as i128, divisor as i128, mode) as i64)
+    }
+
+    /// Split a tax-inclusive amount into (net, tax) so that net + tax is
+    /// exactly the original amount
+    pub fn split_tax_inclusive(self, basis_points: i64, mode: RoundingMode) -> (Self, Self) {
+        let net =
+            Money(div_round(self.0 as i128 * 10_000, 10_000 + basis_points as i128, mode) as i64);
+        (net, self - net)
+    }
+
+    /// Distribute the amount over `weights` so the parts add up exactly,
+    /// handing leftover cents to the largest weights first. Used to spread a
+    /// sale-level discount over its lines.
+    pub fn allocate(self, weights: &[i64]) -> Vec<Self> {
+        let total: i128 = weights.iter().map(|w| *w as i128).sum();
+        if total == 0 {
+            return vec![Money::ZERO; weights.len()];
+        }
+
+        let mut parts: Vec<i64> = weights
+            .iter()
+            .map(|w| (self.0 as i128 * *w as i128 / total) as i64)
+            .collect();
+
+        let remainder = self.0 - parts.iter().sum::<i64>();
+        let step = remainder.signum();
+        let mut order: Vec<usize> = (0..weights.len()).collect();
+        order.sort_by(|a, b| weights[*b].cmp(&weights[*a]));
+
+        for idx in order.iter().cycle().take(remainder.unsigned_abs() as usize) {
+            parts[*idx] += step;
+        }
+
+        parts.into_iter().map(Money).collect()
+    }
+}
+
+/// Integer division with an explicit rounding mode
+fn div_round(numerator: i128, divisor: i128, mode: RoundingMode) -> i128 {
+    let quotient = numerator / divisor;
+    let remainder = numerator % divisor;
+    if remainder == 0 {
+        return quotient;
+    }
+
+    let away = if numerator.signum() * divisor.signum() < 0 {
+        -1
+    } else {
+        1
+    };
+    let twice = remainder.abs() * 2;
+
+    let round_away = match mode {
+        RoundingMode::Down => false,
+        RoundingMode::Up => true,
+        RoundingMode::HalfUp => twice >= divisor.abs(),
+        RoundingMode::HalfEven => {
+            twice > divisor.abs() || (twice == divisor.abs() && quotient % 2 != 0)
+        }
+    };
+
+    if round_away {
+        quotient + away
+    } else {
+        quotient
+    }
+}
+
+/// Shortest round-trip representation without exponent notation
+fn format_plain(value: f64) -> String {
+    let repr = value.to_string();
+    if repr.contains('e') || repr.contains('E') {
+        format!("{:.10}", value)
+    } else {
+        repr
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let abs = self.0.unsigned_abs();
+        write!(f, "{}{}.{:02}", sign, abs / 100, abs % 100)
+    }
+}
+
+impl FromStr for Money {
+    type Err = MoneyError;
+
+    /// Strict parse: more than two decimal places is an error
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let decimals = value.split_once('.').map(|(_, f)| f.len()).unwrap_or(0);
+        if decimals > 2 {
+            return Err(MoneyError::TooPrecise(value.to_string()));
+        }
+        Self::parse_rounded(value, RoundingMode::HalfUp)
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, rhs: Money) -> Money {
+        Money(self.0 + rhs.0)
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, rhs: Money) {
+        self.0 += rhs.0;
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+
+    fn sub(self, rhs: Money) -> Money {
+        Money(self.0 - rhs.0)
+    }
+}
+
+impl SubAssign for Money {
+    fn sub_assign(&mut self, rhs: Money) {
+        self.0 -= rhs.0;
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money(-self.0)
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, Add::add)
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    /// Accepts "12.34" or, for older callers, a JSON number
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Text(String),
+            Number(f64),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Text(s) => s.parse().map_err(serde::de::Error::custom),
+            Raw::Number(n) => {
+                Money::from_f64(n, RoundingMode::HalfUp).map_err(serde::de::Error::custom)
+            }
+        }
+    }
+}
+
+impl rusqlite::types::ToSql for Money {
+    /// Money is always bound as integer cents
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        Ok(rusqlite::types::ToSqlOutput::from(self.0))
+    }
+}
+
+impl rusqlite::types::FromSql for Money {
+    /// Reads `*_cents` INTEGER columns. Legacy DECIMAL columns must go through
+    /// [`Money::from_decimal_value`], since SQLite stores 2.00 there as INTEGER 2.
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        value.as_i64().map(Money)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn money(value: &str) -> Money {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_and_formats() {
+        assert_eq!(money("12.34").cents(), 1234);
+        assert_eq!(money("12.3").cents(), 1230);
+        assert_eq!(money("-0.05").cents(), -5);
+        assert_eq!(money("7").to_string(), "7.00");
+        assert_eq!(Money::from_cents(-5).to_string(), "-0.05");
+        assert_eq!(
+            "1.234".parse::<Money>(),
+            Err(MoneyError::TooPrecise("1.234".to_string()))
+        );
+        assert!(matches!("1.".parse::<Money>(), Err(MoneyError::Invalid(_))));
+        assert!(matches!(
+            "abc".parse::<Money>(),
+            Err(MoneyError::Invalid(_))
+        ));
+        assert_eq!(
+            "99999999999999999999".parse::<Money>(),
+            Err(MoneyError::Overflow)
+        );
+    }
+
+    #[test]
+    fn rounds_with_each_mode() {
+        let round = |value, mode| Money::parse_rounded(value, mode).unwrap().cents();
+        assert_eq!(round("2.345", RoundingMode::HalfUp), 235);
+        assert_eq!(round("-2.345", RoundingMode::HalfUp), -235);
+        assert_eq!(round("2.345", RoundingMode::HalfEven), 234);
+        assert_eq!(round("2.355", RoundingMode::HalfEven), 236);
+        assert_eq!(round("2.349", RoundingMode::Down), 234);
+        assert_eq!(round("-2.349", RoundingMode::Down), -234);
+        assert_eq!(round("2.341", RoundingMode::Up), 235);
+        assert_eq!(round("-2.341", RoundingMode::Up), -235);
+    }
+
+    #[test]
+    fn converts_floats_through_their_shortest_form() {
+        // 2.675 is 2.67499999... as an f64
+        assert_eq!(
+            Money::from_f64(2.675, RoundingMode::HalfUp)
+                .unwrap()
+                .cents(),
+            268
+        );
+        assert_eq!(
+            Money::from_f64(0.1 + 0.2, RoundingMode::HalfUp)
+                .unwrap()
+                .cents(),
+            30
+        );
+        assert!(Money::from_f64(f64::NAN, RoundingMode::HalfUp).is_err());
+    }
+
+    #[test]
+    fn applies_iva() {
+        // 13% of 10.99 = 1.4287
+        assert_eq!(
+            money("10.99")
+                .percent_bps(1300, RoundingMode::HalfUp)
+                .cents(),
+            143
+        );
+        // 13% of 0.50 = 0.065
+        assert_eq!(
+            money("0.50")
+                .percent_bps(1300, RoundingMode::HalfUp)
+                .cents(),
+            7
+        );
+        assert_eq!(
+            money("0.50")
+                .percent_bps(1300, RoundingMode::HalfEven)
+                .cents(),
+            6
+        );
+    }
+
+    #[test]
+    fn splits_tax_inclusive_amounts_exactly() {
+        for amount in ["11.30", "1.00", "0.01", "123.45", "-5.65"] {
+            let gross = money(amount);
+            let (net, tax) = gross.split_tax_inclusive(1300, RoundingMode::HalfUp);
+            assert_eq!(net + tax, gross, "{}", amount);
+        }
+        let (net, tax) = money("11.30").split_tax_inclusive(1300, RoundingMode::HalfUp);
+        assert_eq!((net.cents(), tax.cents()), (1000, 130));
+    }
+
+    #[test]
+    fn allocates_without_losing_cents() {
+        let parts = money("1.00").allocate(&[1, 1, 1]);
+        assert_eq!(
+            parts.iter().map(|p| p.cents()).collect::<Vec<_>>(),
+            [34, 33, 33]
+        );
+
+        let parts = money("10.00").allocate(&[950, 149]);
+        assert_eq!(parts.iter().copied().sum::<Money>(), money("10.00"));
+        assert_eq!(parts[0].cents(), 865);
+
+        let parts = money("-0.05").allocate(&[2, 1]);
+        assert_eq!(parts.iter().copied().sum::<Money>(), money("-0.05"));
+
+        assert_eq!(money("1.00").allocate(&[0, 0]), [Money::ZERO, Money::ZERO]);
+    }
+
+    #[test]
+    fn multiplies_and_divides() {
+        assert_eq!(money("0.99").times(3).unwrap().cents(), 297);
+        assert_eq!(
+            Money::from_cents(i64::MAX).times(2),
+            Err(MoneyError::Overflow)
+        );
+        assert_eq!(
+            money("0.99")
+                .times_decimal("1.5", RoundingMode::HalfUp)
+                .unwrap()
+                .cents(),
+            149
+        );
+        assert_eq!(money("10.00").divide(3, RoundingMode::HalfUp).cents(), 333);
+        assert_eq!(money("0.05").divide(2, RoundingMode::HalfEven).cents(), 2);
+    }
+
+    #[test]
+    fn serializes_as_decimal_strings() {
+        assert_eq!(
+            serde_json::to_string(&money("12.30")).unwrap(),
+            r#""12.30""#
+        );
+        assert_eq!(
+            serde_json::from_str::<Money>(r#""12.30""#).unwrap(),
+            money("12.30")
+        );
+        assert_eq!(
+            serde_json::from_str::<Money>("12.3").unwrap(),
+            money("12.30")
+        );
+        assert!(serde_json::from_str::<Money>(r#""12.345""#).is_err());
+    }
+}
@@ -0,0 +1,711 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::error::AppError;
+use crate::money::{Money, RoundingMode};
+use crate::services::audit::{self, AuditEntry};
+use crate::services::auth_session::{Session, SessionManager};
+use crate::services::database::get_setting;
+use crate::services::db_writer::WriteConnection;
+use crate::services::permissions::Permission;
+use crate::services::pin_auth::AuthenticatedUser;
+use crate::services::sale_totals;
+
+/// How long a supervisor's approval can be used
+const APPROVAL_TTL: Duration = Duration::from_secs(60);
+
+/// Discounts above this share of the amount they apply to need
+/// `discount` approval; a percentage like `tax_rate`
+pub const DISCOUNT_THRESHOLD_SETTING: &str = "discount_approval_threshold";
+const DEFAULT_DISCOUNT_THRESHOLD_BPS: i64 = 1000;
+
+/// Something a cashier may only do with a supervisor's approval
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalAction {
+    VoidSale,
+    Refund,
+    PriceOverride,
+    /// A discount above the POS screen's threshold
+    Discount,
+    OpenDrawer,
+}
+
+impl ApprovalAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ApprovalAction::VoidSale => "void_sale",
+            ApprovalAction::Refund => "refund",
+            ApprovalAction::PriceOverride => "price_override",
+            ApprovalAction::Discount => "discount",
+            ApprovalAction::OpenDrawer => "open_drawer",
+        }
+    }
+
+    /// Permission the approver needs, and that lets a user act without one
+    pub fn permission(self) -> Permission {
+        match self {
+            ApprovalAction::VoidSale => Permission::CanVoid,
+            ApprovalAction::Refund => Permission::CanRefund,
+            ApprovalAction::PriceOverride | ApprovalAction::Discount => {
+                Permission::CanOverridePrices
+            }
+            ApprovalAction::OpenDrawer => Permission::CanOpenDrawer,
+        }
+    }
+}
+
+/// An approval handed out by `request_approval`
+#[derive(Debug, Clone)]
+pub struct Approval {
+    pub action: ApprovalAction,
+    pub record_id: i64,
+    pub approver_id: i64,
+    /// The cashier whose session asked for it
+    pub requested_by: i64,
+    pub window_label: String,
+    expires: Instant,
+}
+
+/// What the webview gets back: the token and who approved what
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApprovalToken {
+    pub token: String,
+    pub action: ApprovalAction,
+    pub record_id: i64,
+    pub approver_id: i64,
+    pub approver_name: String,
+    pub expires_at: String,
+}
+
+/// A token sent with a command, naming what it is being used for
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApprovalUse {
+    pub token: String,
+    pub action: ApprovalAction,
+    pub record_id: i64,
+}
+
+/// Outstanding approvals by token. Each token works once, for one action on
+/// one record, from the window and session that asked for it. Clones share
+/// the same approvals, so write jobs can take one along.
+#[derive(Clone)]
+pub struct ApprovalManager {
+    approvals: Arc<Mutex<HashMap<String, Approval>>>,
+}
+
+impl ApprovalManager {
+    pub fn new() -> Self {
+        Self {
+            approvals: Arc::default(),
+        }
+    }
+
+    pub fn issue(
+        &self,
+        session: &Session,
+        approver: &AuthenticatedUser,
+        action: ApprovalAction,
+        record_id: i64,
+    ) -> ApprovalToken {
+        let now = Instant::now();
+        let token = uuid::Uuid::new_v4().to_string();
+        let mut approvals = self.lock();
+        approvals.retain(|_, a| a.expires > now);
+        approvals.insert(
+            token.clone(),
+            Approval {
+                action,
+                record_id,
+                approver_id: approver.id,
+                requested_by: session.user.id,
+                window_label: session.window_label.clone(),
+                expires: now + APPROVAL_TTL,
+            },
+        );
+
+        ApprovalToken {
+            token,
+            action,
+            record_id,
+            approver_id: approver.id,
+            approver_name: approver.full_name.clone(),
+            expires_at: (Utc::now() + chrono::Duration::from_std(APPROVAL_TTL).unwrap_or_default())
+                .to_rfc3339(),
+        }
+    }
+
+    /// Check a token without using it up, so a write that needs it fails
+    /// before it is queued. A token that doesn't match is removed, as in
+    /// `consume`.
+    pub fn check(&self, session: &Session, used: &ApprovalUse) -> Result<(), AppError> {
+        let mut approvals = self.lock();
+        let approval = approvals
+            .get(&used.token)
+            .filter(|a| a.expires > Instant::now())
+            .ok_or_else(expired)?;
+        if let Err(e) = validate(approval, session, used) {
+            approvals.remove(&used.token);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Use up a token. It is gone afterwards even if it doesn't match, so a
+    /// leaked token can't be probed against other records.
+    pub fn consume(&self, session: &Session, used: &ApprovalUse) -> Result<Approval, AppError> {
+        let approval = self
+            .lock()
+            .remove(&used.token)
+            .filter(|a| a.expires > Instant::now())
+            .ok_or_else(expired)?;
+        validate(&approval, session, used)?;
+        Ok(approval)
+    }
+
+    /// Put back a token taken by a transaction that then rolled back
+    fn restore(&self, token: String, approval: Approval) {
+        self.lock().insert(token, approval);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Approval>> {
+        self.approvals.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for ApprovalManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn expired() -> AppError {
+    AppError::Forbidden("Approval expired or already used".to_string())
+}
+
+fn validate(approval: &Approval, session: &Session, used: &ApprovalUse) -> Result<(), AppError> {
+    if approval.window_label != session.window_label
+        || approval.requested_by != session.user.id
+        || approval.action != used.action
+        || approval.record_id != used.record_id
+    {
+        log::warn!(
+            "Approval for {} on {} rejected: used by user {} for {} on {}",
+            approval.action.as_str(),
+            approval.record_id,
+            session.user.id,
+            used.action.as_str(),
+            used.record_id
+        );
+        return Err(AppError::Forbidden(
+            "Approval was given for a different action or record".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// The caller's session if it may do `action` on `record_id` itself, or
+/// with the supervisor approval it sent, which is checked but not used up;
+/// pass it to `in_transaction`. Fails with `AppError::Forbidden` when it
+/// needs one and has none.
+pub fn authorize(
+    sessions: &SessionManager,
+    approvals: &ApprovalManager,
+    window: &tauri::Window,
+    action: ApprovalAction,
+    record_id: i64,
+    token: Option<String>,
+) -> Result<(Session, Option<ApprovalUse>), AppError> {
+    let session = sessions.require(window)?;
+    if session.has(action.permission()) {
+        return Ok((session, None));
+    }
+
+    let Some(token) = token else {
+        return Err(AppError::Forbidden(format!(
+            "{} needs a supervisor's approval",
+            action.as_str()
+        )));
+    };
+    let used = ApprovalUse {
+        token,
+        action,
+        record_id,
+    };
+    approvals.check(&session, &used)?;
+    Ok((session, Some(used)))
+}
+
+/// Run `job` in an immediate transaction that also uses up `uses` and writes
+/// `approval_used` for each. The tokens are taken when the transaction
+/// begins and put back if anything fails, so only a committed write spends
+/// them.
+pub fn in_transaction<T>(
+    conn: &WriteConnection<'_>,
+    approvals: &ApprovalManager,
+    session: &Session,
+    uses: &[ApprovalUse],
+    job: impl FnOnce(&WriteConnection<'_>, &[Approval]) -> Result<T, AppError>,
+) -> Result<T, AppError> {
+    conn.begin_immediate().map_err(AppError::Database)?;
+
+    let mut taken: Vec<(String, Approval)> = Vec::with_capacity(uses.len());
+    let result = (|| {
+        for used in uses {
+            let approval = approvals.consume(session, used)?;
+            taken.push((used.token.clone(), approval));
+        }
+        let used: Vec<Approval> = taken.iter().map(|(_, a)| a.clone()).collect();
+        let value = job(conn, &used)?;
+        for approval in &used {
+            record_use(conn, approval).map_err(AppError::Database)?;
+        }
+        conn.commit().map_err(AppError::Database)?;
+        Ok(value)
+    })();
+
+    if result.is_err() {
+        conn.rollback();
+        for (token, approval) in taken {
+            approvals.restore(token, approval);
+        }
+    }
+    result
+}
+
+/// Write `approval_used` with both users, in the caller's transaction
+pub fn record_use(conn: &Connection, approval: &Approval) -> Result<(), String> {
+    audit::record(
+        conn,
+        AuditEntry {
+            user_id: Some(approval.requested_by),
+            action: "approval_used",
+            table_name: None,
+            record_id: Some(approval.record_id),
+            old_values: None,
+            new_values: Some(json!({
+                "approval_action": approval.action.as_str(),
+                "approved_by": approval.approver_id,
+                "requested_by": approval.requested_by,
+                "window_label": approval.window_label,
+            })),
+        },
+    )
+}
+
+/// Notes the sales, sale lines and cash movements a batch writes, for
+/// `required_approvals`. A line also notes its sale, whose totals it
+/// changes. The temp triggers live on the writer connection only and are
+/// created on its first batch.
+const TRACK_CHANGES: &str = "
+CREATE TEMP TABLE IF NOT EXISTS approval_candidates (
+    table_name TEXT NOT NULL,
+    row_id INTEGER NOT NULL
+);
+CREATE TEMP TRIGGER IF NOT EXISTS approval_sale_insert AFTER INSERT ON main.transactions
+BEGIN INSERT INTO approval_candidates VALUES ('transactions', NEW.id); END;
+CREATE TEMP TRIGGER IF NOT EXISTS approval_sale_update
+AFTER UPDATE OF subtotal_cents, tax_amount_cents, discount_amount_cents, total_cents, status
+ON main.transactions
+BEGIN INSERT INTO approval_candidates VALUES ('transactions', NEW.id); END;
+CREATE TEMP TRIGGER IF NOT EXISTS approval_item_insert AFTER INSERT ON main.transaction_items
+BEGIN
+    INSERT INTO approval_candidates VALUES ('transaction_items', NEW.id);
+    INSERT INTO approval_candidates VALUES ('transactions', NEW.transaction_id);
+END;
+CREATE TEMP TRIGGER IF NOT EXISTS approval_item_update
+AFTER UPDATE OF quantity, unit_price_cents, discount_amount_cents, total_price_cents
+ON main.transaction_items
+BEGIN
+    INSERT INTO approval_candidates VALUES ('transaction_items', NEW.id);
+    INSERT INTO approval_candidates VALUES ('transactions', NEW.transaction_id);
+END;
+CREATE TEMP TRIGGER IF NOT EXISTS approval_drawer_insert AFTER INSERT ON main.cash_movements
+BEGIN INSERT INTO approval_candidates VALUES ('cash_movements', NEW.id); END;
+DELETE FROM approval_candidates;";
+
+/// Start noting what the rest of the transaction writes
+pub fn track_changes(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(TRACK_CHANGES)
+        .map_err(|e| format!("Failed to track changes for approvals: {}", e))
+}
+
+/// The `discount_approval_threshold` setting in basis points, 10% when
+/// unset
+pub fn discount_threshold_bps(conn: &Connection) -> Result<i64, String> {
+    match get_setting(conn, DISCOUNT_THRESHOLD_SETTING)? {
+        Some(threshold) => Money::parse_rounded(&threshold, RoundingMode::HalfUp)
+            .map(Money::cents)
+            .map_err(|e| format!("Invalid {} setting: {}", DISCOUNT_THRESHOLD_SETTING, e)),
+        None => Ok(DEFAULT_DISCOUNT_THRESHOLD_BPS),
+    }
+}
+
+/// Approvals the changes noted since `track_changes` need, as
+/// (action, record id):
+/// - `discount` for a sale or line discount above the threshold, keyed by
+///   the discount in cents
+/// - `price_override` for a line priced at none of its product's tiers,
+///   keyed by the product id
+/// - `open_drawer` for a cash movement, keyed by the register session id
+///
+/// Totals are recomputed rather than trusted, and anything a line or sale
+/// comes to below them counts as discount. A line is worth quantity x unit
+/// price; a completed sale is worth the larger of its subtotal and the sum
+/// of its lines, plus the larger of its tax and `tax_rate` on that.
+pub fn required_approvals(conn: &Connection) -> Result<Vec<(ApprovalAction, i64)>, String> {
+    let threshold_bps = discount_threshold_bps(conn)?;
+    let tax_rate_bps = sale_totals::tax_rate_bps(conn)?;
+    let mut stmt = conn
+        .prepare(
+            "WITH sales AS (
+                 SELECT t.discount_amount_cents AS discount, t.tax_amount_cents AS tax,
+                        t.total_cents AS total,
+                        MAX(t.subtotal_cents, COALESCE(
+                            (SELECT SUM(i.total_price_cents) FROM main.transaction_items i
+                             WHERE i.transaction_id = t.id), 0)) AS gross
+                 FROM main.transactions t
+                 WHERE t.id IN (SELECT row_id FROM temp.approval_candidates
+                                WHERE table_name = 'transactions')
+                   AND t.status = 'completed' AND t.original_transaction_id IS NULL
+             ),
+             sale_discounts AS (
+                 SELECT gross,
+                        MAX(discount, gross + MAX(tax, (gross * ?2 + 5000) / 10000) - total)
+                            AS discount
+                 FROM sales
+             ),
+             lines AS (
+                 SELECT i.product_id, i.unit_price_cents, p.price_cents,
+                        p.partner_price_cents, p.vip_price_cents,
+                        CAST(ROUND(i.quantity * i.unit_price_cents) AS INTEGER) AS gross,
+                        i.discount_amount_cents AS discount, i.total_price_cents AS total
+                 FROM main.transaction_items i JOIN main.products p ON p.id = i.product_id
+                 WHERE i.id IN (SELECT row_id FROM temp.approval_candidates
+                                WHERE table_name = 'transaction_items')
+             )
+             SELECT 'discount', discount FROM sale_discounts
+             WHERE discount * 10000 > gross * ?1
+             UNION
+             SELECT 'discount', MAX(discount, gross - total) FROM lines
+             WHERE MAX(discount, gross - total) * 10000 > gross * ?1
+             UNION
+             SELECT 'price_override', product_id FROM lines
+             WHERE unit_price_cents NOT IN (price_cents, partner_price_cents, vip_price_cents)
+             UNION
+             SELECT 'open_drawer', m.session_id
+             FROM temp.approval_candidates c JOIN main.cash_movements m ON m.id = c.row_id
+             WHERE c.table_name = 'cash_movements'",
+        )
+        .map_err(|e| format!("Failed to check approvals: {}", e))?;
+    stmt.query_map([threshold_bps, tax_rate_bps], |row| {
+        let action = match row.get_ref(0)?.as_str()? {
+            "discount" => ApprovalAction::Discount,
+            "price_override" => ApprovalAction::PriceOverride,
+            _ => ApprovalAction::OpenDrawer,
+        };
+        Ok((action, row.get(1)?))
+    })
+    .and_then(Iterator::collect)
+    .map_err(|e| format!("Failed to check approvals: {}", e))
+}
+
+/// Fail with `Forbidden` when the noted changes need an approval the session
+/// has neither the permission for nor a token among `approvals`
+pub fn check_required(
+    conn: &Connection,
+    session: &Session,
+    approvals: &[Approval],
+) -> Result<(), AppError> {
+    for (action, record_id) in required_approvals(conn).map_err(AppError::Database)? {
+        let approved = session.has(action.permission())
+            || approvals
+                .iter()
+                .any(|a| a.action == action && a.record_id == record_id);
+        if !approved {
+            log::warn!(
+                "Write by user {} needs approval for {} on {}",
+                session.user.id,
+                action.as_str(),
+                record_id
+            );
+            return Err(AppError::Forbidden(format!(
+                "{} on {} needs a supervisor's approval",
+                action.as_str(),
+                record_id
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: &str = "pos-sale-1";
+
+    fn pos_database() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        crate::services::migrations::migrate(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO users (id, username, pin_hash, role, full_name, permissions)
+                 VALUES (1, 'ana', 'x', 'cashier', 'Ana', '{}'),
+                        (2, 'luis', 'x', 'supervisor', 'Luis', '{}');
+             INSERT INTO categories (id, name) VALUES (1, 'Bebidas');
+             INSERT INTO products (id, name, category_id, price, partner_price, vip_price)
+                 VALUES (1, 'Café', 1, 2.5, 2.25, 2);",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn session(id: i64, role: &str) -> Session {
+        let sessions = SessionManager::new();
+        sessions.start(
+            WINDOW,
+            AuthenticatedUser {
+                id,
+                username: role.to_string(),
+                full_name: role.to_string(),
+                role: role.to_string(),
+                permissions: json!({}),
+            },
+            Duration::from_secs(60),
+        );
+        sessions.current(WINDOW).unwrap()
+    }
+
+    fn supervisor() -> AuthenticatedUser {
+        session(2, "supervisor").user
+    }
+
+    fn use_of(token: &ApprovalToken) -> ApprovalUse {
+        ApprovalUse {
+            token: token.token.clone(),
+            action: token.action,
+            record_id: token.record_id,
+        }
+    }
+
+    fn approvals_used(conn: &Connection) -> i64 {
+        conn.query_row(
+            "SELECT COUNT(*) FROM audit_logs WHERE action = 'approval_used'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    /// Run `sql` as `execute_transaction` does
+    fn write(
+        conn: &Connection,
+        approvals: &ApprovalManager,
+        session: &Session,
+        uses: &[ApprovalUse],
+        sql: &str,
+    ) -> Result<(), AppError> {
+        let conn = WriteConnection::for_tests(conn);
+        in_transaction(&conn, approvals, session, uses, |conn, approved| {
+            track_changes(conn).map_err(AppError::Database)?;
+            conn.execute_batch(sql)
+                .map_err(|e| AppError::Database(e.to_string()))?;
+            check_required(conn, session, approved)
+        })
+    }
+
+    #[test]
+    fn token_is_spent_only_when_the_write_commits() {
+        let conn = pos_database();
+        let approvals = ApprovalManager::new();
+        let cashier = session(1, "cashier");
+        let token = approvals.issue(&cashier, &supervisor(), ApprovalAction::VoidSale, 7);
+        let used = [use_of(&token)];
+
+        let failed = in_transaction(
+            &WriteConnection::for_tests(&conn),
+            &approvals,
+            &cashier,
+            &used,
+            |_, _| Err::<(), _>(AppError::Database("writer failed".to_string())),
+        );
+        assert!(failed.is_err());
+        assert!(approvals.check(&cashier, &used[0]).is_ok());
+        assert_eq!(approvals_used(&conn), 0);
+
+        let approved = in_transaction(
+            &WriteConnection::for_tests(&conn),
+            &approvals,
+            &cashier,
+            &used,
+            |_, approved| Ok(approved[0].approver_id),
+        );
+        assert_eq!(approved.unwrap(), 2);
+        assert_eq!(approvals_used(&conn), 1);
+        assert!(matches!(
+            approvals.check(&cashier, &used[0]),
+            Err(AppError::Forbidden(_))
+        ));
+    }
+
+    #[test]
+    fn mismatched_token_is_removed() {
+        let approvals = ApprovalManager::new();
+        let cashier = session(1, "cashier");
+        let token = approvals.issue(&cashier, &supervisor(), ApprovalAction::VoidSale, 7);
+
+        let mut other_sale = use_of(&token);
+        other_sale.record_id = 8;
+        assert!(approvals.check(&cashier, &other_sale).is_err());
+        assert!(approvals.consume(&cashier, &use_of(&token)).is_err());
+    }
+
+    #[test]
+    fn large_discounts_need_approval() {
+        let conn = pos_database();
+        let approvals = ApprovalManager::new();
+        let cashier = session(1, "cashier");
+        let sale = |number: &str, discount: &str| {
+            format!(
+                "INSERT INTO transactions (transaction_number, user_id, subtotal, tax_amount,
+                     discount_amount, total)
+                 VALUES ('{number}', 1, 10, 1.3, {discount}, 11.3 - {discount})"
+            )
+        };
+
+        write(&conn, &approvals, &cashier, &[], &sale("T-1", "1")).unwrap();
+        let denied = write(&conn, &approvals, &cashier, &[], &sale("T-2", "2"));
+        assert!(matches!(denied, Err(AppError::Forbidden(_))));
+        let sales: i64 = conn
+            .query_row("SELECT COUNT(*) FROM transactions", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(sales, 1);
+
+        let token = approvals.issue(&cashier, &supervisor(), ApprovalAction::Discount, 200);
+        write(
+            &conn,
+            &approvals,
+            &cashier,
+            &[use_of(&token)],
+            &sale("T-2", "2"),
+        )
+        .unwrap();
+        assert_eq!(approvals_used(&conn), 1);
+    }
+
+    #[test]
+    fn prices_off_the_product_tiers_need_approval() {
+        let conn = pos_database();
+        let approvals = ApprovalManager::new();
+        let cashier = session(1, "cashier");
+        conn.execute_batch(
+            "INSERT INTO transactions (id, transaction_number, user_id, subtotal, tax_amount, total)
+                 VALUES (1, 'T-1', 1, 10, 1.3, 11.3);",
+        )
+        .unwrap();
+        let line = |price: &str| {
+            format!(
+                "INSERT INTO transaction_items (transaction_id, product_id, quantity, unit_price,
+                     total_price)
+                 VALUES (1, 1, 1, {price}, {price})"
+            )
+        };
+
+        write(&conn, &approvals, &cashier, &[], &line("2.25")).unwrap();
+        let denied = write(&conn, &approvals, &cashier, &[], &line("1"));
+        assert!(matches!(denied, Err(AppError::Forbidden(_))));
+
+        let token = approvals.issue(&cashier, &supervisor(), ApprovalAction::PriceOverride, 1);
+        write(&conn, &approvals, &cashier, &[use_of(&token)], &line("1")).unwrap();
+    }
+
+    #[test]
+    fn line_totals_below_quantity_times_price_count_as_discounts() {
+        let conn = pos_database();
+        let approvals = ApprovalManager::new();
+        let cashier = session(1, "cashier");
+        conn.execute_batch(
+            "INSERT INTO transactions (id, transaction_number, user_id, subtotal, tax_amount, total)
+                 VALUES (1, 'T-1', 1, 10, 1.3, 11.3);",
+        )
+        .unwrap();
+        // List price and no recorded discount, but the line comes to 0.01
+        let line = "INSERT INTO transaction_items (transaction_id, product_id, quantity,
+                        unit_price, discount_amount, total_price)
+                    VALUES (1, 1, 2, 2.5, 0, 0.01)";
+
+        let denied = write(&conn, &approvals, &cashier, &[], line);
+        assert!(matches!(denied, Err(AppError::Forbidden(_))));
+
+        let token = approvals.issue(&cashier, &supervisor(), ApprovalAction::Discount, 499);
+        write(&conn, &approvals, &cashier, &[use_of(&token)], line).unwrap();
+    }
+
+    #[test]
+    fn sale_totals_below_the_lines_count_as_discounts() {
+        let conn = pos_database();
+        let approvals = ApprovalManager::new();
+        let cashier = session(1, "cashier");
+        let denied_sale = |sql: &str| {
+            let denied = write(&conn, &approvals, &cashier, &[], sql);
+            assert!(matches!(denied, Err(AppError::Forbidden(_))), "{}", sql);
+        };
+
+        // Total far below subtotal + tax - discount
+        denied_sale(
+            "INSERT INTO transactions (transaction_number, user_id, subtotal, tax_amount,
+                 discount_amount, total)
+             VALUES ('T-1', 1, 10, 1.3, 0, 1)",
+        );
+        // Subtotal understated against its own lines
+        denied_sale(
+            "INSERT INTO transactions (id, transaction_number, user_id, subtotal, tax_amount, total)
+                 VALUES (1, 'T-1', 1, 0.1, 0.01, 0.11);
+             INSERT INTO transaction_items (transaction_id, product_id, quantity, unit_price,
+                 total_price)
+                 VALUES (1, 1, 4, 2.5, 10)",
+        );
+        // Tax left off
+        denied_sale(
+            "INSERT INTO transactions (transaction_number, user_id, subtotal, tax_amount, total)
+                 VALUES ('T-1', 1, 10, 0, 10)",
+        );
+
+        write(
+            &conn,
+            &approvals,
+            &cashier,
+            &[],
+            "INSERT INTO transactions (id, transaction_number, user_id, subtotal, tax_amount, total)
+                 VALUES (1, 'T-1', 1, 10, 1.3, 11.3);
+             INSERT INTO transaction_items (transaction_id, product_id, quantity, unit_price,
+                 total_price)
+                 VALUES (1, 1, 4, 2.5, 10)",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn drawer_openings_need_approval_or_permission() {
+        let conn = pos_database();
+        let approvals = ApprovalManager::new();
+        conn.execute_batch(
+            "INSERT INTO cash_register_sessions (id, user_id, opening_balance) VALUES (3, 1, 50);",
+        )
+        .unwrap();
+        let movement = "INSERT INTO cash_movements (session_id, type, amount, reason, performed_by)
+                        VALUES (3, 'withdrawal', 0, 'open drawer', 1)";
+
+        let denied = write(&conn, &approvals, &session(1, "cashier"), &[], movement);
+        assert!(matches!(denied, Err(AppError::Forbidden(_))));
+        write(&conn, &approvals, &session(2, "supervisor"), &[], movement).unwrap();
+    }
+}
@@ -0,0 +1,32 @@
+use rusqlite::{Connection, params};
+use serde_json::Value as JsonValue;
+
+/// A row for `audit_logs`
+#[derive(Debug, Default)]
+pub struct AuditEntry<'a> {
+    /// Acting user, `None` when unknown
+    pub user_id: Option<i64>,
+    pub action: &'a str,
+    pub table_name: Option<&'a str>,
+    pub record_id: Option<i64>,
+    pub old_values: Option<JsonValue>,
+    pub new_values: Option<JsonValue>,
+}
+
+/// Append an entry to `audit_logs` in the caller's transaction
+pub fn record(conn: &Connection, entry: AuditEntry) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO audit_logs (user_id, action, table_name, record_id, old_values, new_values)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            entry.user_id,
+            entry.action,
+            entry.table_name,
+            entry.record_id,
+            entry.old_values.map(|v| v.to_string()),
+            entry.new_values.map(|v| v.to_string())
+        ],
+    )
+    .map(|_| ())
+    .map_err(|e| format!("Failed to write audit entry '{}': {}", entry.action, e))
+}
@@ -0,0 +1,263 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use rusqlite::Connection;
+use serde::Serialize;
+use serde_json::json;
+use tauri::{Emitter, Manager};
+
+use crate::error::AppError;
+use crate::services::audit::{self, AuditEntry};
+use crate::services::database::get_setting;
+use crate::services::db_writer::DbWriter;
+use crate::services::permissions::{self, Permission};
+use crate::services::pin_auth::AuthenticatedUser;
+
+/// Idle minutes before a session ends when `session_timeout` is unset
+const DEFAULT_IDLE_MINUTES: u64 = 480;
+
+/// How often expired sessions are swept and their windows notified
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A logged-in user, bound to the window that logged in
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub id: String,
+    pub window_label: String,
+    pub user: AuthenticatedUser,
+    /// Resolved from the user's role and `users.permissions` at login
+    pub permissions: BTreeSet<Permission>,
+    pub started_at: DateTime<Utc>,
+    pub idle_timeout: Duration,
+    last_activity: Instant,
+}
+
+impl Session {
+    fn is_expired(&self, now: Instant) -> bool {
+        now.duration_since(self.last_activity) >= self.idle_timeout
+    }
+
+    pub fn has(&self, permission: Permission) -> bool {
+        self.permissions.contains(&permission)
+    }
+
+    pub fn info(&self) -> SessionInfo {
+        let remaining = self
+            .idle_timeout
+            .saturating_sub(self.last_activity.elapsed());
+        SessionInfo {
+            session_id: self.id.clone(),
+            window_label: self.window_label.clone(),
+            user: self.user.clone(),
+            permissions: self.permissions.clone(),
+            started_at: self.started_at.to_rfc3339(),
+            expires_at: (Utc::now() + chrono::Duration::from_std(remaining).unwrap_or_default())
+                .to_rfc3339(),
+        }
+    }
+}
+
+/// What the webview sees of a session
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    pub session_id: String,
+    pub window_label: String,
+    pub user: AuthenticatedUser,
+    pub permissions: BTreeSet<Permission>,
+    pub started_at: String,
+    /// When the session ends without further activity
+    pub expires_at: String,
+}
+
+/// Sessions by window label. Commands look the caller up here instead of
+/// trusting a user id sent by the webview.
+pub struct SessionManager {
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl SessionManager {
+    pub fn new() -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Start a session for the window, replacing any previous one
+    pub fn start(
+        &self,
+        window_label: &str,
+        user: AuthenticatedUser,
+        idle_timeout: Duration,
+    ) -> SessionInfo {
+        let session = Session {
+            id: uuid::Uuid::new_v4().to_string(),
+            window_label: window_label.to_string(),
+            permissions: permissions::resolve(&user.role, &user.permissions),
+            user,
+            started_at: Utc::now(),
+            idle_timeout,
+            last_activity: Instant::now(),
+        };
+        let info = session.info();
+        self.lock().insert(window_label.to_string(), session);
+        info
+    }
+
+    /// End the window's session, returning it if there was one
+    pub fn end(&self, window_label: &str) -> Option<Session> {
+        self.lock().remove(window_label)
+    }
+
+    /// The window's session unless it has expired
+    pub fn current(&self, window_label: &str) -> Option<Session> {
+        self.lock()
+            .get(window_label)
+            .filter(|s| !s.is_expired(Instant::now()))
+            .cloned()
+    }
+
+    /// The calling window's session, counting the call as activity.
+    /// Fails with `AppError::Auth` when nobody is logged in there.
+    pub fn require(&self, window: &tauri::Window) -> Result<Session, AppError> {
+        let now = Instant::now();
+        let mut sessions = self.lock();
+        match sessions.get_mut(window.label()) {
+            Some(session) if !session.is_expired(now) => {
+                session.last_activity = now;
+                Ok(session.clone())
+            }
+            Some(_) => Err(AppError::Auth("Session expired".to_string())),
+            None => Err(AppError::Auth("Not logged in".to_string())),
+        }
+    }
+
+    /// The calling window's session if its user has `permission`. Fails
+    /// with `AppError::Forbidden` otherwise.
+    pub fn authorize(
+        &self,
+        window: &tauri::Window,
+        permission: Permission,
+    ) -> Result<Session, AppError> {
+        let session = self.require(window)?;
+        if !session.has(permission) {
+            log::warn!(
+                "User {} in window '{}' denied: missing {}",
+                session.user.id,
+                window.label(),
+                permission.as_str()
+            );
+            return Err(AppError::Forbidden(format!(
+                "Permission {} required",
+                permission.as_str()
+            )));
+        }
+        Ok(session)
+    }
+
+    /// Give a new window the session of the window that opened it
+    pub fn share(&self, from_label: &str, to_label: &str) -> Option<SessionInfo> {
+        let mut sessions = self.lock();
+        let mut session = sessions
+            .get(from_label)
+            .filter(|s| !s.is_expired(Instant::now()))?
+            .clone();
+        session.window_label = to_label.to_string();
+        session.last_activity = Instant::now();
+        let info = session.info();
+        sessions.insert(to_label.to_string(), session);
+        Some(info)
+    }
+
+    /// Remove and return sessions past their idle timeout
+    fn take_expired(&self) -> Vec<Session> {
+        let now = Instant::now();
+        let mut sessions = self.lock();
+        let expired: Vec<String> = sessions
+            .iter()
+            .filter(|(_, s)| s.is_expired(now))
+            .map(|(label, _)| label.clone())
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|label| sessions.remove(&label))
+            .collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Session>> {
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for SessionManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Idle timeout from `session_timeout` (minutes)
+pub fn idle_timeout(conn: &Connection) -> Result<Duration, String> {
+    let minutes = get_setting(conn, "session_timeout")?
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .filter(|m| *m > 0)
+        .unwrap_or(DEFAULT_IDLE_MINUTES);
+    Ok(Duration::from_secs(minutes * 60))
+}
+
+/// Write a session event (`logout`, `session_expired`) to `audit_logs`
+pub async fn audit_event(
+    app_handle: &tauri::AppHandle,
+    action: &'static str,
+    session: &Session,
+) -> Result<(), String> {
+    let user_id = session.user.id;
+    let details = json!({
+        "session_id": session.id,
+        "window_label": session.window_label,
+    });
+    app_handle
+        .state::<DbWriter>()
+        .execute(app_handle, move |conn| {
+            audit::record(
+                conn,
+                AuditEntry {
+                    user_id: Some(user_id),
+                    action,
+                    table_name: Some("users"),
+                    record_id: Some(user_id),
+                    old_values: None,
+                    new_values: Some(details),
+                },
+            )
+        })
+        .await
+}
+
+/// End idle sessions until the app exits. The window gets a
+/// `session-expired` event so it can return to the login screen.
+pub async fn run_expiry(app_handle: tauri::AppHandle) {
+    loop {
+        tokio::time::sleep(EXPIRY_CHECK_INTERVAL).await;
+
+        let expired = app_handle.state::<SessionManager>().take_expired();
+        for session in expired {
+            log::info!(
+                "Session of user {} in window '{}' expired",
+                session.user.id,
+                session.window_label
+            );
+            if let Err(e) = app_handle.emit_to(
+                session.window_label.as_str(),
+                "session-expired",
+                session.user.id,
+            ) {
+                log::debug!("Failed to emit session-expired: {}", e);
+            }
+            if let Err(e) = audit_event(&app_handle, "session_expired", &session).await {
+                log::warn!("Failed to audit session expiry: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,525 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Local, Utc};
+use rusqlite::backup::{Backup, StepResult};
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tauri::{Emitter, Manager};
+
+use crate::services::database::{open_connection, open_read_only_connection, run_migrations};
+use crate::services::{db_encryption, migrations};
+
+const BACKUP_PREFIX: &str = "pos_backup_";
+const BACKUP_EXTENSION: &str = "db";
+const CHECKSUM_EXTENSION: &str = "sha256";
+
+const DEFAULT_FREQUENCY_HOURS: u64 = 24;
+const DEFAULT_RETENTION_COUNT: usize = 14;
+const DEFAULT_RETENTION_DAYS: u64 = 30;
+
+/// How often the scheduler checks whether a backup is due
+const SCHEDULER_TICK: Duration = Duration::from_secs(5 * 60);
+
+/// Attempts for a backup step when the other side holds a lock
+const MAX_COPY_ATTEMPTS: u32 = 10;
+const COPY_RETRY_PAUSE: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupReason {
+    Scheduled,
+    Manual,
+    PreRestore,
+    PreRollback,
+}
+
+impl BackupReason {
+    fn as_str(self) -> &'static str {
+        match self {
+            BackupReason::Scheduled => "scheduled",
+            BackupReason::Manual => "manual",
+            BackupReason::PreRestore => "pre_restore",
+            BackupReason::PreRollback => "pre_rollback",
+        }
+    }
+
+    fn from_file_name(file_name: &str) -> Option<Self> {
+        let stem = file_name.strip_suffix(".db")?;
+        [
+            Self::PreRestore,
+            Self::PreRollback,
+            Self::Scheduled,
+            Self::Manual,
+        ]
+        .into_iter()
+        .find(|reason| stem.ends_with(reason.as_str()))
+    }
+}
+
+const SETTING_KEYS: [&str; 4] = [
+    "backup_directory",
+    "backup_frequency",
+    "backup_retention_count",
+    "backup_retention_days",
+];
+
+/// Backup configuration from `system_settings`, with defaults for missing keys
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupSettings {
+    pub directory: PathBuf,
+    /// `backup_frequency`, in hours; 0 disables scheduled backups
+    pub frequency_hours: u64,
+    /// `backup_retention_count`; 0 keeps any number of files
+    pub retention_count: usize,
+    /// `backup_retention_days`; 0 keeps files of any age
+    pub retention_days: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupInfo {
+    pub file_name: String,
+    pub path: String,
+    pub size_bytes: u64,
+    pub created_at: String,
+    pub reason: Option<BackupReason>,
+    /// SHA-256 recorded when the backup was written
+    pub checksum: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreResult {
+    pub restored: BackupInfo,
+    /// Copy of the database taken right before it was replaced
+    pub safety_backup: BackupInfo,
+    pub backup_schema_version: i64,
+}
+
+impl BackupSettings {
+    pub fn load(app_handle: &tauri::AppHandle) -> Result<Self, String> {
+        let default_dir = app_handle
+            .path()
+            .app_data_dir()
+            .map_err(|e| format!("Failed to get app data dir: {}", e))?
+            .join("backups");
+
+        // Before the first migration there are no settings yet
+        let stored = open_read_only_connection(app_handle)
+            .and_then(|conn| read_settings(&conn))
+            .unwrap_or_default();
+        let setting = |key: &str| -> Option<String> {
+            stored
+                .get(key)
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+
+        let directory = match setting("backup_directory").map(PathBuf::from) {
+            Some(dir) if dir.is_absolute() => dir,
+            Some(dir) => {
+                log::warn!(
+                    "Ignoring relative backup_directory {:?}, using {:?}",
+                    dir,
+                    default_dir
+                );
+                default_dir
+            }
+            None => default_dir,
+        };
+
+        Ok(Self {
+            directory,
+            frequency_hours: parse_setting(setting("backup_frequency"), DEFAULT_FREQUENCY_HOURS),
+            retention_count: parse_setting(
+                setting("backup_retention_count"),
+                DEFAULT_RETENTION_COUNT,
+            ),
+            retention_days: parse_setting(setting("backup_retention_days"), DEFAULT_RETENTION_DAYS),
+        })
+    }
+}
+
+fn parse_setting<T: std::str::FromStr>(value: Option<String>, default: T) -> T {
+    value.and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// Hot backup of the live database using SQLite's online backup API.
+/// The copy is written to a `.partial` file, integrity-checked, and only
+/// then renamed into place, so a listed backup is always a usable one.
+pub fn create_backup(
+    app_handle: &tauri::AppHandle,
+    reason: BackupReason,
+) -> Result<BackupInfo, String> {
+    let settings = BackupSettings::load(app_handle)?;
+    fs::create_dir_all(&settings.directory)
+        .map_err(|e| format!("Failed to create backup directory: {}", e))?;
+
+    let file_name = format!(
+        "{}{}_{}.{}",
+        BACKUP_PREFIX,
+        Local::now().format("%Y%m%d_%H%M%S"),
+        reason.as_str(),
+        BACKUP_EXTENSION
+    );
+    let final_path = settings.directory.join(&file_name);
+    let partial_path = settings.directory.join(format!("{}.partial", file_name));
+
+    let result = write_backup(app_handle, &partial_path).and_then(|_| {
+        let checksum = file_checksum(&partial_path)?;
+        fs::rename(&partial_path, &final_path)
+            .map_err(|e| format!("Failed to finalize backup: {}", e))?;
+        fs::write(
+            checksum_path(&final_path),
+            format!("{}  {}\n", checksum, file_name),
+        )
+        .map_err(|e| format!("Failed to write backup checksum: {}", e))
+    });
+
+    if let Err(e) = result {
+        let _ = fs::remove_file(&partial_path);
+        log::error!("Backup failed: {}", e);
+        return Err(e);
+    }
+
+    let info = backup_info(&final_path)?;
+    log::info!(
+        "Database backup written to {:?} ({} bytes)",
+        final_path,
+        info.size_bytes
+    );
+
+    if let Err(e) = apply_retention(&settings) {
+        log::warn!("Backup retention failed: {}", e);
+    }
+
+    Ok(info)
+}
+
+fn write_backup(app_handle: &tauri::AppHandle, target: &Path) -> Result<(), String> {
+    let source = open_read_only_connection(app_handle)?;
+    // Keyed like the live database, so backups are encrypted too
+    let mut dest = db_encryption::open_database_file(target, OpenFlags::default())?;
+
+    copy_database(&source, &mut dest)?;
+
+    // A backup should be a single self-contained file
+    dest.execute_batch("PRAGMA journal_mode = DELETE;")
+        .map_err(|e| format!("Failed to finalize backup file: {}", e))?;
+    drop(dest);
+
+    verify_backup_file(target).map(|_| ())
+}
+
+/// Copy every page in one step so the result is a consistent snapshot.
+/// In WAL mode the source read doesn't block sales being written.
+fn copy_database(source: &Connection, dest: &mut Connection) -> Result<(), String> {
+    let backup = Backup::new(source, dest).map_err(|e| format!("Failed to start backup: {}", e))?;
+
+    for _ in 0..MAX_COPY_ATTEMPTS {
+        match backup.step(-1) {
+            Ok(StepResult::Done) => return Ok(()),
+            // Busy or Locked: the other side is mid-transaction
+            Ok(_) => std::thread::sleep(COPY_RETRY_PAUSE),
+            Err(e) => return Err(format!("Database copy failed: {}", e)),
+        }
+    }
+
+    Err(format!(
+        "Database copy did not complete after {} attempts, database is busy",
+        MAX_COPY_ATTEMPTS
+    ))
+}
+
+/// Run `PRAGMA integrity_check` and validate the schema history of a backup.
+/// Returns the schema version it was taken at.
+pub fn verify_backup_file(path: &Path) -> Result<i64, String> {
+    let conn = db_encryption::open_database_file(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+
+    let problems: Vec<String> = conn
+        .prepare("PRAGMA integrity_check")
+        .and_then(|mut stmt| {
+            stmt.query_map([], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| format!("Integrity check failed to run: {}", e))?;
+
+    if problems.len() != 1 || problems[0] != "ok" {
+        return Err(format!(
+            "Backup failed integrity check: {}",
+            problems.join("; ")
+        ));
+    }
+
+    let info = migrations::schema_info(&conn)
+        .map_err(|e| format!("Backup has no readable schema history: {}", e))?;
+
+    if info.current_version > info.latest_version {
+        return Err(format!(
+            "Backup schema version {} is newer than this app supports ({})",
+            info.current_version, info.latest_version
+        ));
+    }
+    if let Some(tampered) = info.applied.iter().find(|m| !m.checksum_valid) {
+        return Err(format!(
+            "Backup has a modified migration {} ({})",
+            tampered.version, tampered.name
+        ));
+    }
+
+    Ok(info.current_version)
+}
+
+/// Backups in the configured directory, newest first
+pub fn list_backups(settings: &BackupSettings) -> Result<Vec<BackupInfo>, String> {
+    let entries = match fs::read_dir(&settings.directory) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read backup directory: {}", e)),
+    };
+
+    let mut backups = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(is_backup_name)
+        })
+        .map(|path| backup_info(&path))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    backups.sort_by(|a, b| b.file_name.cmp(&a.file_name));
+    Ok(backups)
+}
+
+/// Delete backups beyond the configured count or age. The newest backup is
+/// always kept, whatever the settings say.
+fn apply_retention(settings: &BackupSettings) -> Result<(), String> {
+    let max_age = Duration::from_secs(settings.retention_days * 24 * 60 * 60);
+    let now = SystemTime::now();
+
+    for (position, backup) in list_backups(settings)?.iter().enumerate().skip(1) {
+        let too_many = settings.retention_count > 0 && position >= settings.retention_count;
+        let too_old = settings.retention_days > 0
+            && fs::metadata(&backup.path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|modified| now.duration_since(modified).ok())
+                .is_some_and(|age| age > max_age);
+
+        if too_many || too_old {
+            log::info!("Removing old backup {}", backup.file_name);
+            let path = PathBuf::from(&backup.path);
+            fs::remove_file(&path)
+                .map_err(|e| format!("Failed to remove {}: {}", backup.file_name, e))?;
+            let _ = fs::remove_file(checksum_path(&path));
+        }
+    }
+
+    Ok(())
+}
+
+/// Every backup setting that is set, in one query
+fn read_settings(conn: &Connection) -> Result<HashMap<String, String>, String> {
+    let mut stmt = conn
+        .prepare("SELECT key, value FROM system_settings WHERE key IN (?, ?, ?, ?) AND value IS NOT NULL")
+        .map_err(|e| format!("Failed to read backup settings: {}", e))?;
+    stmt.query_map(SETTING_KEYS, |row| Ok((row.get(0)?, row.get(1)?)))
+        .and_then(|rows| rows.collect())
+        .map_err(|e| format!("Failed to read backup settings: {}", e))
+}
+
+/// Replace the live database with a backup.
+/// Must run on the database writer so no queued write lands mid-restore.
+/// The pages are copied with the backup API into the open database rather than swapping
+/// files, so no connection is left holding a handle to a deleted file.
+pub fn restore_backup(
+    app_handle: &tauri::AppHandle,
+    file_name: &str,
+) -> Result<RestoreResult, String> {
+    let settings = BackupSettings::load(app_handle)?;
+    let backup_path = resolve_backup_path(&settings, file_name)?;
+
+    verify_checksum(&backup_path)?;
+    let backup_schema_version = verify_backup_file(&backup_path)?;
+
+    let safety_backup = create_backup(app_handle, BackupReason::PreRestore)
+        .map_err(|e| format!("Refusing to restore without a safety backup: {}", e))?;
+
+    // SQLCipher can't copy pages between a plaintext and an encrypted
+    // database, so backups from before encryption are converted first
+    let staged_path = settings.directory.join(format!("{}.restoring", file_name));
+    let source_path =
+        if db_encryption::is_enabled() && db_encryption::is_plaintext_file(&backup_path) {
+            db_encryption::encrypted_copy(&backup_path, &staged_path)?;
+            staged_path.as_path()
+        } else {
+            backup_path.as_path()
+        };
+
+    let source = db_encryption::open_database_file(
+        source_path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    let mut live = open_connection(app_handle)?;
+
+    log::warn!("Restoring database from {}", file_name);
+    let copied = copy_database(&source, &mut live);
+    drop(live);
+    drop(source);
+    let _ = fs::remove_file(&staged_path);
+    copied?;
+
+    // An older backup is brought up to the current schema
+    run_migrations(app_handle)?;
+
+    log::info!(
+        "Database restored from {} (schema version {})",
+        file_name,
+        backup_schema_version
+    );
+
+    Ok(RestoreResult {
+        restored: backup_info(&backup_path)?,
+        safety_backup,
+        backup_schema_version,
+    })
+}
+
+/// Only plain file names from the backup directory are accepted
+fn resolve_backup_path(settings: &BackupSettings, file_name: &str) -> Result<PathBuf, String> {
+    if !is_backup_name(file_name) || file_name.contains(['/', '\\']) || file_name.contains("..") {
+        return Err(format!("'{}' is not a backup file name", file_name));
+    }
+
+    let path = settings.directory.join(file_name);
+    if !path.is_file() {
+        return Err(format!("Backup '{}' not found", file_name));
+    }
+    Ok(path)
+}
+
+fn verify_checksum(path: &Path) -> Result<(), String> {
+    let recorded = match fs::read_to_string(checksum_path(path)) {
+        Ok(contents) => contents
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+        Err(_) => {
+            log::warn!(
+                "No checksum recorded for {:?}, relying on integrity check",
+                path
+            );
+            return Ok(());
+        }
+    };
+
+    if file_checksum(path)? != recorded {
+        return Err("Backup file does not match its recorded checksum".to_string());
+    }
+    Ok(())
+}
+
+/// Run scheduled backups for the lifetime of the app.
+/// Settings are re-read on every tick so changes apply without a restart.
+pub async fn run_scheduler(app_handle: tauri::AppHandle) {
+    loop {
+        let handle = app_handle.clone();
+        let outcome = tokio::task::spawn_blocking(move || backup_if_due(&handle)).await;
+
+        match outcome {
+            Ok(Ok(Some(info))) => {
+                let _ = app_handle.emit_to("main", "backup-completed", &info);
+            }
+            Ok(Ok(None)) => {}
+            Ok(Err(e)) => {
+                log::error!("Scheduled backup failed: {}", e);
+                let _ = app_handle.emit_to("main", "backup-failed", &e);
+            }
+            Err(e) => log::error!("Backup task panicked: {}", e),
+        }
+
+        tokio::time::sleep(SCHEDULER_TICK).await;
+    }
+}
+
+fn backup_if_due(app_handle: &tauri::AppHandle) -> Result<Option<BackupInfo>, String> {
+    let settings = BackupSettings::load(app_handle)?;
+    if settings.frequency_hours == 0 {
+        return Ok(None);
+    }
+
+    let interval = Duration::from_secs(settings.frequency_hours * 60 * 60);
+    let last_backup_age = list_backups(&settings)?
+        .first()
+        .and_then(|latest| fs::metadata(&latest.path).and_then(|m| m.modified()).ok())
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+
+    match last_backup_age {
+        Some(age) if age < interval => Ok(None),
+        _ => create_backup(app_handle, BackupReason::Scheduled).map(Some),
+    }
+}
+
+fn backup_info(path: &Path) -> Result<BackupInfo, String> {
+    let metadata =
+        fs::metadata(path).map_err(|e| format!("Failed to read backup metadata: {}", e))?;
+    let file_name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default()
+        .to_string();
+
+    Ok(BackupInfo {
+        reason: BackupReason::from_file_name(&file_name),
+        checksum: fs::read_to_string(checksum_path(path))
+            .ok()
+            .and_then(|c| c.split_whitespace().next().map(str::to_string)),
+        path: path.to_string_lossy().to_string(),
+        size_bytes: metadata.len(),
+        created_at: metadata
+            .modified()
+            .map(|t| DateTime::<Utc>::from(t).to_rfc3339())
+            .unwrap_or_default(),
+        file_name,
+    })
+}
+
+fn is_backup_name(name: &str) -> bool {
+    name.starts_with(BACKUP_PREFIX) && name.ends_with(&format!(".{}", BACKUP_EXTENSION))
+}
+
+fn checksum_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", CHECKSUM_EXTENSION));
+    PathBuf::from(name)
+}
+
+fn file_checksum(path: &Path) -> Result<String, String> {
+    let mut file = fs::File::open(path).map_err(|e| format!("Failed to read backup: {}", e))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+
+    loop {
+        let read = file
+            .read(&mut buffer)
+            .map_err(|e| format!("Failed to read backup: {}", e))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
@@ -0,0 +1,276 @@
+use rusqlite::Connection;
+
+/// A table whose rows are uploaded to the backend
+#[derive(Debug)]
+pub struct SyncedTable {
+    pub name: &'static str,
+    /// Columns whose change makes the row pending. Sync bookkeeping
+    /// (`backend_id`, `sync_status`, `version`, `last_synced_at`), timestamps
+    /// and the derived `*_cents` columns are left out, so the triggers that
+    /// maintain those don't register as edits.
+    pub tracked_columns: &'static [&'static str],
+    /// Foreign keys to other synced tables, as (column, table). Sync sends
+    /// and receives these as the referenced row's `backend_id`.
+    pub references: &'static [(&'static str, &'static str)],
+    /// What wins when the backend changed a row that also has local edits
+    pub conflict_policy: ConflictPolicy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Catalog data is managed at HQ; its version replaces local edits
+    ServerWins,
+    /// Sales belong to the terminal that recorded them; local edits are
+    /// pushed over the backend's version
+    ClientWins,
+    /// Signed stock deltas: movements from either side are kept and applied
+    /// to `products.stock_quantity`. The same movement edited on both sides
+    /// needs a human decision.
+    MergeStock,
+}
+
+impl SyncedTable {
+    pub fn find(name: &str) -> Option<&'static SyncedTable> {
+        SYNCED_TABLES.iter().find(|t| t.name == name)
+    }
+}
+
+/// Tables captured by the change log. `transaction_items` has no sync
+/// columns; items are sent with their transaction.
+pub const SYNCED_TABLES: &[SyncedTable] = &[
+    SyncedTable {
+        name: "categories",
+        tracked_columns: &["name", "description", "is_active"],
+        references: &[],
+        conflict_policy: ConflictPolicy::ServerWins,
+    },
+    SyncedTable {
+        name: "products",
+        // stock_quantity is left out: every sale changes it, and stock is
+        // reconciled from stock_movements instead
+        tracked_columns: &[
+            "barcode",
+            "name",
+            "description",
+            "category_id",
+            "price",
+            "partner_price",
+            "vip_price",
+            "discount_percentage",
+            "cost",
+            "is_active",
+        ],
+        references: &[("category_id", "categories")],
+        conflict_policy: ConflictPolicy::ServerWins,
+    },
+    SyncedTable {
+        name: "customers",
+        tracked_columns: &[
+            "name",
+            "email",
+            "phone",
+            "address",
+            "nit",
+            "nrc",
+            "tax_id",
+            "taxpayer_type",
+            "is_company",
+            "is_active",
+        ],
+        references: &[],
+        conflict_policy: ConflictPolicy::ServerWins,
+    },
+    SyncedTable {
+        name: "transactions",
+        tracked_columns: &[
+            "transaction_number",
+            "customer_id",
+            "user_id",
+            "session_id",
+            "subtotal",
+            "tax_amount",
+            "discount_amount",
+            "total",
+            "status",
+            "notes",
+            "dte_id",
+            "original_transaction_id",
+            "return_type",
+        ],
+        references: &[
+            ("customer_id", "customers"),
+            ("session_id", "cash_register_sessions"),
+            ("original_transaction_id", "transactions"),
+        ],
+        conflict_policy: ConflictPolicy::ClientWins,
+    },
+    SyncedTable {
+        name: "payments",
+        tracked_columns: &[
+            "transaction_id",
+            "payment_method",
+            "amount",
+            "reference_number",
+            "card_last_four",
+            "notes",
+        ],
+        references: &[("transaction_id", "transactions")],
+        conflict_policy: ConflictPolicy::ClientWins,
+    },
+    SyncedTable {
+        name: "cash_register_sessions",
+        tracked_columns: &[
+            "user_id",
+            "opening_balance",
+            "closing_balance",
+            "expected_balance",
+            "difference",
+            "status",
+            "notes",
+            "opened_at",
+            "closed_at",
+        ],
+        references: &[],
+        conflict_policy: ConflictPolicy::ClientWins,
+    },
+    SyncedTable {
+        name: "cash_movements",
+        tracked_columns: &[
+            "session_id",
+            "type",
+            "amount",
+            "reason",
+            "performed_by",
+            "notes",
+        ],
+        references: &[("session_id", "cash_register_sessions")],
+        conflict_policy: ConflictPolicy::ClientWins,
+    },
+    SyncedTable {
+        name: "stock_movements",
+        tracked_columns: &[
+            "product_id",
+            "movement_type",
+            "quantity",
+            "reference_type",
+            "reference_id",
+            "unit_cost",
+            "notes",
+            "user_id",
+        ],
+        references: &[("product_id", "products")],
+        conflict_policy: ConflictPolicy::MergeStock,
+    },
+];
+
+const CREATE_CHANGE_LOG: &str = "
+CREATE TABLE change_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    table_name TEXT NOT NULL,
+    record_id INTEGER NOT NULL,
+    backend_id TEXT,
+    operation TEXT NOT NULL CHECK (operation IN ('insert', 'update', 'delete')),
+    version INTEGER,
+    changed_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    synced_at DATETIME
+);
+
+CREATE INDEX idx_change_log_pending ON change_log(synced_at, id);
+CREATE INDEX idx_change_log_record ON change_log(table_name, record_id);
+
+-- Set while the sync client writes rows received from the backend, so
+-- they are neither logged nor marked pending again
+CREATE TABLE sync_context (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    applying_remote INTEGER NOT NULL DEFAULT 0
+);
+INSERT INTO sync_context (id, applying_remote) VALUES (1, 0);
+";
+
+const NOT_APPLYING_REMOTE: &str = "(SELECT applying_remote FROM sync_context WHERE id = 1) = 0";
+
+/// SQL for the `change_log` migration: the log table plus insert, update
+/// and delete triggers on every synced table. Rows already pending from
+/// before the migration are logged as inserts.
+pub fn migration_up() -> String {
+    let mut sql = String::from(CREATE_CHANGE_LOG);
+
+    for table in SYNCED_TABLES {
+        let name = table.name;
+        let tracked = table.tracked_columns.join(", ");
+
+        sql.push_str(&format!(
+            "
+CREATE TRIGGER trg_{name}_change_log_insert AFTER INSERT ON {name}
+WHEN {NOT_APPLYING_REMOTE}
+BEGIN
+    UPDATE {name} SET sync_status = 'pending' WHERE id = NEW.id;
+    INSERT INTO change_log (table_name, record_id, backend_id, operation, version)
+    VALUES ('{name}', NEW.id, NEW.backend_id, 'insert', NEW.version);
+END;
+
+CREATE TRIGGER trg_{name}_change_log_update AFTER UPDATE OF {tracked} ON {name}
+WHEN {NOT_APPLYING_REMOTE}
+BEGIN
+    UPDATE {name} SET
+        version = MAX(COALESCE(NEW.version, 0), COALESCE(OLD.version, 0) + 1),
+        sync_status = 'pending'
+    WHERE id = NEW.id;
+    INSERT INTO change_log (table_name, record_id, backend_id, operation, version)
+    SELECT '{name}', id, backend_id, 'update', version FROM {name} WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_{name}_change_log_delete AFTER DELETE ON {name}
+WHEN {NOT_APPLYING_REMOTE}
+BEGIN
+    INSERT INTO change_log (table_name, record_id, backend_id, operation, version)
+    VALUES ('{name}', OLD.id, OLD.backend_id, 'delete', OLD.version);
+END;
+
+INSERT INTO change_log (table_name, record_id, backend_id, operation, version)
+SELECT '{name}', id, backend_id, 'insert', version FROM {name}
+WHERE sync_status = 'pending'
+ORDER BY id;
+"
+        ));
+    }
+
+    sql
+}
+
+pub fn migration_down() -> String {
+    let mut sql = String::new();
+    for table in SYNCED_TABLES {
+        for operation in ["insert", "update", "delete"] {
+            sql.push_str(&format!(
+                "DROP TRIGGER IF EXISTS trg_{}_change_log_{};\n",
+                table.name, operation
+            ));
+        }
+    }
+    sql.push_str("DROP TABLE IF EXISTS sync_context;\nDROP TABLE IF EXISTS change_log;\n");
+    sql
+}
+
+/// Suspend change capture while `apply` writes rows that came from the
+/// backend. Must run inside the caller's transaction so other connections
+/// never see the flag set.
+pub fn apply_remote<T>(
+    conn: &Connection,
+    apply: impl FnOnce() -> Result<T, String>,
+) -> Result<T, String> {
+    set_applying_remote(conn, true)?;
+    let result = apply();
+    let reset = set_applying_remote(conn, false);
+    let value = result?;
+    reset.map(|_| value)
+}
+
+fn set_applying_remote(conn: &Connection, applying: bool) -> Result<(), String> {
+    conn.execute(
+        "UPDATE sync_context SET applying_remote = ? WHERE id = 1",
+        [applying],
+    )
+    .map(|_| ())
+    .map_err(|e| format!("Failed to update sync context: {}", e))
+}
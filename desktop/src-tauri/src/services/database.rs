@@ -1,17 +1,186 @@
-use tauri_plugin_sql::{Migration, MigrationKind};
-
-/// Database migrations for the POS system
-pub fn get_migrations() -> Vec<Migration> {
-    vec![
-        // Migration 1: Create initial tables
-        Migration {
-            version: 1,
-            description: "create_initial_tables",
-            sql: include_str!("../../migrations/001_initial_tables.sql"),
-            kind: MigrationKind::Up,
-        },
-    ]
-}
-
-/// Database connection string for SQLite
-pub const DATABASE_URL: &str = "sqlite:pos_database.db";
+use std::path::PathBuf;
+
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use serde::Serialize;
+use tauri::Manager;
+
+use crate::services::{migrations, permissions};
+
+/// Database file name inside the app data directory
+pub const DATABASE_FILE: &str = "pos_database.db";
+
+/// Resolve the on-disk path of the POS database
+pub fn database_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let app_data_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data dir: {}", e))?;
+    Ok(app_data_dir.join(DATABASE_FILE))
+}
+
+/// Apply pending schema migrations. Called once from the setup hook,
+/// before any window can reach the database.
+pub fn run_migrations(app_handle: &tauri::AppHandle) -> Result<(), String> {
+    let db_path = database_path(app_handle)?;
+    if let Some(dir) = db_path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create app data dir: {}", e))?;
+    }
+
+    let conn = open_connection(app_handle)?;
+    migrations::migrate(&conn)
+}
+
+/// Open a read-write connection with the standard PRAGMAs applied
+pub fn open_connection(app_handle: &tauri::AppHandle) -> Result<Connection, String> {
+    let db_path = database_path(app_handle)?;
+
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open database: {}", e))?;
+
+    configure_pragmas(&conn)?;
+    Ok(conn)
+}
+
+/// Open a read-only connection.
+/// In WAL mode readers never take the write lock, so reports and product
+/// lists can run while a sale is being committed.
+pub fn open_read_only_connection(app_handle: &tauri::AppHandle) -> Result<Connection, String> {
+    let db_path = database_path(app_handle)?;
+
+    let conn = Connection::open_with_flags(
+        &db_path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY
+            | OpenFlags::SQLITE_OPEN_NO_MUTEX
+            | OpenFlags::SQLITE_OPEN_URI,
+    )
+    .map_err(|e| format!("Failed to open read-only database: {}", e))?;
+
+    conn.execute_batch(
+        "PRAGMA cache_size = -16000;
+         PRAGMA temp_store = MEMORY;
+         PRAGMA busy_timeout = 5000;
+         PRAGMA query_only = ON;",
+    )
+    .map_err(|e| format!("Failed to configure read-only PRAGMAs: {}", e))?;
+
+    Ok(conn)
+}
+
+/// Read a `system_settings` value, `None` when the key is not set
+pub fn get_setting(conn: &Connection, key: &str) -> Result<Option<String>, String> {
+    conn.query_row(
+        "SELECT value FROM system_settings WHERE key = ?",
+        [key],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| format!("Failed to read setting '{}': {}", key, e))
+}
+
+/// Insert or overwrite a `system_settings` value maintained by the app
+pub fn set_setting(
+    conn: &Connection,
+    key: &str,
+    value: &str,
+    category: &str,
+) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO system_settings (key, value, category, is_system) VALUES (?1, ?2, ?3, 1)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = CURRENT_TIMESTAMP",
+        [key, value, category],
+    )
+    .map(|_| ())
+    .map_err(|e| format!("Failed to save setting '{}': {}", key, e))
+}
+
+/// A `system_settings` row as the webviews see it
+#[derive(Debug, Serialize)]
+pub struct Setting {
+    pub key: String,
+    pub value: String,
+}
+
+/// Settings whose key starts with `prefix` (all when `None`), without the
+/// ones holding credentials
+pub fn list_settings(conn: &Connection, prefix: Option<&str>) -> Result<Vec<Setting>, String> {
+    let prefix = prefix.unwrap_or("");
+    let mut stmt = conn
+        .prepare(
+            "SELECT key, value FROM system_settings
+             WHERE substr(key, 1, length(?1)) = ?1 ORDER BY key",
+        )
+        .map_err(|e| format!("Failed to read settings: {}", e))?;
+    let rows = stmt
+        .query_map([prefix], |row| {
+            Ok(Setting {
+                key: row.get(0)?,
+                value: row.get(1)?,
+            })
+        })
+        .and_then(Iterator::collect::<Result<Vec<_>, _>>)
+        .map_err(|e| format!("Failed to read settings: {}", e))?;
+    Ok(rows
+        .into_iter()
+        .filter(|setting| !permissions::is_secret_setting(&setting.key))
+        .collect())
+}
+
+/// This installation's id, generated and stored on first use. Identifies
+/// the terminal to the sync backend and in PIN attempt tracking.
+pub fn terminal_id(conn: &Connection) -> Result<String, String> {
+    match get_setting(conn, "terminal_id")? {
+        Some(id) if !id.trim().is_empty() => Ok(id),
+        _ => {
+            let id = uuid::Uuid::new_v4().to_string();
+            set_setting(conn, "terminal_id", &id, "system")?;
+            log::info!("Generated terminal id {}", id);
+            Ok(id)
+        }
+    }
+}
+
+fn configure_pragmas(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "PRAGMA journal_mode = WAL;
+         PRAGMA synchronous = NORMAL;
+         PRAGMA foreign_keys = ON;
+         PRAGMA cache_size = -64000;
+         PRAGMA temp_store = MEMORY;
+         PRAGMA busy_timeout = 5000;
+         PRAGMA defer_foreign_keys = ON;",
+    )
+    .map_err(|e| format!("Failed to configure PRAGMAs: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_settings_by_prefix_without_secrets() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrations::migrate(&conn).unwrap();
+        conn.execute_batch("DELETE FROM system_settings").unwrap();
+        for (key, value) in [
+            ("settings.language", "\"es\""),
+            ("settings_other", "x"),
+            ("sync_api_token", "secret"),
+            ("tax_rate", "13.0"),
+        ] {
+            set_setting(&conn, key, value, "general").unwrap();
+        }
+
+        let keys = |prefix| -> Vec<String> {
+            list_settings(&conn, prefix)
+                .unwrap()
+                .into_iter()
+                .map(|s| s.key)
+                .collect()
+        };
+        assert_eq!(keys(Some("settings.")), ["settings.language"]);
+        assert_eq!(
+            keys(None),
+            ["settings.language", "settings_other", "tax_rate"]
+        );
+    }
+}
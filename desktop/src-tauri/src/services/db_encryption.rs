@@ -0,0 +1,819 @@
+use std::cell::Cell;
+use std::ffi::c_char;
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Once, RwLock};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use iota_stronghold::{KeyProvider, SnapshotPath, Stronghold};
+use rand::RngCore;
+use rusqlite::auto_extension::{init_auto_extension, register_auto_extension};
+use rusqlite::{Connection, OpenFlags, ffi};
+use serde::Serialize;
+use tauri::Manager;
+use zeroize::Zeroizing;
+
+use crate::services::database::DATABASE_FILE;
+use crate::services::vault_unlock::{self, Keyring};
+
+/// Stronghold snapshot holding the SQLCipher keys
+const KEY_VAULT_FILE: &str = "database-key.stronghold";
+/// Keyring entry holding the random snapshot password
+const VAULT_PASSWORD_ACCOUNT: &str = "database-key-vault";
+
+/// File holding a recovery key from `recovery_key`; when set and the vault
+/// can't be opened, it is rebuilt from the keys in the file
+const RECOVERY_KEY_ENV: &str = "POS_DB_RECOVERY_KEY";
+const RECOVERY_KEY_PREFIX: &str = "pos-db-recovery-1:";
+
+const KEYRING_HINT: &str = "Start the OS keyring service (on Linux, a Secret Service such as \
+    gnome-keyring), or set POS_KEYRING=file to keep the password in the app data directory";
+const RECOVERY_HINT: &str = "If the password is lost, restart with POS_DB_RECOVERY_KEY set to \
+    a file holding the recovery key exported with export_database_recovery_key";
+
+const STRONGHOLD_CLIENT: &[u8] = b"pos-database";
+const CURRENT_KEY_RECORD: &[u8] = b"sqlcipher-key";
+/// Keys replaced by a rekey, newest first, kept so older backups stay readable
+const PREVIOUS_KEYS_RECORD: &[u8] = b"sqlcipher-previous-keys";
+/// Key written before a rekey starts, promoted once it has committed
+const PENDING_KEY_RECORD: &[u8] = b"sqlcipher-pending-key";
+
+const KEY_LEN: usize = 32;
+const PLAINTEXT_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// Keys applied to every connection opened on the POS database
+static ACTIVE_KEYS: RwLock<Option<KeySet>> = RwLock::new(None);
+static REGISTER_AUTO_EXTENSION: Once = Once::new();
+
+thread_local! {
+    /// Set while converting a plaintext database, which must open unkeyed
+    static SKIP_AUTO_KEY: Cell<bool> = const { Cell::new(false) };
+}
+
+/// 256-bit raw SQLCipher key
+#[derive(Clone, PartialEq, Eq)]
+pub struct DatabaseKey(Zeroizing<Vec<u8>>);
+
+#[derive(Clone)]
+struct KeySet {
+    current: DatabaseKey,
+    previous: Vec<DatabaseKey>,
+    pending: Option<DatabaseKey>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptionStatus {
+    pub encrypted: bool,
+    pub cipher_version: Option<String>,
+    /// Older keys kept for restoring backups taken before a rekey
+    pub previous_keys: usize,
+}
+
+impl DatabaseKey {
+    fn generate() -> Self {
+        let mut bytes = Zeroizing::new(vec![0u8; KEY_LEN]);
+        rand::rngs::OsRng.fill_bytes(&mut bytes);
+        DatabaseKey(bytes)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() != KEY_LEN {
+            return Err("Stored database key has an invalid length".to_string());
+        }
+        Ok(DatabaseKey(Zeroizing::new(bytes.to_vec())))
+    }
+
+    /// Raw key literal (`x'..'`) understood by `PRAGMA key`; skips SQLCipher's
+    /// passphrase KDF, so opening a connection stays cheap
+    fn sql_literal(&self) -> Zeroizing<String> {
+        let hex: String = self.0.iter().map(|b| format!("{:02x}", b)).collect();
+        Zeroizing::new(format!("x'{}'", hex))
+    }
+}
+
+impl fmt::Debug for DatabaseKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DatabaseKey(..)")
+    }
+}
+
+/// Load or create the database key and encrypt a plaintext database in place.
+/// Runs in the setup hook before migrations, so every connection opened
+/// afterwards is keyed.
+pub fn initialize(app_handle: &tauri::AppHandle) -> Result<(), String> {
+    // Debug builds can keep a plaintext database for the sqlite3 CLI and seeding
+    #[cfg(debug_assertions)]
+    if std::env::var_os("POS_DB_PLAINTEXT").is_some() {
+        log::warn!("POS_DB_PLAINTEXT is set, database encryption disabled");
+        return Ok(());
+    }
+
+    let app_data_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data dir: {}", e))?;
+    fs::create_dir_all(&app_data_dir)
+        .map_err(|e| format!("Failed to create app data dir: {}", e))?;
+
+    let db_path = app_data_dir.join(DATABASE_FILE);
+    recover_interrupted_encryption(&db_path)?;
+
+    let keyring = vault_unlock::open_keyring(app_handle)?;
+    let vault = match KeyVault::open(&app_data_dir, keyring.as_ref()) {
+        Ok(vault) => vault,
+        Err(e) => match std::env::var_os(RECOVERY_KEY_ENV) {
+            Some(recovery_file) => {
+                log::warn!("{}; rebuilding it from {}", e, RECOVERY_KEY_ENV);
+                recover_vault(
+                    &app_data_dir,
+                    &db_path,
+                    keyring.as_ref(),
+                    Path::new(&recovery_file),
+                )?
+            }
+            None => return Err(e),
+        },
+    };
+    let keys = match vault.load()? {
+        Some(keys) => finish_interrupted_rekey(&vault, &db_path, keys)?,
+        None if db_path.exists() && !is_plaintext_file(&db_path) => {
+            return Err(format!(
+                "The database is encrypted but its key vault has no key. {}",
+                RECOVERY_HINT
+            ));
+        }
+        None => {
+            log::info!("Generating database encryption key");
+            let keys = KeySet {
+                current: DatabaseKey::generate(),
+                previous: Vec::new(),
+                pending: None,
+            };
+            vault.save(&keys)?;
+            keys
+        }
+    };
+
+    if is_plaintext_file(&db_path) {
+        encrypt_in_place(&db_path, &keys.current)?;
+    }
+
+    install(keys)
+}
+
+/// Whether connections to the POS database are being keyed
+pub fn is_enabled() -> bool {
+    ACTIVE_KEYS
+        .read()
+        .map(|keys| keys.is_some())
+        .unwrap_or(false)
+}
+
+pub fn status(conn: &Connection) -> Result<EncryptionStatus, String> {
+    let cipher_version = conn
+        .query_row("PRAGMA cipher_version", [], |row| row.get::<_, String>(0))
+        .ok();
+    let previous_keys = ACTIVE_KEYS
+        .read()
+        .map_err(|e| format!("Failed to read key state: {}", e))?
+        .as_ref()
+        .map(|keys| keys.previous.len())
+        .unwrap_or(0);
+
+    Ok(EncryptionStatus {
+        encrypted: is_enabled() && cipher_version.is_some(),
+        cipher_version,
+        previous_keys,
+    })
+}
+
+/// Re-encrypt the database under a fresh key.
+/// Runs on the writer connection. The new key is stored as pending before
+/// any page is rewritten, so a crash mid-rekey can still open the database.
+/// Other open connections keep the old key and must reconnect afterwards.
+pub fn rekey(app_handle: &tauri::AppHandle, conn: &Connection) -> Result<(), String> {
+    let app_data_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data dir: {}", e))?;
+    let keyring = vault_unlock::open_keyring(app_handle)?;
+    let vault = KeyVault::open(&app_data_dir, keyring.as_ref())?;
+
+    let mut keys = active_keys()?.ok_or("Database encryption is not initialized")?;
+    let new_key = DatabaseKey::generate();
+
+    keys.pending = Some(new_key.clone());
+    vault.save(&keys)?;
+
+    if let Err(e) = conn.execute_batch(&format!("PRAGMA rekey = \"{}\";", *new_key.sql_literal())) {
+        keys.pending = None;
+        if let Err(save_error) = vault.save(&keys) {
+            log::warn!("Failed to clear pending database key: {}", save_error);
+        }
+        return Err(format!("Failed to rekey database: {}", e));
+    }
+    conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")
+        .map_err(|e| format!("Failed to checkpoint after rekey: {}", e))?;
+
+    let old_key = std::mem::replace(&mut keys.current, new_key);
+    keys.previous.insert(0, old_key);
+    keys.pending = None;
+    vault.save(&keys)?;
+
+    install(keys)?;
+    log::info!("Database re-encrypted with a new key; export a new recovery key");
+    Ok(())
+}
+
+/// The current and previous keys as one line to keep offline. With
+/// `POS_DB_RECOVERY_KEY` pointing at it, startup rebuilds a vault whose
+/// password was lost, and backups stay readable on a new machine. A rekey
+/// makes it stale for later backups.
+pub fn recovery_key() -> Result<Zeroizing<String>, String> {
+    let keys = active_keys()?.ok_or("Database encryption is not initialized")?;
+    Ok(encode_recovery_key(&keys))
+}
+
+/// Open a database file other than the live one (backups, staged copies).
+/// New files are keyed with the current key; existing encrypted files are
+/// tried against the current and previous keys; plaintext files open as is.
+pub fn open_database_file(path: &Path, flags: OpenFlags) -> Result<Connection, String> {
+    let open = || {
+        Connection::open_with_flags(path, flags)
+            .map_err(|e| format!("Failed to open {:?}: {}", path, e))
+    };
+
+    let Some(keys) = active_keys()? else {
+        return open();
+    };
+
+    let exists = fs::metadata(path).is_ok_and(|m| m.len() > 0);
+    if exists && is_plaintext_file(path) {
+        return open();
+    }
+    if !exists {
+        let conn = open()?;
+        apply_key(&conn, &keys.current)?;
+        return Ok(conn);
+    }
+
+    for key in std::iter::once(&keys.current).chain(&keys.previous) {
+        let conn = open()?;
+        apply_key(&conn, key)?;
+        if can_read(&conn) {
+            return Ok(conn);
+        }
+    }
+
+    Err(format!(
+        "{:?} could not be decrypted with any known database key",
+        path
+    ))
+}
+
+/// Copy a plaintext database into a new file encrypted with the current key
+pub fn encrypted_copy(source: &Path, target: &Path) -> Result<(), String> {
+    let keys = active_keys()?.ok_or("Database encryption is not initialized")?;
+    export_encrypted(source, target, &keys.current)
+}
+
+/// SQLite files start with a fixed header; SQLCipher files look random
+pub fn is_plaintext_file(path: &Path) -> bool {
+    let mut header = [0u8; 16];
+    fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .is_ok_and(|_| &header == PLAINTEXT_HEADER)
+}
+
+fn install(keys: KeySet) -> Result<(), String> {
+    *ACTIVE_KEYS
+        .write()
+        .map_err(|e| format!("Failed to update key state: {}", e))? = Some(keys);
+
+    let mut result = Ok(());
+    REGISTER_AUTO_EXTENSION.call_once(|| {
+        // SAFETY: the handler only issues PRAGMA key on the new connection
+        result = unsafe { register_auto_extension(key_new_connection) }
+            .map_err(|e| format!("Failed to register database key handler: {}", e));
+    });
+    result
+}
+
+fn active_keys() -> Result<Option<KeySet>, String> {
+    ACTIVE_KEYS
+        .read()
+        .map(|keys| keys.clone())
+        .map_err(|e| format!("Failed to read key state: {}", e))
+}
+
+/// Called by SQLite for every new connection in the process, so every
+/// connection opened on the POS database is keyed without passing the key
+/// around.
+unsafe extern "C" fn key_new_connection(
+    db: *mut ffi::sqlite3,
+    pz_err_msg: *mut *mut c_char,
+    _api: *const ffi::sqlite3_api_routines,
+) -> std::ffi::c_int {
+    unsafe { init_auto_extension(db, pz_err_msg, key_if_pos_database) }
+}
+
+fn key_if_pos_database(conn: Connection) -> rusqlite::Result<()> {
+    if SKIP_AUTO_KEY.get() {
+        return Ok(());
+    }
+
+    // Matched by file name, so backups and other files opened with
+    // rusqlite are left alone
+    let is_pos_database = conn
+        .path()
+        .and_then(|p| Path::new(p).file_name())
+        .is_some_and(|name| name == DATABASE_FILE);
+    if !is_pos_database {
+        return Ok(());
+    }
+
+    let key = match ACTIVE_KEYS.read() {
+        Ok(keys) => keys.as_ref().map(|k| k.current.clone()),
+        Err(_) => None,
+    };
+    match key {
+        Some(key) => conn.execute_batch(&format!("PRAGMA key = \"{}\";", *key.sql_literal())),
+        None => Ok(()),
+    }
+}
+
+fn apply_key(conn: &Connection, key: &DatabaseKey) -> Result<(), String> {
+    conn.execute_batch(&format!("PRAGMA key = \"{}\";", *key.sql_literal()))
+        .map_err(|e| format!("Failed to apply database key: {}", e))
+}
+
+fn can_read(conn: &Connection) -> bool {
+    conn.query_row("SELECT count(*) FROM sqlite_master", [], |row| {
+        row.get::<_, i64>(0)
+    })
+    .is_ok()
+}
+
+fn open_unkeyed(path: &Path) -> Result<Connection, String> {
+    SKIP_AUTO_KEY.set(true);
+    let conn = Connection::open(path);
+    SKIP_AUTO_KEY.set(false);
+    conn.map_err(|e| format!("Failed to open {:?}: {}", path, e))
+}
+
+/// Export a plaintext database into a new encrypted file with
+/// `sqlcipher_export`, then check the result opens and is intact
+fn export_encrypted(source: &Path, target: &Path, key: &DatabaseKey) -> Result<(), String> {
+    let _ = fs::remove_file(target);
+
+    let conn = open_unkeyed(source)?;
+    conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")
+        .map_err(|e| format!("Failed to checkpoint plaintext database: {}", e))?;
+    let user_version: i64 = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(|e| format!("Failed to read user_version: {}", e))?;
+
+    conn.execute(
+        "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+        rusqlite::params![target.to_string_lossy(), key.sql_literal().as_str()],
+    )
+    .map_err(|e| format!("Failed to create encrypted database: {}", e))?;
+
+    let exported = conn
+        .query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))
+        .and_then(|_| {
+            conn.execute_batch(&format!(
+                "PRAGMA encrypted.user_version = {};",
+                user_version
+            ))
+        })
+        .map_err(|e| format!("Failed to export encrypted database: {}", e));
+    let _ = conn.execute_batch("DETACH DATABASE encrypted;");
+    drop(conn);
+
+    if let Err(e) = exported {
+        let _ = fs::remove_file(target);
+        return Err(e);
+    }
+
+    let check = Connection::open_with_flags(target, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Failed to open encrypted database: {}", e))?;
+    apply_key(&check, key)?;
+    let integrity: String = check
+        .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+        .map_err(|e| format!("Encrypted database is unreadable: {}", e))?;
+    if integrity != "ok" {
+        let _ = fs::remove_file(target);
+        return Err(format!(
+            "Encrypted database failed integrity check: {}",
+            integrity
+        ));
+    }
+
+    Ok(())
+}
+
+/// Swap a plaintext database for an encrypted copy.
+/// The plaintext file is kept as `.plaintext` until the encrypted one is in
+/// place, so [`recover_interrupted_encryption`] can finish or undo a crash.
+fn encrypt_in_place(db_path: &Path, key: &DatabaseKey) -> Result<(), String> {
+    log::info!("Encrypting existing plaintext database");
+    let encrypting = sibling(db_path, "encrypting");
+    let plaintext = sibling(db_path, "plaintext");
+
+    export_encrypted(db_path, &encrypting, key)?;
+
+    fs::rename(db_path, &plaintext)
+        .map_err(|e| format!("Failed to move plaintext database aside: {}", e))?;
+    for suffix in ["-wal", "-shm"] {
+        let _ = fs::remove_file(with_suffix(db_path, suffix));
+    }
+    fs::rename(&encrypting, db_path)
+        .map_err(|e| format!("Failed to move encrypted database into place: {}", e))?;
+    fs::remove_file(&plaintext)
+        .map_err(|e| format!("Failed to remove plaintext database: {}", e))?;
+
+    log::info!("Database encrypted");
+    Ok(())
+}
+
+fn recover_interrupted_encryption(db_path: &Path) -> Result<(), String> {
+    let plaintext = sibling(db_path, "plaintext");
+    let _ = fs::remove_file(sibling(db_path, "encrypting"));
+
+    if !plaintext.exists() {
+        return Ok(());
+    }
+
+    if db_path.exists() && !is_plaintext_file(db_path) {
+        log::info!("Removing plaintext copy left by an interrupted encryption");
+        fs::remove_file(&plaintext)
+            .map_err(|e| format!("Failed to remove plaintext database: {}", e))
+    } else {
+        log::warn!("Restoring plaintext database after an interrupted encryption");
+        fs::rename(&plaintext, db_path)
+            .map_err(|e| format!("Failed to restore plaintext database: {}", e))
+    }
+}
+
+/// A pending key means the app stopped during a rekey. Whichever key opens
+/// the database becomes current.
+fn finish_interrupted_rekey(
+    vault: &KeyVault,
+    db_path: &Path,
+    mut keys: KeySet,
+) -> Result<KeySet, String> {
+    let Some(pending) = keys.pending.take() else {
+        return Ok(keys);
+    };
+
+    if db_path.exists() && !is_plaintext_file(db_path) && !opens_with(db_path, &keys.current) {
+        if !opens_with(db_path, &pending) {
+            return Err("Database cannot be opened with the stored keys".to_string());
+        }
+        log::warn!("Completing interrupted database rekey");
+        let old_key = std::mem::replace(&mut keys.current, pending);
+        keys.previous.insert(0, old_key);
+    } else {
+        log::warn!("Discarding key from an interrupted database rekey");
+    }
+
+    vault.save(&keys)?;
+    Ok(keys)
+}
+
+fn opens_with(db_path: &Path, key: &DatabaseKey) -> bool {
+    open_unkeyed(db_path)
+        .and_then(|conn| apply_key(&conn, key).map(|_| conn))
+        .is_ok_and(|conn| can_read(&conn))
+}
+
+fn encode_recovery_key(keys: &KeySet) -> Zeroizing<String> {
+    let bytes: Zeroizing<Vec<u8>> = Zeroizing::new(
+        std::iter::once(&keys.current)
+            .chain(&keys.previous)
+            .flat_map(|k| k.0.iter().copied())
+            .collect(),
+    );
+    Zeroizing::new(format!("{}{}", RECOVERY_KEY_PREFIX, BASE64.encode(&*bytes)))
+}
+
+/// Keys from a recovery key, newest first
+fn decode_recovery_key(recovery_key: &str) -> Result<Vec<DatabaseKey>, String> {
+    let invalid = || "Not a database recovery key".to_string();
+    let encoded = recovery_key
+        .trim()
+        .strip_prefix(RECOVERY_KEY_PREFIX)
+        .ok_or_else(invalid)?;
+    let bytes = Zeroizing::new(BASE64.decode(encoded).map_err(|_| invalid())?);
+    if bytes.is_empty() || bytes.len() % KEY_LEN != 0 {
+        return Err(invalid());
+    }
+    bytes.chunks(KEY_LEN).map(DatabaseKey::from_bytes).collect()
+}
+
+/// Replace a vault that can't be opened with one holding the keys from
+/// `recovery_file`. The key that opens the database becomes current; the
+/// old snapshot is kept as `.lost` and a new password goes into the keyring.
+fn recover_vault(
+    app_data_dir: &Path,
+    db_path: &Path,
+    keyring: &dyn Keyring,
+    recovery_file: &Path,
+) -> Result<KeyVault, String> {
+    let recovery_key = Zeroizing::new(
+        fs::read_to_string(recovery_file)
+            .map_err(|e| format!("Failed to read {:?}: {}", recovery_file, e))?,
+    );
+    let mut keys = decode_recovery_key(&recovery_key)?;
+    let current = if db_path.exists() && !is_plaintext_file(db_path) {
+        let position = keys
+            .iter()
+            .position(|key| opens_with(db_path, key))
+            .ok_or_else(|| {
+                format!(
+                    "The recovery key in {:?} doesn't open the database",
+                    recovery_file
+                )
+            })?;
+        keys.remove(position)
+    } else {
+        keys.remove(0)
+    };
+
+    let snapshot_file = app_data_dir.join(KEY_VAULT_FILE);
+    if snapshot_file.exists() {
+        fs::rename(&snapshot_file, with_suffix(&snapshot_file, ".lost"))
+            .map_err(|e| format!("Failed to set aside {:?}: {}", snapshot_file, e))?;
+    }
+    keyring.delete(VAULT_PASSWORD_ACCOUNT).map_err(|e| {
+        format!(
+            "Can't reach the {} keyring: {}. {}",
+            keyring.backend(),
+            e,
+            KEYRING_HINT
+        )
+    })?;
+
+    let vault = KeyVault::open(app_data_dir, keyring)?;
+    vault.save(&KeySet {
+        current,
+        previous: keys,
+        pending: None,
+    })?;
+    log::warn!(
+        "Database key vault rebuilt from the recovery key; unset {}",
+        RECOVERY_KEY_ENV
+    );
+    Ok(vault)
+}
+
+fn sibling(db_path: &Path, extension: &str) -> PathBuf {
+    with_suffix(db_path, &format!(".{}", extension))
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Stronghold snapshot holding the current, previous and pending keys.
+/// Loading or committing a snapshot takes a couple of seconds (Stronghold's
+/// work factor), so it is only touched at startup and on rekey.
+struct KeyVault {
+    stronghold: Stronghold,
+    snapshot_path: SnapshotPath,
+    keyprovider: KeyProvider,
+}
+
+impl KeyVault {
+    fn open(app_data_dir: &Path, keyring: &dyn Keyring) -> Result<Self, String> {
+        let snapshot_file = app_data_dir.join(KEY_VAULT_FILE);
+        let password = read_or_create_vault_password(keyring, snapshot_file.exists())?;
+        let keyprovider = KeyProvider::try_from(password)
+            .map_err(|e| format!("Invalid key vault password: {:?}", e))?;
+
+        let snapshot_path = SnapshotPath::from_path(&snapshot_file);
+        let stronghold = Stronghold::default();
+
+        if snapshot_file.exists() {
+            stronghold
+                .load_client_from_snapshot(STRONGHOLD_CLIENT, &keyprovider, &snapshot_path)
+                .map_err(|e| {
+                    format!(
+                        "Failed to unlock the database key vault with the password in the {} \
+                         keyring: {}. {}",
+                        keyring.backend(),
+                        e,
+                        RECOVERY_HINT
+                    )
+                })?;
+        } else {
+            stronghold
+                .create_client(STRONGHOLD_CLIENT)
+                .map_err(|e| format!("Failed to create database key vault: {}", e))?;
+        }
+
+        Ok(Self {
+            stronghold,
+            snapshot_path,
+            keyprovider,
+        })
+    }
+
+    fn load(&self) -> Result<Option<KeySet>, String> {
+        let store = self.store()?;
+        let get = |record: &[u8]| {
+            store
+                .get(record)
+                .map_err(|e| format!("Failed to read database key: {}", e))
+        };
+
+        let Some(current) = get(CURRENT_KEY_RECORD)? else {
+            return Ok(None);
+        };
+        let previous = get(PREVIOUS_KEYS_RECORD)?
+            .unwrap_or_default()
+            .chunks(KEY_LEN)
+            .map(DatabaseKey::from_bytes)
+            .collect::<Result<Vec<_>, _>>()?;
+        let pending = get(PENDING_KEY_RECORD)?
+            .map(|bytes| DatabaseKey::from_bytes(&bytes))
+            .transpose()?;
+
+        Ok(Some(KeySet {
+            current: DatabaseKey::from_bytes(&current)?,
+            previous,
+            pending,
+        }))
+    }
+
+    fn save(&self, keys: &KeySet) -> Result<(), String> {
+        let store = self.store()?;
+        let insert = |record: &[u8], value: Vec<u8>| {
+            store
+                .insert(record.to_vec(), value, None)
+                .map(|_| ())
+                .map_err(|e| format!("Failed to store database key: {}", e))
+        };
+
+        insert(CURRENT_KEY_RECORD, keys.current.0.to_vec())?;
+        insert(
+            PREVIOUS_KEYS_RECORD,
+            keys.previous
+                .iter()
+                .flat_map(|k| k.0.iter().copied())
+                .collect(),
+        )?;
+        match &keys.pending {
+            Some(pending) => insert(PENDING_KEY_RECORD, pending.0.to_vec())?,
+            None => {
+                store
+                    .delete(PENDING_KEY_RECORD)
+                    .map_err(|e| format!("Failed to store database key: {}", e))?;
+            }
+        }
+
+        self.stronghold
+            .commit_with_keyprovider(&self.snapshot_path, &self.keyprovider)
+            .map_err(|e| format!("Failed to save database key vault: {}", e))
+    }
+
+    fn store(&self) -> Result<iota_stronghold::Store, String> {
+        self.stronghold
+            .get_client(STRONGHOLD_CLIENT)
+            .map(|client| client.store())
+            .map_err(|e| format!("Failed to open database key vault: {}", e))
+    }
+}
+
+/// The snapshot password from the keyring, created on first run. An
+/// existing vault without one can't be opened, so that is an error rather
+/// than a new password.
+fn read_or_create_vault_password(
+    keyring: &dyn Keyring,
+    vault_exists: bool,
+) -> Result<Zeroizing<Vec<u8>>, String> {
+    let keyring_error = |e: String| {
+        format!(
+            "Can't keep the database key vault password in the {} keyring: {}. {}",
+            keyring.backend(),
+            e,
+            KEYRING_HINT
+        )
+    };
+
+    if let Some(stored) = keyring.get(VAULT_PASSWORD_ACCOUNT).map_err(keyring_error)? {
+        return decode_vault_password(&stored);
+    }
+    if vault_exists {
+        return Err(format!(
+            "The database key vault password is missing from the {} keyring. {}",
+            keyring.backend(),
+            RECOVERY_HINT
+        ));
+    }
+
+    let mut password = Zeroizing::new(vec![0u8; KEY_LEN]);
+    rand::rngs::OsRng.fill_bytes(&mut password);
+    let encoded = Zeroizing::new(BASE64.encode(&*password));
+    keyring
+        .set(VAULT_PASSWORD_ACCOUNT, &encoded)
+        .map_err(keyring_error)?;
+    let stored = keyring
+        .get(VAULT_PASSWORD_ACCOUNT)
+        .map_err(keyring_error)?
+        .ok_or_else(|| keyring_error("the password was not kept".to_string()))?;
+    if decode_vault_password(&stored)? != password {
+        return Err(keyring_error("the password read back differs".to_string()));
+    }
+    Ok(password)
+}
+
+fn decode_vault_password(stored: &str) -> Result<Zeroizing<Vec<u8>>, String> {
+    match BASE64.decode(stored.trim()) {
+        Ok(bytes) if bytes.len() == KEY_LEN => Ok(Zeroizing::new(bytes)),
+        _ => Err("Database key vault password in the keyring is corrupted".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct MemoryKeyring(Mutex<HashMap<String, String>>);
+
+    impl Keyring for MemoryKeyring {
+        fn backend(&self) -> &'static str {
+            "memory"
+        }
+
+        fn get(&self, account: &str) -> Result<Option<Zeroizing<String>>, String> {
+            Ok(self
+                .0
+                .lock()
+                .unwrap()
+                .get(account)
+                .cloned()
+                .map(Zeroizing::new))
+        }
+
+        fn set(&self, account: &str, secret: &str) -> Result<(), String> {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(account.to_string(), secret.to_string());
+            Ok(())
+        }
+
+        fn delete(&self, account: &str) -> Result<bool, String> {
+            Ok(self.0.lock().unwrap().remove(account).is_some())
+        }
+    }
+
+    #[test]
+    fn creates_the_vault_password_in_the_keyring() {
+        let keyring = MemoryKeyring::default();
+
+        let password = read_or_create_vault_password(&keyring, false).unwrap();
+        assert_eq!(password.len(), KEY_LEN);
+        assert_eq!(
+            read_or_create_vault_password(&keyring, true).unwrap(),
+            password
+        );
+    }
+
+    #[test]
+    fn refuses_a_missing_or_corrupted_password() {
+        let keyring = MemoryKeyring::default();
+        let missing = read_or_create_vault_password(&keyring, true).unwrap_err();
+        assert!(missing.contains(RECOVERY_KEY_ENV), "{}", missing);
+        assert!(keyring.get(VAULT_PASSWORD_ACCOUNT).unwrap().is_none());
+
+        keyring.set(VAULT_PASSWORD_ACCOUNT, "not base64!").unwrap();
+        assert!(read_or_create_vault_password(&keyring, true).is_err());
+    }
+
+    #[test]
+    fn recovery_key_round_trips() {
+        let keys = KeySet {
+            current: DatabaseKey::generate(),
+            previous: vec![DatabaseKey::generate()],
+            pending: Some(DatabaseKey::generate()),
+        };
+
+        let recovered = decode_recovery_key(&encode_recovery_key(&keys)).unwrap();
+        assert_eq!(recovered, [keys.current, keys.previous[0].clone()]);
+        assert!(decode_recovery_key("pos-db-recovery-1:AAAA").is_err());
+        assert!(decode_recovery_key(&BASE64.encode([0u8; KEY_LEN])).is_err());
+    }
+}
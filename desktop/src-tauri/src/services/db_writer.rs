@@ -0,0 +1,360 @@
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::{Duration, Instant};
+
+use rusqlite::{Connection, ErrorCode};
+use serde::Serialize;
+
+use crate::services::database::open_connection;
+
+/// Attempts for a statement when another connection holds the lock.
+/// Each attempt already waits up to `busy_timeout` inside SQLite.
+const MAX_BUSY_ATTEMPTS: u32 = 4;
+const BUSY_BACKOFF_BASE: Duration = Duration::from_millis(50);
+
+/// Queue waits above this are logged so slow checkouts can be traced
+const SLOW_WAIT_THRESHOLD: Duration = Duration::from_millis(500);
+
+type WriteJob = Box<dyn FnOnce(&WriteConnection<'_>) + Send>;
+
+/// Single writer for the POS database.
+/// Every sale window's write goes through one queue and one connection, so
+/// they never race each other for `BEGIN IMMEDIATE`.
+pub struct DbWriter {
+    sender: Mutex<Option<mpsc::Sender<WriteJob>>>,
+    metrics: Arc<WriterMetrics>,
+}
+
+/// Connection handed to write jobs; lock acquisition retries on BUSY/LOCKED
+pub struct WriteConnection<'a> {
+    conn: &'a Connection,
+    metrics: &'a WriterMetrics,
+}
+
+#[derive(Debug, Default)]
+pub struct WriterMetrics {
+    jobs: AtomicU64,
+    queue_depth: AtomicU64,
+    total_wait_us: AtomicU64,
+    max_wait_us: AtomicU64,
+    last_wait_us: AtomicU64,
+    busy_retries: AtomicU64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WriterMetricsSnapshot {
+    pub jobs: u64,
+    pub queue_depth: u64,
+    pub avg_wait_ms: f64,
+    pub max_wait_ms: f64,
+    pub last_wait_ms: f64,
+    pub busy_retries: u64,
+}
+
+impl DbWriter {
+    pub fn new() -> Self {
+        Self {
+            sender: Mutex::new(None),
+            metrics: Arc::new(WriterMetrics::default()),
+        }
+    }
+
+    /// Queue a write job and wait for its result
+    pub async fn execute<T, F>(&self, app_handle: &tauri::AppHandle, job: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&WriteConnection<'_>) -> Result<T, String> + Send + 'static,
+    {
+        let sender = self.sender(app_handle)?;
+        let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+        let enqueued_at = Instant::now();
+        let metrics = self.metrics.clone();
+
+        metrics.queue_depth.fetch_add(1, Ordering::Relaxed);
+
+        let queued: WriteJob = Box::new(move |conn| {
+            metrics.queue_depth.fetch_sub(1, Ordering::Relaxed);
+            metrics.record_wait(enqueued_at.elapsed());
+            let _ = reply_tx.send(job(conn));
+        });
+
+        if sender.send(queued).is_err() {
+            self.metrics.queue_depth.fetch_sub(1, Ordering::Relaxed);
+            self.reset_sender();
+            return Err("Database writer stopped, please retry".to_string());
+        }
+
+        reply_rx
+            .await
+            .map_err(|_| "Database writer dropped the request".to_string())?
+    }
+
+    pub fn metrics(&self) -> WriterMetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// Start the writer thread on first use
+    fn sender(&self, app_handle: &tauri::AppHandle) -> Result<mpsc::Sender<WriteJob>, String> {
+        let mut guard = self
+            .sender
+            .lock()
+            .map_err(|e| format!("Failed to acquire writer lock: {}", e))?;
+
+        if let Some(sender) = guard.as_ref() {
+            return Ok(sender.clone());
+        }
+
+        let conn = open_connection(app_handle)?;
+        let (sender, receiver) = mpsc::channel::<WriteJob>();
+        let metrics = self.metrics.clone();
+
+        std::thread::Builder::new()
+            .name("db-writer".to_string())
+            .spawn(move || {
+                log::info!("Database writer started");
+                for job in receiver {
+                    job(&WriteConnection {
+                        conn: &conn,
+                        metrics: &metrics,
+                    });
+                }
+                log::info!("Database writer stopped");
+            })
+            .map_err(|e| format!("Failed to start database writer: {}", e))?;
+
+        *guard = Some(sender.clone());
+        Ok(sender)
+    }
+
+    /// Drop the writer connection so the next job opens a fresh one, e.g.
+    /// after a restore or rekey replaced what it had open. Jobs already
+    /// queued still run on the old connection.
+    pub fn reset_sender(&self) {
+        if let Ok(mut guard) = self.sender.lock() {
+            *guard = None;
+        }
+    }
+}
+
+impl Default for DbWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WriteConnection<'_> {
+    /// `BEGIN IMMEDIATE`, retried with backoff while another connection
+    /// (e.g. a backup or maintenance run) holds the write lock
+    pub fn begin_immediate(&self) -> Result<(), String> {
+        self.with_busy_retry("begin transaction", || {
+            self.conn.execute_batch("BEGIN IMMEDIATE")
+        })
+    }
+
+    pub fn commit(&self) -> Result<(), String> {
+        self.with_busy_retry("commit", || self.conn.execute_batch("COMMIT"))
+    }
+
+    pub fn rollback(&self) {
+        if let Err(e) = self.conn.execute_batch("ROLLBACK") {
+            log::warn!("Rollback failed: {}", e);
+        }
+    }
+
+    /// Run `job` in an immediate transaction, rolled back if it fails
+    pub fn in_transaction<T>(
+        &self,
+        job: impl FnOnce(&Self) -> Result<T, String>,
+    ) -> Result<T, String> {
+        self.begin_immediate()?;
+
+        let value = match job(self) {
+            Ok(value) => value,
+            Err(e) => {
+                self.rollback();
+                return Err(e);
+            }
+        };
+
+        if let Err(e) = self.commit() {
+            self.rollback();
+            return Err(e);
+        }
+        Ok(value)
+    }
+
+    fn with_busy_retry(
+        &self,
+        action: &str,
+        op: impl FnMut() -> rusqlite::Result<()>,
+    ) -> Result<(), String> {
+        retry_on_busy(
+            action,
+            || {
+                self.metrics.busy_retries.fetch_add(1, Ordering::Relaxed);
+            },
+            op,
+        )
+        .map_err(|e| {
+            if is_busy(&e) {
+                format!(
+                    "Database is busy, failed to {} after {} attempts",
+                    action, MAX_BUSY_ATTEMPTS
+                )
+            } else {
+                format!("Failed to {}: {}", action, e)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+impl<'a> WriteConnection<'a> {
+    /// A writer connection over `conn`, for running write jobs in tests
+    pub fn for_tests(conn: &'a Connection) -> Self {
+        static METRICS: std::sync::LazyLock<WriterMetrics> =
+            std::sync::LazyLock::new(WriterMetrics::default);
+        Self {
+            conn,
+            metrics: &METRICS,
+        }
+    }
+}
+
+impl Deref for WriteConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn
+    }
+}
+
+impl WriterMetrics {
+    fn record_wait(&self, wait: Duration) {
+        let wait_us = wait.as_micros() as u64;
+        self.jobs.fetch_add(1, Ordering::Relaxed);
+        self.total_wait_us.fetch_add(wait_us, Ordering::Relaxed);
+        self.max_wait_us.fetch_max(wait_us, Ordering::Relaxed);
+        self.last_wait_us.store(wait_us, Ordering::Relaxed);
+
+        if wait > SLOW_WAIT_THRESHOLD {
+            log::warn!("Database write waited {:?} in the writer queue", wait);
+        }
+    }
+
+    fn snapshot(&self) -> WriterMetricsSnapshot {
+        let jobs = self.jobs.load(Ordering::Relaxed);
+        let total_wait_us = self.total_wait_us.load(Ordering::Relaxed);
+
+        WriterMetricsSnapshot {
+            jobs,
+            queue_depth: self.queue_depth.load(Ordering::Relaxed),
+            avg_wait_ms: if jobs == 0 {
+                0.0
+            } else {
+                total_wait_us as f64 / jobs as f64 / 1000.0
+            },
+            max_wait_ms: self.max_wait_us.load(Ordering::Relaxed) as f64 / 1000.0,
+            last_wait_ms: self.last_wait_us.load(Ordering::Relaxed) as f64 / 1000.0,
+            busy_retries: self.busy_retries.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Run `op`, retrying with backoff while another connection holds the lock.
+/// A statement that fails with BUSY has made no change, so it is safe to run
+/// again, also inside a transaction. `on_retry` is called before each wait.
+pub fn retry_on_busy<T>(
+    action: &str,
+    mut on_retry: impl FnMut(),
+    mut op: impl FnMut() -> rusqlite::Result<T>,
+) -> rusqlite::Result<T> {
+    let mut attempt = 1;
+    loop {
+        match op() {
+            Err(e) if is_busy(&e) && attempt < MAX_BUSY_ATTEMPTS => {
+                let backoff = BUSY_BACKOFF_BASE * 2u32.pow(attempt - 1);
+                log::warn!(
+                    "Database busy on {} (attempt {}/{}), retrying in {:?}",
+                    action,
+                    attempt,
+                    MAX_BUSY_ATTEMPTS,
+                    backoff
+                );
+                on_retry();
+                std::thread::sleep(backoff);
+                attempt += 1;
+            }
+            Err(e) if is_busy(&e) => {
+                log::error!("Database still busy after {} attempts: {}", attempt, e);
+                return Err(e);
+            }
+            result => return result,
+        }
+    }
+}
+
+fn is_busy(error: &rusqlite::Error) -> bool {
+    matches!(
+        error,
+        rusqlite::Error::SqliteFailure(e, _)
+            if matches!(e.code, ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn busy() -> rusqlite::Error {
+        rusqlite::Error::SqliteFailure(rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY), None)
+    }
+
+    #[test]
+    fn retries_busy_statements() {
+        let mut calls = 0;
+        let mut retries = 0;
+        let result = retry_on_busy(
+            "test",
+            || retries += 1,
+            || {
+                calls += 1;
+                if calls < 3 { Err(busy()) } else { Ok(calls) }
+            },
+        );
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(retries, 2);
+    }
+
+    #[test]
+    fn gives_up_when_still_busy() {
+        let mut calls = 0;
+        let result: rusqlite::Result<()> = retry_on_busy(
+            "test",
+            || (),
+            || {
+                calls += 1;
+                Err(busy())
+            },
+        );
+        assert!(is_busy(&result.unwrap_err()));
+        assert_eq!(calls, MAX_BUSY_ATTEMPTS);
+    }
+
+    #[test]
+    fn does_not_retry_other_errors() {
+        let mut calls = 0;
+        let result: rusqlite::Result<()> = retry_on_busy(
+            "test",
+            || (),
+            || {
+                calls += 1;
+                Err(rusqlite::Error::QueryReturnedNoRows)
+            },
+        );
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+}
@@ -0,0 +1,343 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::Utc;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+
+use crate::services::database::{get_setting, open_read_only_connection};
+use crate::services::db_writer::{DbWriter, WriteConnection};
+
+/// Last report, also used as the scheduler's "last run" marker
+const REPORT_FILE: &str = "maintenance-report.json";
+
+const DEFAULT_INTERVAL_HOURS: u64 = 24;
+const SCHEDULER_TICK: Duration = Duration::from_secs(15 * 60);
+
+/// `integrity_check` stops after this many problems
+const MAX_INTEGRITY_ERRORS: usize = 100;
+
+/// Outcome of one maintenance run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MaintenanceReport {
+    pub started_at: String,
+    pub duration_ms: u64,
+    /// False when integrity or foreign key checks found problems, or a step failed
+    pub healthy: bool,
+    pub integrity_problems: Vec<String>,
+    pub foreign_key_violations: Vec<ForeignKeyViolation>,
+    pub checkpoint: Option<CheckpointResult>,
+    pub analyzed: bool,
+    pub vacuum: Option<VacuumResult>,
+    /// Steps that could not run, with the reason
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForeignKeyViolation {
+    pub table: String,
+    pub rowid: Option<i64>,
+    pub parent: String,
+    pub constraint_index: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckpointResult {
+    /// A reader kept the WAL from being fully reset
+    pub busy: bool,
+    pub wal_frames: i64,
+    pub checkpointed_frames: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VacuumResult {
+    pub free_pages_before: i64,
+    pub free_pages_after: i64,
+    /// The database was switched to incremental auto-vacuum with a full VACUUM
+    pub converted_to_incremental: bool,
+    /// The switch is still to do; only a manual run does the full VACUUM
+    #[serde(default)]
+    pub conversion_pending: bool,
+}
+
+/// Run integrity and foreign key checks, then checkpoint, ANALYZE and an
+/// incremental VACUUM. The checks use a read-only connection so sales keep
+/// flowing; the rest runs on the writer. Nothing is rewritten when the
+/// checks find corruption. The one-time full VACUUM that switches an old
+/// database to incremental auto-vacuum rewrites the whole file and blocks
+/// sales meanwhile, so it only runs with `allow_full_vacuum`, from the
+/// explicit command.
+pub async fn run_maintenance(
+    app_handle: &tauri::AppHandle,
+    writer: &DbWriter,
+    allow_full_vacuum: bool,
+) -> Result<MaintenanceReport, String> {
+    let started = Instant::now();
+    let started_at = Utc::now().to_rfc3339();
+
+    let handle = app_handle.clone();
+    let (integrity_problems, foreign_key_violations) =
+        tokio::task::spawn_blocking(move || -> Result<_, String> {
+            let conn = open_read_only_connection(&handle)?;
+            Ok((integrity_check(&conn)?, foreign_key_check(&conn)?))
+        })
+        .await
+        .map_err(|e| format!("Maintenance task failed: {}", e))??;
+
+    let mut report = MaintenanceReport {
+        started_at,
+        duration_ms: 0,
+        healthy: integrity_problems.is_empty() && foreign_key_violations.is_empty(),
+        integrity_problems,
+        foreign_key_violations,
+        checkpoint: None,
+        analyzed: false,
+        vacuum: None,
+        errors: Vec::new(),
+    };
+
+    if report.integrity_problems.is_empty() {
+        report = writer
+            .execute(app_handle, move |conn| {
+                Ok(optimize(conn, report, allow_full_vacuum))
+            })
+            .await?;
+    } else {
+        report
+            .errors
+            .push("Skipped checkpoint, ANALYZE and VACUUM: integrity check failed".to_string());
+    }
+
+    report.healthy &= report.errors.is_empty();
+    report.duration_ms = started.elapsed().as_millis() as u64;
+
+    if report.healthy {
+        log::info!(
+            "Database maintenance completed in {} ms",
+            report.duration_ms
+        );
+    } else {
+        log::error!(
+            "Database maintenance found problems: {} integrity, {} foreign key, errors: {:?}",
+            report.integrity_problems.len(),
+            report.foreign_key_violations.len(),
+            report.errors
+        );
+        if let Err(e) = app_handle.emit_to("main", "database-maintenance-failed", &report) {
+            log::warn!("Failed to emit database-maintenance-failed: {}", e);
+        }
+    }
+
+    if let Err(e) = save_report(app_handle, &report) {
+        log::warn!("Failed to save maintenance report: {}", e);
+    }
+
+    Ok(report)
+}
+
+/// Report from the most recent run, if any
+pub fn last_report(app_handle: &tauri::AppHandle) -> Result<Option<MaintenanceReport>, String> {
+    let path = report_path(app_handle)?;
+    match fs::read_to_string(&path) {
+        Ok(json) => serde_json::from_str(&json)
+            .map(Some)
+            .map_err(|e| format!("Failed to read maintenance report: {}", e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Failed to read maintenance report: {}", e)),
+    }
+}
+
+/// Run maintenance every `maintenance_interval_hours` (0 disables it).
+/// Waits one tick after startup so it doesn't compete with opening the POS.
+pub async fn run_scheduler(app_handle: tauri::AppHandle) {
+    loop {
+        tokio::time::sleep(SCHEDULER_TICK).await;
+
+        let handle = app_handle.clone();
+        match tokio::task::spawn_blocking(move || is_due(&handle)).await {
+            Ok(Ok(true)) => {}
+            Ok(Ok(false)) => continue,
+            Ok(Err(e)) => {
+                log::warn!("Failed to check maintenance schedule: {}", e);
+                continue;
+            }
+            Err(e) => {
+                log::error!("Maintenance schedule task panicked: {}", e);
+                continue;
+            }
+        }
+
+        let writer = app_handle.state::<DbWriter>();
+        if let Err(e) = run_maintenance(&app_handle, &writer, false).await {
+            log::error!("Scheduled database maintenance failed: {}", e);
+            let _ = app_handle.emit_to("main", "database-maintenance-failed", &e);
+        }
+    }
+}
+
+fn is_due(app_handle: &tauri::AppHandle) -> Result<bool, String> {
+    let conn = open_read_only_connection(app_handle)?;
+    let interval_hours = get_setting(&conn, "maintenance_interval_hours")?
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_INTERVAL_HOURS);
+    if interval_hours == 0 {
+        return Ok(false);
+    }
+
+    let last_run_age = fs::metadata(report_path(app_handle)?)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+
+    Ok(last_run_age.is_none_or(|age| age >= Duration::from_secs(interval_hours * 60 * 60)))
+}
+
+fn integrity_check(conn: &Connection) -> Result<Vec<String>, String> {
+    let mut stmt = conn
+        .prepare(&format!("PRAGMA integrity_check({})", MAX_INTEGRITY_ERRORS))
+        .map_err(|e| format!("Failed to run integrity check: {}", e))?;
+    let messages = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Failed to run integrity check: {}", e))?;
+
+    Ok(messages.into_iter().filter(|m| m != "ok").collect())
+}
+
+fn foreign_key_check(conn: &Connection) -> Result<Vec<ForeignKeyViolation>, String> {
+    let mut stmt = conn
+        .prepare("PRAGMA foreign_key_check")
+        .map_err(|e| format!("Failed to run foreign key check: {}", e))?;
+    stmt.query_map([], |row| {
+        Ok(ForeignKeyViolation {
+            table: row.get(0)?,
+            rowid: row.get(1)?,
+            parent: row.get(2)?,
+            constraint_index: row.get(3)?,
+        })
+    })
+    .and_then(|rows| rows.collect())
+    .map_err(|e| format!("Failed to run foreign key check: {}", e))
+}
+
+/// Checkpoint, ANALYZE and VACUUM; each step records its own failure so
+/// one problem doesn't hide the others
+fn optimize(
+    conn: &WriteConnection<'_>,
+    mut report: MaintenanceReport,
+    allow_full_vacuum: bool,
+) -> MaintenanceReport {
+    match checkpoint(conn) {
+        Ok(result) => report.checkpoint = Some(result),
+        Err(e) => report.errors.push(e),
+    }
+
+    match conn.execute_batch("ANALYZE;") {
+        Ok(()) => report.analyzed = true,
+        Err(e) => report.errors.push(format!("ANALYZE failed: {}", e)),
+    }
+
+    match incremental_vacuum(conn, allow_full_vacuum) {
+        Ok(result) => report.vacuum = Some(result),
+        Err(e) => report.errors.push(e),
+    }
+
+    report
+}
+
+fn checkpoint(conn: &Connection) -> Result<CheckpointResult, String> {
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| {
+        Ok(CheckpointResult {
+            busy: row.get::<_, i64>(0)? != 0,
+            wal_frames: row.get(1)?,
+            checkpointed_frames: row.get(2)?,
+        })
+    })
+    .map_err(|e| format!("WAL checkpoint failed: {}", e))
+}
+
+/// Databases created without `auto_vacuum = INCREMENTAL` need one full
+/// VACUUM to switch modes, done only if `allow_full_vacuum`; after that only
+/// free pages are released
+fn incremental_vacuum(conn: &Connection, allow_full_vacuum: bool) -> Result<VacuumResult, String> {
+    let pragma_i64 = |sql: &str| -> Result<i64, String> {
+        conn.query_row(sql, [], |row| row.get(0))
+            .map_err(|e| format!("{} failed: {}", sql, e))
+    };
+
+    let free_pages_before = pragma_i64("PRAGMA freelist_count")?;
+    let needs_conversion = pragma_i64("PRAGMA auto_vacuum")? != 2;
+    let converted_to_incremental = needs_conversion && allow_full_vacuum;
+
+    if needs_conversion && !allow_full_vacuum {
+        log::info!(
+            "Database still needs a full VACUUM to switch to incremental auto-vacuum; run maintenance manually"
+        );
+    } else if converted_to_incremental {
+        log::info!("Switching database to incremental auto-vacuum");
+        conn.execute_batch("PRAGMA auto_vacuum = INCREMENTAL; VACUUM;")
+            .map_err(|e| format!("VACUUM failed: {}", e))?;
+    } else {
+        // Frees one page per step, so every row has to be read
+        conn.prepare("PRAGMA incremental_vacuum")
+            .and_then(|mut stmt| stmt.query_map([], |_| Ok(()))?.collect::<Result<(), _>>())
+            .map_err(|e| format!("Incremental VACUUM failed: {}", e))?;
+    }
+
+    Ok(VacuumResult {
+        free_pages_before,
+        free_pages_after: pragma_i64("PRAGMA freelist_count")?,
+        converted_to_incremental,
+        conversion_pending: needs_conversion && !allow_full_vacuum,
+    })
+}
+
+fn report_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+    app_handle
+        .path()
+        .app_data_dir()
+        .map(|dir| dir.join(REPORT_FILE))
+        .map_err(|e| format!("Failed to get app data dir: {}", e))
+}
+
+fn save_report(app_handle: &tauri::AppHandle, report: &MaintenanceReport) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(report)
+        .map_err(|e| format!("Failed to serialize maintenance report: {}", e))?;
+    fs::write(report_path(app_handle)?, json)
+        .map_err(|e| format!("Failed to write maintenance report: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auto_vacuum(conn: &Connection) -> i64 {
+        conn.query_row("PRAGMA auto_vacuum", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn scheduled_runs_leave_the_full_vacuum_pending() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE t (x); INSERT INTO t VALUES (1);")
+            .unwrap();
+
+        let result = incremental_vacuum(&conn, false).unwrap();
+        assert!(result.conversion_pending);
+        assert!(!result.converted_to_incremental);
+        assert_eq!(auto_vacuum(&conn), 0);
+
+        let result = incremental_vacuum(&conn, true).unwrap();
+        assert!(result.converted_to_incremental);
+        assert_eq!(auto_vacuum(&conn), 2);
+
+        let result = incremental_vacuum(&conn, false).unwrap();
+        assert!(!result.conversion_pending);
+    }
+}
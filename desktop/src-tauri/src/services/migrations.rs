@@ -0,0 +1,539 @@
+use std::borrow::Cow;
+use std::time::Instant;
+
+use rusqlite::{Connection, OptionalExtension};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::services::{change_log, sync_conflicts};
+
+/// A schema change with an optional way back
+pub struct SchemaMigration {
+    pub version: i64,
+    pub name: &'static str,
+    pub up: MigrationSql,
+    pub down: Option<MigrationSql>,
+}
+
+/// Where a migration's SQL comes from
+#[derive(Clone, Copy)]
+pub enum MigrationSql {
+    /// A file under `migrations/`
+    File(&'static str),
+    /// Built in Rust, for repetitive DDL such as per-table triggers. The
+    /// output is checksummed like a file, so the generator must not change
+    /// once the migration has shipped.
+    Generated(fn() -> String),
+}
+
+impl MigrationSql {
+    pub fn sql(&self) -> Cow<'static, str> {
+        match self {
+            MigrationSql::File(sql) => Cow::Borrowed(sql),
+            MigrationSql::Generated(generate) => Cow::Owned(generate()),
+        }
+    }
+}
+
+/// Every migration known to this build, in order
+pub const MIGRATIONS: &[SchemaMigration] = &[
+    SchemaMigration {
+        version: 1,
+        name: "create_initial_tables",
+        up: MigrationSql::File(include_str!("../../migrations/001_initial_tables.sql")),
+        // Reverting it would drop every table and all data with them
+        down: None,
+    },
+    SchemaMigration {
+        version: 2,
+        name: "money_integer_cents",
+        up: MigrationSql::File(include_str!("../../migrations/002_money_cents.sql")),
+        down: Some(MigrationSql::File(include_str!(
+            "../../migrations/002_money_cents.down.sql"
+        ))),
+    },
+    SchemaMigration {
+        version: 3,
+        name: "product_search",
+        up: MigrationSql::File(include_str!("../../migrations/003_product_search.sql")),
+        down: Some(MigrationSql::File(include_str!(
+            "../../migrations/003_product_search.down.sql"
+        ))),
+    },
+    SchemaMigration {
+        version: 4,
+        name: "change_log",
+        up: MigrationSql::Generated(change_log::migration_up),
+        down: Some(MigrationSql::Generated(change_log::migration_down)),
+    },
+    SchemaMigration {
+        version: 5,
+        name: "sync_attempts",
+        up: MigrationSql::File(include_str!("../../migrations/005_sync_attempts.sql")),
+        down: Some(MigrationSql::File(include_str!(
+            "../../migrations/005_sync_attempts.down.sql"
+        ))),
+    },
+    SchemaMigration {
+        version: 6,
+        name: "sync_conflicts",
+        up: MigrationSql::Generated(sync_conflicts::migration_up),
+        down: Some(MigrationSql::Generated(sync_conflicts::migration_down)),
+    },
+    SchemaMigration {
+        version: 7,
+        name: "pin_lockout",
+        up: MigrationSql::File(include_str!("../../migrations/007_pin_lockout.sql")),
+        down: Some(MigrationSql::File(include_str!(
+            "../../migrations/007_pin_lockout.down.sql"
+        ))),
+    },
+    SchemaMigration {
+        version: 8,
+        name: "money_cents_write_path",
+        up: MigrationSql::File(include_str!(
+            "../../migrations/008_money_cents_write_path.sql"
+        )),
+        down: Some(MigrationSql::File(include_str!(
+            "../../migrations/008_money_cents_write_path.down.sql"
+        ))),
+    },
+    SchemaMigration {
+        version: 9,
+        name: "sync_parked_changes",
+        up: MigrationSql::File(include_str!("../../migrations/009_sync_parked_changes.sql")),
+        down: Some(MigrationSql::File(include_str!(
+            "../../migrations/009_sync_parked_changes.down.sql"
+        ))),
+    },
+    SchemaMigration {
+        version: 10,
+        name: "dte_invalidation",
+        up: MigrationSql::File(include_str!("../../migrations/010_dte_invalidation.sql")),
+        down: Some(MigrationSql::File(include_str!(
+            "../../migrations/010_dte_invalidation.down.sql"
+        ))),
+    },
+];
+
+const CREATE_HISTORY_TABLE: &str = "CREATE TABLE IF NOT EXISTS schema_migrations (
+    version INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    checksum TEXT NOT NULL,
+    applied_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    execution_ms INTEGER NOT NULL DEFAULT 0
+)";
+
+/// A row of `schema_migrations`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub name: String,
+    pub checksum: String,
+    pub applied_at: Option<String>,
+    pub execution_ms: i64,
+    /// False when the recorded checksum differs from the SQL in this build
+    pub checksum_valid: bool,
+    pub reversible: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaInfo {
+    pub current_version: i64,
+    pub latest_version: i64,
+    pub applied: Vec<AppliedMigration>,
+    pub pending: Vec<i64>,
+    pub sqlite_version: String,
+}
+
+/// Bring the database up to date.
+/// Runs at startup before any window opens; an error here stops the app
+/// rather than letting it write to a schema it doesn't understand.
+pub fn migrate(conn: &Connection) -> Result<(), String> {
+    ensure_history_table(conn)?;
+    adopt_plugin_history(conn)?;
+
+    let applied = applied_migrations(conn)?;
+    verify_applied(&applied)?;
+
+    let current = applied.iter().map(|m| m.version).max().unwrap_or(0);
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        apply_up(conn, migration)?;
+    }
+
+    Ok(())
+}
+
+/// Revert migrations newer than `target_version`, newest first.
+/// For support staff downgrading an install: the next start of this build
+/// re-applies them, so this is followed by installing the older build.
+pub fn rollback_to(conn: &Connection, target_version: i64) -> Result<Vec<i64>, String> {
+    if target_version < 1 {
+        return Err("Target version must be at least 1".to_string());
+    }
+
+    let applied = applied_migrations(conn)?;
+    verify_applied(&applied)?;
+
+    // History is ordered by version, so reversing gives newest first
+    let to_revert: Vec<&SchemaMigration> = applied
+        .iter()
+        .rev()
+        .filter(|m| m.version > target_version)
+        .filter_map(|m| find_migration(m.version))
+        .collect();
+
+    if let Some(irreversible) = to_revert.iter().find(|m| m.down.is_none()) {
+        return Err(format!(
+            "Migration {} ({}) cannot be reverted",
+            irreversible.version, irreversible.name
+        ));
+    }
+
+    conn.execute_batch("BEGIN IMMEDIATE")
+        .map_err(|e| format!("Failed to begin rollback: {}", e))?;
+
+    let result = to_revert.iter().try_for_each(|migration| {
+        log::warn!(
+            "Reverting migration {} ({})",
+            migration.version,
+            migration.name
+        );
+        let down = migration.down.map(|sql| sql.sql()).unwrap_or_default();
+        conn.execute_batch(&down)
+            .map_err(|e| format!("Failed to revert migration {}: {}", migration.version, e))?;
+        conn.execute(
+            "DELETE FROM schema_migrations WHERE version = ?",
+            [migration.version],
+        )
+        .map_err(|e| format!("Failed to update migration history: {}", e))?;
+        Ok(())
+    });
+
+    match result.and_then(|_| {
+        conn.execute_batch("COMMIT")
+            .map_err(|e| format!("Failed to commit rollback: {}", e))
+    }) {
+        Ok(()) => Ok(to_revert.iter().map(|m| m.version).collect()),
+        Err(e) => {
+            let _ = conn.execute_batch("ROLLBACK");
+            Err(e)
+        }
+    }
+}
+
+/// Works on a read-only connection; the history table exists once
+/// [`migrate`] has run at startup
+pub fn schema_info(conn: &Connection) -> Result<SchemaInfo, String> {
+    let applied = applied_migrations(conn)?;
+    let current_version = applied.iter().map(|m| m.version).max().unwrap_or(0);
+
+    Ok(SchemaInfo {
+        current_version,
+        latest_version: latest_version(),
+        pending: MIGRATIONS
+            .iter()
+            .filter(|m| !applied.iter().any(|a| a.version == m.version))
+            .map(|m| m.version)
+            .collect(),
+        applied,
+        sqlite_version: rusqlite::version().to_string(),
+    })
+}
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.iter().map(|m| m.version).max().unwrap_or(0)
+}
+
+/// SHA-256 of the migration SQL with line endings normalized, so a Windows
+/// checkout with CRLF produces the same checksum
+pub fn checksum(sql: &str) -> String {
+    format!("{:x}", Sha256::digest(sql.replace("\r\n", "\n").as_bytes()))
+}
+
+fn find_migration(version: i64) -> Option<&'static SchemaMigration> {
+    MIGRATIONS.iter().find(|m| m.version == version)
+}
+
+fn ensure_history_table(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(CREATE_HISTORY_TABLE)
+        .map_err(|e| format!("Failed to create migration history: {}", e))
+}
+
+/// Installs created before this runner were migrated by tauri-plugin-sql.
+/// Take over its history once so those migrations aren't applied twice.
+fn adopt_plugin_history(conn: &Connection) -> Result<(), String> {
+    let already_tracked: i64 = conn
+        .query_row("SELECT COUNT(*) FROM schema_migrations", [], |row| {
+            row.get(0)
+        })
+        .map_err(|e| format!("Failed to read migration history: {}", e))?;
+    if already_tracked > 0 {
+        return Ok(());
+    }
+
+    let has_plugin_table = conn
+        .query_row(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'",
+            [],
+            |_| Ok(()),
+        )
+        .optional()
+        .map_err(|e| format!("Failed to inspect schema: {}", e))?
+        .is_some();
+    if !has_plugin_table {
+        return Ok(());
+    }
+
+    let mut stmt = conn
+        .prepare("SELECT version FROM _sqlx_migrations WHERE success = 1 ORDER BY version")
+        .map_err(|e| format!("Failed to read plugin migrations: {}", e))?;
+    let versions = stmt
+        .query_map([], |row| row.get::<_, i64>(0))
+        .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Failed to read plugin migrations: {}", e))?;
+
+    for version in versions {
+        let Some(migration) = find_migration(version) else {
+            return Err(format!(
+                "Database has migration {} which this version of the app does not know",
+                version
+            ));
+        };
+        conn.execute(
+            "INSERT INTO schema_migrations (version, name, checksum) VALUES (?, ?, ?)",
+            rusqlite::params![
+                migration.version,
+                migration.name,
+                checksum(&migration.up.sql())
+            ],
+        )
+        .map_err(|e| format!("Failed to record migration {}: {}", version, e))?;
+        log::info!(
+            "Adopted migration {} ({}) from tauri-plugin-sql history",
+            migration.version,
+            migration.name
+        );
+    }
+
+    Ok(())
+}
+
+fn applied_migrations(conn: &Connection) -> Result<Vec<AppliedMigration>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT version, name, checksum, applied_at, execution_ms
+             FROM schema_migrations ORDER BY version",
+        )
+        .map_err(|e| format!("Failed to read migration history: {}", e))?;
+
+    stmt.query_map([], |row| {
+        let version: i64 = row.get(0)?;
+        let recorded: String = row.get(2)?;
+        let known = find_migration(version);
+        Ok(AppliedMigration {
+            version,
+            name: row.get(1)?,
+            checksum_valid: known.is_some_and(|m| checksum(&m.up.sql()) == recorded),
+            reversible: known.is_some_and(|m| m.down.is_some()),
+            checksum: recorded,
+            applied_at: row.get(3)?,
+            execution_ms: row.get(4)?,
+        })
+    })
+    .and_then(|rows| rows.collect())
+    .map_err(|e| format!("Failed to read migration history: {}", e))
+}
+
+/// Refuse schemas written by a newer build or migrations edited after release
+fn verify_applied(applied: &[AppliedMigration]) -> Result<(), String> {
+    let latest = latest_version();
+    if let Some(newer) = applied.iter().find(|m| m.version > latest) {
+        return Err(format!(
+            "Database schema version {} is newer than this app supports ({}). Please update the app.",
+            newer.version, latest
+        ));
+    }
+
+    if let Some(tampered) = applied.iter().find(|m| !m.checksum_valid) {
+        return Err(format!(
+            "Checksum mismatch for migration {} ({}): the applied schema differs from this build",
+            tampered.version, tampered.name
+        ));
+    }
+
+    Ok(())
+}
+
+fn apply_up(conn: &Connection, migration: &SchemaMigration) -> Result<(), String> {
+    log::info!(
+        "Applying migration {} ({})",
+        migration.version,
+        migration.name
+    );
+    let started = Instant::now();
+
+    conn.execute_batch("BEGIN IMMEDIATE")
+        .map_err(|e| format!("Failed to begin migration {}: {}", migration.version, e))?;
+
+    let up = migration.up.sql();
+    let result = conn
+        .execute_batch(&up)
+        .map_err(|e| format!("Migration {} failed: {}", migration.version, e))
+        .and_then(|_| {
+            conn.execute(
+                "INSERT INTO schema_migrations (version, name, checksum, execution_ms)
+                 VALUES (?, ?, ?, ?)",
+                rusqlite::params![
+                    migration.version,
+                    migration.name,
+                    checksum(&up),
+                    started.elapsed().as_millis() as i64
+                ],
+            )
+            .map_err(|e| format!("Failed to record migration {}: {}", migration.version, e))
+        })
+        .and_then(|_| {
+            conn.execute_batch("COMMIT")
+                .map_err(|e| format!("Failed to commit migration {}: {}", migration.version, e))
+        });
+
+    if result.is_err() {
+        let _ = conn.execute_batch("ROLLBACK");
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migrated() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO categories (id, name) VALUES (1, 'General')",
+            [],
+        )
+        .unwrap();
+        conn
+    }
+
+    fn price(conn: &Connection, id: i64) -> (f64, i64) {
+        conn.query_row(
+            "SELECT price, price_cents FROM products WHERE id = ?",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn money_cents_are_the_write_path() {
+        let conn = migrated();
+
+        // Legacy writers set only the DECIMAL column
+        conn.execute(
+            "INSERT INTO products (id, name, category_id, price, partner_price, vip_price)
+             VALUES (1, 'a', 1, 2.675, 2, 2)",
+            [],
+        )
+        .unwrap();
+        assert_eq!(price(&conn, 1).1, 268);
+
+        // Cents win over the DECIMAL value written alongside them
+        conn.execute(
+            "INSERT INTO products (id, name, category_id, price, price_cents, partner_price, vip_price)
+             VALUES (2, 'b', 1, 0, 1999, 2, 2)",
+            [],
+        )
+        .unwrap();
+        assert_eq!(price(&conn, 2), (19.99, 1999));
+
+        conn.execute("UPDATE products SET price_cents = 1050 WHERE id = 2", [])
+            .unwrap();
+        assert_eq!(price(&conn, 2), (10.5, 1050));
+
+        conn.execute("UPDATE products SET price = 3.1 WHERE id = 2", [])
+            .unwrap();
+        assert_eq!(price(&conn, 2), (3.1, 310));
+    }
+
+    fn versions(conn: &Connection) -> Vec<i64> {
+        applied_migrations(conn)
+            .unwrap()
+            .iter()
+            .map(|m| m.version)
+            .collect()
+    }
+
+    #[test]
+    fn applies_every_migration_once() {
+        let conn = migrated();
+        let all: Vec<i64> = MIGRATIONS.iter().map(|m| m.version).collect();
+        assert_eq!(versions(&conn), all);
+
+        migrate(&conn).unwrap();
+        assert_eq!(versions(&conn), all);
+        assert!(schema_info(&conn).unwrap().pending.is_empty());
+    }
+
+    #[test]
+    fn checksums_ignore_line_endings() {
+        assert_eq!(checksum("a;\r\nb;"), checksum("a;\nb;"));
+        assert_ne!(checksum("a;"), checksum("b;"));
+    }
+
+    #[test]
+    fn refuses_edited_migrations() {
+        let conn = migrated();
+        conn.execute(
+            "UPDATE schema_migrations SET checksum = 'edited' WHERE version = 3",
+            [],
+        )
+        .unwrap();
+
+        let err = migrate(&conn).unwrap_err();
+        assert!(err.contains("Checksum mismatch for migration 3"), "{}", err);
+        assert!(rollback_to(&conn, 2).is_err());
+    }
+
+    #[test]
+    fn refuses_newer_schemas() {
+        let conn = migrated();
+        conn.execute(
+            "INSERT INTO schema_migrations (version, name, checksum) VALUES (?, 'future', '')",
+            [latest_version() + 1],
+        )
+        .unwrap();
+        assert!(migrate(&conn).unwrap_err().contains("newer than this app"));
+    }
+
+    #[test]
+    fn rolls_back_and_reapplies() {
+        let conn = migrated();
+        let reverted = rollback_to(&conn, 3).unwrap();
+        let expected: Vec<i64> = MIGRATIONS
+            .iter()
+            .rev()
+            .map(|m| m.version)
+            .filter(|v| *v > 3)
+            .collect();
+        assert_eq!(reverted, expected);
+        assert_eq!(versions(&conn), [1, 2, 3]);
+
+        migrate(&conn).unwrap();
+        assert_eq!(versions(&conn).len(), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn never_reverts_the_initial_tables() {
+        let conn = migrated();
+        assert!(rollback_to(&conn, 0).is_err());
+        assert!(rollback_to(&conn, -1).is_err());
+        assert_eq!(versions(&conn).len(), MIGRATIONS.len());
+        assert!(!applied_migrations(&conn).unwrap()[0].reversible);
+    }
+}
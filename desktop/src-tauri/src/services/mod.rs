@@ -1,2 +1,26 @@
+pub mod approvals;
+pub mod audit;
+pub mod auth_session;
+pub mod backup;
+pub mod change_log;
 pub mod database;
+pub mod db_encryption;
+pub mod db_writer;
+pub mod maintenance;
+pub mod migrations;
+pub mod permissions;
+pub mod pin_auth;
+pub mod pin_hash;
+pub mod pin_lockout;
+pub mod product_search;
+pub mod sale_totals;
+pub mod sales;
+pub mod secret_policy;
 pub mod secure_storage;
+pub mod sql_value;
+pub mod sync;
+pub mod sync_client;
+pub mod sync_conflicts;
+pub mod sync_store;
+pub mod vault_key;
+pub mod vault_unlock;
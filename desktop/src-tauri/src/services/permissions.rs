@@ -0,0 +1,424 @@
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+
+use rusqlite::Connection;
+use rusqlite::hooks::{AuthAction, AuthContext, Authorization};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+/// What a logged-in user may do. Sensitive commands check one of these
+/// against the caller's session before running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// Ring up sales, take payments, open and close the cash register
+    CanSell,
+    CanRefund,
+    /// Void or correct completed sales
+    CanVoid,
+    /// Price overrides and discounts above the POS screen's threshold
+    CanOverridePrices,
+    CanOpenDrawer,
+    CanViewReports,
+    /// Products, categories and stock adjustments
+    CanManageProducts,
+    CanManageCustomers,
+    CanManageUsers,
+    /// `system_settings`
+    CanManageSettings,
+    /// Signing certificate and the secure storage holding its key
+    CanManageCertificates,
+    /// Schema changes, backups, rekeying, maintenance and sync conflicts
+    CanManageDatabase,
+    CanCloseApp,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 13] = [
+        Permission::CanSell,
+        Permission::CanRefund,
+        Permission::CanVoid,
+        Permission::CanOverridePrices,
+        Permission::CanOpenDrawer,
+        Permission::CanViewReports,
+        Permission::CanManageProducts,
+        Permission::CanManageCustomers,
+        Permission::CanManageUsers,
+        Permission::CanManageSettings,
+        Permission::CanManageCertificates,
+        Permission::CanManageDatabase,
+        Permission::CanCloseApp,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Permission::CanSell => "can_sell",
+            Permission::CanRefund => "can_refund",
+            Permission::CanVoid => "can_void",
+            Permission::CanOverridePrices => "can_override_prices",
+            Permission::CanOpenDrawer => "can_open_drawer",
+            Permission::CanViewReports => "can_view_reports",
+            Permission::CanManageProducts => "can_manage_products",
+            Permission::CanManageCustomers => "can_manage_customers",
+            Permission::CanManageUsers => "can_manage_users",
+            Permission::CanManageSettings => "can_manage_settings",
+            Permission::CanManageCertificates => "can_manage_certificates",
+            Permission::CanManageDatabase => "can_manage_database",
+            Permission::CanCloseApp => "can_close_app",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.as_str() == name)
+    }
+}
+
+/// Permissions every user with the role has
+fn role_grants(role: &str) -> &'static [Permission] {
+    use Permission::*;
+    match role {
+        "admin" => &Permission::ALL,
+        "supervisor" => &[
+            CanSell,
+            CanRefund,
+            CanVoid,
+            CanOverridePrices,
+            CanOpenDrawer,
+            CanViewReports,
+            CanManageProducts,
+            CanManageCustomers,
+            CanCloseApp,
+        ],
+        "cashier" => &[CanSell],
+        _ => &[],
+    }
+}
+
+/// `users.permissions` entries (`{"resource": ["action", ..]}`) and what
+/// they grant
+fn resource_grant(resource: &str, action: &str) -> Option<Permission> {
+    use Permission::*;
+    match (resource, action) {
+        ("transactions", "create") => Some(CanSell),
+        ("transactions", "refund") => Some(CanRefund),
+        ("transactions", "update" | "delete") => Some(CanVoid),
+        ("reports", _) => Some(CanViewReports),
+        ("products" | "categories", "create" | "update" | "delete") => Some(CanManageProducts),
+        ("customers", "delete") => Some(CanManageCustomers),
+        ("users", "create" | "update" | "delete") => Some(CanManageUsers),
+        ("settings", "update") => Some(CanManageSettings),
+        _ => None,
+    }
+}
+
+/// The user's role grants plus any extra ones in `users.permissions`,
+/// either as `{"resource": ["action", ..]}` or a list of permission names
+pub fn resolve(role: &str, extra: &JsonValue) -> BTreeSet<Permission> {
+    let mut granted: BTreeSet<Permission> = role_grants(role).iter().copied().collect();
+
+    match extra {
+        JsonValue::Object(resources) => {
+            for (resource, actions) in resources {
+                let actions = actions.as_array().into_iter().flatten();
+                granted.extend(
+                    actions
+                        .filter_map(JsonValue::as_str)
+                        .filter_map(|action| resource_grant(resource, action)),
+                );
+            }
+        }
+        JsonValue::Array(names) => {
+            granted.extend(
+                names
+                    .iter()
+                    .filter_map(JsonValue::as_str)
+                    .filter_map(Permission::parse),
+            );
+        }
+        _ => {}
+    }
+
+    granted
+}
+
+/// A statement the session isn't allowed to run
+#[derive(Debug, Clone)]
+pub struct Denied {
+    /// Position of the statement in the flattened batch
+    pub index: usize,
+    pub action: String,
+    /// `None` when no permission allows it, e.g. reading PIN hashes
+    pub required: Option<Permission>,
+}
+
+impl Denied {
+    pub fn message(&self) -> String {
+        match self.required {
+            Some(required) => format!(
+                "Statement {} ({}) requires permission {}",
+                self.index,
+                self.action,
+                required.as_str()
+            ),
+            None => format!(
+                "Statement {} ({}) is not allowed from the webview",
+                self.index, self.action
+            ),
+        }
+    }
+}
+
+/// `system_settings` keys holding credentials. Their values never leave
+/// the backend; see `get_system_settings`.
+pub fn is_secret_setting(key: &str) -> bool {
+    ["_token", "_password", "_secret", "_key"]
+        .iter()
+        .any(|suffix| key.ends_with(suffix))
+}
+
+/// Columns no session may read through SQL: PIN hashes, the lockout
+/// counters, and setting values, which may hold credentials and are served
+/// by `get_system_settings` with those filtered out
+fn secret_read(table: &str, column: &str) -> bool {
+    matches!(
+        (table, column),
+        ("users", "pin_hash") | ("pin_attempts", _) | ("system_settings", "value")
+    )
+}
+
+#[derive(Clone, Copy)]
+enum Write {
+    Insert,
+    Update,
+    Delete,
+}
+
+/// Permission needed to change rows of `table`. Tables not listed here are
+/// internal (change log, sync and lockout state) and need
+/// `CanManageDatabase`.
+fn table_permission(table: &str, write: Write) -> Option<Permission> {
+    use Permission::*;
+    Some(match (table, write) {
+        ("transactions" | "transaction_items" | "payments" | "dte", Write::Insert) => CanSell,
+        ("transactions" | "transaction_items" | "payments" | "dte", _) => CanVoid,
+        ("cash_register_sessions" | "cash_movements", _) => CanSell,
+        ("customers", Write::Insert | Write::Update) => CanSell,
+        ("customers", Write::Delete) => CanManageCustomers,
+        ("stock_movements", Write::Insert) => CanSell,
+        ("products" | "categories" | "stock_movements", _) => CanManageProducts,
+        ("users", _) => CanManageUsers,
+        ("system_settings", _) => CanManageSettings,
+        // Anyone logged in may add to the audit trail but not rewrite it
+        ("audit_logs", Write::Insert) => return None,
+        _ => CanManageDatabase,
+    })
+}
+
+/// An action and the permission it needs, `None` when nothing allows it
+type Need = (String, Option<Permission>);
+
+/// What one authorizer action needs, `None` for allowed reads and
+/// transaction control
+fn action_permission(action: &AuthAction<'_>) -> Option<Need> {
+    let (write, table) = match *action {
+        AuthAction::Insert { table_name } => (Write::Insert, table_name),
+        AuthAction::Update { table_name, .. } => (Write::Update, table_name),
+        AuthAction::Delete { table_name } => (Write::Delete, table_name),
+        AuthAction::Read {
+            table_name,
+            column_name,
+        } => {
+            return secret_read(table_name, column_name)
+                .then(|| (format!("read {}.{}", table_name, column_name), None));
+        }
+        AuthAction::Select
+        | AuthAction::Function { .. }
+        | AuthAction::Recursive
+        | AuthAction::Transaction { .. }
+        | AuthAction::Savepoint { .. } => return None,
+        AuthAction::Pragma { pragma_name, .. } => {
+            return Some((
+                format!("pragma {}", pragma_name),
+                Some(Permission::CanManageDatabase),
+            ));
+        }
+        AuthAction::Attach { .. } | AuthAction::Detach { .. } => {
+            return Some(("attach".to_string(), Some(Permission::CanManageDatabase)));
+        }
+        _ => {
+            return Some((
+                "schema change".to_string(),
+                Some(Permission::CanManageDatabase),
+            ));
+        }
+    };
+    let verb = match write {
+        Write::Insert => "insert into",
+        Write::Update => "update",
+        Write::Delete => "delete from",
+    };
+    table_permission(table, write).map(|required| (format!("{} {}", verb, table), Some(required)))
+}
+
+/// Prepare each statement without running it and check every table it
+/// writes against `granted`, and that it reads no secret columns. Writes
+/// and reads made by triggers are covered by the statement that fires them.
+/// Read-only queries go through the same check.
+pub fn check_statements<'a>(
+    conn: &Connection,
+    statements: impl IntoIterator<Item = &'a str>,
+    granted: &BTreeSet<Permission>,
+) -> Result<Result<(), Denied>, String> {
+    let required: Arc<Mutex<Vec<Need>>> = Arc::default();
+    let collected = Arc::clone(&required);
+    conn.authorizer(Some(move |ctx: AuthContext<'_>| {
+        let need = match ctx.accessor {
+            None => action_permission(&ctx.action),
+            Some(_) => None,
+        };
+        if let Some(need) = need {
+            collected
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(need);
+        }
+        Authorization::Allow
+    }));
+
+    let result = (|| {
+        for (index, sql) in statements.into_iter().enumerate() {
+            conn.prepare(sql)
+                .map_err(|e| format!("Statement {}: {}", index, e))?;
+            let needs = std::mem::take(&mut *required.lock().unwrap_or_else(|e| e.into_inner()));
+            let denied = needs
+                .into_iter()
+                .find(|(_, p)| p.is_none_or(|p| !granted.contains(&p)));
+            if let Some((action, required)) = denied {
+                return Ok(Err(Denied {
+                    index,
+                    action,
+                    required,
+                }));
+            }
+        }
+        Ok(Ok(()))
+    })();
+    conn.authorizer(None::<fn(AuthContext<'_>) -> Authorization>);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migrated() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        crate::services::migrations::migrate(&conn).unwrap();
+        conn
+    }
+
+    fn check(conn: &Connection, sql: &str, role: &str) -> Result<(), Denied> {
+        check_statements(conn, [sql], &resolve(role, &JsonValue::Null)).unwrap()
+    }
+
+    #[test]
+    fn checks_writes_against_the_role() {
+        let conn = migrated();
+        let sale =
+            "INSERT INTO transactions (transaction_number, user_id, subtotal, tax_amount, total)
+                    VALUES ('T-1', 1, 1, 0, 1)";
+        // The money and change log triggers it fires need nothing extra
+        assert!(check(&conn, sale, "cashier").is_ok());
+
+        let denied = check(&conn, "UPDATE products SET name = 'x'", "cashier").unwrap_err();
+        assert_eq!(denied.required, Some(Permission::CanManageProducts));
+        assert!(check(&conn, "UPDATE products SET name = 'x'", "supervisor").is_ok());
+
+        let denied = check(&conn, "DELETE FROM audit_logs", "supervisor").unwrap_err();
+        assert_eq!(denied.required, Some(Permission::CanManageDatabase));
+        assert!(check(&conn, "PRAGMA user_version = 1", "supervisor").is_err());
+    }
+
+    #[test]
+    fn reports_the_denied_statement() {
+        let conn = migrated();
+        let denied = check_statements(
+            &conn,
+            ["SELECT 1", "DELETE FROM users"],
+            &resolve("cashier", &JsonValue::Null),
+        )
+        .unwrap()
+        .unwrap_err();
+        assert_eq!(denied.index, 1);
+        assert_eq!(denied.action, "delete from users");
+        assert_eq!(
+            denied.message(),
+            "Statement 1 (delete from users) requires permission can_manage_users"
+        );
+    }
+
+    #[test]
+    fn denies_secret_reads_to_everyone() {
+        let conn = migrated();
+        for sql in [
+            "SELECT pin_hash FROM users",
+            "SELECT * FROM users",
+            "SELECT COUNT(*) FROM pin_attempts",
+            "SELECT value FROM system_settings WHERE key = 'tax_rate'",
+            "INSERT INTO audit_logs (user_id, action, new_values)
+             SELECT id, 'x', pin_hash FROM users",
+        ] {
+            let denied = check(&conn, sql, "admin").unwrap_err();
+            assert_eq!(denied.required, None, "{}", sql);
+            assert!(
+                denied
+                    .message()
+                    .ends_with("is not allowed from the webview")
+            );
+        }
+
+        assert!(
+            check(
+                &conn,
+                "SELECT id, username, full_name FROM users",
+                "cashier"
+            )
+            .is_ok()
+        );
+        assert!(check(&conn, "SELECT key FROM system_settings", "cashier").is_ok());
+    }
+
+    #[test]
+    fn allows_pin_and_setting_writes_to_their_managers() {
+        let conn = migrated();
+        for sql in [
+            "UPDATE users SET pin_hash = 'x' WHERE id = 1",
+            "INSERT INTO system_settings (key, value) VALUES ('a', 'b')
+             ON CONFLICT(key) DO UPDATE SET value = 'b'",
+            "INSERT OR REPLACE INTO system_settings (key, value) VALUES ('a', 'b')",
+        ] {
+            assert!(check(&conn, sql, "admin").is_ok(), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn secret_settings() {
+        assert!(is_secret_setting("sync_api_token"));
+        assert!(is_secret_setting("dte_cert_password"));
+        assert!(!is_secret_setting("tax_rate"));
+        assert!(!is_secret_setting("settings.language"));
+    }
+
+    #[test]
+    fn resolves_extra_grants() {
+        let extra = serde_json::json!({"products": ["update"], "reports": ["view"]});
+        let granted = resolve("cashier", &extra);
+        assert!(granted.contains(&Permission::CanManageProducts));
+        assert!(granted.contains(&Permission::CanViewReports));
+        assert!(!granted.contains(&Permission::CanVoid));
+
+        let named = resolve("cashier", &serde_json::json!(["can_void", "bogus"]));
+        assert!(named.contains(&Permission::CanVoid));
+        assert_eq!(named.len(), 2);
+    }
+}
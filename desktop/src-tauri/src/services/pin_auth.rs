@@ -0,0 +1,581 @@
+use argon2::{
+    Argon2,
+    password_hash::{PasswordHash, PasswordVerifier},
+};
+use chrono::Utc;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+use serde_json::{Value as JsonValue, json};
+use sha2::{Digest, Sha256};
+
+use crate::services::audit::{self, AuditEntry};
+use crate::services::database::{get_setting, terminal_id};
+use crate::services::permissions::{self, Permission};
+use crate::services::pin_hash::{self, PinHashParams};
+use crate::services::pin_lockout::{self, Attempts, LockoutPolicy, Scope};
+
+/// `system_settings` switch for unsalted SHA-256 PIN hashes. They keep
+/// working (and are upgraded on login) until it is set to `false`.
+pub const LEGACY_PIN_SETTING: &str = "auth_legacy_pin_hashes";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinCheck {
+    Valid,
+    /// Matched a legacy SHA-256 hash that should be replaced
+    ValidLegacy,
+    Invalid,
+}
+
+/// User returned by a successful PIN login
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatedUser {
+    pub id: i64,
+    pub username: String,
+    pub full_name: String,
+    pub role: String,
+    pub permissions: JsonValue,
+}
+
+/// Result of a PIN attempt
+#[derive(Debug)]
+pub enum LoginOutcome {
+    Success(AuthenticatedUser),
+    /// Wrong PIN, or an unknown or inactive user
+    Failed(Attempts),
+    /// Refused without checking the PIN
+    Blocked(Attempts),
+}
+
+impl LoginOutcome {
+    /// The user, or the message to show on the login screen
+    pub fn into_result(self) -> Result<AuthenticatedUser, String> {
+        match self {
+            LoginOutcome::Success(user) => Ok(user),
+            LoginOutcome::Failed(attempts) if !attempts.locked => Err("Invalid PIN".to_string()),
+            LoginOutcome::Failed(attempts) | LoginOutcome::Blocked(attempts) => {
+                Err(attempts.message(Utc::now()))
+            }
+        }
+    }
+}
+
+/// An active user as the login screen lists it
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginUser {
+    pub id: i64,
+    pub username: String,
+    pub full_name: String,
+    pub role: String,
+}
+
+/// User row as needed for a login
+#[derive(Debug)]
+pub struct UserCredentials {
+    pub user: AuthenticatedUser,
+    pub pin_hash: String,
+    pub is_active: bool,
+}
+
+/// Unsalted SHA-256 hex digest from before PINs moved to Argon2
+pub fn is_legacy_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Check a PIN against an Argon2 hash (with the parameters stored in it),
+/// or a legacy SHA-256 one while `allow_legacy` is set
+pub fn check_pin(pin: &str, hash: &str, allow_legacy: bool) -> PinCheck {
+    if let Ok(parsed_hash) = PasswordHash::new(hash) {
+        return if Argon2::default()
+            .verify_password(pin.as_bytes(), &parsed_hash)
+            .is_ok()
+        {
+            PinCheck::Valid
+        } else {
+            PinCheck::Invalid
+        };
+    }
+
+    if allow_legacy && is_legacy_hash(hash) {
+        let pin_hash = format!("{:x}", Sha256::digest(pin.as_bytes()));
+        if pin_hash.eq_ignore_ascii_case(hash) {
+            return PinCheck::ValidLegacy;
+        }
+    }
+
+    PinCheck::Invalid
+}
+
+/// Whether legacy SHA-256 hashes are still accepted; on unless the
+/// setting says otherwise
+pub fn legacy_pins_allowed(conn: &Connection) -> Result<bool, String> {
+    Ok(get_setting(conn, LEGACY_PIN_SETTING)?.is_none_or(|v| !matches!(v.trim(), "false" | "0")))
+}
+
+/// Users still on a legacy hash; the switch can be turned off at zero
+pub fn legacy_hash_count(conn: &Connection) -> Result<i64, String> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM users
+         WHERE length(pin_hash) = 64 AND pin_hash NOT GLOB '*[^0-9A-Fa-f]*'",
+        [],
+        |row| row.get(0),
+    )
+    .map_err(|e| format!("Failed to count legacy PIN hashes: {}", e))
+}
+
+/// Active users by name, for picking who logs in. Carries no credentials.
+pub fn login_users(conn: &Connection) -> Result<Vec<LoginUser>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, username, full_name, role FROM users
+             WHERE is_active = 1 ORDER BY full_name",
+        )
+        .map_err(|e| format!("Failed to list users: {}", e))?;
+    stmt.query_map([], |row| {
+        Ok(LoginUser {
+            id: row.get(0)?,
+            username: row.get(1)?,
+            full_name: row.get(2)?,
+            role: row.get(3)?,
+        })
+    })
+    .and_then(Iterator::collect)
+    .map_err(|e| format!("Failed to list users: {}", e))
+}
+
+pub fn find_user(conn: &Connection, user_id: i64) -> Result<Option<UserCredentials>, String> {
+    let row = conn
+        .query_row(
+            "SELECT id, username, full_name, role, permissions, pin_hash, is_active
+             FROM users WHERE id = ?",
+            [user_id],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, String>(5)?,
+                    row.get::<_, bool>(6)?,
+                ))
+            },
+        )
+        .optional()
+        .map_err(|e| format!("Failed to read user {}: {}", user_id, e))?;
+
+    let Some((id, username, full_name, role, permissions, pin_hash, is_active)) = row else {
+        return Ok(None);
+    };
+    let permissions = serde_json::from_str(&permissions)
+        .map_err(|e| format!("User {} has invalid permissions: {}", id, e))?;
+
+    Ok(Some(UserCredentials {
+        user: AuthenticatedUser {
+            id,
+            username,
+            full_name,
+            role,
+            permissions,
+        },
+        pin_hash,
+        is_active,
+    }))
+}
+
+/// Short name of a hash's scheme for `audit_logs`
+fn describe_hash(hash: &str) -> String {
+    match PinHashParams::of_hash(hash) {
+        Some(params) => params.describe(),
+        None if is_legacy_hash(hash) => "sha256".to_string(),
+        None => "unknown".to_string(),
+    }
+}
+
+/// Replace a verified legacy or outdated hash with a rehash and audit it.
+/// Runs in the caller's transaction on the writer; does nothing if the PIN
+/// was changed since it was read.
+pub fn replace_pin_hash(
+    conn: &Connection,
+    user_id: i64,
+    old_hash: &str,
+    new_hash: &str,
+) -> Result<bool, String> {
+    let updated = conn
+        .execute(
+            "UPDATE users SET pin_hash = ?1, updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?2 AND pin_hash = ?3",
+            params![new_hash, user_id, old_hash],
+        )
+        .map_err(|e| format!("Failed to update PIN hash of user {}: {}", user_id, e))?;
+    if updated == 0 {
+        return Ok(false);
+    }
+
+    audit::record(
+        conn,
+        AuditEntry {
+            user_id: Some(user_id),
+            action: "pin_rehashed",
+            table_name: Some("users"),
+            record_id: Some(user_id),
+            old_values: Some(json!({ "pin_hash": describe_hash(old_hash) })),
+            new_values: Some(json!({ "pin_hash": describe_hash(new_hash) })),
+        },
+    )?;
+
+    log::info!(
+        "Upgraded PIN hash of user {} from {} to {}",
+        user_id,
+        describe_hash(old_hash),
+        describe_hash(new_hash)
+    );
+    if is_legacy_hash(old_hash) && legacy_hash_count(conn)? == 0 {
+        log::info!(
+            "No legacy PIN hashes left; {} can be set to false",
+            LEGACY_PIN_SETTING
+        );
+    }
+    Ok(true)
+}
+
+/// A PIN attempt as read before the PIN is checked, so the Argon2 work can
+/// run off the writer
+#[derive(Debug)]
+pub struct PendingAttempt {
+    user_id: i64,
+    purpose: String,
+    ignore_terminal_lock: bool,
+    credentials: Option<UserCredentials>,
+    allow_legacy: bool,
+    params: PinHashParams,
+    /// The user's failure count when read; `record_attempt` refuses the
+    /// result if another attempt changed it meanwhile
+    failed_count: i64,
+    /// The user's block when read; the PIN isn't checked then
+    block: Option<Attempts>,
+}
+
+/// A `PendingAttempt` whose PIN has been checked
+#[derive(Debug)]
+pub struct CheckedAttempt {
+    attempt: PendingAttempt,
+    /// `Invalid` when the user was blocked and the PIN wasn't checked
+    check: PinCheck,
+    /// Replacement for a legacy hash or one made with weaker parameters
+    new_hash: Option<String>,
+}
+
+/// Read what checking a user's PIN needs. Writes nothing, so it can use a
+/// read-only connection.
+pub fn load_attempt(
+    conn: &Connection,
+    user_id: i64,
+    purpose: &str,
+    ignore_terminal_lock: bool,
+) -> Result<PendingAttempt, String> {
+    let subject = user_id.to_string();
+    Ok(PendingAttempt {
+        user_id,
+        purpose: purpose.to_string(),
+        ignore_terminal_lock,
+        credentials: find_user(conn, user_id)?,
+        allow_legacy: legacy_pins_allowed(conn)?,
+        params: PinHashParams::load(conn)?,
+        failed_count: pin_lockout::failed_count(conn, Scope::User, &subject)?,
+        block: pin_lockout::blocked(conn, Scope::User, &subject, Utc::now())?,
+    })
+}
+
+impl PendingAttempt {
+    /// Check the PIN, and hash it again if the stored hash is outdated.
+    /// This is the slow part; run it off the writer.
+    pub fn check(self, pin: &str) -> Result<CheckedAttempt, String> {
+        if self.block.is_some() {
+            return Ok(CheckedAttempt {
+                attempt: self,
+                check: PinCheck::Invalid,
+                new_hash: None,
+            });
+        }
+
+        let check = self
+            .credentials
+            .as_ref()
+            .filter(|c| c.is_active)
+            .map_or(PinCheck::Invalid, |c| {
+                check_pin(pin, &c.pin_hash, self.allow_legacy)
+            });
+        let new_hash = match &self.credentials {
+            Some(c)
+                if check != PinCheck::Invalid
+                    && pin_hash::needs_rehash(&c.pin_hash, &self.params) =>
+            {
+                Some(pin_hash::hash(pin, &self.params)?)
+            }
+            _ => None,
+        };
+        Ok(CheckedAttempt {
+            attempt: self,
+            check,
+            new_hash,
+        })
+    }
+}
+
+/// Count a checked PIN attempt with brute-force protection: refused while
+/// the user or terminal is blocked, failures counted against both, and
+/// every attempt written to `audit_logs`. The outdated hash of a user who
+/// got in is replaced. Run it in a transaction on the writer; it re-checks
+/// the blocks and the user's failure count, and refuses an attempt that
+/// raced another so parallel attempts can't skip the backoff.
+/// `ignore_terminal_lock` (set at `load_attempt`) lets a manager
+/// authenticate on a locked terminal to unlock it.
+pub fn record_attempt(conn: &Connection, checked: CheckedAttempt) -> Result<LoginOutcome, String> {
+    let CheckedAttempt {
+        attempt,
+        check,
+        new_hash,
+    } = checked;
+    let now = Utc::now();
+    let policy = LockoutPolicy::load(conn)?;
+    let terminal = terminal_id(conn)?;
+    let user_id = attempt.user_id;
+    let purpose = attempt.purpose.as_str();
+    let subject = user_id.to_string();
+
+    let credentials = find_user(conn, user_id)?;
+    let known_user = credentials.as_ref().map(|c| c.user.id);
+    let audit_attempt = |action: &str, details: JsonValue| {
+        audit::record(
+            conn,
+            AuditEntry {
+                user_id: known_user,
+                action,
+                table_name: Some("users"),
+                record_id: known_user,
+                old_values: None,
+                new_values: Some(details),
+            },
+        )
+    };
+
+    let terminal_block = if attempt.ignore_terminal_lock {
+        None
+    } else {
+        pin_lockout::blocked(conn, Scope::Terminal, &terminal, now)?
+    };
+    let failed_count = pin_lockout::failed_count(conn, Scope::User, &subject)?;
+    let raced = (failed_count != attempt.failed_count).then_some(Attempts {
+        scope: Scope::User,
+        failed_count,
+        blocked_until: None,
+        locked: false,
+    });
+    let block = terminal_block
+        .or(pin_lockout::blocked(conn, Scope::User, &subject, now)?)
+        .or(raced)
+        .or(attempt.block);
+    if let Some(block) = block {
+        audit_attempt(
+            "login_blocked",
+            json!({
+                "user_id": user_id,
+                "terminal_id": terminal,
+                "purpose": purpose,
+                "scope": block.scope,
+                "blocked_until": block.blocked_until.map(|t| t.to_rfc3339()),
+                "concurrent": raced.is_some(),
+            }),
+        )?;
+        return Ok(LoginOutcome::Blocked(block));
+    }
+
+    // The PIN was checked against the hash as loaded; a user changed since
+    // doesn't get in
+    let unchanged = |c: &UserCredentials| {
+        c.is_active
+            && attempt
+                .credentials
+                .as_ref()
+                .is_some_and(|loaded| loaded.pin_hash == c.pin_hash)
+    };
+    let Some(credentials) = credentials.filter(|c| check != PinCheck::Invalid && unchanged(c))
+    else {
+        let user = pin_lockout::record_failure(conn, &policy, Scope::User, &subject, now)?;
+        let terminal_attempts =
+            pin_lockout::record_failure(conn, &policy, Scope::Terminal, &terminal, now)?;
+        audit_attempt(
+            "login_failed",
+            json!({
+                "user_id": user_id,
+                "terminal_id": terminal,
+                "purpose": purpose,
+                "failed_count": user.failed_count,
+                "terminal_failed_count": terminal_attempts.failed_count,
+                "locked": user.locked || terminal_attempts.locked,
+            }),
+        )?;
+        let reported = if terminal_attempts.locked && !user.locked {
+            terminal_attempts
+        } else {
+            user
+        };
+        return Ok(LoginOutcome::Failed(reported));
+    };
+
+    pin_lockout::reset(conn, Scope::User, &subject)?;
+    audit_attempt(
+        "login_success",
+        json!({ "user_id": user_id, "terminal_id": terminal, "purpose": purpose }),
+    )?;
+    if let Some(new_hash) = new_hash {
+        replace_pin_hash(conn, user_id, &credentials.pin_hash, &new_hash)?;
+    }
+    Ok(LoginOutcome::Success(credentials.user))
+}
+
+/// Clear the PIN lockout of a user (if given) and of this terminal after a
+/// manager authenticates with `manager`, loaded with `ignore_terminal_lock`.
+/// The inner `Err` is an authentication or authorization failure; the
+/// caller should still commit so the failed attempt is counted.
+pub fn unlock(
+    conn: &Connection,
+    manager: CheckedAttempt,
+    user_id: Option<i64>,
+) -> Result<Result<(), String>, String> {
+    let manager = match record_attempt(conn, manager)?.into_result() {
+        Ok(manager) => manager,
+        Err(message) => return Ok(Err(message)),
+    };
+    if !permissions::resolve(&manager.role, &manager.permissions)
+        .contains(&Permission::CanManageUsers)
+    {
+        audit::record(
+            conn,
+            AuditEntry {
+                user_id: Some(manager.id),
+                action: "pin_unlock_denied",
+                table_name: Some("users"),
+                record_id: user_id,
+                ..AuditEntry::default()
+            },
+        )?;
+        return Ok(Err(format!(
+            "{} cannot unlock PIN lockouts (needs {})",
+            manager.full_name,
+            Permission::CanManageUsers.as_str()
+        )));
+    }
+
+    let terminal = terminal_id(conn)?;
+    let user_cleared = match user_id {
+        Some(id) => pin_lockout::reset(conn, Scope::User, &id.to_string())?,
+        None => false,
+    };
+    let terminal_cleared = pin_lockout::reset(conn, Scope::Terminal, &terminal)?;
+
+    audit::record(
+        conn,
+        AuditEntry {
+            user_id: Some(manager.id),
+            action: "pin_unlocked",
+            table_name: Some("users"),
+            record_id: user_id,
+            old_values: None,
+            new_values: Some(json!({
+                "terminal_id": terminal,
+                "user_cleared": user_cleared,
+                "terminal_cleared": terminal_cleared,
+            })),
+        },
+    )?;
+    log::info!(
+        "PIN lockout cleared by user {} (user {:?}, terminal {})",
+        manager.id,
+        user_id,
+        terminal
+    );
+    Ok(Ok(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db_with_user(pin_hash: &str) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        crate::services::migrations::migrate(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO users (id, username, pin_hash, role, full_name, permissions)
+             VALUES (1, 'ana', ?1, 'cashier', 'Ana', '{}')",
+            [pin_hash],
+        )
+        .unwrap();
+        conn
+    }
+
+    fn attempt(conn: &Connection, pin: &str) -> CheckedAttempt {
+        load_attempt(conn, 1, "login", false)
+            .unwrap()
+            .check(pin)
+            .unwrap()
+    }
+
+    #[test]
+    fn attempts_checked_in_parallel_cannot_skip_the_backoff() {
+        let conn = db_with_user(&pin_hash::hash("1234", &PinHashParams::default()).unwrap());
+        let wrong = attempt(&conn, "0000");
+        let right = attempt(&conn, "1234");
+
+        assert!(matches!(
+            record_attempt(&conn, wrong).unwrap(),
+            LoginOutcome::Failed(_)
+        ));
+        // Checked before the failure was counted, so refused either way
+        assert!(matches!(
+            record_attempt(&conn, right).unwrap(),
+            LoginOutcome::Blocked(_)
+        ));
+    }
+
+    #[test]
+    fn unlocking_needs_can_manage_users() {
+        let hash = pin_hash::hash("1234", &PinHashParams::default()).unwrap();
+        let conn = db_with_user(&hash);
+        conn.execute(
+            "INSERT INTO users (id, username, pin_hash, role, full_name, permissions)
+             VALUES (2, 'luis', ?1, 'supervisor', 'Luis', '{}'),
+                    (3, 'eva', ?1, 'supervisor', 'Eva', '[\"can_manage_users\"]')",
+            [&hash],
+        )
+        .unwrap();
+        let unlock_as = |manager_id: i64| {
+            let manager = load_attempt(&conn, manager_id, "unlock", true)
+                .unwrap()
+                .check("1234")
+                .unwrap();
+            unlock(&conn, manager, Some(1)).unwrap()
+        };
+
+        assert!(unlock_as(2).unwrap_err().contains("can_manage_users"));
+        assert!(unlock_as(3).is_ok());
+    }
+
+    #[test]
+    fn legacy_hash_is_replaced_when_the_attempt_is_recorded() {
+        let legacy = format!("{:x}", Sha256::digest(b"1234"));
+        let conn = db_with_user(&legacy);
+        let checked = attempt(&conn, "1234");
+        assert!(checked.new_hash.is_some());
+
+        assert!(matches!(
+            record_attempt(&conn, checked).unwrap(),
+            LoginOutcome::Success(_)
+        ));
+        let stored = find_user(&conn, 1).unwrap().unwrap().pin_hash;
+        assert!(!is_legacy_hash(&stored));
+        assert_eq!(check_pin("1234", &stored, false), PinCheck::Valid);
+    }
+}
@@ -0,0 +1,241 @@
+use std::time::{Duration, Instant};
+
+use argon2::{
+    Algorithm, Argon2, Params, Version,
+    password_hash::{PasswordHash, PasswordHasher, SaltString, rand_core::OsRng},
+};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::services::database::{get_setting, set_setting};
+
+/// Lowest memory cost accepted from settings or the benchmark
+pub const MIN_MEMORY_KIB: u32 = 8 * 1024;
+pub const MAX_MEMORY_KIB: u32 = 256 * 1024;
+pub const MAX_ITERATIONS: u32 = 10;
+pub const MAX_PARALLELISM: u32 = 4;
+
+/// Verification time the benchmark aims for when none is given
+pub const DEFAULT_TARGET: Duration = Duration::from_millis(250);
+
+const MEMORY_SETTING: &str = "pin_argon2_memory_kib";
+const ITERATIONS_SETTING: &str = "pin_argon2_iterations";
+const PARALLELISM_SETTING: &str = "pin_argon2_parallelism";
+
+/// Argon2id cost for new PIN hashes, from `system_settings` (category
+/// `security`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PinHashParams {
+    /// `pin_argon2_memory_kib`
+    pub memory_kib: u32,
+    /// `pin_argon2_iterations`
+    pub iterations: u32,
+    /// `pin_argon2_parallelism`
+    pub parallelism: u32,
+}
+
+impl Default for PinHashParams {
+    fn default() -> Self {
+        Self {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl PinHashParams {
+    /// Configured parameters; the defaults if any setting is missing or out
+    /// of range
+    pub fn load(conn: &Connection) -> Result<Self, String> {
+        let setting = |key: &str| -> Result<Option<u32>, String> {
+            Ok(get_setting(conn, key)?.and_then(|v| v.trim().parse().ok()))
+        };
+        let (Some(memory_kib), Some(iterations), Some(parallelism)) = (
+            setting(MEMORY_SETTING)?,
+            setting(ITERATIONS_SETTING)?,
+            setting(PARALLELISM_SETTING)?,
+        ) else {
+            return Ok(Self::default());
+        };
+
+        let params = Self {
+            memory_kib,
+            iterations,
+            parallelism,
+        };
+        if let Err(e) = params.validate() {
+            log::warn!("Ignoring PIN hash settings: {}", e);
+            return Ok(Self::default());
+        }
+        Ok(params)
+    }
+
+    pub fn save(&self, conn: &Connection) -> Result<(), String> {
+        self.validate()?;
+        set_setting(
+            conn,
+            MEMORY_SETTING,
+            &self.memory_kib.to_string(),
+            "security",
+        )?;
+        set_setting(
+            conn,
+            ITERATIONS_SETTING,
+            &self.iterations.to_string(),
+            "security",
+        )?;
+        set_setting(
+            conn,
+            PARALLELISM_SETTING,
+            &self.parallelism.to_string(),
+            "security",
+        )
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(MIN_MEMORY_KIB..=MAX_MEMORY_KIB).contains(&self.memory_kib) {
+            return Err(format!(
+                "Memory cost must be between {} and {} KiB",
+                MIN_MEMORY_KIB, MAX_MEMORY_KIB
+            ));
+        }
+        if !(1..=MAX_ITERATIONS).contains(&self.iterations) {
+            return Err(format!(
+                "Iterations must be between 1 and {}",
+                MAX_ITERATIONS
+            ));
+        }
+        if !(1..=MAX_PARALLELISM).contains(&self.parallelism) {
+            return Err(format!(
+                "Parallelism must be between 1 and {}",
+                MAX_PARALLELISM
+            ));
+        }
+        Ok(())
+    }
+
+    /// Parameters of an Argon2id PHC string, `None` for anything else
+    pub fn of_hash(hash: &str) -> Option<Self> {
+        let parsed = PasswordHash::new(hash).ok()?;
+        if parsed.algorithm != Algorithm::Argon2id.ident()
+            || parsed.version != Some(Version::V0x13.into())
+        {
+            return None;
+        }
+        let params = Params::try_from(&parsed).ok()?;
+        Some(Self {
+            memory_kib: params.m_cost(),
+            iterations: params.t_cost(),
+            parallelism: params.p_cost(),
+        })
+    }
+
+    /// Cheaper than `target` in any dimension
+    pub fn is_weaker_than(&self, target: &Self) -> bool {
+        self.memory_kib < target.memory_kib
+            || self.iterations < target.iterations
+            || self.parallelism < target.parallelism
+    }
+
+    /// Short form for logs and `audit_logs`
+    pub fn describe(&self) -> String {
+        format!(
+            "argon2id m={},t={},p={}",
+            self.memory_kib, self.iterations, self.parallelism
+        )
+    }
+
+    fn hasher(&self) -> Result<Argon2<'static>, String> {
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+            .map_err(|e| format!("Invalid Argon2 parameters: {}", e))?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+}
+
+/// Argon2id hash in PHC string format
+pub fn hash(pin: &str, params: &PinHashParams) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    params
+        .hasher()?
+        .hash_password(pin.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| format!("Failed to hash PIN: {}", e))
+}
+
+/// Whether a verified hash should be replaced: a legacy or non-Argon2id
+/// hash, or one made with weaker parameters than `params`
+pub fn needs_rehash(hash: &str, params: &PinHashParams) -> bool {
+    PinHashParams::of_hash(hash).is_none_or(|current| current.is_weaker_than(params))
+}
+
+/// Outcome of `benchmark`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PinHashBenchmark {
+    pub params: PinHashParams,
+    /// Time one hash with `params` took here
+    pub measured_ms: u64,
+    pub target_ms: u64,
+    /// Whether `params` were saved as the new settings
+    pub applied: bool,
+}
+
+/// Find the costliest parameters that verify within `target` on this
+/// machine. Memory is raised first since it is what makes offline cracking
+/// expensive, then iterations. Falls back to the minimum when even that is
+/// slower than `target`. Takes several times `target` to run.
+pub fn benchmark(target: Duration) -> Result<PinHashBenchmark, String> {
+    let mut params = PinHashParams {
+        memory_kib: MIN_MEMORY_KIB,
+        iterations: 1,
+        parallelism: 1,
+    };
+    let mut elapsed = time_hash(&params)?;
+
+    while params.memory_kib * 2 <= MAX_MEMORY_KIB {
+        let next = PinHashParams {
+            memory_kib: params.memory_kib * 2,
+            ..params
+        };
+        let took = time_hash(&next)?;
+        if took > target {
+            break;
+        }
+        params = next;
+        elapsed = took;
+    }
+
+    while params.iterations < MAX_ITERATIONS {
+        let next = PinHashParams {
+            iterations: params.iterations + 1,
+            ..params
+        };
+        let took = time_hash(&next)?;
+        if took > target {
+            break;
+        }
+        params = next;
+        elapsed = took;
+    }
+
+    log::info!(
+        "PIN hash benchmark: {} takes {}ms (target {}ms)",
+        params.describe(),
+        elapsed.as_millis(),
+        target.as_millis()
+    );
+    Ok(PinHashBenchmark {
+        params,
+        measured_ms: elapsed.as_millis() as u64,
+        target_ms: target.as_millis() as u64,
+        applied: false,
+    })
+}
+
+fn time_hash(params: &PinHashParams) -> Result<Duration, String> {
+    let started = Instant::now();
+    hash("000000", params)?;
+    Ok(started.elapsed())
+}
@@ -0,0 +1,431 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+
+use crate::services::database::get_setting;
+
+/// Thresholds from `system_settings` (category `security`)
+#[derive(Debug, Clone, Copy)]
+pub struct LockoutPolicy {
+    /// Failures before a user is locked (`pin_max_attempts`)
+    pub max_attempts: i64,
+    /// Failures for any user before the terminal is locked
+    /// (`pin_terminal_max_attempts`)
+    pub terminal_max_attempts: i64,
+    /// Delay after the first failure, doubled after each one
+    /// (`pin_delay_base_seconds`)
+    pub base_delay: TimeDelta,
+    /// Cap on that delay (`pin_delay_max_seconds`)
+    pub max_delay: TimeDelta,
+    /// Lock duration; failures older than this are forgotten
+    /// (`pin_lockout_minutes`)
+    pub lockout: TimeDelta,
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            terminal_max_attempts: 20,
+            base_delay: TimeDelta::seconds(1),
+            max_delay: TimeDelta::seconds(60),
+            lockout: TimeDelta::minutes(15),
+        }
+    }
+}
+
+/// Accepted setting ranges; values outside them are clamped so a typo
+/// can't disable the lockout or overflow the delay arithmetic
+const MAX_ATTEMPTS_RANGE: (i64, i64) = (1, 100);
+const TERMINAL_MAX_ATTEMPTS_RANGE: (i64, i64) = (1, 1000);
+const DELAY_SECONDS_RANGE: (i64, i64) = (1, 3600);
+const LOCKOUT_MINUTES_RANGE: (i64, i64) = (1, 7 * 24 * 60);
+
+impl LockoutPolicy {
+    pub fn load(conn: &Connection) -> Result<Self, String> {
+        let defaults = Self::default();
+        let setting = |key: &str, default: i64, (min, max): (i64, i64)| -> Result<i64, String> {
+            let value = get_setting(conn, key)?
+                .and_then(|v| v.trim().parse::<i64>().ok())
+                .filter(|v| *v > 0)
+                .unwrap_or(default);
+            if !(min..=max).contains(&value) {
+                log::warn!(
+                    "{} = {} is out of range, using {}",
+                    key,
+                    value,
+                    value.clamp(min, max)
+                );
+            }
+            Ok(value.clamp(min, max))
+        };
+
+        let base_delay = setting(
+            "pin_delay_base_seconds",
+            defaults.base_delay.num_seconds(),
+            DELAY_SECONDS_RANGE,
+        )?;
+        let max_delay = setting(
+            "pin_delay_max_seconds",
+            defaults.max_delay.num_seconds(),
+            DELAY_SECONDS_RANGE,
+        )?
+        .max(base_delay);
+        let lockout = setting(
+            "pin_lockout_minutes",
+            defaults.lockout.num_minutes(),
+            LOCKOUT_MINUTES_RANGE,
+        )?;
+
+        Ok(Self {
+            max_attempts: setting(
+                "pin_max_attempts",
+                defaults.max_attempts,
+                MAX_ATTEMPTS_RANGE,
+            )?,
+            terminal_max_attempts: setting(
+                "pin_terminal_max_attempts",
+                defaults.terminal_max_attempts,
+                TERMINAL_MAX_ATTEMPTS_RANGE,
+            )?,
+            base_delay: TimeDelta::try_seconds(base_delay).unwrap_or(defaults.base_delay),
+            max_delay: TimeDelta::try_seconds(max_delay).unwrap_or(defaults.max_delay),
+            lockout: TimeDelta::try_minutes(lockout).unwrap_or(defaults.lockout),
+        })
+    }
+
+    fn max_attempts_for(&self, scope: Scope) -> i64 {
+        match scope {
+            Scope::User => self.max_attempts,
+            Scope::Terminal => self.terminal_max_attempts,
+        }
+    }
+
+    /// Wait after the `failed_count`th failure in a row
+    fn delay_after(&self, failed_count: i64) -> TimeDelta {
+        let doublings = failed_count.saturating_sub(1).clamp(0, 16) as u32;
+        self.base_delay
+            .checked_mul(1 << doublings)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+}
+
+/// `now + delta`, saturating instead of panicking at the end of time
+fn later(now: DateTime<Utc>, delta: TimeDelta) -> DateTime<Utc> {
+    now.checked_add_signed(delta)
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    User,
+    Terminal,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::User => "user",
+            Scope::Terminal => "terminal",
+        }
+    }
+}
+
+/// Failure count of a user or terminal after an attempt
+#[derive(Debug, Clone, Copy)]
+pub struct Attempts {
+    pub scope: Scope,
+    pub failed_count: i64,
+    /// No attempt is accepted before this
+    pub blocked_until: Option<DateTime<Utc>>,
+    /// Blocked for the full lockout, not just the backoff delay
+    pub locked: bool,
+}
+
+impl Attempts {
+    /// Message for the login screen while blocked
+    pub fn message(&self, now: DateTime<Utc>) -> String {
+        let wait = self
+            .blocked_until
+            .map_or(0, |until| until.signed_duration_since(now).num_seconds())
+            .max(1);
+        match (self.scope, self.locked) {
+            (Scope::User, true) => format!(
+                "Account locked after {} failed PIN attempts. Ask a manager to unlock it or try again in {} minutes",
+                self.failed_count,
+                (wait + 59) / 60
+            ),
+            (Scope::Terminal, true) => format!(
+                "Terminal locked after {} failed PIN attempts. Ask a manager to unlock it or try again in {} minutes",
+                self.failed_count,
+                (wait + 59) / 60
+            ),
+            (_, false) => format!(
+                "Too many failed PIN attempts. Try again in {} seconds",
+                wait
+            ),
+        }
+    }
+}
+
+struct AttemptRow {
+    failed_count: i64,
+    last_failed_at: Option<DateTime<Utc>>,
+    blocked_until: Option<DateTime<Utc>>,
+    locked: bool,
+}
+
+/// Current block on a user or terminal, if any
+pub fn blocked(
+    conn: &Connection,
+    scope: Scope,
+    subject: &str,
+    now: DateTime<Utc>,
+) -> Result<Option<Attempts>, String> {
+    Ok(
+        load(conn, scope, subject)?.and_then(|row| match row.blocked_until {
+            Some(until) if until > now => Some(Attempts {
+                scope,
+                failed_count: row.failed_count,
+                blocked_until: Some(until),
+                locked: row.locked,
+            }),
+            _ => None,
+        }),
+    )
+}
+
+/// Count a failed attempt and block the user's next one for the backoff
+/// delay, or the user or terminal for the lockout once its limit is
+/// reached. Failures start over after an expired lock or `lockout` without
+/// failures; a failure during a lock extends it.
+pub fn record_failure(
+    conn: &Connection,
+    policy: &LockoutPolicy,
+    scope: Scope,
+    subject: &str,
+    now: DateTime<Utc>,
+) -> Result<Attempts, String> {
+    let previous = load(conn, scope, subject)?.filter(|row| {
+        if row.locked {
+            row.blocked_until.is_some_and(|until| until > now)
+        } else {
+            row.last_failed_at
+                .is_some_and(|at| now.signed_duration_since(at) < policy.lockout)
+        }
+    });
+    let failed_count = previous.map_or(0, |row| row.failed_count).saturating_add(1);
+
+    let locked = failed_count >= policy.max_attempts_for(scope);
+    let blocked_until = match scope {
+        _ if locked => Some(later(now, policy.lockout)),
+        Scope::User => Some(later(now, policy.delay_after(failed_count))),
+        // Other users keep logging in at full speed until the terminal locks
+        Scope::Terminal => None,
+    };
+
+    conn.execute(
+        "INSERT INTO pin_attempts (scope, subject, failed_count, last_failed_at, blocked_until, locked)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT (scope, subject) DO UPDATE SET
+             failed_count = excluded.failed_count,
+             last_failed_at = excluded.last_failed_at,
+             blocked_until = excluded.blocked_until,
+             locked = excluded.locked",
+        params![
+            scope.as_str(),
+            subject,
+            failed_count,
+            now.to_rfc3339(),
+            blocked_until.map(|t| t.to_rfc3339()),
+            locked
+        ],
+    )
+    .map_err(|e| format!("Failed to record PIN attempt: {}", e))?;
+
+    if locked {
+        log::warn!(
+            "PIN {} {} locked after {} failed attempts",
+            scope.as_str(),
+            subject,
+            failed_count
+        );
+    }
+
+    Ok(Attempts {
+        scope,
+        failed_count,
+        blocked_until,
+        locked,
+    })
+}
+
+/// Forget the failures of a user or terminal, after a successful login or
+/// a manager unlock. Returns whether there was anything to clear.
+pub fn reset(conn: &Connection, scope: Scope, subject: &str) -> Result<bool, String> {
+    conn.execute(
+        "DELETE FROM pin_attempts WHERE scope = ? AND subject = ?",
+        params![scope.as_str(), subject],
+    )
+    .map(|n| n > 0)
+    .map_err(|e| format!("Failed to reset PIN attempts: {}", e))
+}
+
+/// Stored failure count of a user or terminal, expired or not, so a caller
+/// can tell whether attempts were recorded since it last looked
+pub fn failed_count(conn: &Connection, scope: Scope, subject: &str) -> Result<i64, String> {
+    Ok(load(conn, scope, subject)?.map_or(0, |row| row.failed_count))
+}
+
+fn load(conn: &Connection, scope: Scope, subject: &str) -> Result<Option<AttemptRow>, String> {
+    conn.query_row(
+        "SELECT failed_count, last_failed_at, blocked_until, locked
+         FROM pin_attempts WHERE scope = ? AND subject = ?",
+        params![scope.as_str(), subject],
+        |row| {
+            Ok(AttemptRow {
+                failed_count: row.get(0)?,
+                last_failed_at: parse_time(row.get(1)?),
+                blocked_until: parse_time(row.get(2)?),
+                locked: row.get(3)?,
+            })
+        },
+    )
+    .optional()
+    .map_err(|e| format!("Failed to read PIN attempts: {}", e))
+}
+
+fn parse_time(value: Option<String>) -> Option<DateTime<Utc>> {
+    value
+        .and_then(|v| DateTime::parse_from_rfc3339(&v).ok())
+        .map(|t| t.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::database::set_setting;
+    use crate::services::migrations::migrate;
+
+    fn db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate(&conn).unwrap();
+        conn
+    }
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2026-03-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn delay_doubles_up_to_the_cap() {
+        let policy = LockoutPolicy::default();
+        let delays: Vec<i64> = (1..=8)
+            .map(|n| policy.delay_after(n).num_seconds())
+            .collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 32, 60, 60]);
+        assert_eq!(policy.delay_after(0).num_seconds(), 1);
+        assert_eq!(policy.delay_after(i64::MAX).num_seconds(), 60);
+    }
+
+    #[test]
+    fn clamps_out_of_range_settings() {
+        let conn = db();
+        for (key, value) in [
+            ("pin_max_attempts", "0"),
+            ("pin_terminal_max_attempts", "99999999"),
+            ("pin_delay_base_seconds", "9223372036854775807"),
+            ("pin_delay_max_seconds", "5"),
+            ("pin_lockout_minutes", "9223372036854775807"),
+        ] {
+            set_setting(&conn, key, value, "security").unwrap();
+        }
+
+        let policy = LockoutPolicy::load(&conn).unwrap();
+        assert_eq!(policy.max_attempts, 5, "zero falls back to the default");
+        assert_eq!(policy.terminal_max_attempts, 1000);
+        assert_eq!(policy.base_delay.num_seconds(), 3600);
+        assert_eq!(policy.max_delay.num_seconds(), 3600, "never below the base");
+        assert_eq!(policy.lockout.num_minutes(), 7 * 24 * 60);
+
+        // Large values must not overflow the arithmetic
+        assert_eq!(policy.delay_after(40).num_seconds(), 3600);
+        let attempts = record_failure(&conn, &policy, Scope::User, "1", now()).unwrap();
+        assert_eq!(attempts.blocked_until, Some(now() + TimeDelta::hours(1)));
+    }
+
+    #[test]
+    fn unparseable_settings_use_the_defaults() {
+        let conn = db();
+        set_setting(&conn, "pin_delay_base_seconds", "soon", "security").unwrap();
+        set_setting(&conn, "pin_lockout_minutes", "-15", "security").unwrap();
+
+        let policy = LockoutPolicy::load(&conn).unwrap();
+        assert_eq!(policy.base_delay.num_seconds(), 1);
+        assert_eq!(policy.lockout.num_minutes(), 15);
+    }
+
+    #[test]
+    fn backs_off_then_locks_the_user() {
+        let conn = db();
+        let policy = LockoutPolicy::default();
+        let now = now();
+
+        let mut waits = Vec::new();
+        for _ in 0..4 {
+            let attempts = record_failure(&conn, &policy, Scope::User, "7", now).unwrap();
+            assert!(!attempts.locked);
+            waits.push((attempts.blocked_until.unwrap() - now).num_seconds());
+        }
+        assert_eq!(waits, [1, 2, 4, 8]);
+        assert!(blocked(&conn, Scope::User, "7", now).unwrap().is_some());
+        assert!(
+            blocked(&conn, Scope::User, "7", now + TimeDelta::seconds(8))
+                .unwrap()
+                .is_none()
+        );
+
+        let attempts = record_failure(&conn, &policy, Scope::User, "7", now).unwrap();
+        assert!(attempts.locked);
+        assert_eq!(attempts.failed_count, 5);
+        assert_eq!(attempts.blocked_until, Some(now + policy.lockout));
+        assert!(attempts.message(now).contains("15 minutes"));
+
+        assert!(reset(&conn, Scope::User, "7").unwrap());
+        assert!(blocked(&conn, Scope::User, "7", now).unwrap().is_none());
+    }
+
+    #[test]
+    fn failures_older_than_the_lockout_are_forgotten() {
+        let conn = db();
+        let policy = LockoutPolicy::default();
+        let now = now();
+
+        record_failure(&conn, &policy, Scope::User, "7", now).unwrap();
+        record_failure(&conn, &policy, Scope::User, "7", now).unwrap();
+        let later = now + policy.lockout + TimeDelta::seconds(1);
+        let attempts = record_failure(&conn, &policy, Scope::User, "7", later).unwrap();
+        assert_eq!(attempts.failed_count, 1);
+    }
+
+    #[test]
+    fn terminal_is_only_blocked_once_locked() {
+        let conn = db();
+        let policy = LockoutPolicy {
+            terminal_max_attempts: 3,
+            ..LockoutPolicy::default()
+        };
+        let now = now();
+
+        for _ in 0..2 {
+            let attempts = record_failure(&conn, &policy, Scope::Terminal, "t", now).unwrap();
+            assert_eq!(attempts.blocked_until, None);
+        }
+        let attempts = record_failure(&conn, &policy, Scope::Terminal, "t", now).unwrap();
+        assert!(attempts.locked);
+        assert!(blocked(&conn, Scope::Terminal, "t", now).unwrap().is_some());
+    }
+}
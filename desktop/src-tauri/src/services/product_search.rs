@@ -0,0 +1,533 @@
+use rusqlite::{Connection, OptionalExtension, ToSql};
+use serde::Serialize;
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+use crate::money::Money;
+
+/// Query terms shorter than this are ignored; single letters match too much
+/// of the catalog to be useful and aren't covered by the prefix indexes
+const MIN_TERM_CHARS: usize = 2;
+const MAX_TERMS: usize = 8;
+
+/// A query made only of digits at least this long is treated as a barcode
+const MIN_BARCODE_PREFIX: usize = 3;
+
+/// Spelling candidates tried per unknown term
+const MAX_CORRECTIONS: usize = 5;
+
+/// How a product matched the query
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MatchKind {
+    /// Exact barcode or barcode prefix
+    Barcode,
+    /// Every term matched as a word prefix
+    Text,
+    /// At least one term only matched after spelling correction
+    Fuzzy,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProductMatch {
+    pub id: i64,
+    pub barcode: Option<String>,
+    pub name: String,
+    pub description: Option<String>,
+    pub category_id: i64,
+    pub category_name: Option<String>,
+    pub price: Money,
+    pub stock_quantity: f64,
+    pub is_active: bool,
+    pub matched_by: MatchKind,
+    /// Higher is more relevant; only comparable within one search
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchFilters {
+    /// Empty means every category
+    pub category_ids: Vec<i64>,
+    pub include_inactive: bool,
+    pub limit: usize,
+}
+
+/// Search active products by barcode, name, description and category.
+/// A numeric query is looked up as a barcode first; text matches are ranked
+/// by bm25 and, when nothing matches, retried with spelling corrections.
+/// An empty query with a category filter lists those categories' products.
+pub fn search_products(
+    conn: &Connection,
+    query: &str,
+    filters: &SearchFilters,
+) -> Result<Vec<ProductMatch>, String> {
+    let query = query.trim();
+    let terms = tokenize(query);
+
+    if terms.is_empty() {
+        return if !query.is_empty() || filters.category_ids.is_empty() {
+            Ok(Vec::new())
+        } else {
+            browse(conn, filters)
+        };
+    }
+
+    let mut results = Vec::new();
+    if query.len() >= MIN_BARCODE_PREFIX && query.chars().all(|c| c.is_ascii_digit()) {
+        results = barcode_lookup(conn, query, filters)?;
+        // A scanned barcode should add exactly one item to the sale
+        let exact = results
+            .first()
+            .filter(|r| r.barcode.as_deref() == Some(query));
+        if let Some(exact) = exact {
+            return Ok(vec![exact.clone()]);
+        }
+        if results.len() >= filters.limit {
+            return Ok(results);
+        }
+    }
+
+    let expression = terms
+        .iter()
+        .map(|t| prefix_term(t))
+        .collect::<Vec<_>>()
+        .join(" AND ");
+    let mut text = full_text(conn, &expression, MatchKind::Text, filters)?;
+
+    if text.is_empty()
+        && results.is_empty()
+        && let Some(expression) = corrected_expression(conn, &terms)?
+    {
+        text = full_text(conn, &expression, MatchKind::Fuzzy, filters)?;
+    }
+
+    for product in text {
+        if results.len() >= filters.limit {
+            break;
+        }
+        if !results.iter().any(|r| r.id == product.id) {
+            results.push(product);
+        }
+    }
+    Ok(results)
+}
+
+/// Lowercase, strip accents and split into words, the same way the
+/// `unicode61 remove_diacritics 2` tokenizer indexes product text
+pub fn tokenize(text: &str) -> Vec<String> {
+    let folded: String = text
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect();
+
+    let mut terms: Vec<String> = Vec::new();
+    for term in folded.split(|c: char| !c.is_alphanumeric()) {
+        if term.chars().count() >= MIN_TERM_CHARS && !terms.iter().any(|t| t == term) {
+            terms.push(term.to_string());
+        }
+    }
+    terms.truncate(MAX_TERMS);
+    terms
+}
+
+/// Terms only contain alphanumerics, but quote them anyway so FTS5 never
+/// reads one as an operator (`and`, `or`, `not`, `near`)
+fn prefix_term(term: &str) -> String {
+    format!("\"{}\"*", term.replace('"', "\"\""))
+}
+
+fn exact_term(term: &str) -> String {
+    format!("\"{}\"", term.replace('"', "\"\""))
+}
+
+const SELECT_PRODUCT: &str = "SELECT p.id, p.barcode, p.name, p.description, p.category_id, c.name,
+        COALESCE(p.price_cents, CAST(ROUND(p.price * 100) AS INTEGER)),
+        p.stock_quantity, p.is_active";
+
+fn barcode_lookup(
+    conn: &Connection,
+    prefix: &str,
+    filters: &SearchFilters,
+) -> Result<Vec<ProductMatch>, String> {
+    let (sql, params) = barcode_query(prefix, filters);
+    query_products(conn, &sql, &params, MatchKind::Barcode)
+}
+
+fn barcode_query(prefix: &str, filters: &SearchFilters) -> (String, Vec<Box<dyn ToSql>>) {
+    // Digits are ASCII, so bumping the last one gives the end of the range.
+    // Without ANALYZE stats the planner would rather scan every active
+    // product through idx_products_active; `+` keeps is_active off the index
+    // so the barcode range drives the lookup.
+    let mut upper = prefix.to_string();
+    let last = upper.pop().map(|c| (c as u8 + 1) as char).unwrap_or('0');
+    upper.push(last);
+
+    let (filter_sql, mut params) = filter_clause(filters, "+p.is_active");
+    let sql = format!(
+        "{SELECT_PRODUCT}, CASE WHEN p.barcode = ?1 THEN 2.0 ELSE 1.0 END AS score
+         FROM products p
+         LEFT JOIN categories c ON c.id = p.category_id
+         WHERE p.barcode >= ?1 AND p.barcode < ?2{filter_sql}
+         ORDER BY score DESC, p.barcode
+         LIMIT {}",
+        filters.limit
+    );
+    params.insert(0, Box::new(upper));
+    params.insert(0, Box::new(prefix.to_string()));
+
+    (sql, params)
+}
+
+fn full_text(
+    conn: &Connection,
+    expression: &str,
+    kind: MatchKind,
+    filters: &SearchFilters,
+) -> Result<Vec<ProductMatch>, String> {
+    let (filter_sql, mut params) = filter_clause(filters, "p.is_active");
+    let sql = format!(
+        "{SELECT_PRODUCT}, -f.rank AS score
+         FROM products_fts f
+         JOIN products p ON p.id = f.rowid
+         LEFT JOIN categories c ON c.id = p.category_id
+         WHERE products_fts MATCH ?1{filter_sql}
+         ORDER BY f.rank
+         LIMIT {}",
+        filters.limit
+    );
+    params.insert(0, Box::new(expression.to_string()));
+
+    query_products(conn, &sql, &params, kind)
+}
+
+fn browse(conn: &Connection, filters: &SearchFilters) -> Result<Vec<ProductMatch>, String> {
+    let (filter_sql, params) = filter_clause(filters, "p.is_active");
+    let sql = format!(
+        "{SELECT_PRODUCT}, 0.0 AS score
+         FROM products p
+         LEFT JOIN categories c ON c.id = p.category_id
+         WHERE 1 = 1{filter_sql}
+         ORDER BY p.name COLLATE NOCASE
+         LIMIT {}",
+        filters.limit
+    );
+    query_products(conn, &sql, &params, MatchKind::Text)
+}
+
+/// Active and category conditions, numbered after the query's own parameters.
+/// `active` is the is_active expression, `+p.is_active` to keep it off
+/// idx_products_active.
+fn filter_clause(filters: &SearchFilters, active: &str) -> (String, Vec<Box<dyn ToSql>>) {
+    let mut sql = String::new();
+    let mut params: Vec<Box<dyn ToSql>> = Vec::new();
+
+    if !filters.include_inactive {
+        sql.push_str(&format!(" AND {} = 1", active));
+    }
+    if !filters.category_ids.is_empty() {
+        let placeholders = vec!["?"; filters.category_ids.len()].join(", ");
+        sql.push_str(&format!(" AND p.category_id IN ({})", placeholders));
+        params.extend(
+            filters
+                .category_ids
+                .iter()
+                .map(|id| Box::new(*id) as Box<dyn ToSql>),
+        );
+    }
+
+    (sql, params)
+}
+
+fn query_products(
+    conn: &Connection,
+    sql: &str,
+    params: &[Box<dyn ToSql>],
+    kind: MatchKind,
+) -> Result<Vec<ProductMatch>, String> {
+    let mut stmt = conn
+        .prepare_cached(sql)
+        .map_err(|e| format!("Failed to prepare product search: {}", e))?;
+
+    stmt.query_map(rusqlite::params_from_iter(params), |row| {
+        Ok(ProductMatch {
+            id: row.get(0)?,
+            barcode: row.get(1)?,
+            name: row.get(2)?,
+            description: row.get(3)?,
+            category_id: row.get(4)?,
+            category_name: row.get(5)?,
+            price: Money::from_cents(row.get(6)?),
+            stock_quantity: row.get(7)?,
+            is_active: row.get(8)?,
+            score: row.get(9)?,
+            matched_by: kind,
+        })
+    })
+    .and_then(|rows| rows.collect())
+    .map_err(|e| format!("Product search failed: {}", e))
+}
+
+/// Rebuild the match expression with close spellings for terms that match
+/// nothing in the index. `None` when some term has no plausible correction.
+fn corrected_expression(conn: &Connection, terms: &[String]) -> Result<Option<String>, String> {
+    let mut parts = Vec::with_capacity(terms.len());
+    let mut corrected = false;
+
+    for term in terms {
+        if has_prefix_match(conn, term)? {
+            parts.push(prefix_term(term));
+            continue;
+        }
+
+        let candidates = spelling_candidates(conn, term)?;
+        if candidates.is_empty() {
+            return Ok(None);
+        }
+        corrected = true;
+        let alternatives: Vec<String> = candidates.iter().map(|c| exact_term(c)).collect();
+        parts.push(format!("({})", alternatives.join(" OR ")));
+    }
+
+    Ok(corrected.then(|| parts.join(" AND ")))
+}
+
+/// The vocabulary is sorted, so the first term at or after `term` is the
+/// only one that needs checking
+fn has_prefix_match(conn: &Connection, term: &str) -> Result<bool, String> {
+    conn.query_row(
+        "SELECT term FROM products_fts_vocab WHERE term >= ?1 ORDER BY term LIMIT 1",
+        [term],
+        |row| row.get::<_, String>(0),
+    )
+    .optional()
+    .map(|next| next.is_some_and(|next| next.starts_with(term)))
+    .map_err(|e| format!("Failed to read search vocabulary: {}", e))
+}
+
+/// Allowed edits for a term of this length; short terms aren't corrected
+fn max_edits(term_chars: usize) -> usize {
+    match term_chars {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// Indexed words within the allowed edit distance of `term`, either as a
+/// whole word or as the start of a longer one (the user is still typing).
+/// Closest first, then most common. Only words with the same first letter
+/// are considered: scanning the whole vocabulary is too slow for
+/// as-you-type search, and typos in the first letter are rare.
+fn spelling_candidates(conn: &Connection, term: &str) -> Result<Vec<String>, String> {
+    let term_chars = term.chars().count();
+    let edits = max_edits(term_chars);
+    // Numbers are barcodes or strengths ("500 mg"); a near miss is a different product
+    if edits == 0 || term.chars().all(|c| c.is_ascii_digit()) {
+        return Ok(Vec::new());
+    }
+
+    let Some(first) = term.chars().next() else {
+        return Ok(Vec::new());
+    };
+    let next = char::from_u32(first as u32 + 1).unwrap_or(char::MAX);
+
+    let mut stmt = conn
+        .prepare_cached("SELECT term, doc FROM products_fts_vocab WHERE term >= ?1 AND term < ?2")
+        .map_err(|e| format!("Failed to read search vocabulary: {}", e))?;
+    let mut rows = stmt
+        .query([first.to_string(), next.to_string()])
+        .map_err(|e| format!("Failed to read search vocabulary: {}", e))?;
+
+    let mut candidates: Vec<(usize, i64, String)> = Vec::new();
+    while let Some(row) = rows
+        .next()
+        .map_err(|e| format!("Failed to read search vocabulary: {}", e))?
+    {
+        let word: String = row
+            .get(0)
+            .map_err(|e| format!("Failed to read search vocabulary: {}", e))?;
+        let distance = edit_distance(term, &word, term_chars);
+        if distance <= edits {
+            let docs: i64 = row.get(1).unwrap_or(0);
+            candidates.push((distance, -docs, word));
+        }
+    }
+
+    candidates.sort();
+    Ok(candidates
+        .into_iter()
+        .take(MAX_CORRECTIONS)
+        .map(|(_, _, word)| word)
+        .collect())
+}
+
+/// Optimal string alignment distance to the whole word or, for longer
+/// words, to its first `term_chars` characters
+fn edit_distance(term: &str, word: &str, term_chars: usize) -> usize {
+    let whole = strsim::osa_distance(term, word);
+    match word.char_indices().nth(term_chars) {
+        Some((end, _)) => whole.min(strsim::osa_distance(term, &word[..end])),
+        None => whole,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::services::migrations;
+
+    /// About the size of a pharmacy's catalog
+    const CATALOG_SIZE: i64 = 20_000;
+    const WORDS: [&str; 12] = [
+        "acetaminofen",
+        "ibuprofeno",
+        "amoxicilina",
+        "loratadina",
+        "omeprazol",
+        "jarabe",
+        "tabletas",
+        "capsulas",
+        "suspension",
+        "crema",
+        "vitamina",
+        "infantil",
+    ];
+
+    fn catalog() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        migrations::migrate(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO categories (id, name) VALUES (1, 'Medicamentos'), (2, 'Higiene');",
+        )
+        .unwrap();
+
+        let tx = conn.unchecked_transaction().unwrap();
+        {
+            let mut insert = tx
+                .prepare(
+                    "INSERT INTO products (barcode, name, description, category_id, price, partner_price, vip_price, is_active)
+                     VALUES (?, ?, ?, ?, 1.5, 1.4, 1.3, ?)",
+                )
+                .unwrap();
+            for i in 0..CATALOG_SIZE {
+                let name = format!(
+                    "{} {} {}mg",
+                    WORDS[i as usize % WORDS.len()],
+                    WORDS[(i as usize / WORDS.len()) % WORDS.len()],
+                    i % 1000
+                );
+                insert
+                    .execute(rusqlite::params![
+                        format!("7401{:09}", i),
+                        name,
+                        WORDS[(i as usize * 7) % WORDS.len()],
+                        1 + i % 2,
+                        i % 10 != 0,
+                    ])
+                    .unwrap();
+            }
+        }
+        tx.commit().unwrap();
+        conn
+    }
+
+    fn filters() -> SearchFilters {
+        SearchFilters {
+            limit: 20,
+            ..SearchFilters::default()
+        }
+    }
+
+    #[test]
+    fn barcode_lookup_uses_the_barcode_index() {
+        let conn = catalog();
+        let (sql, params) = barcode_query("7401", &filters());
+        let plan: Vec<String> = conn
+            .prepare(&format!("EXPLAIN QUERY PLAN {}", sql))
+            .unwrap()
+            .query_map(rusqlite::params_from_iter(&params), |row| {
+                row.get::<_, String>(3)
+            })
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        // The UNIQUE constraint's index or idx_products_barcode
+        assert!(
+            plan.iter().any(
+                |step| step.contains("USING INDEX") && step.contains("barcode")
+                    || step.contains("sqlite_autoindex_products")
+            ),
+            "{:?}",
+            plan
+        );
+        assert!(
+            !plan.iter().any(|step| step.contains("idx_products_active")),
+            "{:?}",
+            plan
+        );
+    }
+
+    #[test]
+    fn finds_products_by_barcode_and_text() {
+        let conn = catalog();
+
+        let exact = search_products(&conn, "7401000000123", &filters()).unwrap();
+        assert_eq!(exact.len(), 1);
+        assert_eq!(exact[0].matched_by, MatchKind::Barcode);
+
+        let prefix = search_products(&conn, "740100000012", &filters()).unwrap();
+        assert!(prefix.iter().all(|p| p.is_active));
+        assert!(!prefix.is_empty());
+
+        let text = search_products(&conn, "Ibuprofeno jarabe", &filters()).unwrap();
+        assert!(!text.is_empty());
+        assert!(text.iter().all(|p| p.matched_by == MatchKind::Text));
+
+        let fuzzy = search_products(&conn, "ibuprofneo", &filters()).unwrap();
+        assert!(!fuzzy.is_empty());
+        assert!(fuzzy.iter().all(|p| p.matched_by == MatchKind::Fuzzy));
+    }
+
+    /// The sale screen searches as the cashier types, so each search has to
+    /// stay under 10 ms. Timings only mean something in an optimized build:
+    /// `cargo test --release -- --ignored search_meets_latency_target`
+    #[test]
+    #[ignore = "timing test, run in release"]
+    fn search_meets_latency_target() {
+        let conn = catalog();
+        conn.execute_batch("ANALYZE;").unwrap();
+
+        for query in [
+            "7401000012345",
+            "74010000",
+            "amoxicilina",
+            "vitamina infantil",
+            "lorat",
+            "omeprasol",
+        ] {
+            // Warm the statement cache like a running app would
+            search_products(&conn, query, &filters()).unwrap();
+
+            let mut timings: Vec<Duration> = (0..20)
+                .map(|_| {
+                    let started = Instant::now();
+                    search_products(&conn, query, &filters()).unwrap();
+                    started.elapsed()
+                })
+                .collect();
+            timings.sort();
+            let median = timings[timings.len() / 2];
+            assert!(
+                median < Duration::from_millis(10),
+                "'{}' took {:?}",
+                query,
+                median
+            );
+        }
+    }
+}
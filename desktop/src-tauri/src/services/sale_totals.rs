@@ -0,0 +1,263 @@
+use rusqlite::Connection;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::money::{Money, RoundingMode};
+use crate::services::database::get_setting;
+
+/// IVA in El Salvador, used when `tax_rate` is not set
+pub const DEFAULT_TAX_RATE_BPS: i64 = 1300;
+
+/// A cart line as the sale screen sends it
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SaleLineInput {
+    pub unit_price: Money,
+    /// Decimal quantity, e.g. 1.5 for weighed products
+    #[serde(deserialize_with = "quantity_from_json")]
+    pub quantity: String,
+    #[serde(default)]
+    pub discount_amount: Money,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SaleLineTotals {
+    pub unit_price: Money,
+    pub quantity: String,
+    pub discount_amount: Money,
+    /// `unit_price * quantity - discount_amount`
+    pub total_price: Money,
+    /// This line's share of the sale-level discount
+    pub allocated_discount: Money,
+}
+
+/// Totals for `transactions` and the DTE summary, exact to the cent: the
+/// lines add up to `subtotal` and the allocated discounts to
+/// `discount_amount`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SaleTotals {
+    pub lines: Vec<SaleLineTotals>,
+    pub subtotal: Money,
+    pub tax_rate_bps: i64,
+    pub tax_amount: Money,
+    pub discount_amount: Money,
+    pub total: Money,
+}
+
+/// Completed sales for the day
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SalesSummary {
+    pub sales_count: i64,
+    pub total_amount: Money,
+    pub average_ticket: Money,
+}
+
+/// Price the lines, add IVA on the subtotal and take off the sale-level
+/// discount, all in integer cents with half-up rounding
+pub fn calculate(
+    lines: &[SaleLineInput],
+    discount_amount: Money,
+    tax_rate_bps: i64,
+) -> Result<SaleTotals, String> {
+    if discount_amount.is_negative() {
+        return Err("Discount can't be negative".to_string());
+    }
+
+    let mut priced = Vec::with_capacity(lines.len());
+    for (i, line) in lines.iter().enumerate() {
+        if line.unit_price.is_negative() || line.discount_amount.is_negative() {
+            return Err(format!("Line {}: amounts can't be negative", i));
+        }
+        let gross = line
+            .unit_price
+            .times_decimal(&line.quantity, RoundingMode::HalfUp)
+            .map_err(|e| format!("Line {}: {}", i, e))?;
+        if line.discount_amount > gross {
+            return Err(format!("Line {}: discount is larger than the line", i));
+        }
+        priced.push(gross - line.discount_amount);
+    }
+
+    let subtotal: Money = priced.iter().copied().sum();
+    if discount_amount > subtotal {
+        return Err("Discount is larger than the sale".to_string());
+    }
+    let tax_amount = subtotal.percent_bps(tax_rate_bps, RoundingMode::HalfUp);
+    let weights: Vec<i64> = priced.iter().map(|total| total.cents()).collect();
+    let allocated = discount_amount.allocate(&weights);
+
+    Ok(SaleTotals {
+        lines: lines
+            .iter()
+            .zip(priced)
+            .zip(allocated)
+            .map(|((line, total_price), allocated_discount)| SaleLineTotals {
+                unit_price: line.unit_price,
+                quantity: line.quantity.clone(),
+                discount_amount: line.discount_amount,
+                total_price,
+                allocated_discount,
+            })
+            .collect(),
+        subtotal,
+        tax_rate_bps,
+        tax_amount,
+        discount_amount,
+        total: subtotal + tax_amount - discount_amount,
+    })
+}
+
+/// The `tax_rate` setting ("13.0", a percentage) in basis points
+pub fn tax_rate_bps(conn: &Connection) -> Result<i64, String> {
+    match get_setting(conn, "tax_rate")? {
+        // A percentage with two decimals is a whole number of basis points
+        Some(rate) => Money::parse_rounded(&rate, RoundingMode::HalfUp)
+            .map(Money::cents)
+            .map_err(|e| format!("Invalid tax_rate setting: {}", e)),
+        None => Ok(DEFAULT_TAX_RATE_BPS),
+    }
+}
+
+/// Today's completed sales, optionally for one cashier, summed from the
+/// `total_cents` column
+pub fn today_summary(conn: &Connection, user_id: Option<i64>) -> Result<SalesSummary, String> {
+    let (sales_count, total_cents): (i64, i64) = conn
+        .query_row(
+            "SELECT COUNT(*), COALESCE(SUM(total_cents), 0) FROM transactions
+             WHERE status = 'completed'
+               AND DATE(created_at) = DATE('now', 'localtime')
+               AND (?1 IS NULL OR user_id = ?1)",
+            [user_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|e| format!("Failed to summarize sales: {}", e))?;
+
+    let total_amount = Money::from_cents(total_cents);
+    let average_ticket = match sales_count {
+        0 => Money::ZERO,
+        count => total_amount.divide(count, RoundingMode::HalfUp),
+    };
+    Ok(SalesSummary {
+        sales_count,
+        total_amount,
+        average_ticket,
+    })
+}
+
+/// Accept `1.5` or `"1.5"`, and only positive plain decimals
+fn quantity_from_json<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let quantity = match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Number(n) => n.to_string(),
+        serde_json::Value::String(s) => s,
+        other => {
+            return Err(serde::de::Error::custom(format!(
+                "Invalid quantity {}",
+                other
+            )));
+        }
+    };
+
+    let (int_part, frac_part) = quantity.split_once('.').unwrap_or((&quantity, ""));
+    let valid = !int_part.is_empty()
+        && int_part.chars().all(|c| c.is_ascii_digit())
+        && frac_part.chars().all(|c| c.is_ascii_digit())
+        && quantity.chars().any(|c| ('1'..='9').contains(&c));
+    if valid {
+        Ok(quantity)
+    } else {
+        Err(serde::de::Error::custom(format!(
+            "Invalid quantity '{}'",
+            quantity
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(unit_price: &str, quantity: &str, discount: &str) -> SaleLineInput {
+        SaleLineInput {
+            unit_price: unit_price.parse().unwrap(),
+            quantity: quantity.to_string(),
+            discount_amount: discount.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn prices_lines_and_tax_in_cents() {
+        let totals = calculate(
+            &[line("3.25", "3", "0.25"), line("0.99", "1.5", "0")],
+            "0.00".parse().unwrap(),
+            1300,
+        )
+        .unwrap();
+
+        assert_eq!(totals.lines[0].total_price.cents(), 950);
+        // 0.99 * 1.5 = 1.485 -> 1.49
+        assert_eq!(totals.lines[1].total_price.cents(), 149);
+        assert_eq!(totals.subtotal.cents(), 1099);
+        // 13% of 10.99 = 1.4287 -> 1.43
+        assert_eq!(totals.tax_amount.cents(), 143);
+        assert_eq!(totals.total.cents(), 1242);
+    }
+
+    #[test]
+    fn allocates_sale_discount_exactly() {
+        let totals = calculate(
+            &[
+                line("1.00", "1", "0"),
+                line("1.00", "1", "0"),
+                line("1.00", "1", "0"),
+            ],
+            "1.00".parse().unwrap(),
+            1300,
+        )
+        .unwrap();
+
+        let allocated: Vec<i64> = totals
+            .lines
+            .iter()
+            .map(|l| l.allocated_discount.cents())
+            .collect();
+        assert_eq!(allocated.iter().sum::<i64>(), 100);
+        assert_eq!(allocated, [34, 33, 33]);
+        assert_eq!(totals.total.cents(), 300 + 39 - 100);
+    }
+
+    #[test]
+    fn rejects_oversized_discounts() {
+        assert!(calculate(&[line("1.00", "1", "1.01")], Money::ZERO, 1300).is_err());
+        assert!(calculate(&[line("1.00", "1", "0")], "1.01".parse().unwrap(), 1300).is_err());
+    }
+
+    #[test]
+    fn parses_quantities() {
+        let parse = |json: &str| serde_json::from_str::<SaleLineInput>(json).map(|l| l.quantity);
+        assert_eq!(parse(r#"{"unitPrice":"1.00","quantity":2}"#).unwrap(), "2");
+        assert_eq!(
+            parse(r#"{"unitPrice":"1.00","quantity":"1.5"}"#).unwrap(),
+            "1.5"
+        );
+        assert!(parse(r#"{"unitPrice":"1.00","quantity":0}"#).is_err());
+        assert!(parse(r#"{"unitPrice":"1.00","quantity":-1}"#).is_err());
+        assert!(parse(r#"{"unitPrice":"1.00","quantity":"1e3"}"#).is_err());
+    }
+
+    #[test]
+    fn reads_tax_rate_setting() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE system_settings (key TEXT PRIMARY KEY, value TEXT)")
+            .unwrap();
+        assert_eq!(tax_rate_bps(&conn), Ok(DEFAULT_TAX_RATE_BPS));
+
+        conn.execute(
+            "INSERT INTO system_settings (key, value) VALUES ('tax_rate', '13.0')",
+            [],
+        )
+        .unwrap();
+        assert_eq!(tax_rate_bps(&conn), Ok(1300));
+    }
+}
@@ -0,0 +1,179 @@
+use rusqlite::{Connection, OptionalExtension, params};
+use serde_json::json;
+
+use crate::services::audit::{self, AuditEntry};
+
+/// What voiding a sale changed besides its status
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct VoidOutcome {
+    /// Stock movements written to put the sold units back
+    pub stock_reversed: usize,
+    /// DTEs flagged for an invalidation event
+    pub dte_flagged: usize,
+}
+
+/// Cancel a completed sale, in the caller's transaction: put its sold units
+/// back with `return` stock movements, flag its DTEs for invalidation and
+/// write `sale_voided` to `audit_logs`. `approved_by` is the supervisor who
+/// approved it for a user without `can_void`.
+pub fn void_sale(
+    conn: &Connection,
+    sale_id: i64,
+    reason: Option<&str>,
+    user_id: i64,
+    approved_by: Option<i64>,
+) -> Result<VoidOutcome, String> {
+    let status: Option<String> = conn
+        .query_row(
+            "SELECT status FROM transactions WHERE id = ?",
+            [sale_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| format!("Failed to read sale {}: {}", sale_id, e))?;
+
+    match status.as_deref() {
+        None => return Err("Sale not found".to_string()),
+        Some("completed") => {}
+        Some(_) => return Err("Only completed sales can be voided".to_string()),
+    }
+
+    let notes = match reason {
+        Some(reason) => format!("ANULADO: {}", reason),
+        None => "ANULADO".to_string(),
+    };
+    conn.execute(
+        "UPDATE transactions SET status = 'cancelled', notes = ?, updated_at = CURRENT_TIMESTAMP
+         WHERE id = ?",
+        params![notes, sale_id],
+    )
+    .map_err(|e| format!("Failed to void sale {}: {}", sale_id, e))?;
+
+    let outcome = VoidOutcome {
+        stock_reversed: reverse_stock(conn, sale_id, user_id)?,
+        dte_flagged: conn
+            .execute(
+                "UPDATE dte SET invalidation_requested_at = CURRENT_TIMESTAMP,
+                     dte_updated_at = CURRENT_TIMESTAMP
+                 WHERE transaction_id = ? AND dte_type <> 'INVALIDACION'
+                   AND dte_status <> 'rejected' AND invalidation_requested_at IS NULL",
+                [sale_id],
+            )
+            .map_err(|e| format!("Failed to flag DTEs of sale {}: {}", sale_id, e))?,
+    };
+
+    audit::record(
+        conn,
+        AuditEntry {
+            user_id: Some(user_id),
+            action: "sale_voided",
+            table_name: Some("transactions"),
+            record_id: Some(sale_id),
+            old_values: Some(json!({ "status": "completed" })),
+            new_values: Some(json!({
+                "status": "cancelled",
+                "reason": reason,
+                "approved_by": approved_by,
+                "stock_reversed": outcome.stock_reversed,
+                "dte_flagged": outcome.dte_flagged,
+            })),
+        },
+    )?;
+    Ok(outcome)
+}
+
+/// Write a `return` movement undoing each `sale` movement of the sale and
+/// apply it to `products.stock_quantity`
+fn reverse_stock(conn: &Connection, sale_id: i64, user_id: i64) -> Result<usize, String> {
+    let movements = conn
+        .prepare(
+            "SELECT product_id, quantity, unit_cost FROM stock_movements
+             WHERE reference_type = 'transaction' AND reference_id = ?
+               AND movement_type = 'sale'",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map([sale_id], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, f64>(1)?,
+                    row.get::<_, Option<f64>>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| format!("Failed to read stock movements of sale {}: {}", sale_id, e))?;
+
+    for (product_id, quantity, unit_cost) in &movements {
+        conn.execute(
+            "INSERT INTO stock_movements
+                 (product_id, movement_type, quantity, reference_type, reference_id,
+                  unit_cost, notes, user_id)
+             VALUES (?1, 'return', ?2, 'transaction', ?3, ?4, 'ANULADO', ?5)",
+            params![product_id, -quantity, sale_id, unit_cost, user_id],
+        )
+        .and_then(|_| {
+            conn.execute(
+                "UPDATE products SET stock_quantity = stock_quantity + ?1 WHERE id = ?2",
+                params![-quantity, product_id],
+            )
+        })
+        .map_err(|e| format!("Failed to restore stock of product {}: {}", product_id, e))?;
+    }
+    Ok(movements.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sale_with_stock() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        crate::services::migrations::migrate(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO users (id, username, pin_hash, role, full_name, permissions)
+                 VALUES (1, 'ana', 'x', 'cashier', 'Ana', '{}');
+             INSERT INTO categories (id, name) VALUES (1, 'Bebidas');
+             INSERT INTO products (id, name, category_id, price, partner_price, vip_price, stock_quantity)
+                 VALUES (1, 'Café', 1, 2.5, 2.5, 2.5, 8);
+             INSERT INTO transactions (id, transaction_number, user_id, subtotal, tax_amount, total)
+                 VALUES (1, 'T-1', 1, 5, 0, 5);
+             INSERT INTO stock_movements (product_id, movement_type, quantity, reference_type, reference_id)
+                 VALUES (1, 'sale', -2, 'transaction', 1);
+             INSERT INTO dte (transaction_id, dte_type, dte_control_number, dte_date, dte_json, dte_status)
+                 VALUES (1, 'FCF', 'DTE-1', '2026-01-01', '{}', 'accepted');",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn void_cancels_restocks_and_flags_the_dte() {
+        let conn = sale_with_stock();
+        let outcome = void_sale(&conn, 1, Some("error de cobro"), 1, Some(2)).unwrap();
+        assert_eq!(
+            outcome,
+            VoidOutcome {
+                stock_reversed: 1,
+                dte_flagged: 1
+            }
+        );
+
+        let (status, stock, returned, flagged): (String, f64, f64, bool) = conn
+            .query_row(
+                "SELECT t.status, p.stock_quantity,
+                     (SELECT quantity FROM stock_movements WHERE movement_type = 'return'),
+                     (SELECT invalidation_requested_at IS NOT NULL FROM dte)
+                 FROM transactions t, products p",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(status, "cancelled");
+        assert_eq!(stock, 10.0);
+        assert_eq!(returned, 2.0);
+        assert!(flagged);
+
+        // A cancelled sale can't be voided again, so stock isn't restored twice
+        assert!(void_sale(&conn, 1, None, 1, None).is_err());
+    }
+}
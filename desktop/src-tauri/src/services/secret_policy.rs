@@ -0,0 +1,186 @@
+use crate::error::AppError;
+use crate::services::auth_session::Session;
+use crate::services::permissions::Permission;
+
+/// Bearer token for the sync backend, read by `services/sync.rs`
+pub const SYNC_TOKEN: &str = "sync_api_token";
+
+/// Windows a secret may be read or written from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowScope {
+    Main,
+    Any,
+}
+
+/// Who may read a secret through `get_secret`, and store or remove it
+/// through `store_secret` / `remove_secret`
+#[derive(Debug, Clone, Copy)]
+pub struct SecretPolicy {
+    /// Whether the value may leave Rust at all
+    pub readable: bool,
+    pub windows: WindowScope,
+    /// Needed on top of a valid session to read it
+    pub permission: Option<Permission>,
+    /// Needed to store or remove it
+    pub write_permission: Permission,
+}
+
+/// Used only inside Rust; `get_secret` never returns it
+const RUST_ONLY: SecretPolicy = SecretPolicy {
+    readable: false,
+    windows: WindowScope::Main,
+    permission: None,
+    write_permission: Permission::CanManageCertificates,
+};
+
+/// Policy for a secure storage key. Anything not listed stays in Rust,
+/// including the signing certificate (`dte_certificate`,
+/// `dte_private_key_*`), its password (`dte_cert_password`), the database
+/// key and the vault password.
+pub fn policy(key: &str) -> SecretPolicy {
+    match key {
+        // Sync backend credential, shown and edited on the settings screen
+        SYNC_TOKEN => SecretPolicy {
+            readable: true,
+            windows: WindowScope::Main,
+            permission: Some(Permission::CanManageSettings),
+            write_permission: Permission::CanManageSettings,
+        },
+        _ => RUST_ONLY,
+    }
+}
+
+/// Whether `session`, in the window it is bound to, may read `key`. Every
+/// refusal is logged with the key, user and window.
+pub fn check(key: &str, session: &Session) -> Result<(), AppError> {
+    let policy = policy(key);
+    if !policy.readable {
+        return deny("get_secret", key, session, "never leaves Rust");
+    }
+    check_scope(
+        "get_secret",
+        key,
+        session,
+        policy.windows,
+        policy.permission,
+    )
+}
+
+/// Whether `session` may store or remove `key`. Refusals are logged like
+/// `check`'s.
+pub fn check_write(key: &str, session: &Session) -> Result<(), AppError> {
+    let policy = policy(key);
+    check_scope(
+        "store/remove_secret",
+        key,
+        session,
+        policy.windows,
+        Some(policy.write_permission),
+    )
+}
+
+fn check_scope(
+    command: &str,
+    key: &str,
+    session: &Session,
+    windows: WindowScope,
+    permission: Option<Permission>,
+) -> Result<(), AppError> {
+    if windows == WindowScope::Main && session.window_label != "main" {
+        return deny(command, key, session, "main window only");
+    }
+    match permission {
+        Some(permission) if !session.has(permission) => deny(
+            command,
+            key,
+            session,
+            &format!("missing {}", permission.as_str()),
+        ),
+        _ => Ok(()),
+    }
+}
+
+fn deny(command: &str, key: &str, session: &Session, reason: &str) -> Result<(), AppError> {
+    log::warn!(
+        "{} '{}' denied for user {} in window '{}': {}",
+        command,
+        key,
+        session.user.id,
+        session.window_label,
+        reason
+    );
+    Err(AppError::Forbidden(format!(
+        "Secret '{}' can't be accessed here",
+        key
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::services::auth_session::SessionManager;
+    use crate::services::pin_auth::AuthenticatedUser;
+
+    fn session(window: &str, role: &str) -> Session {
+        let sessions = SessionManager::new();
+        sessions.start(
+            window,
+            AuthenticatedUser {
+                id: 1,
+                username: role.to_string(),
+                full_name: role.to_string(),
+                role: role.to_string(),
+                permissions: json!({}),
+            },
+            Duration::from_secs(60),
+        );
+        sessions.current(window).unwrap()
+    }
+
+    fn forbidden(result: Result<(), AppError>) -> bool {
+        matches!(result, Err(AppError::Forbidden(_)))
+    }
+
+    #[test]
+    fn sync_token_needs_settings_in_the_main_window() {
+        let policy = policy(SYNC_TOKEN);
+        assert!(policy.readable);
+        assert_eq!(policy.permission, Some(Permission::CanManageSettings));
+        assert_eq!(policy.write_permission, Permission::CanManageSettings);
+
+        let admin = session("main", "admin");
+        assert!(check(SYNC_TOKEN, &admin).is_ok());
+        assert!(check_write(SYNC_TOKEN, &admin).is_ok());
+
+        let sale_window = session("pos-sale-1", "admin");
+        assert!(forbidden(check(SYNC_TOKEN, &sale_window)));
+        assert!(forbidden(check_write(SYNC_TOKEN, &sale_window)));
+
+        let supervisor = session("main", "supervisor");
+        assert!(forbidden(check(SYNC_TOKEN, &supervisor)));
+        assert!(forbidden(check_write(SYNC_TOKEN, &supervisor)));
+    }
+
+    #[test]
+    fn other_keys_never_leave_rust() {
+        let admin = session("main", "admin");
+        for key in ["dte_cert_password", "dte_private_key_1", "unknown"] {
+            assert!(!policy(key).readable);
+            assert!(forbidden(check(key, &admin)), "{}", key);
+            assert!(check_write(key, &admin).is_ok(), "{}", key);
+        }
+
+        assert!(forbidden(check_write(
+            "dte_cert_password",
+            &session("main", "supervisor")
+        )));
+        assert!(forbidden(check_write(
+            "dte_cert_password",
+            &session("pos-sale-1", "admin")
+        )));
+    }
+}
@@ -1,16 +1,42 @@
+use iota_stronghold::{Client, KeyProvider, SnapshotPath, Stronghold};
 use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use tauri::Manager;
 use tokio::sync::RwLock;
+use zeroize::Zeroizing;
 
-#[derive(Debug, Clone)]
+use crate::error::AppError;
+use crate::services::vault_key::{self, VaultKdf};
+
+/// Secure storage snapshot in the app data dir
+pub const VAULT_FILE: &str = "secure-storage.stronghold";
+
+/// Stronghold client that holds the secrets inside the snapshot
+const CLIENT_PATH: &[u8] = b"secure-storage";
+
+/// Encrypted key-value vault persisted as a Stronghold snapshot. Closed until
+/// `initialize` opens it with the vault password.
+#[derive(Clone)]
 pub struct SecureStorageManager {
-    initialized: Arc<RwLock<bool>>,
+    vault: Arc<RwLock<Option<Arc<Vault>>>>,
+}
+
+/// An open snapshot and the key it is written with
+struct Vault {
+    stronghold: Stronghold,
+    client: Client,
+    snapshot_path: SnapshotPath,
+    key: KeyProvider,
 }
 
 #[derive(Debug, thiserror::Error, Serialize)]
 pub enum SecureStorageError {
     #[error("Storage not initialized")]
     NotInitialized,
+    #[error("Wrong vault password")]
+    WrongPassword,
     #[error("Storage error: {0}")]
     StorageError(String),
     #[error("Serialization error: {0}")]
@@ -29,88 +55,387 @@ impl From<&str> for SecureStorageError {
     }
 }
 
+impl From<SecureStorageError> for AppError {
+    fn from(error: SecureStorageError) -> Self {
+        match error {
+            SecureStorageError::WrongPassword => AppError::Auth(error.to_string()),
+            _ => AppError::System(error.to_string()),
+        }
+    }
+}
+
 impl From<serde_json::Error> for SecureStorageError {
     fn from(error: serde_json::Error) -> Self {
         SecureStorageError::SerializationError(error.to_string())
     }
 }
 
-#[allow(dead_code)]
+/// `secure-storage.stronghold` in the app data dir, which is created if
+/// missing
+pub fn vault_path(app_handle: &tauri::AppHandle) -> Result<String, SecureStorageError> {
+    let app_data_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data dir: {}", e))?;
+    fs::create_dir_all(&app_data_dir)
+        .map_err(|e| format!("Failed to create app data dir: {}", e))?;
+
+    let vault_path = app_data_dir.join(VAULT_FILE);
+    vault_path
+        .to_str()
+        .map(str::to_string)
+        .ok_or_else(|| SecureStorageError::from("Invalid vault path"))
+}
+
 impl SecureStorageManager {
     pub fn new() -> Self {
         Self {
-            initialized: Arc::new(RwLock::new(false)),
+            vault: Arc::new(RwLock::new(None)),
         }
     }
 
+    /// Open the snapshot at `vault_path` with the key derived from
+    /// `password`, creating an empty vault and its key parameters if the
+    /// file doesn't exist yet. Fails with `WrongPassword` before the
+    /// snapshot is touched.
     pub async fn initialize(
         &self,
-        _password_hash: Vec<u8>,
-        _vault_path: &str,
+        password: Zeroizing<String>,
+        vault_path: &str,
     ) -> Result<(), SecureStorageError> {
-        // In Tauri v2, Stronghold initialization is handled by the plugin
-        // We just track that the manager has been "initialized"
-        let mut initialized = self.initialized.write().await;
-        *initialized = true;
+        let snapshot_file = PathBuf::from(vault_path);
+
+        let vault = blocking(move || {
+            let kdf_path = vault_key::kdf_path(&snapshot_file);
+            let snapshot_path = SnapshotPath::from_path(&snapshot_file);
+            recover_interrupted_change(&snapshot_path, &kdf_path, password.as_bytes())?;
+
+            let stronghold = Stronghold::default();
+
+            let (key, client) = if snapshot_path.exists() {
+                let kdf = VaultKdf::load(&kdf_path)?.ok_or("Vault key parameters are missing")?;
+                let key = kdf
+                    .unlock(password.as_bytes())?
+                    .ok_or(SecureStorageError::WrongPassword)?;
+                let key = key_provider(key)?;
+                let client = stronghold
+                    .load_client_from_snapshot(CLIENT_PATH, &key, &snapshot_path)
+                    .map_err(|e| format!("Failed to open vault: {}", e))?;
+                (key, client)
+            } else {
+                log::info!(
+                    "Creating secure storage vault at {}",
+                    snapshot_file.display()
+                );
+                let derived = VaultKdf::create(password.as_bytes())?;
+                derived.kdf.save(&kdf_path)?;
+                let client = stronghold
+                    .create_client(CLIENT_PATH)
+                    .map_err(|e| format!("Failed to create vault: {}", e))?;
+                (key_provider(derived.key)?, client)
+            };
+
+            let vault = Vault {
+                stronghold,
+                client,
+                snapshot_path,
+                key,
+            };
+            vault.commit()?;
+            Ok(vault)
+        })
+        .await?;
+
+        if let Some(previous) = self.vault.write().await.replace(Arc::new(vault)) {
+            previous.close();
+        }
+        Ok(())
+    }
+
+    /// Re-encrypt the open vault under a key derived from `new_password`
+    /// with a fresh salt. The snapshot and then its key parameters are
+    /// written as `.pending` files and swapped in afterwards; both are
+    /// removed if that fails, and `initialize` finishes or rolls back a swap
+    /// a crash interrupted.
+    pub async fn change_password(
+        &self,
+        current_password: Zeroizing<String>,
+        new_password: Zeroizing<String>,
+    ) -> Result<(), SecureStorageError> {
+        let mut guard = self.vault.write().await;
+        let vault = guard.clone().ok_or(SecureStorageError::NotInitialized)?;
+
+        let rekeyed = blocking(move || {
+            let snapshot_file = vault.snapshot_path.as_path().to_path_buf();
+            let kdf_path = vault_key::kdf_path(&snapshot_file);
+
+            let kdf = VaultKdf::load(&kdf_path)?.ok_or("Vault key parameters are missing")?;
+            if kdf.unlock(current_password.as_bytes())?.is_none() {
+                return Err(SecureStorageError::WrongPassword);
+            }
+
+            let derived = VaultKdf::create(new_password.as_bytes())?;
+            let rekeyed = Vault {
+                stronghold: vault.stronghold.clone(),
+                client: vault.client.clone(),
+                snapshot_path: vault.snapshot_path.clone(),
+                key: key_provider(derived.key)?,
+            };
+
+            let pending_snapshot = vault_key::pending_path(&snapshot_file);
+            let pending_kdf = vault_key::pending_path(&kdf_path);
+            // The snapshot goes first, so a `.kdf.pending` only exists next
+            // to a complete `.stronghold.pending` or once the new snapshot
+            // replaced the old one
+            let swapped = rekeyed
+                .stronghold
+                .commit_with_keyprovider(&SnapshotPath::from_path(&pending_snapshot), &rekeyed.key)
+                .map_err(|e| format!("Failed to write vault: {}", e))
+                .and_then(|_| derived.kdf.write(&pending_kdf))
+                .and_then(|_| {
+                    fs::rename(&pending_snapshot, &snapshot_file)
+                        .map_err(|e| format!("Failed to replace vault: {}", e))
+                });
+            if let Err(e) = swapped {
+                discard(&pending_snapshot);
+                discard(&pending_kdf);
+                return Err(e.into());
+            }
+            if let Err(e) = fs::rename(&pending_kdf, &kdf_path) {
+                log::warn!("Vault key parameters left pending until restart: {}", e);
+            }
+            Ok(rekeyed)
+        })
+        .await?;
+
+        *guard = Some(Arc::new(rekeyed));
+        log::info!("Secure storage vault password changed");
         Ok(())
     }
 
     pub async fn is_initialized(&self) -> bool {
-        *self.initialized.read().await
+        self.vault.read().await.is_some()
     }
 
+    /// Store `secret_data` under `key`, replacing any previous value, and
+    /// write the snapshot
     pub async fn store_secret(
         &self,
-        _key: &str,
-        _secret_data: &[u8],
+        key: &str,
+        secret_data: &[u8],
     ) -> Result<(), SecureStorageError> {
-        if !self.is_initialized().await {
-            return Err(SecureStorageError::NotInitialized);
-        }
+        let vault = self.open().await?;
+        let (key, secret_data) = (key.as_bytes().to_vec(), secret_data.to_vec());
 
-        // In Tauri v2, secret storage is handled by the Stronghold plugin via JavaScript
-        // This method will be called from commands that interact with the plugin
-        Ok(())
+        blocking(move || {
+            vault
+                .client
+                .store()
+                .insert(key, secret_data, None)
+                .map_err(|e| format!("Failed to store secret: {}", e))?;
+            vault.commit()
+        })
+        .await
     }
 
-    pub async fn get_secret(&self, _key: &str) -> Result<Vec<u8>, SecureStorageError> {
-        if !self.is_initialized().await {
-            return Err(SecureStorageError::NotInitialized);
-        }
+    /// The secret under `key`, `None` if there is none
+    pub async fn get_secret(&self, key: &str) -> Result<Option<Vec<u8>>, SecureStorageError> {
+        let vault = self.open().await?;
+        Ok(vault
+            .client
+            .store()
+            .get(key.as_bytes())
+            .map_err(|e| format!("Failed to read secret: {}", e))?)
+    }
 
-        // This will be implemented via Stronghold plugin commands
-        // For now, return empty data
-        Ok(vec![])
+    pub async fn has_secret(&self, key: &str) -> Result<bool, SecureStorageError> {
+        let vault = self.open().await?;
+        Ok(vault
+            .client
+            .store()
+            .contains_key(key.as_bytes())
+            .map_err(|e| format!("Failed to read secret: {}", e))?)
     }
 
-    pub async fn has_secret(&self, _key: &str) -> Result<bool, SecureStorageError> {
-        if !self.is_initialized().await {
-            return Err(SecureStorageError::NotInitialized);
-        }
+    /// Delete the secret under `key`; a missing key is not an error
+    pub async fn remove_secret(&self, key: &str) -> Result<(), SecureStorageError> {
+        let vault = self.open().await?;
+        let key = key.as_bytes().to_vec();
 
-        // This will be implemented via Stronghold plugin commands
-        Ok(false)
+        blocking(move || {
+            let removed = vault
+                .client
+                .store()
+                .delete(&key)
+                .map_err(|e| format!("Failed to remove secret: {}", e))?;
+            if removed.is_some() {
+                vault.commit()?;
+            }
+            Ok(())
+        })
+        .await
     }
 
-    pub async fn remove_secret(&self, _key: &str) -> Result<(), SecureStorageError> {
-        if !self.is_initialized().await {
-            return Err(SecureStorageError::NotInitialized);
-        }
+    /// Keys of all stored secrets, sorted
+    pub async fn list_keys(&self) -> Result<Vec<String>, SecureStorageError> {
+        let vault = self.open().await?;
+        let mut keys: Vec<String> = vault
+            .client
+            .store()
+            .keys()
+            .map_err(|e| format!("Failed to list secrets: {}", e))?
+            .into_iter()
+            .map(|key| String::from_utf8_lossy(&key).into_owned())
+            .collect();
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// Delete every secret, write the now empty snapshot and close the vault
+    pub async fn clear(&self) -> Result<(), SecureStorageError> {
+        let mut guard = self.vault.write().await;
+        let vault = guard.clone().ok_or(SecureStorageError::NotInitialized)?;
+
+        blocking(move || {
+            vault
+                .client
+                .store()
+                .clear()
+                .map_err(|e| format!("Failed to clear secrets: {}", e))?;
+            vault.commit()?;
+            vault.close();
+            Ok(())
+        })
+        .await?;
 
-        // This will be implemented via Stronghold plugin commands
+        *guard = None;
         Ok(())
     }
 
-    pub async fn clear(&self) -> Result<(), SecureStorageError> {
-        if !self.is_initialized().await {
-            return Err(SecureStorageError::NotInitialized);
+    async fn open(&self) -> Result<Arc<Vault>, SecureStorageError> {
+        self.vault
+            .read()
+            .await
+            .clone()
+            .ok_or(SecureStorageError::NotInitialized)
+    }
+}
+
+/// Finish or roll back a password change cut short. Both pending files
+/// mean the old snapshot is still in place. A lone pending key file is
+/// promoted only if the snapshot opens with it and `password`, since then
+/// the new snapshot already replaced the old one; one left by a change that
+/// never got that far is removed once the current parameters open it.
+fn recover_interrupted_change(
+    snapshot_path: &SnapshotPath,
+    kdf_path: &Path,
+    password: &[u8],
+) -> Result<(), SecureStorageError> {
+    let pending_snapshot = vault_key::pending_path(snapshot_path.as_path());
+    let pending_kdf = vault_key::pending_path(kdf_path);
+
+    if pending_snapshot.exists() {
+        log::warn!("Discarding interrupted vault password change");
+        remove(&pending_snapshot)?;
+        if pending_kdf.exists() {
+            remove(&pending_kdf)?;
         }
+        return Ok(());
+    }
+    if !pending_kdf.exists() {
+        return Ok(());
+    }
 
-        // Clear in-memory state only
-        let mut initialized = self.initialized.write().await;
-        *initialized = false;
-        Ok(())
+    let pending = match VaultKdf::load(&pending_kdf) {
+        Ok(pending) => pending,
+        Err(e) => {
+            log::warn!("Removing unreadable pending vault key parameters: {}", e);
+            return Ok(remove(&pending_kdf)?);
+        }
+    };
+    if !snapshot_path.exists() {
+        return Ok(remove(&pending_kdf)?);
+    }
+    if let Some(pending) = pending
+        && opens_snapshot(snapshot_path, &pending, password)?
+    {
+        log::warn!("Completing interrupted vault password change");
+        return fs::rename(&pending_kdf, kdf_path)
+            .map_err(|e| format!("Failed to replace vault key parameters: {}", e).into());
     }
+    if let Some(current) = VaultKdf::load(kdf_path)?
+        && opens_snapshot(snapshot_path, &current, password)?
+    {
+        log::warn!("Discarding vault key parameters of an unfinished password change");
+        return Ok(remove(&pending_kdf)?);
+    }
+    // Neither opens the snapshot with this password; keep both for the
+    // right one
+    Err(SecureStorageError::WrongPassword)
+}
+
+/// Whether the snapshot decrypts with the key `kdf` derives from `password`
+fn opens_snapshot(
+    snapshot_path: &SnapshotPath,
+    kdf: &VaultKdf,
+    password: &[u8],
+) -> Result<bool, SecureStorageError> {
+    let Some(key) = kdf.unlock(password)? else {
+        return Ok(false);
+    };
+    let stronghold = Stronghold::default();
+    let opened = stronghold
+        .load_client_from_snapshot(CLIENT_PATH, &key_provider(key)?, snapshot_path)
+        .is_ok();
+    if let Err(e) = stronghold.clear() {
+        log::warn!("Failed to clear vault from memory: {}", e);
+    }
+    Ok(opened)
+}
+
+fn remove(path: &Path) -> Result<(), String> {
+    fs::remove_file(path).map_err(|e| format!("Failed to remove {}: {}", path.display(), e))
+}
+
+/// Remove a pending file after a failed password change, if it was written
+fn discard(path: &Path) {
+    if path.exists()
+        && let Err(e) = remove(path)
+    {
+        log::warn!("{}", e);
+    }
+}
+
+fn key_provider(key: Zeroizing<Vec<u8>>) -> Result<KeyProvider, SecureStorageError> {
+    KeyProvider::try_from(key).map_err(|e| format!("Invalid vault key: {:?}", e).into())
+}
+
+impl Vault {
+    /// Write the client state to the snapshot file, encrypted with the key.
+    /// Slow on purpose (age's scrypt), so callers run it on a blocking thread.
+    fn commit(&self) -> Result<(), SecureStorageError> {
+        self.stronghold
+            .commit_with_keyprovider(&self.snapshot_path, &self.key)
+            .map_err(|e| SecureStorageError::from(format!("Failed to write vault: {}", e)))
+    }
+
+    /// Wipe the decrypted state from memory; the snapshot file is untouched
+    fn close(&self) {
+        if let Err(e) = self.stronghold.clear() {
+            log::warn!("Failed to clear vault from memory: {}", e);
+        }
+    }
+}
+
+/// Run snapshot I/O off the async runtime
+async fn blocking<T, F>(f: F) -> Result<T, SecureStorageError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, SecureStorageError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| SecureStorageError::from(format!("Vault task failed: {}", e)))?
 }
 
 impl Default for SecureStorageManager {
@@ -118,3 +443,136 @@ impl Default for SecureStorageManager {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh app data dir for one test's vault
+    fn vault_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pos-vault-{}-{}", name, uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(VAULT_FILE)
+    }
+
+    fn password(password: &str) -> Zeroizing<String> {
+        Zeroizing::new(password.to_string())
+    }
+
+    async fn open(
+        snapshot_file: &Path,
+        pw: &str,
+    ) -> Result<SecureStorageManager, SecureStorageError> {
+        let storage = SecureStorageManager::new();
+        storage
+            .initialize(password(pw), snapshot_file.to_str().unwrap())
+            .await?;
+        Ok(storage)
+    }
+
+    async fn with_secret(snapshot_file: &Path, pw: &str) {
+        let storage = open(snapshot_file, pw).await.unwrap();
+        storage.store_secret("api_token", b"s3cret").await.unwrap();
+    }
+
+    async fn secret(storage: &SecureStorageManager) -> Option<Vec<u8>> {
+        storage.get_secret("api_token").await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn promotes_pending_key_that_opens_the_snapshot() {
+        let snapshot_file = vault_file("promote");
+        let kdf_path = vault_key::kdf_path(&snapshot_file);
+        let pending_kdf = vault_key::pending_path(&kdf_path);
+        with_secret(&snapshot_file, "old").await;
+        let old_kdf = fs::read(&kdf_path).unwrap();
+
+        let storage = open(&snapshot_file, "old").await.unwrap();
+        storage
+            .change_password(password("old"), password("new"))
+            .await
+            .unwrap();
+        // Crash after the new snapshot replaced the old one, before its key
+        fs::rename(&kdf_path, &pending_kdf).unwrap();
+        fs::write(&kdf_path, old_kdf).unwrap();
+
+        assert!(matches!(
+            open(&snapshot_file, "old").await,
+            Err(SecureStorageError::WrongPassword)
+        ));
+        assert!(pending_kdf.exists());
+
+        let storage = open(&snapshot_file, "new").await.unwrap();
+        assert_eq!(secret(&storage).await.as_deref(), Some(&b"s3cret"[..]));
+        assert!(!pending_kdf.exists());
+        let _ = fs::remove_dir_all(snapshot_file.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn removes_pending_key_that_does_not_open_the_snapshot() {
+        let snapshot_file = vault_file("stale");
+        let pending_kdf = vault_key::pending_path(&vault_key::kdf_path(&snapshot_file));
+        with_secret(&snapshot_file, "old").await;
+        // Left by a change whose snapshot never replaced the old one
+        VaultKdf::create(b"new")
+            .unwrap()
+            .kdf
+            .write(&pending_kdf)
+            .unwrap();
+
+        assert!(matches!(
+            open(&snapshot_file, "new").await,
+            Err(SecureStorageError::WrongPassword)
+        ));
+        assert!(pending_kdf.exists());
+
+        let storage = open(&snapshot_file, "old").await.unwrap();
+        assert_eq!(secret(&storage).await.as_deref(), Some(&b"s3cret"[..]));
+        assert!(!pending_kdf.exists());
+        let _ = fs::remove_dir_all(snapshot_file.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn rolls_back_a_change_with_both_files_pending() {
+        let snapshot_file = vault_file("rollback");
+        let pending_snapshot = vault_key::pending_path(&snapshot_file);
+        let pending_kdf = vault_key::pending_path(&vault_key::kdf_path(&snapshot_file));
+        with_secret(&snapshot_file, "old").await;
+        fs::copy(&snapshot_file, &pending_snapshot).unwrap();
+        VaultKdf::create(b"new")
+            .unwrap()
+            .kdf
+            .write(&pending_kdf)
+            .unwrap();
+
+        let storage = open(&snapshot_file, "old").await.unwrap();
+        assert_eq!(secret(&storage).await.as_deref(), Some(&b"s3cret"[..]));
+        assert!(!pending_snapshot.exists());
+        assert!(!pending_kdf.exists());
+        let _ = fs::remove_dir_all(snapshot_file.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn failed_change_leaves_the_old_vault() {
+        let snapshot_file = vault_file("failed");
+        let pending_snapshot = vault_key::pending_path(&snapshot_file);
+        let pending_kdf = vault_key::pending_path(&vault_key::kdf_path(&snapshot_file));
+        with_secret(&snapshot_file, "old").await;
+
+        let storage = open(&snapshot_file, "old").await.unwrap();
+        // The key parameters can't be written
+        fs::create_dir(&pending_kdf).unwrap();
+        assert!(
+            storage
+                .change_password(password("old"), password("new"))
+                .await
+                .is_err()
+        );
+        assert!(!pending_snapshot.exists());
+
+        fs::remove_dir(&pending_kdf).unwrap();
+        let storage = open(&snapshot_file, "old").await.unwrap();
+        assert_eq!(secret(&storage).await.as_deref(), Some(&b"s3cret"[..]));
+        let _ = fs::remove_dir_all(snapshot_file.parent().unwrap());
+    }
+}
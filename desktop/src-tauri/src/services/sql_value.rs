@@ -0,0 +1,435 @@
+use base64::Engine;
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use rusqlite::types::{Value, ValueRef};
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+
+use crate::money::Money;
+
+/// SQLite's CURRENT_TIMESTAMP format, used for every DATETIME column
+const SQLITE_DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// How query rows are encoded for the webview
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResultEncoding {
+    /// Plain JSON: numbers, strings, base64 blobs
+    #[default]
+    Plain,
+    /// Tagged values that mirror [`TypedParam`] and round-trip exactly
+    Typed,
+}
+
+/// Explicitly typed parameter, e.g. `{"type":"decimal","value":"12.34"}`.
+/// Plain JSON strings and numbers keep their previous meaning.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TypedParam {
+    Blob {
+        base64: String,
+    },
+    /// Exact decimal for a DECIMAL column, which stores the same value:
+    /// "12.34" reads back as 12.34. `scale`, if given, caps the decimal
+    /// places.
+    Decimal {
+        value: String,
+        #[serde(default)]
+        scale: Option<u32>,
+    },
+    Datetime {
+        value: String,
+    },
+    Integer {
+        value: String,
+    },
+    /// Exact amount with at most two decimals, e.g. "12.34", bound as
+    /// integer cents for a `*_cents` column
+    Money {
+        value: String,
+    },
+    Real {
+        value: f64,
+    },
+    Text {
+        value: String,
+    },
+}
+
+const TYPED_PARAM_TAGS: &[&str] = &[
+    "blob", "decimal", "datetime", "integer", "money", "real", "text",
+];
+
+/// Name and declared type of a result column
+#[derive(Debug, Clone)]
+pub struct ResultColumn {
+    pub name: String,
+    pub decl_type: Option<String>,
+}
+
+/// Collect column metadata once per prepared statement
+pub fn result_columns(stmt: &rusqlite::Statement<'_>) -> Vec<ResultColumn> {
+    stmt.columns()
+        .iter()
+        .map(|c| ResultColumn {
+            name: c.name().to_string(),
+            decl_type: c.decl_type().map(|t| t.to_uppercase()),
+        })
+        .collect()
+}
+
+/// Convert IPC parameters into SQLite values
+pub fn json_to_sql_values(params: &[JsonValue]) -> Result<Vec<Value>, String> {
+    params.iter().map(json_to_sql_value).collect()
+}
+
+pub fn json_to_sql_value(param: &JsonValue) -> Result<Value, String> {
+    Ok(match param {
+        JsonValue::Null => Value::Null,
+        JsonValue::Bool(b) => Value::Integer(*b as i64),
+        JsonValue::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Value::Integer(i)
+            } else if let Some(f) = n.as_f64() {
+                Value::Real(f)
+            } else {
+                Value::Text(n.to_string())
+            }
+        }
+        JsonValue::String(s) => Value::Text(s.clone()),
+        JsonValue::Object(map) if is_typed_param(map) => decode_typed_param(param)?,
+        other => Value::Text(other.to_string()),
+    })
+}
+
+fn is_typed_param(map: &serde_json::Map<String, JsonValue>) -> bool {
+    match map.get("type") {
+        Some(JsonValue::String(tag)) => TYPED_PARAM_TAGS.contains(&tag.as_str()),
+        _ => false,
+    }
+}
+
+fn decode_typed_param(param: &JsonValue) -> Result<Value, String> {
+    let typed: TypedParam = serde_json::from_value(param.clone())
+        .map_err(|e| format!("Invalid typed parameter {}: {}", param, e))?;
+
+    match typed {
+        TypedParam::Blob { base64 } => base64::engine::general_purpose::STANDARD
+            .decode(base64.as_bytes())
+            .map(Value::Blob)
+            .map_err(|e| format!("Invalid base64 blob: {}", e)),
+        TypedParam::Decimal { value, scale } => decimal_value(&value, scale),
+        TypedParam::Datetime { value } => normalize_datetime(&value)
+            .map(Value::Text)
+            .ok_or_else(|| format!("Invalid datetime '{}'", value)),
+        TypedParam::Integer { value } => value
+            .parse::<i64>()
+            .map(Value::Integer)
+            .map_err(|_| format!("Invalid integer '{}'", value)),
+        // Integer cents for the `*_cents` columns, which are authoritative;
+        // triggers keep the DECIMAL mirror in step
+        TypedParam::Money { value } => value
+            .parse::<Money>()
+            .map(|money| Value::Integer(money.cents()))
+            .map_err(|e| e.to_string()),
+        TypedParam::Real { value } => Ok(Value::Real(value)),
+        TypedParam::Text { value } => Ok(Value::Text(value)),
+    }
+}
+
+/// Bind a decimal literal so a DECIMAL column (NUMERIC affinity) keeps its
+/// value: whole numbers as INTEGER, others as the REAL whose shortest form
+/// reads back as the same digits. Literals a REAL can't hold exactly, or
+/// with more places than `scale`, are errors rather than silent rounding.
+fn decimal_value(value: &str, scale: Option<u32>) -> Result<Value, String> {
+    let invalid = || format!("Invalid decimal '{}'", value);
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value),
+    };
+    let (int_part, frac_part) = match digits.split_once('.') {
+        Some((int_part, frac_part)) if !frac_part.is_empty() => (int_part, frac_part),
+        Some(_) => return Err(invalid()),
+        None => (digits, ""),
+    };
+    if int_part.is_empty()
+        || !int_part.chars().all(|c| c.is_ascii_digit())
+        || !frac_part.chars().all(|c| c.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+    if let Some(scale) = scale
+        && frac_part.len() > scale as usize
+    {
+        return Err(format!(
+            "Decimal '{}' has more than {} decimal places",
+            value, scale
+        ));
+    }
+
+    let out_of_range = || format!("Decimal '{}' can't be stored exactly", value);
+    let frac_part = frac_part.trim_end_matches('0');
+    if frac_part.is_empty() {
+        let units: i64 = int_part.parse().map_err(|_| out_of_range())?;
+        return Ok(Value::Integer(if negative { -units } else { units }));
+    }
+
+    let real: f64 = value.parse().map_err(|_| invalid())?;
+    let int_part = match int_part.trim_start_matches('0') {
+        "" => "0",
+        int_part => int_part,
+    };
+    let exact = format!(
+        "{}{}.{}",
+        if negative { "-" } else { "" },
+        int_part,
+        frac_part
+    );
+    if format_decimal(&real.to_string(), Some(frac_part.len())) != exact {
+        return Err(out_of_range());
+    }
+    Ok(Value::Real(real))
+}
+
+/// Accept RFC 3339 or SQLite-style timestamps and store them in UTC using
+/// the same format as CURRENT_TIMESTAMP. Dates without time stay as dates.
+fn normalize_datetime(value: &str) -> Option<String> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Some(dt.naive_utc().format(SQLITE_DATETIME_FORMAT).to_string());
+    }
+
+    for format in ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"] {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(value, format) {
+            return Some(dt.format(SQLITE_DATETIME_FORMAT).to_string());
+        }
+    }
+
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .map(|d| d.format("%Y-%m-%d").to_string())
+}
+
+/// Convert a result row into a JSON object
+pub fn row_to_json(
+    row: &rusqlite::Row<'_>,
+    columns: &[ResultColumn],
+    encoding: ResultEncoding,
+) -> Result<serde_json::Map<String, JsonValue>, rusqlite::Error> {
+    let mut map = serde_json::Map::new();
+    for (i, column) in columns.iter().enumerate() {
+        let value = row.get_ref(i)?;
+        let json_val = match encoding {
+            ResultEncoding::Plain => sql_value_to_json(value),
+            ResultEncoding::Typed if column.name.ends_with("_cents") => {
+                sql_cents_to_typed_json(value)
+            }
+            ResultEncoding::Typed => sql_value_to_typed_json(value, column.decl_type.as_deref()),
+        };
+        map.insert(column.name.clone(), json_val);
+    }
+    Ok(map)
+}
+
+pub fn sql_value_to_json(value: ValueRef<'_>) -> JsonValue {
+    match value {
+        ValueRef::Null => JsonValue::Null,
+        ValueRef::Integer(i) => JsonValue::Number(i.into()),
+        ValueRef::Real(f) => serde_json::Number::from_f64(f)
+            .map(JsonValue::Number)
+            .unwrap_or(JsonValue::Null),
+        ValueRef::Text(t) => JsonValue::String(String::from_utf8_lossy(t).to_string()),
+        ValueRef::Blob(b) => JsonValue::String(base64::engine::general_purpose::STANDARD.encode(b)),
+    }
+}
+
+/// Encode a value as a tagged object. Integers travel as strings so values
+/// above 2^53 survive JavaScript, and DECIMAL columns are formatted with
+/// their declared scale from the stored digits, without f64 arithmetic.
+pub fn sql_value_to_typed_json(value: ValueRef<'_>, decl_type: Option<&str>) -> JsonValue {
+    let decimal_scale = decl_type.and_then(decimal_scale);
+    let is_datetime = decl_type.is_some_and(|t| t.contains("DATE") || t.contains("TIME"));
+
+    match value {
+        ValueRef::Null => JsonValue::Null,
+        ValueRef::Integer(i) => match decimal_scale {
+            Some(scale) => tagged("decimal", format_decimal(&i.to_string(), scale)),
+            None => tagged("integer", i.to_string()),
+        },
+        ValueRef::Real(f) => match decimal_scale {
+            Some(scale) => tagged("decimal", format_decimal(&f.to_string(), scale)),
+            None => serde_json::json!({ "type": "real", "value": f }),
+        },
+        ValueRef::Text(t) => {
+            let text = String::from_utf8_lossy(t).to_string();
+            if is_datetime {
+                tagged("datetime", datetime_to_rfc3339(&text).unwrap_or(text))
+            } else {
+                tagged("text", text)
+            }
+        }
+        ValueRef::Blob(b) => serde_json::json!({
+            "type": "blob",
+            "base64": base64::engine::general_purpose::STANDARD.encode(b),
+        }),
+    }
+}
+
+/// `*_cents` columns are reported as money rather than bare integers
+fn sql_cents_to_typed_json(value: ValueRef<'_>) -> JsonValue {
+    match value {
+        ValueRef::Integer(cents) => tagged("money", Money::from_cents(cents).to_string()),
+        other => sql_value_to_typed_json(other, None),
+    }
+}
+
+fn tagged(kind: &str, value: String) -> JsonValue {
+    serde_json::json!({ "type": kind, "value": value })
+}
+
+/// Scale of a DECIMAL/NUMERIC declared type: `DECIMAL(10,2)` -> `Some(Some(2))`,
+/// `NUMERIC` -> `Some(None)`, anything else -> `None`
+fn decimal_scale(decl_type: &str) -> Option<Option<usize>> {
+    if !(decl_type.starts_with("DECIMAL") || decl_type.starts_with("NUMERIC")) {
+        return None;
+    }
+
+    let scale = decl_type
+        .split_once(',')
+        .and_then(|(_, rest)| rest.trim_end_matches(')').trim().parse().ok());
+    Some(scale)
+}
+
+/// Round a plain decimal literal (`i64`/`f64` Display output, which is the
+/// shortest round-trip form) half away from zero to `scale` places, working
+/// on the digits so 2.675 rounds to 2.68
+fn format_decimal(repr: &str, scale: Option<usize>) -> String {
+    let Some(scale) = scale else {
+        return repr.to_string();
+    };
+    let (negative, digits) = match repr.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, repr),
+    };
+    let (int_part, frac_part) = digits.split_once('.').unwrap_or((digits, ""));
+
+    let (kept, round_up) = if frac_part.len() > scale {
+        (
+            format!("{}{}", int_part, &frac_part[..scale]),
+            frac_part.as_bytes()[scale] >= b'5',
+        )
+    } else {
+        (
+            format!("{}{:0<width$}", int_part, frac_part, width = scale),
+            false,
+        )
+    };
+    let Ok(units) = kept.parse::<u128>() else {
+        return repr.to_string();
+    };
+    let units = units + round_up as u128;
+
+    let divisor = 10u128.pow(scale as u32);
+    let sign = if negative && units != 0 { "-" } else { "" };
+    if scale == 0 {
+        format!("{}{}", sign, units)
+    } else {
+        format!(
+            "{}{}.{:0width$}",
+            sign,
+            units / divisor,
+            units % divisor,
+            width = scale
+        )
+    }
+}
+
+fn datetime_to_rfc3339(value: &str) -> Option<String> {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f")
+        .ok()
+        .map(|dt| {
+            dt.and_utc()
+                .to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binds_decimals_by_value() {
+        assert_eq!(decimal_value("12.34", Some(2)), Ok(Value::Real(12.34)));
+        assert_eq!(decimal_value("12.30", None), Ok(Value::Real(12.3)));
+        assert_eq!(decimal_value("-0.5", Some(3)), Ok(Value::Real(-0.5)));
+        assert_eq!(decimal_value("7.00", None), Ok(Value::Integer(7)));
+        assert_eq!(decimal_value("-7", Some(0)), Ok(Value::Integer(-7)));
+        assert!(decimal_value("1.234", Some(2)).is_err());
+        assert!(decimal_value("1.", None).is_err());
+        assert!(decimal_value("1e3", None).is_err());
+        assert!(decimal_value("99999999999999999999", None).is_err());
+        // More digits than a REAL holds
+        assert!(decimal_value("12345678901234567.89", None).is_err());
+    }
+
+    #[test]
+    fn decodes_typed_decimal() {
+        let param = serde_json::json!({ "type": "decimal", "value": "12.34" });
+        assert_eq!(json_to_sql_value(&param), Ok(Value::Real(12.34)));
+    }
+
+    #[test]
+    fn decimals_round_trip_through_a_decimal_column() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::services::migrations::migrate(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO categories (id, name) VALUES (1, 'Bebidas')",
+            [],
+        )
+        .unwrap();
+
+        for (id, price, cents) in [(1, "12.34", 1234), (2, "0.10", 10), (3, "19.00", 1900)] {
+            let params = json_to_sql_values(&[
+                serde_json::json!(id),
+                serde_json::json!({ "type": "decimal", "value": price, "scale": 2 }),
+            ])
+            .unwrap();
+            conn.execute(
+                "INSERT INTO products (id, name, category_id, price, partner_price, vip_price)
+                 VALUES (?1, 'P', 1, ?2, ?2, ?2)",
+                rusqlite::params_from_iter(params),
+            )
+            .unwrap();
+
+            let mut stmt = conn
+                .prepare("SELECT price, price_cents FROM products WHERE id = ?")
+                .unwrap();
+            let columns = result_columns(&stmt);
+            let row = stmt
+                .query_row([id], |row| {
+                    row_to_json(row, &columns, ResultEncoding::Typed)
+                })
+                .unwrap();
+            assert_eq!(row["price"], tagged("decimal", price.to_string()));
+            assert_eq!(row["price_cents"], tagged("money", price.to_string()));
+            let stored: i64 = conn
+                .query_row(
+                    "SELECT price_cents FROM products WHERE id = ?",
+                    [id],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(stored, cents);
+        }
+    }
+
+    #[test]
+    fn formats_decimals_from_digits() {
+        // {:.2} on the f64 would give 2.67
+        assert_eq!(format_decimal(&2.675f64.to_string(), Some(2)), "2.68");
+        assert_eq!(format_decimal(&1.005f64.to_string(), Some(2)), "1.01");
+        assert_eq!(format_decimal(&(-0.125f64).to_string(), Some(2)), "-0.13");
+        assert_eq!(format_decimal(&(-0.001f64).to_string(), Some(2)), "0.00");
+        assert_eq!(format_decimal("12", Some(2)), "12.00");
+        assert_eq!(format_decimal("9.995", Some(2)), "10.00");
+        assert_eq!(format_decimal("3.5", None), "3.5");
+    }
+}
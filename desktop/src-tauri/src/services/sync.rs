@@ -0,0 +1,503 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{Emitter, Manager};
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+use crate::services::database::open_read_only_connection;
+use crate::services::db_writer::DbWriter;
+use crate::services::secret_policy::SYNC_TOKEN;
+use crate::services::secure_storage::{SecureStorageError, SecureStorageManager};
+use crate::services::sync_client::{SyncClient, SyncError};
+use crate::services::sync_store::{self, BatchOutcome, OutboxStatus};
+
+/// Polling intervals from the sync strategy (CLAUDE-decisions.md, ADR-002)
+const ACTIVE_INTERVAL: Duration = Duration::from_secs(5);
+const NORMAL_INTERVAL: Duration = Duration::from_secs(10);
+const IDLE_INTERVAL: Duration = Duration::from_secs(30);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Quiet polls at the normal interval before dropping to the idle one
+const IDLE_AFTER_POLLS: u32 = 3;
+
+/// How often to look for a backend URL while sync is not configured
+const UNCONFIGURED_INTERVAL: Duration = Duration::from_secs(60);
+
+/// `sync-status` is sent after every poll and at least this often while
+/// waiting for the next one
+const STATUS_EVENT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Background sync with the backend. Each poll pushes one batch of pending
+/// changes and pulls the backend's changes in the same request, then
+/// reports finished sales.
+pub struct SyncEngine {
+    wake: Notify,
+    state: Mutex<SyncState>,
+    client: tokio::sync::Mutex<Option<SyncClient>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncState {
+    pub configured: bool,
+    pub offline: bool,
+    pub last_error: Option<String>,
+    /// Failed polls in a row; non-zero while backing off
+    pub consecutive_failures: u32,
+    pub quiet_polls: u32,
+    /// Wait before the next poll, in milliseconds
+    pub next_poll_ms: u64,
+}
+
+/// Reported by `get_sync_status` and the `sync-status` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncStatus {
+    /// A backend is configured and answered the last poll
+    pub online: bool,
+    #[serde(flatten)]
+    pub engine: SyncState,
+    #[serde(flatten)]
+    pub outbox: OutboxStatus,
+}
+
+impl SyncEngine {
+    pub fn new() -> Self {
+        Self {
+            wake: Notify::new(),
+            state: Mutex::new(SyncState::default()),
+            client: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Poll now instead of waiting out the interval, e.g. after a sale
+    pub fn wake(&self) {
+        self.wake.notify_one();
+    }
+
+    pub fn snapshot(&self) -> SyncState {
+        self.state.lock().map(|s| s.clone()).unwrap_or_default()
+    }
+
+    /// Push one batch, pull remote changes and report finished sales.
+    /// Acknowledgements are recorded before the pulled changes are applied,
+    /// each in its own transaction. Returns `None` when no backend is
+    /// configured.
+    pub async fn sync_once(
+        &self,
+        app_handle: &tauri::AppHandle,
+    ) -> Result<Option<(BatchOutcome, bool)>, SyncError> {
+        let writer = app_handle.state::<DbWriter>();
+
+        let Some(mut config) = writer
+            .execute(app_handle, |conn| sync_store::load_config(conn))
+            .await?
+        else {
+            return Ok(None);
+        };
+        config.token = sync_token(app_handle).await?;
+
+        let mut client = self.client.lock().await;
+        if client.as_ref().is_none_or(|c| c.config() != &config) {
+            *client = Some(SyncClient::new(config)?);
+        }
+        let Some(client) = client.as_ref() else {
+            return Ok(None);
+        };
+
+        let batch = writer
+            .execute(app_handle, |conn| {
+                conn.in_transaction(|conn| sync_store::prepare_batch(conn))
+            })
+            .await?;
+
+        let (server, next_sequence) = client
+            .exchange_changes(&batch.change_set, batch.sequence)
+            .await?;
+
+        let has_more = batch.has_more;
+        let server = Arc::new(server);
+        let pushed = Arc::clone(&server);
+        let outcome = writer
+            .execute(app_handle, move |conn| {
+                conn.in_transaction(|conn| {
+                    sync_store::record_push_results(conn, &batch.records, &pushed)
+                })
+            })
+            .await?;
+        let mut outcome = writer
+            .execute(app_handle, move |conn| {
+                conn.in_transaction(|conn| {
+                    sync_store::apply_pulled_changes(conn, &server, next_sequence, outcome)
+                })
+            })
+            .await?;
+
+        outcome.reported = self.report_sales(app_handle, client).await?;
+        Ok(Some((outcome, has_more)))
+    }
+
+    /// Send one batch of completed and voided sales to `/sales/report`
+    async fn report_sales(
+        &self,
+        app_handle: &tauri::AppHandle,
+        client: &SyncClient,
+    ) -> Result<usize, SyncError> {
+        let writer = app_handle.state::<DbWriter>();
+        let terminal_id = client.config().terminal_id.clone();
+        let sales = writer
+            .execute(app_handle, move |conn| {
+                conn.in_transaction(|conn| sync_store::sales_to_report(conn, &terminal_id))
+            })
+            .await?;
+        if sales.is_empty() {
+            return Ok(0);
+        }
+
+        client.report_sales(&sales).await?;
+        let ids: Vec<i64> = sales.iter().map(|sale| sale.local_id).collect();
+        let reported = ids.len();
+        writer
+            .execute(app_handle, move |conn| {
+                conn.in_transaction(|conn| sync_store::mark_sales_reported(conn, &ids))
+            })
+            .await?;
+        Ok(reported)
+    }
+
+    /// Engine state plus the outbox counts read from the database
+    pub async fn status(&self, app_handle: &tauri::AppHandle) -> Result<SyncStatus, String> {
+        let handle = app_handle.clone();
+        let outbox = tokio::task::spawn_blocking(move || {
+            let conn = open_read_only_connection(&handle)?;
+            sync_store::outbox_status(&conn)
+        })
+        .await
+        .map_err(|e| format!("Sync status task failed: {}", e))??;
+
+        let engine = self.snapshot();
+        Ok(SyncStatus {
+            online: engine.configured && engine.consecutive_failures == 0 && !engine.offline,
+            engine,
+            outbox,
+        })
+    }
+
+    fn record_success(&self, outcome: BatchOutcome, has_more: bool) -> Duration {
+        let mut state = self.lock_state();
+        state.configured = true;
+        state.offline = false;
+        state.last_error = None;
+        state.consecutive_failures = 0;
+
+        let interval = if has_more {
+            Duration::ZERO
+        } else if outcome.pushed + outcome.pulled > 0 {
+            state.quiet_polls = 0;
+            ACTIVE_INTERVAL
+        } else {
+            state.quiet_polls = state.quiet_polls.saturating_add(1);
+            if state.quiet_polls > IDLE_AFTER_POLLS {
+                IDLE_INTERVAL
+            } else {
+                NORMAL_INTERVAL
+            }
+        };
+        state.next_poll_ms = interval.as_millis() as u64;
+        interval
+    }
+
+    fn record_failure(&self, error: &SyncError) -> Duration {
+        let mut state = self.lock_state();
+        state.configured = true;
+        state.offline = matches!(error, SyncError::Offline(_));
+        state.last_error = Some(error.to_string());
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+
+        let exponent = state.consecutive_failures.saturating_sub(1).min(16);
+        let interval = ACTIVE_INTERVAL
+            .saturating_mul(1 << exponent)
+            .min(MAX_BACKOFF);
+        state.next_poll_ms = interval.as_millis() as u64;
+        interval
+    }
+
+    fn record_unconfigured(&self) -> Duration {
+        let mut state = self.lock_state();
+        *state = SyncState {
+            next_poll_ms: UNCONFIGURED_INTERVAL.as_millis() as u64,
+            ..SyncState::default()
+        };
+        UNCONFIGURED_INTERVAL
+    }
+
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, SyncState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for SyncEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The backend token from secure storage. One still in `system_settings`
+/// from older versions is moved there first; an empty one is just dropped.
+async fn sync_token(app_handle: &tauri::AppHandle) -> Result<Option<String>, SyncError> {
+    let writer = app_handle.state::<DbWriter>();
+    let storage = app_handle.state::<SecureStorageManager>();
+
+    let legacy = writer
+        .execute(app_handle, |conn| sync_store::legacy_token(conn))
+        .await?;
+    let (token, legacy_done) = resolve_token(&storage, legacy).await?;
+    if legacy_done {
+        writer
+            .execute(app_handle, |conn| sync_store::remove_legacy_token(conn))
+            .await?;
+    }
+    Ok(token)
+}
+
+/// The token to poll with, and whether the `system_settings` one (if any)
+/// can be deleted. While the vault is locked, e.g. no password was enrolled
+/// for startup, a legacy token is still used where it is and otherwise the
+/// poll goes out without one.
+async fn resolve_token(
+    storage: &SecureStorageManager,
+    legacy: Option<String>,
+) -> Result<(Option<String>, bool), SyncError> {
+    let unavailable =
+        |e: SecureStorageError| SyncError::Local(format!("Sync token unavailable: {}", e));
+    let legacy = legacy.map(|token| token.trim().to_string());
+    let moved = match legacy {
+        // Nothing worth moving, whether or not the vault is open
+        Some(token) if token.is_empty() => true,
+        Some(token) if !storage.is_initialized().await => {
+            log::debug!("Secure storage is locked, syncing with the token in system_settings");
+            return Ok((Some(token), false));
+        }
+        Some(token) => {
+            if storage.has_secret(SYNC_TOKEN).await.map_err(unavailable)? {
+                log::warn!("Dropping the sync token in system_settings, secure storage has one");
+            } else {
+                storage
+                    .store_secret(SYNC_TOKEN, token.as_bytes())
+                    .await
+                    .map_err(unavailable)?;
+                log::info!("Moved the sync token from system_settings to secure storage");
+            }
+            true
+        }
+        None => false,
+    };
+
+    if !storage.is_initialized().await {
+        log::debug!("Secure storage is locked, syncing without a token");
+        return Ok((None, moved));
+    }
+    let token = storage.get_secret(SYNC_TOKEN).await.map_err(unavailable)?;
+    let token = token
+        .map(String::from_utf8)
+        .transpose()
+        .map_err(|_| SyncError::Local("Sync token is not valid UTF-8".to_string()))?;
+    Ok((token.filter(|t| !t.trim().is_empty()), moved))
+}
+
+/// Poll the backend until the app exits. Backs off while idle or offline;
+/// `SyncEngine::wake` starts a poll right away.
+pub async fn run_scheduler(app_handle: tauri::AppHandle) {
+    let engine = app_handle.state::<SyncEngine>();
+
+    loop {
+        let interval = match engine.sync_once(&app_handle).await {
+            Ok(Some((outcome, has_more))) => {
+                if outcome.pushed
+                    + outcome.rejected
+                    + outcome.pulled
+                    + outcome.parked
+                    + outcome.reported
+                    > 0
+                {
+                    log::info!(
+                        "Sync pushed {}, rejected {}, pulled {}, parked {}, reported {} sales",
+                        outcome.pushed,
+                        outcome.rejected,
+                        outcome.pulled,
+                        outcome.parked,
+                        outcome.reported
+                    );
+                }
+                engine.record_success(outcome, has_more)
+            }
+            Ok(None) => engine.record_unconfigured(),
+            Err(e) => {
+                let interval = engine.record_failure(&e);
+                match e {
+                    SyncError::Offline(_) => log::debug!("Sync skipped, backend offline: {}", e),
+                    _ => log::warn!("Sync failed: {}", e),
+                }
+                interval
+            }
+        };
+
+        emit_status(&app_handle, &engine).await;
+        wait_for_next_poll(&app_handle, &engine, interval).await;
+    }
+}
+
+/// Sleep until the interval is up or the engine is woken, sending
+/// `sync-status` every `STATUS_EVENT_INTERVAL` meanwhile
+async fn wait_for_next_poll(
+    app_handle: &tauri::AppHandle,
+    engine: &SyncEngine,
+    interval: Duration,
+) {
+    let deadline = Instant::now() + interval;
+    loop {
+        let wait = deadline
+            .saturating_duration_since(Instant::now())
+            .min(STATUS_EVENT_INTERVAL);
+        if wait.is_zero()
+            || tokio::time::timeout(wait, engine.wake.notified())
+                .await
+                .is_ok()
+        {
+            return;
+        }
+        if Instant::now() < deadline {
+            emit_status(app_handle, engine).await;
+        }
+    }
+}
+
+async fn emit_status(app_handle: &tauri::AppHandle, engine: &SyncEngine) {
+    match engine.status(app_handle).await {
+        Ok(status) => {
+            if let Err(e) = app_handle.emit_to("main", "sync-status", &status) {
+                log::debug!("Failed to emit sync-status: {}", e);
+            }
+        }
+        Err(e) => log::warn!("Failed to read sync status: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::sync_client::tests::{client, stub_server, sync_response};
+    use crate::services::sync_client::{CHANGE_SET_FORMAT, ChangeSet, SyncConfig};
+
+    fn empty_change_set() -> ChangeSet {
+        ChangeSet {
+            format: CHANGE_SET_FORMAT,
+            changes: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn polls_without_a_token_while_the_vault_is_locked() {
+        let locked = SecureStorageManager::new();
+        assert_eq!(resolve_token(&locked, None).await.unwrap(), (None, false));
+        // A seeded empty setting is dropped without opening the vault
+        assert_eq!(
+            resolve_token(&locked, Some(String::new())).await.unwrap(),
+            (None, true)
+        );
+        // A legacy token keeps working until the vault is open to take it
+        assert_eq!(
+            resolve_token(&locked, Some("old-token".to_string()))
+                .await
+                .unwrap(),
+            (Some("old-token".to_string()), false)
+        );
+
+        let server =
+            stub_server(|_, _| (200, sync_response(serde_json::json!({"changes": []}), 0)));
+        let (token, _) = resolve_token(&locked, None).await.unwrap();
+        let client = SyncClient::new(SyncConfig {
+            base_url: format!("{}/", server.url),
+            token,
+            terminal_id: "terminal-1".to_string(),
+        })
+        .unwrap();
+        client
+            .exchange_changes(&empty_change_set(), 0)
+            .await
+            .unwrap();
+        assert_eq!(server.requests.lock().unwrap()[0].authorization, None);
+    }
+
+    #[tokio::test]
+    async fn offline_backend_backs_off_exponentially_up_to_the_cap() {
+        let server = stub_server(|_, _| (503, "maintenance".to_string()));
+        let client = client(&server.url);
+        let engine = SyncEngine::new();
+
+        let mut intervals = Vec::new();
+        for _ in 0..8 {
+            let error = client
+                .exchange_changes(&empty_change_set(), 0)
+                .await
+                .unwrap_err();
+            intervals.push(engine.record_failure(&error).as_secs());
+        }
+        assert_eq!(intervals, [5, 10, 20, 40, 80, 160, 300, 300]);
+
+        let state = engine.snapshot();
+        assert!(state.offline);
+        assert_eq!(state.consecutive_failures, 8);
+        assert_eq!(state.next_poll_ms, 300_000);
+        assert_eq!(server.requests.lock().unwrap().len(), 8);
+    }
+
+    #[tokio::test]
+    async fn a_successful_poll_resets_the_backoff() {
+        let server = stub_server(|_, _| (500, "boom".to_string()));
+        let engine = SyncEngine::new();
+
+        let error = client(&server.url)
+            .exchange_changes(&empty_change_set(), 0)
+            .await
+            .unwrap_err();
+        engine.record_failure(&error);
+        engine.record_failure(&error);
+        // A backend error isn't offline, but still backs off
+        assert!(!engine.snapshot().offline);
+        assert_eq!(engine.snapshot().next_poll_ms, 10_000);
+
+        let interval = engine.record_success(
+            BatchOutcome {
+                pulled: 1,
+                ..BatchOutcome::default()
+            },
+            false,
+        );
+        assert_eq!(interval, ACTIVE_INTERVAL);
+        let state = engine.snapshot();
+        assert_eq!(state.consecutive_failures, 0);
+        assert!(state.last_error.is_none());
+
+        // Quiet polls slow down to the idle interval
+        let quiet: Vec<_> = (0..5)
+            .map(|_| engine.record_success(BatchOutcome::default(), false))
+            .collect();
+        assert_eq!(
+            quiet,
+            [
+                NORMAL_INTERVAL,
+                NORMAL_INTERVAL,
+                NORMAL_INTERVAL,
+                IDLE_INTERVAL,
+                IDLE_INTERVAL
+            ]
+        );
+        assert_eq!(
+            engine.record_success(BatchOutcome::default(), true),
+            Duration::ZERO
+        );
+    }
+}
@@ -0,0 +1,472 @@
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::money::Money;
+
+/// Identifies the JSON document carried in the `changes` / `server_changes`
+/// byte fields of `/sync/changes` (base64, see ADR 002)
+pub const CHANGE_SET_FORMAT: &str = "pos-change-log/1";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, thiserror::Error)]
+pub enum SyncError {
+    /// The backend couldn't be reached; retried with backoff
+    #[error("Backend unreachable: {0}")]
+    Offline(String),
+    #[error("Backend returned {status}: {body}")]
+    Http { status: u16, body: String },
+    #[error("Invalid sync response: {0}")]
+    Protocol(String),
+    #[error("{0}")]
+    Local(String),
+}
+
+impl From<String> for SyncError {
+    fn from(s: String) -> Self {
+        SyncError::Local(s)
+    }
+}
+
+/// Connection settings read from `system_settings`, and the token from
+/// secure storage
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncConfig {
+    pub base_url: String,
+    pub token: Option<String>,
+    pub terminal_id: String,
+}
+
+/// Upsert or delete of one row, in either direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeOperation {
+    Upsert,
+    Delete,
+}
+
+/// Local changes sent to the backend
+#[derive(Debug, Serialize)]
+pub struct ChangeSet {
+    pub format: &'static str,
+    pub changes: Vec<OutboundChange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OutboundChange {
+    /// Newest `change_log` id folded into this change; echoed in the response
+    pub change_id: i64,
+    pub table: String,
+    pub backend_id: String,
+    pub operation: ChangeOperation,
+    pub version: i64,
+    /// Backend version this edit was based on, `None` for rows the backend
+    /// hasn't confirmed yet. A mismatch is reported back as a conflict.
+    pub base_version: Option<i64>,
+    /// Row columns with foreign keys replaced by backend ids; `None` for deletes
+    pub data: Option<serde_json::Map<String, JsonValue>>,
+}
+
+/// What the backend did with our changes, plus its changes since `sequence`
+#[derive(Debug, Default, Deserialize)]
+pub struct ServerChanges {
+    #[serde(default)]
+    pub acknowledged: Vec<Acknowledgement>,
+    #[serde(default)]
+    pub rejected: Vec<Rejection>,
+    #[serde(default)]
+    pub changes: Vec<RemoteChange>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Acknowledgement {
+    pub change_id: i64,
+    /// Version stored by the backend, when it differs from ours
+    pub version: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Rejection {
+    pub change_id: i64,
+    pub reason: String,
+    /// The backend's current row when the rejection is a version conflict
+    #[serde(default)]
+    pub current: Option<RemoteChange>,
+}
+
+/// Kept as JSON in `sync_parked_changes` while it can't be applied
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteChange {
+    pub table: String,
+    pub backend_id: String,
+    pub operation: ChangeOperation,
+    pub version: i64,
+    /// Columns by local name, foreign keys as backend ids
+    #[serde(default)]
+    pub data: Option<serde_json::Map<String, JsonValue>>,
+}
+
+/// A completed or voided sale for `/sales/report` (`Sale` in the OpenAPI
+/// spec). Amounts go out as JSON numbers.
+#[derive(Debug, Serialize)]
+pub struct SaleReport {
+    /// Local transaction, marked reported once the backend accepts it
+    #[serde(skip)]
+    pub local_id: i64,
+    pub id: String,
+    pub terminal_id: String,
+    pub cashier_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub customer_id: Option<String>,
+    pub items: Vec<SaleReportItem>,
+    #[serde(serialize_with = "money_number")]
+    pub subtotal: Money,
+    #[serde(serialize_with = "money_number")]
+    pub tax_total: Money,
+    #[serde(serialize_with = "money_number")]
+    pub discount_total: Money,
+    #[serde(serialize_with = "money_number")]
+    pub total: Money,
+    /// CASH, CARD, TRANSFER or MIXED
+    pub payment_method: &'static str,
+    /// COMPLETED or VOIDED
+    pub status: &'static str,
+    pub invoice_number: String,
+    pub created_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub voided_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub void_reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SaleReportItem {
+    pub product_id: String,
+    pub product_name: String,
+    pub quantity: f64,
+    #[serde(serialize_with = "money_number")]
+    pub unit_price: Money,
+    #[serde(serialize_with = "money_number")]
+    pub discount: Money,
+    /// This line's share of the sale's IVA
+    #[serde(serialize_with = "money_number")]
+    pub tax: Money,
+    /// `unit_price * quantity - discount`
+    #[serde(serialize_with = "money_number")]
+    pub subtotal: Money,
+    #[serde(serialize_with = "money_number")]
+    pub total: Money,
+}
+
+fn money_number<S: serde::Serializer>(money: &Money, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(money.to_f64())
+}
+
+#[derive(Debug, Serialize)]
+struct SyncChangesRequest<'a> {
+    terminal_id: &'a str,
+    changes: String,
+    sequence: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct SyncChangesResponse {
+    accepted: bool,
+    #[serde(default)]
+    server_changes: String,
+    next_sequence: Option<i64>,
+}
+
+/// HTTP client for `/sync/changes` and `/sales/report` in `backend/api/openapi.yaml`
+pub struct SyncClient {
+    http: reqwest::Client,
+    config: SyncConfig,
+}
+
+impl SyncClient {
+    pub fn new(config: SyncConfig) -> Result<Self, SyncError> {
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .build()
+            .map_err(|e| SyncError::Local(format!("Failed to create HTTP client: {}", e)))?;
+        Ok(Self { http, config })
+    }
+
+    pub fn config(&self) -> &SyncConfig {
+        &self.config
+    }
+
+    /// Push a change set and pull the backend's changes after `sequence`.
+    /// Returns the decoded server changes and the cursor for the next pull.
+    pub async fn exchange_changes(
+        &self,
+        change_set: &ChangeSet,
+        sequence: i64,
+    ) -> Result<(ServerChanges, i64), SyncError> {
+        let payload = serde_json::to_vec(change_set)
+            .map_err(|e| SyncError::Local(format!("Failed to encode changes: {}", e)))?;
+        let request = SyncChangesRequest {
+            terminal_id: &self.config.terminal_id,
+            changes: BASE64.encode(payload),
+            sequence,
+        };
+
+        let response: SyncChangesResponse = self
+            .send(self.http.post(self.url("/sync/changes")).json(&request))
+            .await?
+            .json()
+            .await
+            .map_err(|e| SyncError::Protocol(e.to_string()))?;
+
+        if !response.accepted {
+            return Err(SyncError::Protocol(
+                "Backend did not accept the change set".to_string(),
+            ));
+        }
+
+        let server_changes = if response.server_changes.is_empty() {
+            ServerChanges::default()
+        } else {
+            let bytes = BASE64
+                .decode(response.server_changes.as_bytes())
+                .map_err(|e| SyncError::Protocol(format!("server_changes: {}", e)))?;
+            serde_json::from_slice(&bytes)
+                .map_err(|e| SyncError::Protocol(format!("server_changes: {}", e)))?
+        };
+
+        Ok((server_changes, response.next_sequence.unwrap_or(sequence)))
+    }
+
+    /// Send completed and voided sales to `/sales/report`
+    pub async fn report_sales(&self, sales: &[SaleReport]) -> Result<(), SyncError> {
+        self.send(self.http.post(self.url("/sales/report")).json(sales))
+            .await
+            .map(|_| ())
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.config.base_url.trim_end_matches('/'), path)
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, SyncError> {
+        let request = match &self.config.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        };
+
+        let response = request.send().await.map_err(|e| {
+            if e.is_connect() || e.is_timeout() {
+                SyncError::Offline(e.to_string())
+            } else {
+                SyncError::Protocol(e.to_string())
+            }
+        })?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        // Gateways answer 502-504 while the backend itself is down
+        if matches!(status.as_u16(), 502..=504) {
+            return Err(SyncError::Offline(format!("{} {}", status, body)));
+        }
+        Err(SyncError::Http {
+            status: status.as_u16(),
+            body,
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    /// A request as the stub received it
+    #[derive(Debug, Clone)]
+    pub struct StubRequest {
+        pub path: String,
+        pub authorization: Option<String>,
+        pub body: String,
+    }
+
+    /// Backend stand-in on a local port, answering each request with
+    /// `respond(path, body)` as (status, JSON body)
+    pub struct StubServer {
+        pub url: String,
+        pub requests: Arc<Mutex<Vec<StubRequest>>>,
+    }
+
+    pub fn stub_server(
+        respond: impl Fn(&str, &str) -> (u16, String) + Send + 'static,
+    ) -> StubServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&requests);
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    return;
+                };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let path = line.split_whitespace().nth(1).unwrap_or("").to_string();
+
+                let mut length = 0;
+                let mut authorization = None;
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    let Some((name, value)) = line.trim_end().split_once(':') else {
+                        break;
+                    };
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    } else if name.eq_ignore_ascii_case("authorization") {
+                        authorization = Some(value.trim().to_string());
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let body = String::from_utf8(body).unwrap();
+
+                let (status, response) = respond(&path, &body);
+                seen.lock().unwrap().push(StubRequest {
+                    path,
+                    authorization,
+                    body,
+                });
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Stub\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    response.len(),
+                    response
+                )
+                .unwrap();
+            }
+        });
+
+        StubServer { url, requests }
+    }
+
+    /// `/sync/changes` answer carrying `server_changes`
+    pub fn sync_response(server_changes: JsonValue, next_sequence: i64) -> String {
+        serde_json::json!({
+            "accepted": true,
+            "server_changes": BASE64.encode(server_changes.to_string()),
+            "next_sequence": next_sequence,
+        })
+        .to_string()
+    }
+
+    /// The change set a `/sync/changes` request carried
+    pub fn sent_changes(body: &str) -> JsonValue {
+        let request: JsonValue = serde_json::from_str(body).unwrap();
+        let bytes = BASE64.decode(request["changes"].as_str().unwrap()).unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    pub fn client(url: &str) -> SyncClient {
+        SyncClient::new(SyncConfig {
+            base_url: format!("{}/", url),
+            token: Some("secret-token".to_string()),
+            terminal_id: "terminal-1".to_string(),
+        })
+        .unwrap()
+    }
+
+    fn empty_change_set() -> ChangeSet {
+        ChangeSet {
+            format: CHANGE_SET_FORMAT,
+            changes: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn exchange_decodes_server_changes_and_sends_the_token() {
+        let server = stub_server(|_, _| {
+            let changes = serde_json::json!({
+                "changes": [{
+                    "table": "categories",
+                    "backend_id": "cat-1",
+                    "operation": "upsert",
+                    "version": 3,
+                    "data": { "name": "Vitaminas" },
+                }],
+            });
+            (200, sync_response(changes, 42))
+        });
+
+        let (changes, next) = client(&server.url)
+            .exchange_changes(&empty_change_set(), 7)
+            .await
+            .unwrap();
+        assert_eq!(next, 42);
+        assert_eq!(changes.changes.len(), 1);
+        assert_eq!(changes.changes[0].version, 3);
+
+        let requests = server.requests.lock().unwrap();
+        assert_eq!(requests[0].path, "/sync/changes");
+        assert_eq!(
+            requests[0].authorization.as_deref(),
+            Some("Bearer secret-token")
+        );
+        let body: JsonValue = serde_json::from_str(&requests[0].body).unwrap();
+        assert_eq!(body["terminal_id"], "terminal-1");
+        assert_eq!(body["sequence"], 7);
+        assert_eq!(sent_changes(&requests[0].body)["format"], CHANGE_SET_FORMAT);
+    }
+
+    #[tokio::test]
+    async fn gateway_errors_are_offline_and_others_are_http() {
+        let server = stub_server(|_, body| {
+            let sequence: JsonValue = serde_json::from_str(body).unwrap();
+            match sequence["sequence"].as_i64() {
+                Some(1) => (503, "{}".to_string()),
+                _ => (500, "boom".to_string()),
+            }
+        });
+        let client = client(&server.url);
+
+        let offline = client.exchange_changes(&empty_change_set(), 1).await;
+        assert!(matches!(offline, Err(SyncError::Offline(_))));
+
+        let failed = client.exchange_changes(&empty_change_set(), 2).await;
+        assert!(matches!(
+            failed,
+            Err(SyncError::Http { status: 500, ref body }) if body == "boom"
+        ));
+    }
+
+    #[tokio::test]
+    async fn unreachable_backend_is_offline() {
+        // Bind and drop to get a port nothing listens on
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let result = client(&format!("http://127.0.0.1:{}", port))
+            .exchange_changes(&empty_change_set(), 0)
+            .await;
+        assert!(matches!(result, Err(SyncError::Offline(_))));
+    }
+}
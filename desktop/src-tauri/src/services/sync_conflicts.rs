@@ -0,0 +1,322 @@
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::services::change_log::{self, SYNCED_TABLES, SyncedTable};
+use crate::services::sync_client::{ChangeOperation, RemoteChange};
+use crate::services::sync_store;
+
+const CREATE_SYNC_CONFLICTS: &str = "
+-- Rows edited here and at the backend that need a human decision. The
+-- backend's side is kept until someone picks one.
+CREATE TABLE sync_conflicts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    table_name TEXT NOT NULL,
+    record_id INTEGER NOT NULL,
+    backend_id TEXT NOT NULL,
+    remote_operation TEXT NOT NULL CHECK (remote_operation IN ('upsert', 'delete')),
+    remote_version INTEGER NOT NULL,
+    remote_data TEXT,
+    detected_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    resolved_at DATETIME,
+    resolution TEXT CHECK (resolution IN ('keep_local', 'keep_remote'))
+);
+
+CREATE UNIQUE INDEX idx_sync_conflicts_open ON sync_conflicts(table_name, record_id)
+WHERE resolved_at IS NULL;
+";
+
+/// SQL for the `sync_conflicts` migration: the conflict table plus a
+/// `base_version` column on every synced table, the backend version local
+/// edits are based on. Rows already synced start from their current version.
+pub fn migration_up() -> String {
+    let mut sql = String::from(CREATE_SYNC_CONFLICTS);
+    for table in SYNCED_TABLES {
+        sql.push_str(&format!(
+            "
+ALTER TABLE {name} ADD COLUMN base_version INTEGER;
+UPDATE {name} SET base_version = version
+WHERE sync_status = 'synced' AND backend_id IS NOT NULL;
+",
+            name = table.name
+        ));
+    }
+    sql
+}
+
+pub fn migration_down() -> String {
+    let mut sql = String::from(
+        "DROP INDEX IF EXISTS idx_sync_conflicts_open;\nDROP TABLE IF EXISTS sync_conflicts;\n",
+    );
+    for table in SYNCED_TABLES {
+        sql.push_str(&format!(
+            "UPDATE {name} SET sync_status = 'pending' WHERE sync_status = 'conflict';\n\
+             ALTER TABLE {name} DROP COLUMN base_version;\n",
+            name = table.name
+        ));
+    }
+    sql
+}
+
+/// An open conflict with both sides of the row
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncConflict {
+    pub id: i64,
+    pub table: String,
+    pub record_id: i64,
+    pub backend_id: String,
+    pub detected_at: String,
+    /// Current local row, `None` if it was deleted here
+    pub local_data: Option<serde_json::Map<String, JsonValue>>,
+    pub local_version: Option<i64>,
+    pub remote_operation: ChangeOperation,
+    pub remote_version: i64,
+    /// Backend row, foreign keys as backend ids; `None` for a remote delete
+    pub remote_data: Option<serde_json::Map<String, JsonValue>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolution {
+    /// Push the local row over the backend's version
+    KeepLocal,
+    /// Apply the backend's version and drop the local edits
+    KeepRemote,
+}
+
+impl Resolution {
+    fn as_str(self) -> &'static str {
+        match self {
+            Resolution::KeepLocal => "keep_local",
+            Resolution::KeepRemote => "keep_remote",
+        }
+    }
+}
+
+/// Backend version held by the row's open conflict, `None` if there is none
+pub fn open_version(conn: &Connection, table: &str, record_id: i64) -> Result<Option<i64>, String> {
+    conn.query_row(
+        "SELECT remote_version FROM sync_conflicts
+         WHERE table_name = ? AND record_id = ? AND resolved_at IS NULL",
+        params![table, record_id],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| format!("Failed to read sync conflicts: {}", e))
+}
+
+/// Open a conflict for the row, or replace the backend's side of an open
+/// one with a newer change. The row is marked `sync_status = 'conflict'`
+/// and its local edits are held back until the conflict is resolved.
+pub fn record(
+    conn: &Connection,
+    table: &SyncedTable,
+    record_id: i64,
+    change: &RemoteChange,
+) -> Result<(), String> {
+    let remote_data = change
+        .data
+        .as_ref()
+        .map(|data| JsonValue::Object(data.clone()).to_string());
+
+    conn.execute(
+        "INSERT INTO sync_conflicts
+             (table_name, record_id, backend_id, remote_operation, remote_version, remote_data)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT (table_name, record_id) WHERE resolved_at IS NULL DO UPDATE SET
+             remote_operation = excluded.remote_operation,
+             remote_version = excluded.remote_version,
+             remote_data = excluded.remote_data",
+        params![
+            table.name,
+            record_id,
+            change.backend_id,
+            operation_name(change.operation),
+            change.version,
+            remote_data
+        ],
+    )
+    .map_err(|e| format!("Failed to record sync conflict: {}", e))?;
+
+    conn.execute(
+        &format!(
+            "UPDATE {} SET sync_status = 'conflict' WHERE id = ?",
+            table.name
+        ),
+        [record_id],
+    )
+    .map_err(|e| {
+        format!(
+            "Failed to mark {} {} in conflict: {}",
+            table.name, record_id, e
+        )
+    })?;
+
+    log::warn!(
+        "Sync conflict on {} {}: edited here and at the backend (version {})",
+        table.name,
+        record_id,
+        change.version
+    );
+    Ok(())
+}
+
+/// Open conflicts, oldest first
+pub fn list_open(conn: &Connection) -> Result<Vec<SyncConflict>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, table_name, record_id, backend_id, detected_at,
+                    remote_operation, remote_version, remote_data
+             FROM sync_conflicts WHERE resolved_at IS NULL ORDER BY id",
+        )
+        .map_err(|e| format!("Failed to read sync conflicts: {}", e))?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, String>(5)?,
+                row.get::<_, i64>(6)?,
+                row.get::<_, Option<String>>(7)?,
+            ))
+        })
+        .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Failed to read sync conflicts: {}", e))?;
+
+    rows.into_iter()
+        .map(
+            |(id, table, record_id, backend_id, detected_at, operation, version, data)| {
+                let local = sync_store::select_row(conn, &table, record_id)?;
+                let local_version = local
+                    .as_ref()
+                    .and_then(|row| row.get("version"))
+                    .and_then(JsonValue::as_i64);
+                Ok(SyncConflict {
+                    id,
+                    table,
+                    record_id,
+                    backend_id,
+                    detected_at,
+                    local_data: local.map(|mut row| {
+                        row.retain(|column, _| sync_store::is_row_data(column));
+                        row
+                    }),
+                    local_version,
+                    remote_operation: parse_operation(&operation),
+                    remote_version: version,
+                    remote_data: parse_data(data.as_deref())?,
+                })
+            },
+        )
+        .collect()
+}
+
+/// Settle an open conflict. Runs in the caller's transaction on the writer.
+pub fn resolve(conn: &Connection, conflict_id: i64, resolution: Resolution) -> Result<(), String> {
+    let open = conn
+        .query_row(
+            "SELECT table_name, record_id, backend_id, remote_operation, remote_version, remote_data
+             FROM sync_conflicts WHERE id = ? AND resolved_at IS NULL",
+            [conflict_id],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, i64>(4)?,
+                    row.get::<_, Option<String>>(5)?,
+                ))
+            },
+        )
+        .optional()
+        .map_err(|e| format!("Failed to read sync conflict {}: {}", conflict_id, e))?;
+
+    let Some((table_name, record_id, backend_id, operation, version, data)) = open else {
+        return Err(format!(
+            "Sync conflict {} does not exist or is already resolved",
+            conflict_id
+        ));
+    };
+    let table = SyncedTable::find(&table_name).ok_or_else(|| {
+        format!(
+            "Sync conflict {} is on unknown table {}",
+            conflict_id, table_name
+        )
+    })?;
+
+    match resolution {
+        Resolution::KeepLocal => {
+            sync_store::override_remote_version(conn, table.name, record_id, version)?;
+            // Queue the row again even if its earlier entries were parked
+            conn.execute(
+                &format!(
+                    "INSERT INTO change_log (table_name, record_id, backend_id, operation, version)
+                     SELECT '{name}', id, backend_id, 'update', version FROM {name} WHERE id = ?",
+                    name = table.name
+                ),
+                [record_id],
+            )
+            .map_err(|e| format!("Failed to queue {} {}: {}", table.name, record_id, e))?;
+        }
+        Resolution::KeepRemote => {
+            let change = RemoteChange {
+                table: table_name.clone(),
+                backend_id,
+                operation: parse_operation(&operation),
+                version,
+                data: parse_data(data.as_deref())?,
+            };
+            let exists = sync_store::select_row(conn, table.name, record_id)?.is_some();
+            change_log::apply_remote(conn, || {
+                sync_store::write_remote_change(conn, table, &change, exists.then_some(record_id))
+            })?;
+            sync_store::discard_local_changes(
+                conn,
+                table.name,
+                record_id,
+                "Discarded in conflict resolution",
+            )?;
+        }
+    }
+
+    conn.execute(
+        "UPDATE sync_conflicts SET resolved_at = CURRENT_TIMESTAMP, resolution = ? WHERE id = ?",
+        params![resolution.as_str(), conflict_id],
+    )
+    .map_err(|e| format!("Failed to resolve sync conflict {}: {}", conflict_id, e))?;
+
+    log::info!(
+        "Sync conflict {} on {} {} resolved: {}",
+        conflict_id,
+        table.name,
+        record_id,
+        resolution.as_str()
+    );
+    Ok(())
+}
+
+fn operation_name(operation: ChangeOperation) -> &'static str {
+    match operation {
+        ChangeOperation::Upsert => "upsert",
+        ChangeOperation::Delete => "delete",
+    }
+}
+
+fn parse_operation(name: &str) -> ChangeOperation {
+    if name == "delete" {
+        ChangeOperation::Delete
+    } else {
+        ChangeOperation::Upsert
+    }
+}
+
+fn parse_data(data: Option<&str>) -> Result<Option<serde_json::Map<String, JsonValue>>, String> {
+    data.map(serde_json::from_str)
+        .transpose()
+        .map_err(|e| format!("Invalid conflict data: {}", e))
+}
@@ -0,0 +1,1703 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use rusqlite::types::Value;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+
+use crate::money::Money;
+use crate::services::change_log::{self, ConflictPolicy, SyncedTable};
+use crate::services::database::{get_setting, set_setting, terminal_id};
+use crate::services::sql_value::{ResultEncoding, json_to_sql_value, result_columns, row_to_json};
+use crate::services::sync_client::{
+    CHANGE_SET_FORMAT, ChangeOperation, ChangeSet, OutboundChange, RemoteChange, SaleReport,
+    SaleReportItem, ServerChanges, SyncConfig,
+};
+use crate::services::sync_conflicts;
+
+/// Records sent per `/sync/changes` call
+pub const BATCH_SIZE: usize = 200;
+
+/// Rejected changes are resent this many times before they're parked
+pub const MAX_ATTEMPTS: i64 = 5;
+
+/// Parents before children, so the backend can resolve references
+/// within one batch
+const PUSH_ORDER: &[&str] = &[
+    "categories",
+    "customers",
+    "cash_register_sessions",
+    "products",
+    "transactions",
+    "payments",
+    "cash_movements",
+    "stock_movements",
+];
+
+/// Local sync bookkeeping, never sent as row data
+const BOOKKEEPING_COLUMNS: &[&str] = &[
+    "id",
+    "backend_id",
+    "sync_status",
+    "version",
+    "base_version",
+    "last_synced_at",
+    "reported_at",
+];
+
+const SETTINGS_CATEGORY: &str = "sync";
+
+/// A record in the outgoing batch, kept to apply the backend's answer
+#[derive(Debug)]
+pub struct PendingRecord {
+    pub table: &'static SyncedTable,
+    pub record_id: i64,
+    pub change_id: i64,
+    pub operation: ChangeOperation,
+    /// Row version sent; the row is only marked synced if it still matches
+    pub version: i64,
+}
+
+#[derive(Debug)]
+pub struct PreparedBatch {
+    pub change_set: ChangeSet,
+    pub records: Vec<PendingRecord>,
+    pub sequence: i64,
+    /// The outbox had more records than fit in this batch
+    pub has_more: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BatchOutcome {
+    pub pushed: usize,
+    pub rejected: usize,
+    /// Remote changes received, plus parked ones that applied on retry
+    pub pulled: usize,
+    /// Rows set aside as `sync_status = 'conflict'`
+    pub conflicts: usize,
+    /// Remote changes that failed to apply and were parked
+    pub parked: usize,
+    /// Sales sent to `/sales/report`
+    pub reported: usize,
+}
+
+/// What happened to one change from the backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemoteOutcome {
+    Applied,
+    /// Not newer than the version this terminal last synced
+    Stale,
+    /// Local edits win; they are pushed over the backend's version
+    KeptLocal,
+    /// Left for a manager in `sync_conflicts`
+    Conflict,
+}
+
+/// Outbound changes waiting for one table
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TableBacklog {
+    pub table: String,
+    /// Records with changes still to send
+    pub pending: i64,
+    /// Records parked after `MAX_ATTEMPTS` rejections
+    pub failed: i64,
+}
+
+/// What the terminal still owes the backend, read from the database
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboxStatus {
+    pub tables: Vec<TableBacklog>,
+    pub pending_changes: i64,
+    pub failed_changes: i64,
+    pub open_conflicts: i64,
+    /// Remote changes waiting in `sync_parked_changes`
+    pub parked_remote_changes: i64,
+    /// DTEs not yet accepted by Hacienda
+    pub pending_dte: i64,
+    pub last_push_at: Option<String>,
+    pub last_pull_at: Option<String>,
+}
+
+/// Sync token stored in `system_settings` by older versions; it now lives in
+/// secure storage
+const LEGACY_TOKEN_SETTING: &str = "sync_api_token";
+
+/// Backend settings, `None` while `sync_api_url` is empty. A terminal id is
+/// generated and stored on first use. The token is left empty for the
+/// caller to fill in from secure storage.
+pub fn load_config(conn: &Connection) -> Result<Option<SyncConfig>, String> {
+    let base_url = match get_setting(conn, "sync_api_url")? {
+        Some(url) if !url.trim().is_empty() => url.trim().to_string(),
+        _ => return Ok(None),
+    };
+    let terminal_id = terminal_id(conn)?;
+
+    Ok(Some(SyncConfig {
+        base_url,
+        token: None,
+        terminal_id,
+    }))
+}
+
+/// A sync token still in `system_settings`, to be moved to secure storage
+pub fn legacy_token(conn: &Connection) -> Result<Option<String>, String> {
+    get_setting(conn, LEGACY_TOKEN_SETTING)
+}
+
+pub fn remove_legacy_token(conn: &Connection) -> Result<(), String> {
+    conn.execute(
+        "DELETE FROM system_settings WHERE key = ?",
+        [LEGACY_TOKEN_SETTING],
+    )
+    .map(|_| ())
+    .map_err(|e| format!("Failed to remove {}: {}", LEGACY_TOKEN_SETTING, e))
+}
+
+/// Collect the oldest pending records into a change set. Several log
+/// entries for one record collapse into its latest state. Rows get a
+/// `backend_id` here if they don't have one yet, and records with nothing
+/// to send (e.g. created and deleted before any sync) are marked synced.
+pub fn prepare_batch(conn: &Connection) -> Result<PreparedBatch, String> {
+    let entries = pending_entries(conn)?;
+    let has_more = entries.len() == BATCH_SIZE;
+
+    let mut records = Vec::with_capacity(entries.len());
+    let mut changes = Vec::with_capacity(entries.len());
+
+    for entry in entries {
+        let Some(table) = SyncedTable::find(&entry.table_name) else {
+            log::warn!(
+                "Skipping change log entry for unknown table {}",
+                entry.table_name
+            );
+            mark_logged_synced(conn, &entry.table_name, entry.record_id, entry.change_id)?;
+            continue;
+        };
+
+        let change = if entry.operation == "delete" {
+            entry.backend_id.map(|backend_id| OutboundChange {
+                change_id: entry.change_id,
+                table: table.name.to_string(),
+                backend_id,
+                operation: ChangeOperation::Delete,
+                version: entry.version.unwrap_or(0),
+                base_version: None,
+                data: None,
+            })
+        } else {
+            row_change(conn, table, entry.record_id, entry.change_id)?
+        };
+
+        match change {
+            Some(change) => {
+                records.push(PendingRecord {
+                    table,
+                    record_id: entry.record_id,
+                    change_id: change.change_id,
+                    operation: change.operation,
+                    version: change.version,
+                });
+                changes.push(change);
+            }
+            None => mark_logged_synced(conn, table.name, entry.record_id, entry.change_id)?,
+        }
+    }
+
+    let rank = |table: &str| {
+        PUSH_ORDER
+            .iter()
+            .position(|t| *t == table)
+            .unwrap_or(usize::MAX)
+    };
+    changes.sort_by_key(|c| (rank(&c.table), c.change_id));
+
+    Ok(PreparedBatch {
+        change_set: ChangeSet {
+            format: CHANGE_SET_FORMAT,
+            changes,
+        },
+        records,
+        sequence: load_cursor(conn)?,
+        has_more,
+    })
+}
+
+/// Record the backend's answer to our changes: acknowledged records
+/// become synced and rejected ones count an attempt. Runs in its own
+/// transaction before the pulled changes are applied, so a remote change
+/// that fails can't undo the acknowledgements.
+pub fn record_push_results(
+    conn: &Connection,
+    records: &[PendingRecord],
+    server: &ServerChanges,
+) -> Result<BatchOutcome, String> {
+    let by_change: HashMap<i64, &PendingRecord> =
+        records.iter().map(|r| (r.change_id, r)).collect();
+    let mut outcome = BatchOutcome::default();
+
+    for ack in &server.acknowledged {
+        let Some(record) = by_change.get(&ack.change_id) else {
+            log::warn!("Backend acknowledged unknown change {}", ack.change_id);
+            continue;
+        };
+        mark_logged_synced(conn, record.table.name, record.record_id, record.change_id)?;
+        if record.operation == ChangeOperation::Upsert {
+            mark_row_synced(conn, record, ack.version)?;
+        }
+        outcome.pushed += 1;
+    }
+
+    for rejection in &server.rejected {
+        let Some(record) = by_change.get(&rejection.change_id) else {
+            log::warn!("Backend rejected unknown change {}", rejection.change_id);
+            continue;
+        };
+        // Version conflicts are settled with the pulled changes
+        if rejection.current.is_some() {
+            continue;
+        }
+        log::warn!(
+            "Backend rejected {} {}: {}",
+            record.table.name,
+            record.record_id,
+            rejection.reason
+        );
+        conn.execute(
+            "UPDATE change_log SET attempts = attempts + 1, last_error = ?1
+             WHERE table_name = ?2 AND record_id = ?3 AND id <= ?4 AND synced_at IS NULL",
+            params![
+                rejection.reason,
+                record.table.name,
+                record.record_id,
+                record.change_id
+            ],
+        )
+        .map_err(|e| format!("Failed to record sync rejection: {}", e))?;
+        outcome.rejected += 1;
+    }
+
+    if outcome.pushed > 0 {
+        let now = Utc::now().to_rfc3339();
+        set_setting(conn, "sync_last_push_at", &now, SETTINGS_CATEGORY)?;
+    }
+    Ok(outcome)
+}
+
+/// Apply the backend's changes, and the current rows attached to version
+/// conflicts, without logging them, then move the pull cursor forward.
+/// Each change is written in its own savepoint: one that fails is parked in
+/// `sync_parked_changes` with its error and the rest still apply. Changes
+/// parked by earlier polls are retried afterwards, so one waiting for a
+/// parent applies in the poll that brings the parent. Version conflicts are
+/// settled by the table's `ConflictPolicy`.
+pub fn apply_pulled_changes(
+    conn: &Connection,
+    server: &ServerChanges,
+    next_sequence: i64,
+    mut outcome: BatchOutcome,
+) -> Result<BatchOutcome, String> {
+    outcome.pulled += server.changes.len();
+    let version_conflicts = server.rejected.iter().filter_map(|r| r.current.as_ref());
+
+    change_log::apply_remote(conn, || {
+        let parked_before = last_parked_id(conn)?;
+        for change in version_conflicts.chain(&server.changes) {
+            match apply_in_savepoint(conn, change)? {
+                Ok(result) => {
+                    if result == RemoteOutcome::Conflict {
+                        outcome.conflicts += 1;
+                    }
+                    unpark(conn, change)?;
+                }
+                Err(error) => {
+                    log::warn!(
+                        "Parking remote {} {} version {}: {}",
+                        change.table,
+                        change.backend_id,
+                        change.version,
+                        error
+                    );
+                    park(conn, change, &error)?;
+                    outcome.parked += 1;
+                }
+            }
+        }
+        for (parked_id, change) in parked_changes(conn, parked_before)? {
+            match apply_in_savepoint(conn, &change)? {
+                Ok(result) => {
+                    outcome.pulled += 1;
+                    if result == RemoteOutcome::Conflict {
+                        outcome.conflicts += 1;
+                    }
+                    unpark(conn, &change)?;
+                }
+                Err(error) => {
+                    conn.execute(
+                        "UPDATE sync_parked_changes
+                         SET attempts = attempts + 1, last_error = ?1,
+                             last_attempt_at = CURRENT_TIMESTAMP
+                         WHERE id = ?2",
+                        params![error, parked_id],
+                    )
+                    .map_err(|e| format!("Failed to update parked change: {}", e))?;
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    set_setting(
+        conn,
+        "sync_cursor",
+        &next_sequence.to_string(),
+        SETTINGS_CATEGORY,
+    )?;
+    let now = Utc::now().to_rfc3339();
+    set_setting(conn, "sync_last_pull_at", &now, SETTINGS_CATEGORY)?;
+    Ok(outcome)
+}
+
+/// Apply one remote change, rolling back its writes if it fails. The outer
+/// error is a failure of the savepoint itself; the inner one is the
+/// change's, to be parked.
+fn apply_in_savepoint(
+    conn: &Connection,
+    change: &RemoteChange,
+) -> Result<Result<RemoteOutcome, String>, String> {
+    conn.execute_batch("SAVEPOINT remote_change")
+        .map_err(|e| format!("Failed to start savepoint: {}", e))?;
+    let result = apply_remote_change(conn, change);
+    if result.is_err() {
+        conn.execute_batch("ROLLBACK TO remote_change")
+            .map_err(|e| format!("Failed to roll back remote change: {}", e))?;
+    }
+    conn.execute_batch("RELEASE remote_change")
+        .map_err(|e| format!("Failed to release savepoint: {}", e))?;
+    Ok(result)
+}
+
+fn last_parked_id(conn: &Connection) -> Result<i64, String> {
+    conn.query_row(
+        "SELECT COALESCE(MAX(id), 0) FROM sync_parked_changes",
+        [],
+        |row| row.get(0),
+    )
+    .map_err(|e| format!("Failed to read parked changes: {}", e))
+}
+
+/// Parked changes up to `max_id`, oldest first
+fn parked_changes(conn: &Connection, max_id: i64) -> Result<Vec<(i64, RemoteChange)>, String> {
+    let mut stmt = conn
+        .prepare("SELECT id, change FROM sync_parked_changes WHERE id <= ? ORDER BY id")
+        .map_err(|e| format!("Failed to read parked changes: {}", e))?;
+    let rows: Vec<(i64, String)> = stmt
+        .query_map([max_id], |row| Ok((row.get(0)?, row.get(1)?)))
+        .and_then(|rows| rows.collect())
+        .map_err(|e| format!("Failed to read parked changes: {}", e))?;
+
+    let mut changes = Vec::with_capacity(rows.len());
+    for (id, json) in rows {
+        match serde_json::from_str(&json) {
+            Ok(change) => changes.push((id, change)),
+            Err(e) => log::warn!("Skipping unreadable parked change {}: {}", id, e),
+        }
+    }
+    Ok(changes)
+}
+
+/// Keep a failed change, replacing an older parked version of the same row
+fn park(conn: &Connection, change: &RemoteChange, error: &str) -> Result<(), String> {
+    let json = serde_json::to_string(change)
+        .map_err(|e| format!("Failed to encode remote change: {}", e))?;
+    conn.execute(
+        "INSERT INTO sync_parked_changes (table_name, backend_id, version, change, last_error)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT (table_name, backend_id) DO UPDATE SET
+             version = excluded.version, change = excluded.change,
+             last_error = excluded.last_error, attempts = attempts + 1,
+             last_attempt_at = CURRENT_TIMESTAMP
+         WHERE excluded.version >= sync_parked_changes.version",
+        params![change.table, change.backend_id, change.version, json, error],
+    )
+    .map(|_| ())
+    .map_err(|e| format!("Failed to park remote change: {}", e))
+}
+
+/// A change that applied supersedes parked versions of its row up to its own
+fn unpark(conn: &Connection, change: &RemoteChange) -> Result<(), String> {
+    conn.execute(
+        "DELETE FROM sync_parked_changes
+         WHERE table_name = ?1 AND backend_id = ?2 AND version <= ?3",
+        params![change.table, change.backend_id, change.version],
+    )
+    .map(|_| ())
+    .map_err(|e| format!("Failed to clear parked change: {}", e))
+}
+
+/// Completed and voided sales the backend already has through
+/// `/sync/changes` but hasn't had reported yet, oldest first. A sale voided
+/// after its report is sent again (migration 9 clears `reported_at`).
+pub fn sales_to_report(conn: &Connection, terminal_id: &str) -> Result<Vec<SaleReport>, String> {
+    struct Row {
+        id: i64,
+        backend_id: String,
+        cashier_id: String,
+        customer_id: Option<String>,
+        amounts: [i64; 4],
+        status: String,
+        transaction_number: String,
+        created_at: String,
+        updated_at: String,
+        void_reason: Option<String>,
+    }
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT t.id, t.backend_id, COALESCE(u.backend_user_id, CAST(t.user_id AS TEXT)),
+                    c.backend_id, t.subtotal_cents, t.tax_amount_cents,
+                    t.discount_amount_cents, t.total_cents, t.status, t.transaction_number,
+                    t.created_at, t.updated_at,
+                    (SELECT json_extract(a.new_values, '$.reason') FROM audit_logs a
+                     WHERE a.action = 'sale_voided' AND a.table_name = 'transactions'
+                       AND a.record_id = t.id
+                     ORDER BY a.id DESC LIMIT 1)
+             FROM transactions t
+             JOIN users u ON u.id = t.user_id
+             LEFT JOIN customers c ON c.id = t.customer_id
+             WHERE t.reported_at IS NULL AND t.sync_status = 'synced'
+               AND t.backend_id IS NOT NULL
+               AND t.status IN ('completed', 'cancelled', 'voided')
+             ORDER BY t.id
+             LIMIT ?",
+        )
+        .map_err(|e| format!("Failed to read sales to report: {}", e))?;
+    let rows: Vec<Row> = stmt
+        .query_map([BATCH_SIZE as i64], |row| {
+            Ok(Row {
+                id: row.get(0)?,
+                backend_id: row.get(1)?,
+                cashier_id: row.get(2)?,
+                customer_id: row.get(3)?,
+                amounts: [row.get(4)?, row.get(5)?, row.get(6)?, row.get(7)?],
+                status: row.get(8)?,
+                transaction_number: row.get(9)?,
+                created_at: row.get(10)?,
+                updated_at: row.get(11)?,
+                void_reason: row.get(12)?,
+            })
+        })
+        .and_then(|rows| rows.collect())
+        .map_err(|e| format!("Failed to read sales to report: {}", e))?;
+
+    let mut sales = Vec::with_capacity(rows.len());
+    for row in rows {
+        let [subtotal, tax_total, discount_total, total] = row.amounts.map(Money::from_cents);
+        let voided = row.status != "completed";
+        let created_at = report_timestamp(&row.created_at);
+        sales.push(SaleReport {
+            local_id: row.id,
+            id: row.backend_id,
+            terminal_id: terminal_id.to_string(),
+            cashier_id: row.cashier_id,
+            customer_id: row.customer_id,
+            items: sale_report_items(conn, row.id, tax_total)?,
+            subtotal,
+            tax_total,
+            discount_total,
+            total,
+            payment_method: sale_payment_method(conn, row.id)?,
+            status: if voided { "VOIDED" } else { "COMPLETED" },
+            invoice_number: row.transaction_number,
+            completed_at: Some(created_at.clone()),
+            created_at,
+            voided_at: voided.then(|| report_timestamp(&row.updated_at)),
+            void_reason: row.void_reason.filter(|_| voided),
+        });
+    }
+    Ok(sales)
+}
+
+/// Sales the backend accepted in `/sales/report`. Only `reported_at`
+/// changes, so this isn't logged as an edit.
+pub fn mark_sales_reported(conn: &Connection, ids: &[i64]) -> Result<(), String> {
+    let mut stmt = conn
+        .prepare("UPDATE transactions SET reported_at = CURRENT_TIMESTAMP WHERE id = ?")
+        .map_err(|e| format!("Failed to mark sales reported: {}", e))?;
+    for id in ids {
+        stmt.execute([id])
+            .map_err(|e| format!("Failed to mark sale {} reported: {}", id, e))?;
+    }
+    Ok(())
+}
+
+/// Lines of a reported sale, with the sale's IVA split over them by amount
+fn sale_report_items(
+    conn: &Connection,
+    transaction_id: i64,
+    tax_total: Money,
+) -> Result<Vec<SaleReportItem>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT ti.product_id, p.name, ti.quantity, ti.unit_price_cents,
+                    ti.discount_amount_cents, ti.total_price_cents
+             FROM transaction_items ti
+             JOIN products p ON p.id = ti.product_id
+             WHERE ti.transaction_id = ?
+             ORDER BY ti.id",
+        )
+        .map_err(|e| format!("Failed to read transaction items: {}", e))?;
+    let lines: Vec<(i64, String, f64, i64, i64, i64)> = stmt
+        .query_map([transaction_id], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+            ))
+        })
+        .and_then(|rows| rows.collect())
+        .map_err(|e| format!("Failed to read transaction items: {}", e))?;
+
+    let weights: Vec<i64> = lines.iter().map(|line| line.5).collect();
+    let taxes = tax_total.allocate(&weights);
+
+    let mut items = Vec::with_capacity(lines.len());
+    for ((product_id, name, quantity, unit_price, discount, subtotal), tax) in
+        lines.into_iter().zip(taxes)
+    {
+        let subtotal = Money::from_cents(subtotal);
+        items.push(SaleReportItem {
+            product_id: ensure_backend_id(conn, "products", product_id)?,
+            product_name: name,
+            quantity,
+            unit_price: Money::from_cents(unit_price),
+            discount: Money::from_cents(discount),
+            tax,
+            subtotal,
+            total: subtotal + tax,
+        });
+    }
+    Ok(items)
+}
+
+/// MIXED for split payments. Sales without payment rows predate split
+/// payments and were cash; checks and store credit have no API value and
+/// are reported as MIXED.
+fn sale_payment_method(conn: &Connection, transaction_id: i64) -> Result<&'static str, String> {
+    let mut stmt = conn
+        .prepare("SELECT DISTINCT payment_method FROM payments WHERE transaction_id = ?")
+        .map_err(|e| format!("Failed to read payments: {}", e))?;
+    let methods: Vec<String> = stmt
+        .query_map([transaction_id], |row| row.get(0))
+        .and_then(|rows| rows.collect())
+        .map_err(|e| format!("Failed to read payments: {}", e))?;
+
+    Ok(match methods.as_slice() {
+        [] => "CASH",
+        [method] => match method.as_str() {
+            "cash" => "CASH",
+            "card" => "CARD",
+            "transfer" => "TRANSFER",
+            _ => "MIXED",
+        },
+        _ => "MIXED",
+    })
+}
+
+/// `CURRENT_TIMESTAMP` values are UTC without a zone; the API wants RFC 3339
+fn report_timestamp(value: &str) -> String {
+    chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+        .map(|t| t.and_utc().to_rfc3339())
+        .unwrap_or_else(|_| value.to_string())
+}
+
+/// Pending and parked records per synced table, open conflicts, pending
+/// DTEs and the last successful push and pull
+pub fn outbox_status(conn: &Connection) -> Result<OutboxStatus, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT table_name,
+                    COUNT(DISTINCT CASE WHEN attempts < ?1 THEN record_id END),
+                    COUNT(DISTINCT CASE WHEN attempts >= ?1 THEN record_id END)
+             FROM change_log
+             WHERE synced_at IS NULL
+             GROUP BY table_name",
+        )
+        .map_err(|e| format!("Failed to read change log: {}", e))?;
+    let counts: HashMap<String, (i64, i64)> = stmt
+        .query_map([MAX_ATTEMPTS], |row| {
+            Ok((row.get(0)?, (row.get(1)?, row.get(2)?)))
+        })
+        .and_then(|rows| rows.collect())
+        .map_err(|e| format!("Failed to read change log: {}", e))?;
+
+    let tables: Vec<TableBacklog> = PUSH_ORDER
+        .iter()
+        .map(|name| {
+            let (pending, failed) = counts.get(*name).copied().unwrap_or_default();
+            TableBacklog {
+                table: name.to_string(),
+                pending,
+                failed,
+            }
+        })
+        .collect();
+
+    let count = |sql: &str| -> Result<i64, String> {
+        conn.query_row(sql, [], |row| row.get(0))
+            .map_err(|e| format!("Failed to read sync status: {}", e))
+    };
+
+    Ok(OutboxStatus {
+        pending_changes: tables.iter().map(|t| t.pending).sum(),
+        failed_changes: tables.iter().map(|t| t.failed).sum(),
+        tables,
+        open_conflicts: count("SELECT COUNT(*) FROM sync_conflicts WHERE resolved_at IS NULL")?,
+        parked_remote_changes: count("SELECT COUNT(*) FROM sync_parked_changes")?,
+        pending_dte: count("SELECT COUNT(*) FROM dte WHERE dte_status = 'pending'")?,
+        last_push_at: get_setting(conn, "sync_last_push_at")?,
+        last_pull_at: get_setting(conn, "sync_last_pull_at")?,
+    })
+}
+
+struct LogEntry {
+    table_name: String,
+    record_id: i64,
+    change_id: i64,
+    backend_id: Option<String>,
+    operation: String,
+    version: Option<i64>,
+}
+
+/// Latest pending entry of the oldest pending records
+fn pending_entries(conn: &Connection) -> Result<Vec<LogEntry>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT c.table_name, c.record_id, c.id, c.backend_id, c.operation, c.version
+             FROM change_log c
+             JOIN (
+                 SELECT MAX(id) AS id FROM change_log l
+                 WHERE synced_at IS NULL AND attempts < ?1
+                   AND NOT EXISTS (
+                       SELECT 1 FROM sync_conflicts s
+                       WHERE s.table_name = l.table_name AND s.record_id = l.record_id
+                         AND s.resolved_at IS NULL
+                   )
+                 GROUP BY table_name, record_id
+                 ORDER BY MIN(id)
+                 LIMIT ?2
+             ) latest ON latest.id = c.id
+             ORDER BY c.id",
+        )
+        .map_err(|e| format!("Failed to read change log: {}", e))?;
+
+    stmt.query_map(params![MAX_ATTEMPTS, BATCH_SIZE as i64], |row| {
+        Ok(LogEntry {
+            table_name: row.get(0)?,
+            record_id: row.get(1)?,
+            change_id: row.get(2)?,
+            backend_id: row.get(3)?,
+            operation: row.get(4)?,
+            version: row.get(5)?,
+        })
+    })
+    .and_then(|rows| rows.collect())
+    .map_err(|e| format!("Failed to read change log: {}", e))
+}
+
+/// Current row as an upsert, `None` when the row no longer exists
+fn row_change(
+    conn: &Connection,
+    table: &SyncedTable,
+    record_id: i64,
+    change_id: i64,
+) -> Result<Option<OutboundChange>, String> {
+    let Some(mut data) = select_row(conn, table.name, record_id)? else {
+        return Ok(None);
+    };
+
+    let backend_id = ensure_backend_id(conn, table.name, record_id)?;
+    let version = data.get("version").and_then(JsonValue::as_i64).unwrap_or(1);
+    let base_version = data.get("base_version").and_then(JsonValue::as_i64);
+    data.retain(|column, _| is_row_data(column));
+
+    for (column, referenced) in table.references {
+        if let Some(local_id) = data.get(*column).and_then(JsonValue::as_i64) {
+            let backend = ensure_backend_id(conn, referenced, local_id)?;
+            data.insert(column.to_string(), JsonValue::String(backend));
+        }
+    }
+
+    if table.name == "transactions" {
+        data.insert("items".to_string(), transaction_items(conn, record_id)?);
+    }
+
+    Ok(Some(OutboundChange {
+        change_id,
+        table: table.name.to_string(),
+        backend_id,
+        operation: ChangeOperation::Upsert,
+        version,
+        base_version,
+        data: Some(data),
+    }))
+}
+
+/// Bookkeeping and the trigger-derived `*_cents` columns stay local
+pub fn is_row_data(column: &str) -> bool {
+    !BOOKKEEPING_COLUMNS.contains(&column) && !column.ends_with("_cents")
+}
+
+/// Items travel inside their transaction, products as backend ids
+fn transaction_items(conn: &Connection, transaction_id: i64) -> Result<JsonValue, String> {
+    let mut stmt = conn
+        .prepare("SELECT * FROM transaction_items WHERE transaction_id = ? ORDER BY id")
+        .map_err(|e| format!("Failed to read transaction items: {}", e))?;
+    let columns = result_columns(&stmt);
+    let items = stmt
+        .query_map([transaction_id], |row| {
+            row_to_json(row, &columns, ResultEncoding::Plain)
+        })
+        .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Failed to read transaction items: {}", e))?;
+
+    let mut out = Vec::with_capacity(items.len());
+    for mut item in items {
+        item.retain(|column, _| is_row_data(column) && column != "transaction_id");
+        if let Some(product_id) = item.get("product_id").and_then(JsonValue::as_i64) {
+            let backend = ensure_backend_id(conn, "products", product_id)?;
+            item.insert("product_id".to_string(), JsonValue::String(backend));
+        }
+        out.push(JsonValue::Object(item));
+    }
+    Ok(JsonValue::Array(out))
+}
+
+pub fn select_row(
+    conn: &Connection,
+    table: &str,
+    id: i64,
+) -> Result<Option<serde_json::Map<String, JsonValue>>, String> {
+    let mut stmt = conn
+        .prepare(&format!("SELECT * FROM {} WHERE id = ?", table))
+        .map_err(|e| format!("Failed to read {}: {}", table, e))?;
+    let columns = result_columns(&stmt);
+    stmt.query_row([id], |row| {
+        row_to_json(row, &columns, ResultEncoding::Plain)
+    })
+    .optional()
+    .map_err(|e| format!("Failed to read {}: {}", table, e))
+}
+
+/// Backend ids are generated locally, so a record can be referenced
+/// before the backend has seen it. Only untracked columns change, so this
+/// isn't logged as an edit.
+fn ensure_backend_id(conn: &Connection, table: &str, id: i64) -> Result<String, String> {
+    let existing: Option<String> = conn
+        .query_row(
+            &format!("SELECT backend_id FROM {} WHERE id = ?", table),
+            [id],
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("Failed to read backend id of {} {}: {}", table, id, e))?;
+    if let Some(backend_id) = existing {
+        return Ok(backend_id);
+    }
+
+    let backend_id = uuid::Uuid::new_v4().to_string();
+    conn.execute(
+        &format!("UPDATE {} SET backend_id = ?1 WHERE id = ?2", table),
+        params![backend_id, id],
+    )
+    .map_err(|e| format!("Failed to assign backend id to {} {}: {}", table, id, e))?;
+    Ok(backend_id)
+}
+
+fn mark_logged_synced(
+    conn: &Connection,
+    table: &str,
+    record_id: i64,
+    change_id: i64,
+) -> Result<(), String> {
+    conn.execute(
+        "UPDATE change_log SET synced_at = CURRENT_TIMESTAMP, last_error = NULL
+         WHERE table_name = ?1 AND record_id = ?2 AND id <= ?3 AND synced_at IS NULL",
+        params![table, record_id, change_id],
+    )
+    .map(|_| ())
+    .map_err(|e| format!("Failed to mark changes synced: {}", e))
+}
+
+/// Rows edited again since the batch was prepared keep their pending state
+fn mark_row_synced(
+    conn: &Connection,
+    record: &PendingRecord,
+    backend_version: Option<i64>,
+) -> Result<(), String> {
+    conn.execute(
+        &format!(
+            "UPDATE {} SET sync_status = 'synced', last_synced_at = CURRENT_TIMESTAMP,
+                 version = COALESCE(?1, version), base_version = COALESCE(?1, version)
+             WHERE id = ?2 AND version IS ?3",
+            record.table.name
+        ),
+        params![backend_version, record.record_id, record.version],
+    )
+    .map(|_| ())
+    .map_err(|e| {
+        format!(
+            "Failed to mark {} {} synced: {}",
+            record.table.name, record.record_id, e
+        )
+    })
+}
+
+/// Local state of a row the backend sent a change for
+struct LocalRow {
+    id: i64,
+    base_version: Option<i64>,
+    /// Edited since the last sync, or already in conflict
+    edited: bool,
+}
+
+/// Apply one change from the backend by `backend_id`. A row edited on both
+/// sides since the last sync is settled by the table's `ConflictPolicy`.
+fn apply_remote_change(conn: &Connection, change: &RemoteChange) -> Result<RemoteOutcome, String> {
+    let Some(table) = SyncedTable::find(&change.table) else {
+        log::warn!("Ignoring remote change for unknown table {}", change.table);
+        return Ok(RemoteOutcome::Stale);
+    };
+
+    let local: Option<LocalRow> = conn
+        .query_row(
+            &format!(
+                "SELECT id, base_version, sync_status IN ('pending', 'conflict')
+                 FROM {} WHERE backend_id = ?",
+                table.name
+            ),
+            [&change.backend_id],
+            |row| {
+                Ok(LocalRow {
+                    id: row.get(0)?,
+                    base_version: row.get(1)?,
+                    edited: row.get(2)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(|e| {
+            format!(
+                "Failed to look up {} {}: {}",
+                table.name, change.backend_id, e
+            )
+        })?;
+
+    let Some(local) = local else {
+        if change.operation == ChangeOperation::Delete {
+            return Ok(RemoteOutcome::Stale);
+        }
+        write_remote_change(conn, table, change, None)?;
+        return Ok(RemoteOutcome::Applied);
+    };
+
+    if local
+        .base_version
+        .is_some_and(|base| change.version <= base)
+    {
+        return Ok(RemoteOutcome::Stale);
+    }
+    if let Some(open_version) = sync_conflicts::open_version(conn, table.name, local.id)? {
+        // Keep the newest backend side on the open conflict
+        if change.version <= open_version {
+            return Ok(RemoteOutcome::Stale);
+        }
+        sync_conflicts::record(conn, table, local.id, change)?;
+        return Ok(RemoteOutcome::Conflict);
+    }
+    if !local.edited {
+        write_remote_change(conn, table, change, Some(local.id))?;
+        return Ok(RemoteOutcome::Applied);
+    }
+
+    match (table.conflict_policy, change.operation) {
+        (ConflictPolicy::ServerWins, _) => {
+            log::info!(
+                "{} {} edited here and at the backend; keeping backend version {}",
+                table.name,
+                local.id,
+                change.version
+            );
+            write_remote_change(conn, table, change, Some(local.id))?;
+            discard_local_changes(
+                conn,
+                table.name,
+                local.id,
+                &format!("Replaced by backend version {}", change.version),
+            )?;
+            Ok(RemoteOutcome::Applied)
+        }
+        (ConflictPolicy::ClientWins, ChangeOperation::Upsert) => {
+            override_remote_version(conn, table.name, local.id, change.version)?;
+            Ok(RemoteOutcome::KeptLocal)
+        }
+        // A remote delete of a local sale, or a stock movement edited on
+        // both sides, can't be settled automatically
+        _ => {
+            sync_conflicts::record(conn, table, local.id, change)?;
+            Ok(RemoteOutcome::Conflict)
+        }
+    }
+}
+
+/// Write a remote change as-is: only tracked columns, the backend's
+/// version, and for stock movements the quantity difference applied to the
+/// product's stock. Capture must already be suspended.
+pub fn write_remote_change(
+    conn: &Connection,
+    table: &SyncedTable,
+    change: &RemoteChange,
+    local_id: Option<i64>,
+) -> Result<(), String> {
+    let previous_stock = match (table.conflict_policy, local_id) {
+        (ConflictPolicy::MergeStock, Some(id)) => stock_delta(conn, id)?,
+        _ => None,
+    };
+
+    if change.operation == ChangeOperation::Delete {
+        if let Some(id) = local_id {
+            conn.execute(&format!("DELETE FROM {} WHERE id = ?", table.name), [id])
+                .map_err(|e| {
+                    format!(
+                        "Failed to delete {} {}: {}",
+                        table.name, change.backend_id, e
+                    )
+                })?;
+        }
+        return adjust_stock(conn, previous_stock, None);
+    }
+
+    let Some(data) = &change.data else {
+        return Err(format!(
+            "Remote upsert of {} {} has no data",
+            table.name, change.backend_id
+        ));
+    };
+
+    let mut columns = Vec::new();
+    let mut values = Vec::new();
+    for column in table.tracked_columns {
+        let Some(value) = data.get(*column) else {
+            continue;
+        };
+        let value = match table.references.iter().find(|(c, _)| c == column) {
+            Some((_, referenced)) => local_reference(conn, referenced, value)?,
+            None => json_to_sql_value(value)?,
+        };
+        columns.push(*column);
+        values.push(value);
+    }
+    // version and base_version
+    values.push(Value::Integer(change.version));
+    values.push(Value::Integer(change.version));
+
+    let sql = match local_id {
+        Some(id) => {
+            let assignments: String = columns.iter().map(|c| format!("{} = ?, ", c)).collect();
+            values.push(Value::Integer(id));
+            format!(
+                "UPDATE {} SET {}version = ?, base_version = ?, sync_status = 'synced',
+                     last_synced_at = CURRENT_TIMESTAMP
+                 WHERE id = ?",
+                table.name, assignments
+            )
+        }
+        None => {
+            values.push(Value::Text(change.backend_id.clone()));
+            let placeholders = "?, ".repeat(columns.len());
+            let names: String = columns.iter().map(|c| format!("{}, ", c)).collect();
+            format!(
+                "INSERT INTO {} ({}version, base_version, backend_id, sync_status, last_synced_at)
+                 VALUES ({}?, ?, ?, 'synced', CURRENT_TIMESTAMP)",
+                table.name, names, placeholders
+            )
+        }
+    };
+
+    conn.execute(&sql, rusqlite::params_from_iter(values))
+        .map_err(|e| {
+            format!(
+                "Failed to apply remote {} {}: {}",
+                table.name, change.backend_id, e
+            )
+        })?;
+
+    if table.conflict_policy == ConflictPolicy::MergeStock {
+        let id = match local_id {
+            Some(id) => id,
+            None => conn.last_insert_rowid(),
+        };
+        adjust_stock(conn, previous_stock, stock_delta(conn, id)?)?;
+    }
+    Ok(())
+}
+
+/// Product and signed quantity of a stock movement
+fn stock_delta(conn: &Connection, movement_id: i64) -> Result<Option<(i64, f64)>, String> {
+    conn.query_row(
+        "SELECT product_id, quantity FROM stock_movements WHERE id = ?",
+        [movement_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+    .map_err(|e| format!("Failed to read stock movement {}: {}", movement_id, e))
+}
+
+/// Move product stock from a movement's old quantity to its new one.
+/// `stock_quantity` isn't tracked, so this is not logged.
+fn adjust_stock(
+    conn: &Connection,
+    before: Option<(i64, f64)>,
+    after: Option<(i64, f64)>,
+) -> Result<(), String> {
+    let update = |product_id: i64, delta: f64| {
+        conn.execute(
+            "UPDATE products SET stock_quantity = stock_quantity + ?1 WHERE id = ?2",
+            params![delta, product_id],
+        )
+        .map(|_| ())
+        .map_err(|e| format!("Failed to adjust stock of product {}: {}", product_id, e))
+    };
+    if let Some((product_id, quantity)) = before {
+        update(product_id, -quantity)?;
+    }
+    if let Some((product_id, quantity)) = after {
+        update(product_id, quantity)?;
+    }
+    Ok(())
+}
+
+/// Drop a record's unsent log entries, keeping the reason
+pub fn discard_local_changes(
+    conn: &Connection,
+    table: &str,
+    record_id: i64,
+    reason: &str,
+) -> Result<(), String> {
+    conn.execute(
+        "UPDATE change_log SET synced_at = CURRENT_TIMESTAMP, last_error = ?1
+         WHERE table_name = ?2 AND record_id = ?3 AND synced_at IS NULL",
+        params![reason, table, record_id],
+    )
+    .map(|_| ())
+    .map_err(|e| format!("Failed to discard local changes: {}", e))
+}
+
+/// Base local edits on the backend's version so the next push replaces it
+pub fn override_remote_version(
+    conn: &Connection,
+    table: &str,
+    record_id: i64,
+    remote_version: i64,
+) -> Result<(), String> {
+    conn.execute(
+        &format!(
+            "UPDATE {} SET version = MAX(COALESCE(version, 0), ?1 + 1), base_version = ?1,
+                 sync_status = 'pending'
+             WHERE id = ?2",
+            table
+        ),
+        params![remote_version, record_id],
+    )
+    .map(|_| ())
+    .map_err(|e| format!("Failed to update {} {}: {}", table, record_id, e))
+}
+
+/// Foreign keys arrive as backend ids; the referenced row must already exist
+fn local_reference(conn: &Connection, table: &str, value: &JsonValue) -> Result<Value, String> {
+    let backend_id = match value {
+        JsonValue::Null => return Ok(Value::Null),
+        JsonValue::String(id) => id,
+        other => return Err(format!("Invalid {} reference {}", table, other)),
+    };
+    conn.query_row(
+        &format!("SELECT id FROM {} WHERE backend_id = ?", table),
+        [backend_id],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| format!("Failed to resolve {} {}: {}", table, backend_id, e))?
+    .map(Value::Integer)
+    .ok_or_else(|| format!("Unknown {} {}", table, backend_id))
+}
+
+fn load_cursor(conn: &Connection) -> Result<i64, String> {
+    Ok(get_setting(conn, "sync_cursor")?
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::services::migrations::migrate;
+    use crate::services::sync_client::tests::{client, sent_changes, stub_server, sync_response};
+
+    fn db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate(&conn).unwrap();
+        conn
+    }
+
+    fn status(conn: &Connection, table: &str, id: i64) -> String {
+        conn.query_row(
+            &format!("SELECT sync_status FROM {} WHERE id = ?", table),
+            [id],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    fn count(conn: &Connection, sql: &str) -> i64 {
+        conn.query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    fn category(backend_id: &str, version: i64, name: &str) -> JsonValue {
+        json!({
+            "table": "categories",
+            "backend_id": backend_id,
+            "operation": "upsert",
+            "version": version,
+            "data": { "name": name, "is_active": 1 },
+        })
+    }
+
+    fn product(backend_id: &str, category: &str) -> JsonValue {
+        json!({
+            "table": "products",
+            "backend_id": backend_id,
+            "operation": "upsert",
+            "version": 1,
+            "data": {
+                "name": "Acetaminofén 500mg",
+                "category_id": category,
+                "price": 1.5,
+                "partner_price": 1.4,
+                "vip_price": 1.3,
+            },
+        })
+    }
+
+    /// Poll the stub once the way `SyncEngine::sync_once` does, pushing and
+    /// pulling in separate transactions
+    async fn poll(conn: &Connection, url: &str) -> BatchOutcome {
+        let batch = prepare_batch(conn).unwrap();
+        let (server, next_sequence) = client(url)
+            .exchange_changes(&batch.change_set, batch.sequence)
+            .await
+            .unwrap();
+        let push = conn.unchecked_transaction().unwrap();
+        let outcome = record_push_results(&push, &batch.records, &server).unwrap();
+        push.commit().unwrap();
+        let pull = conn.unchecked_transaction().unwrap();
+        let outcome = apply_pulled_changes(&pull, &server, next_sequence, outcome).unwrap();
+        pull.commit().unwrap();
+        outcome
+    }
+
+    #[tokio::test]
+    async fn acknowledged_changes_become_synced() {
+        let conn = db();
+        conn.execute("INSERT INTO categories (name) VALUES ('Analgésicos')", [])
+            .unwrap();
+        assert_eq!(status(&conn, "categories", 1), "pending");
+
+        let server = stub_server(|_, body| {
+            let acknowledged: Vec<JsonValue> = sent_changes(body)["changes"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|c| json!({ "change_id": c["change_id"], "version": 4 }))
+                .collect();
+            (
+                200,
+                sync_response(json!({ "acknowledged": acknowledged }), 1),
+            )
+        });
+
+        let outcome = poll(&conn, &server.url).await;
+        assert_eq!((outcome.pushed, outcome.rejected), (1, 0));
+        assert_eq!(status(&conn, "categories", 1), "synced");
+        assert_eq!(
+            count(
+                &conn,
+                "SELECT COUNT(*) FROM change_log WHERE synced_at IS NULL"
+            ),
+            0
+        );
+        assert_eq!(
+            count(&conn, "SELECT base_version FROM categories WHERE id = 1"),
+            4
+        );
+
+        let sent = sent_changes(&server.requests.lock().unwrap()[0].body);
+        assert_eq!(sent["changes"][0]["table"], "categories");
+        assert_eq!(sent["changes"][0]["data"]["name"], "Analgésicos");
+    }
+
+    #[tokio::test]
+    async fn acknowledgements_survive_a_failing_pull() {
+        let conn = db();
+        conn.execute("INSERT INTO categories (name) VALUES ('Analgésicos')", [])
+            .unwrap();
+
+        // Acknowledges the push and sends a product whose category is unknown
+        let server = stub_server(|_, body| {
+            let change_id = sent_changes(body)["changes"][0]["change_id"].clone();
+            let changes = json!({
+                "acknowledged": [{ "change_id": change_id }],
+                "changes": [product("prod-1", "cat-missing"), category("cat-2", 1, "Vitaminas")],
+            });
+            (200, sync_response(changes, 9))
+        });
+
+        let outcome = poll(&conn, &server.url).await;
+        assert_eq!(outcome.pushed, 1);
+        assert_eq!((outcome.pulled, outcome.parked), (2, 1));
+        assert_eq!(status(&conn, "categories", 1), "synced");
+
+        // The valid change applied and the cursor moved past both
+        assert_eq!(
+            count(
+                &conn,
+                "SELECT COUNT(*) FROM categories WHERE backend_id = 'cat-2'"
+            ),
+            1
+        );
+        assert_eq!(load_cursor(&conn).unwrap(), 9);
+
+        let (table, error, attempts): (String, String, i64) = conn
+            .query_row(
+                "SELECT table_name, last_error, attempts FROM sync_parked_changes",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(table, "products");
+        assert!(
+            error.contains("Unknown categories cat-missing"),
+            "{}",
+            error
+        );
+        assert_eq!(attempts, 1);
+        assert_eq!(outbox_status(&conn).unwrap().parked_remote_changes, 1);
+
+        // Remote writes aren't logged back
+        assert_eq!(
+            count(
+                &conn,
+                "SELECT COUNT(*) FROM change_log WHERE synced_at IS NULL"
+            ),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn parked_changes_apply_once_their_parent_arrives() {
+        let conn = db();
+        let polls = std::sync::atomic::AtomicUsize::new(0);
+        let server = stub_server(move |_, _| {
+            let changes = match polls.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                0 => json!({ "changes": [product("prod-1", "cat-1")] }),
+                1 => json!({ "changes": [] }),
+                _ => json!({ "changes": [category("cat-1", 1, "Analgésicos")] }),
+            };
+            (200, sync_response(changes, 1))
+        });
+
+        assert_eq!(poll(&conn, &server.url).await.parked, 1);
+
+        // Retried on the next poll and still waiting
+        let outcome = poll(&conn, &server.url).await;
+        assert_eq!((outcome.pulled, outcome.parked), (0, 0));
+        assert_eq!(count(&conn, "SELECT attempts FROM sync_parked_changes"), 2);
+
+        let outcome = poll(&conn, &server.url).await;
+        assert_eq!((outcome.pulled, outcome.parked), (2, 0));
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM sync_parked_changes"), 0);
+        assert_eq!(
+            count(
+                &conn,
+                "SELECT COUNT(*) FROM products p JOIN categories c ON c.id = p.category_id
+                 WHERE p.backend_id = 'prod-1' AND c.backend_id = 'cat-1'"
+            ),
+            1
+        );
+    }
+
+    #[test]
+    fn config_leaves_the_token_to_secure_storage() {
+        let conn = db();
+        set_setting(
+            &conn,
+            "sync_api_url",
+            "https://pos.example",
+            SETTINGS_CATEGORY,
+        )
+        .unwrap();
+        set_setting(&conn, LEGACY_TOKEN_SETTING, "old-token", SETTINGS_CATEGORY).unwrap();
+
+        let config = load_config(&conn).unwrap().unwrap();
+        assert_eq!(config.token, None);
+        assert_eq!(legacy_token(&conn).unwrap().as_deref(), Some("old-token"));
+
+        remove_legacy_token(&conn).unwrap();
+        assert_eq!(legacy_token(&conn).unwrap(), None);
+    }
+
+    #[test]
+    fn a_newer_version_replaces_the_parked_one() {
+        let conn = db();
+        let pull = |changes: JsonValue| {
+            let server: ServerChanges = serde_json::from_value(changes).unwrap();
+            apply_pulled_changes(&conn, &server, 1, BatchOutcome::default()).unwrap()
+        };
+
+        let mut older = product("prod-1", "cat-1");
+        older["version"] = json!(1);
+        let mut newer = product("prod-1", "cat-1");
+        newer["version"] = json!(2);
+        pull(json!({ "changes": [newer] }));
+        pull(json!({ "changes": [older] }));
+
+        assert_eq!(
+            count(&conn, "SELECT version FROM sync_parked_changes"),
+            2,
+            "an older version must not replace the parked one"
+        );
+    }
+
+    fn apply(conn: &Connection, change: JsonValue) -> RemoteOutcome {
+        let change: RemoteChange = serde_json::from_value(change).unwrap();
+        change_log::apply_remote(conn, || apply_remote_change(conn, &change)).unwrap()
+    }
+
+    fn pending_log(conn: &Connection) -> i64 {
+        count(
+            conn,
+            "SELECT COUNT(*) FROM change_log WHERE synced_at IS NULL",
+        )
+    }
+
+    fn sale(version: i64, notes: &str) -> JsonValue {
+        json!({
+            "table": "transactions",
+            "backend_id": "sale-1",
+            "operation": "upsert",
+            "version": version,
+            "data": {
+                "transaction_number": "T-0001",
+                "user_id": 1,
+                "subtotal": 5.0,
+                "tax_amount": 0.65,
+                "discount_amount": 0,
+                "total": 5.65,
+                "status": "completed",
+                "notes": notes,
+            },
+        })
+    }
+
+    fn delete(table: &str, backend_id: &str, version: i64) -> JsonValue {
+        json!({
+            "table": table,
+            "backend_id": backend_id,
+            "operation": "delete",
+            "version": version,
+        })
+    }
+
+    fn movement(version: i64, quantity: f64) -> JsonValue {
+        json!({
+            "table": "stock_movements",
+            "backend_id": "move-1",
+            "operation": "upsert",
+            "version": version,
+            "data": {
+                "product_id": "prod-1",
+                "movement_type": "purchase",
+                "quantity": quantity,
+            },
+        })
+    }
+
+    fn stock(conn: &Connection) -> f64 {
+        conn.query_row(
+            "SELECT stock_quantity FROM products WHERE backend_id = 'prod-1'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn server_wins_replaces_local_catalog_edits() {
+        let conn = db();
+        assert_eq!(
+            apply(&conn, category("cat-1", 1, "Analgésicos")),
+            RemoteOutcome::Applied
+        );
+        assert_eq!(status(&conn, "categories", 1), "synced");
+        assert_eq!(pending_log(&conn), 0);
+
+        conn.execute("UPDATE categories SET name = 'Local' WHERE id = 1", [])
+            .unwrap();
+        assert_eq!(status(&conn, "categories", 1), "pending");
+        assert_eq!(pending_log(&conn), 1);
+
+        assert_eq!(
+            apply(&conn, category("cat-1", 2, "Oficina central")),
+            RemoteOutcome::Applied
+        );
+        let (name, base): (String, i64) = conn
+            .query_row(
+                "SELECT name, base_version FROM categories WHERE id = 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((name.as_str(), base), ("Oficina central", 2));
+        assert_eq!(status(&conn, "categories", 1), "synced");
+
+        // The local edit is dropped with the reason kept
+        assert_eq!(pending_log(&conn), 0);
+        let reason: String = conn
+            .query_row(
+                "SELECT last_error FROM change_log WHERE operation = 'update'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(reason, "Replaced by backend version 2");
+    }
+
+    #[test]
+    fn changes_not_newer_than_the_base_version_are_stale() {
+        let conn = db();
+        apply(&conn, category("cat-1", 2, "Analgésicos"));
+        assert_eq!(
+            apply(&conn, category("cat-1", 2, "Otro")),
+            RemoteOutcome::Stale
+        );
+        assert_eq!(
+            apply(&conn, category("cat-1", 1, "Viejo")),
+            RemoteOutcome::Stale
+        );
+        assert_eq!(
+            apply(&conn, delete("categories", "cat-unknown", 1)),
+            RemoteOutcome::Stale
+        );
+        assert_eq!(
+            count(
+                &conn,
+                "SELECT COUNT(*) FROM categories WHERE name = 'Analgésicos'"
+            ),
+            1
+        );
+    }
+
+    #[test]
+    fn client_wins_pushes_local_sales_over_the_backend() {
+        let conn = db();
+        cashier(&conn);
+        assert_eq!(apply(&conn, sale(1, "")), RemoteOutcome::Applied);
+
+        conn.execute("UPDATE transactions SET notes = 'local' WHERE id = 1", [])
+            .unwrap();
+        assert_eq!(apply(&conn, sale(3, "backend")), RemoteOutcome::KeptLocal);
+
+        let (notes, version, base): (String, i64, i64) = conn
+            .query_row(
+                "SELECT notes, version, base_version FROM transactions WHERE id = 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        // Local row kept, rebased on the backend's version for the next push
+        assert_eq!((notes.as_str(), version, base), ("local", 4, 3));
+        assert_eq!(status(&conn, "transactions", 1), "pending");
+        assert_eq!(pending_log(&conn), 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM sync_conflicts"), 0);
+    }
+
+    #[test]
+    fn remote_delete_of_an_edited_sale_opens_a_conflict() {
+        let conn = db();
+        cashier(&conn);
+        apply(&conn, sale(1, ""));
+        conn.execute("UPDATE transactions SET notes = 'local' WHERE id = 1", [])
+            .unwrap();
+
+        assert_eq!(
+            apply(&conn, delete("transactions", "sale-1", 2)),
+            RemoteOutcome::Conflict
+        );
+        assert_eq!(status(&conn, "transactions", 1), "conflict");
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM transactions"), 1);
+
+        // A newer backend change replaces the conflict's remote side, an
+        // older one is ignored
+        assert_eq!(apply(&conn, sale(4, "backend")), RemoteOutcome::Conflict);
+        assert_eq!(apply(&conn, sale(3, "older")), RemoteOutcome::Stale);
+        let (operation, version): (String, i64) = conn
+            .query_row(
+                "SELECT remote_operation, remote_version FROM sync_conflicts
+                 WHERE resolved_at IS NULL",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((operation.as_str(), version), ("upsert", 4));
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM sync_conflicts"), 1);
+    }
+
+    #[test]
+    fn merge_stock_applies_remote_movement_deltas() {
+        let conn = db();
+        apply(&conn, category("cat-1", 1, "General"));
+        apply(&conn, product("prod-1", "cat-1"));
+
+        assert_eq!(apply(&conn, movement(1, 5.0)), RemoteOutcome::Applied);
+        assert_eq!(stock(&conn), 5.0);
+
+        // Only the difference from the previous quantity is applied
+        assert_eq!(apply(&conn, movement(2, 8.0)), RemoteOutcome::Applied);
+        assert_eq!(stock(&conn), 8.0);
+
+        assert_eq!(
+            apply(&conn, delete("stock_movements", "move-1", 3)),
+            RemoteOutcome::Applied
+        );
+        assert_eq!(stock(&conn), 0.0);
+    }
+
+    #[test]
+    fn merge_stock_movement_edited_on_both_sides_opens_a_conflict() {
+        let conn = db();
+        apply(&conn, category("cat-1", 1, "General"));
+        apply(&conn, product("prod-1", "cat-1"));
+        apply(&conn, movement(1, 5.0));
+        conn.execute(
+            "UPDATE stock_movements SET notes = 'recount' WHERE backend_id = 'move-1'",
+            [],
+        )
+        .unwrap();
+
+        assert_eq!(apply(&conn, movement(2, 3.0)), RemoteOutcome::Conflict);
+        assert_eq!(stock(&conn), 5.0);
+        assert_eq!(status(&conn, "stock_movements", 1), "conflict");
+    }
+
+    fn cashier(conn: &Connection) {
+        conn.execute(
+            "INSERT INTO users (id, username, pin_hash, role, full_name, permissions, backend_user_id)
+             VALUES (1, 'ana', 'x', 'cashier', 'Ana', '[]', 'user-1')",
+            [],
+        )
+        .unwrap();
+    }
+
+    fn completed_sale(conn: &Connection) {
+        cashier(conn);
+        conn.execute_batch(
+            "INSERT INTO categories (id, name) VALUES (1, 'General');
+             INSERT INTO products (id, name, category_id, price, partner_price, vip_price, backend_id)
+             VALUES (1, 'Acetaminofén', 1, 1.50, 1.50, 1.50, 'prod-1'),
+                    (2, 'Ibuprofeno', 1, 2.00, 2.00, 2.00, 'prod-2');
+             INSERT INTO transactions (id, transaction_number, user_id, subtotal, tax_amount,
+                                       discount_amount, total, backend_id)
+             VALUES (1, 'T-0001', 1, 5.00, 0.65, 0, 5.65, 'sale-1');
+             INSERT INTO transaction_items (transaction_id, product_id, quantity, unit_price,
+                                            total_price)
+             VALUES (1, 1, 2, 1.50, 3.00), (1, 2, 1, 2.00, 2.00);
+             INSERT INTO payments (transaction_id, payment_method, amount)
+             VALUES (1, 'card', 5.65);
+             UPDATE transactions SET sync_status = 'synced';",
+        )
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn sales_are_reported_once_and_again_when_voided() {
+        let conn = db();
+        completed_sale(&conn);
+        let server = stub_server(|_, _| (200, "{}".to_string()));
+        let client = client(&server.url);
+
+        let sales = sales_to_report(&conn, "terminal-1").unwrap();
+        assert_eq!(sales.len(), 1);
+        client.report_sales(&sales).await.unwrap();
+        mark_sales_reported(&conn, &[sales[0].local_id]).unwrap();
+        assert!(sales_to_report(&conn, "terminal-1").unwrap().is_empty());
+
+        let sent: JsonValue = {
+            let requests = server.requests.lock().unwrap();
+            assert_eq!(requests[0].path, "/sales/report");
+            serde_json::from_str(&requests[0].body).unwrap()
+        };
+        let sale = &sent[0];
+        assert_eq!(sale["id"], "sale-1");
+        assert_eq!(sale["cashier_id"], "user-1");
+        assert_eq!(sale["status"], "COMPLETED");
+        assert_eq!(sale["payment_method"], "CARD");
+        assert_eq!(sale["invoice_number"], "T-0001");
+        assert_eq!(sale["total"], 5.65);
+        assert!(sale.get("local_id").is_none());
+        assert!(sale.get("customer_id").is_none());
+
+        // IVA split over the lines by amount, adding up to the sale's
+        let items = sale["items"].as_array().unwrap();
+        assert_eq!(items[0]["product_id"], "prod-1");
+        assert_eq!(items[0]["tax"], 0.39);
+        assert_eq!(items[1]["tax"], 0.26);
+        assert_eq!(items[0]["total"], 3.39);
+
+        conn.execute_batch(
+            "UPDATE transactions SET status = 'cancelled' WHERE id = 1;
+             INSERT INTO audit_logs (user_id, action, table_name, record_id, new_values)
+             VALUES (1, 'sale_voided', 'transactions', 1, '{\"reason\":\"Cliente desistió\"}');",
+        )
+        .unwrap();
+        // Reported again once the voided sale has been pushed
+        assert!(sales_to_report(&conn, "terminal-1").unwrap().is_empty());
+        conn.execute("UPDATE transactions SET sync_status = 'synced'", [])
+            .unwrap();
+        let sales = sales_to_report(&conn, "terminal-1").unwrap();
+        assert_eq!(sales.len(), 1);
+        assert_eq!(sales[0].status, "VOIDED");
+        assert_eq!(sales[0].void_reason.as_deref(), Some("Cliente desistió"));
+        assert!(sales[0].voided_at.is_some());
+    }
+
+    #[test]
+    fn unsynced_and_open_sales_are_not_reported() {
+        let conn = db();
+        completed_sale(&conn);
+        conn.execute("UPDATE transactions SET sync_status = 'pending'", [])
+            .unwrap();
+        assert!(sales_to_report(&conn, "terminal-1").unwrap().is_empty());
+
+        conn.execute(
+            "UPDATE transactions SET sync_status = 'synced', status = 'held'",
+            [],
+        )
+        .unwrap();
+        assert!(sales_to_report(&conn, "terminal-1").unwrap().is_empty());
+    }
+}
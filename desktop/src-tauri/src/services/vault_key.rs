@@ -0,0 +1,130 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+/// Bytes of the Stronghold snapshot key
+pub const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+
+/// Cost for new vaults. Stored with each vault, so raising it later only
+/// affects vaults created or re-keyed afterwards.
+const MEMORY_KIB: u32 = 64 * 1024;
+const ITERATIONS: u32 = 3;
+const PARALLELISM: u32 = 1;
+
+/// How the vault key is derived from the unlock secret, kept next to the
+/// snapshot as `<snapshot>.kdf`. `check` is the second half of the Argon2id
+/// output: it tells a wrong password apart without revealing the key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultKdf {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    salt: String,
+    check: String,
+}
+
+type KeyAndCheck = (Zeroizing<Vec<u8>>, Zeroizing<Vec<u8>>);
+
+/// A derived snapshot key and the `VaultKdf` that reproduces it
+pub struct DerivedKey {
+    pub key: Zeroizing<Vec<u8>>,
+    pub kdf: VaultKdf,
+}
+
+impl VaultKdf {
+    /// Derive a key for a new vault, or a re-keyed one, with a fresh salt
+    pub fn create(password: &[u8]) -> Result<DerivedKey, String> {
+        let mut salt = [0u8; SALT_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut salt);
+
+        let mut kdf = VaultKdf {
+            memory_kib: MEMORY_KIB,
+            iterations: ITERATIONS,
+            parallelism: PARALLELISM,
+            salt: BASE64.encode(salt),
+            check: String::new(),
+        };
+        let (key, check) = kdf.derive(password)?;
+        kdf.check = BASE64.encode(&*check);
+        Ok(DerivedKey { key, kdf })
+    }
+
+    /// The vault key for `password`, or `None` if it is not the vault's
+    /// password
+    pub fn unlock(&self, password: &[u8]) -> Result<Option<Zeroizing<Vec<u8>>>, String> {
+        let expected = BASE64
+            .decode(&self.check)
+            .map_err(|e| format!("Vault key parameters are corrupted: {}", e))?;
+        let (key, check) = self.derive(password)?;
+        Ok((*check == expected).then_some(key))
+    }
+
+    pub fn load(path: &Path) -> Result<Option<Self>, String> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents =
+            fs::read(path).map_err(|e| format!("Failed to read vault key parameters: {}", e))?;
+        serde_json::from_slice(&contents)
+            .map(Some)
+            .map_err(|e| format!("Vault key parameters are corrupted: {}", e))
+    }
+
+    /// Replace the parameters at `path` through its pending file, so a crash
+    /// leaves either the old or the new ones
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let pending = pending_path(path);
+        self.write(&pending)?;
+        fs::rename(&pending, path)
+            .map_err(|e| format!("Failed to write vault key parameters: {}", e))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let contents = serde_json::to_vec_pretty(self)
+            .map_err(|e| format!("Failed to encode vault key parameters: {}", e))?;
+        fs::write(path, contents)
+            .map_err(|e| format!("Failed to write vault key parameters: {}", e))
+    }
+
+    /// 64 bytes of Argon2id output: the key, then the check
+    fn derive(&self, password: &[u8]) -> Result<KeyAndCheck, String> {
+        let salt = BASE64
+            .decode(&self.salt)
+            .map_err(|e| format!("Vault key parameters are corrupted: {}", e))?;
+        let params = Params::new(
+            self.memory_kib,
+            self.iterations,
+            self.parallelism,
+            Some(KEY_LEN * 2),
+        )
+        .map_err(|e| format!("Invalid vault key parameters: {}", e))?;
+
+        let mut output = Zeroizing::new(vec![0u8; KEY_LEN * 2]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(password, &salt, &mut output)
+            .map_err(|e| format!("Failed to derive vault key: {}", e))?;
+
+        let check = Zeroizing::new(output.split_off(KEY_LEN));
+        Ok((output, check))
+    }
+}
+
+/// `secure-storage.stronghold` -> `secure-storage.kdf`
+pub fn kdf_path(snapshot_path: &Path) -> PathBuf {
+    snapshot_path.with_extension("kdf")
+}
+
+/// Sibling `<file>.pending`, written before it replaces `path`
+pub fn pending_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".pending");
+    path.with_file_name(name)
+}
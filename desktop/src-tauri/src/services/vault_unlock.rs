@@ -0,0 +1,294 @@
+use std::fs;
+use std::path::PathBuf;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use rand::RngCore;
+use serde::Serialize;
+use tauri::{Emitter, Manager};
+use zeroize::Zeroizing;
+
+use crate::services::secure_storage::{self, SecureStorageError, SecureStorageManager};
+
+/// Keyring entry holding the vault password
+const ACCOUNT: &str = "vault-password";
+/// New password written before a rotation re-keys the vault, promoted once
+/// it has
+const PENDING_ACCOUNT: &str = "vault-password-pending";
+
+/// `POS_KEYRING=file` keeps the secret in the app data dir instead, for
+/// headless machines without a keyring daemon
+const KEYRING_ENV: &str = "POS_KEYRING";
+const FILE_KEYRING_DIR: &str = "keyring";
+
+/// Where the vault password is kept between runs
+pub trait Keyring: Send + Sync {
+    /// `os` or `file`, for status and logs
+    fn backend(&self) -> &'static str;
+    fn get(&self, account: &str) -> Result<Option<Zeroizing<String>>, String>;
+    fn set(&self, account: &str, secret: &str) -> Result<(), String>;
+    /// `false` if nothing was stored
+    fn delete(&self, account: &str) -> Result<bool, String>;
+}
+
+/// Secret Service on Linux, Credential Manager on Windows, Keychain on macOS
+pub struct OsKeyring {
+    service: String,
+}
+
+impl OsKeyring {
+    fn entry(&self, account: &str) -> Result<keyring::Entry, String> {
+        keyring::Entry::new(&self.service, account)
+            .map_err(|e| format!("Failed to open OS keyring: {}", e))
+    }
+}
+
+impl Keyring for OsKeyring {
+    fn backend(&self) -> &'static str {
+        "os"
+    }
+
+    fn get(&self, account: &str) -> Result<Option<Zeroizing<String>>, String> {
+        match self.entry(account)?.get_password() {
+            Ok(secret) => Ok(Some(Zeroizing::new(secret))),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(format!("Failed to read OS keyring: {}", e)),
+        }
+    }
+
+    fn set(&self, account: &str, secret: &str) -> Result<(), String> {
+        self.entry(account)?
+            .set_password(secret)
+            .map_err(|e| format!("Failed to write OS keyring: {}", e))
+    }
+
+    fn delete(&self, account: &str) -> Result<bool, String> {
+        match self.entry(account)?.delete_credential() {
+            Ok(()) => Ok(true),
+            Err(keyring::Error::NoEntry) => Ok(false),
+            Err(e) => Err(format!("Failed to delete from OS keyring: {}", e)),
+        }
+    }
+}
+
+/// One `<account>.secret` file per entry, readable only by the current user
+pub struct FileKeyring {
+    dir: PathBuf,
+}
+
+impl FileKeyring {
+    fn path(&self, account: &str) -> PathBuf {
+        self.dir.join(format!("{}.secret", account))
+    }
+}
+
+impl Keyring for FileKeyring {
+    fn backend(&self) -> &'static str {
+        "file"
+    }
+
+    fn get(&self, account: &str) -> Result<Option<Zeroizing<String>>, String> {
+        let path = self.path(account);
+        if !path.exists() {
+            return Ok(None);
+        }
+        fs::read_to_string(&path)
+            .map(|secret| Some(Zeroizing::new(secret)))
+            .map_err(|e| format!("Failed to read {:?}: {}", path, e))
+    }
+
+    fn set(&self, account: &str, secret: &str) -> Result<(), String> {
+        use std::io::Write;
+
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to create {:?}: {}", self.dir, e))?;
+        let path = self.path(account);
+        let tmp = path.with_extension("tmp");
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+            .open(&tmp)
+            .and_then(|mut file| file.write_all(secret.as_bytes()))
+            .and_then(|_| fs::rename(&tmp, &path))
+            .map_err(|e| format!("Failed to write {:?}: {}", path, e))
+    }
+
+    fn delete(&self, account: &str) -> Result<bool, String> {
+        let path = self.path(account);
+        if !path.exists() {
+            return Ok(false);
+        }
+        fs::remove_file(&path)
+            .map(|_| true)
+            .map_err(|e| format!("Failed to delete {:?}: {}", path, e))
+    }
+}
+
+/// The OS keyring for this app, or the file fallback if `POS_KEYRING` is
+/// `file`
+pub fn open_keyring(app_handle: &tauri::AppHandle) -> Result<Box<dyn Keyring>, String> {
+    match std::env::var(KEYRING_ENV).as_deref() {
+        Ok("file") => {
+            let app_data_dir = app_handle
+                .path()
+                .app_data_dir()
+                .map_err(|e| format!("Failed to get app data dir: {}", e))?;
+            log::warn!("{}=file is set, vault passwords kept in files", KEYRING_ENV);
+            Ok(Box::new(FileKeyring {
+                dir: app_data_dir.join(FILE_KEYRING_DIR),
+            }))
+        }
+        _ => Ok(Box::new(OsKeyring {
+            service: app_handle.config().identifier.clone(),
+        })),
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultUnlockStatus {
+    pub backend: &'static str,
+    /// Whether a vault password is stored for unattended unlock
+    pub enrolled: bool,
+}
+
+/// Unattended unlock of the secure storage vault with a password kept in
+/// the keyring
+pub struct VaultUnlock {
+    keyring: Box<dyn Keyring>,
+}
+
+impl VaultUnlock {
+    /// Unlock through the keyring from [`open_keyring`]
+    pub fn open(app_handle: &tauri::AppHandle) -> Result<Self, String> {
+        Ok(Self {
+            keyring: open_keyring(app_handle)?,
+        })
+    }
+
+    pub fn status(&self) -> Result<VaultUnlockStatus, String> {
+        Ok(VaultUnlockStatus {
+            backend: self.keyring.backend(),
+            enrolled: self.keyring.get(ACCOUNT)?.is_some(),
+        })
+    }
+
+    /// Open the vault with the stored password. `false` if none is
+    /// enrolled. Finishes a rotation cut short between re-keying the vault
+    /// and storing the new password.
+    pub async fn unlock(
+        &self,
+        storage: &SecureStorageManager,
+        vault_path: &str,
+    ) -> Result<bool, SecureStorageError> {
+        let Some(password) = self.keyring.get(ACCOUNT)? else {
+            return Ok(false);
+        };
+        let pending = self.keyring.get(PENDING_ACCOUNT)?;
+
+        match (storage.initialize(password, vault_path).await, pending) {
+            (Ok(()), None) => Ok(true),
+            (Ok(()), Some(_)) => {
+                log::warn!("Discarding interrupted vault password rotation");
+                self.keyring.delete(PENDING_ACCOUNT)?;
+                Ok(true)
+            }
+            (Err(SecureStorageError::WrongPassword), Some(pending)) => {
+                storage.initialize(pending.clone(), vault_path).await?;
+                log::warn!("Completing interrupted vault password rotation");
+                self.keyring.set(ACCOUNT, &pending)?;
+                self.keyring.delete(PENDING_ACCOUNT)?;
+                Ok(true)
+            }
+            (Err(e), _) => Err(e),
+        }
+    }
+
+    /// Check `password` against the vault, creating the vault if there is
+    /// none yet, and store it for unattended unlock
+    pub async fn enroll(
+        &self,
+        storage: &SecureStorageManager,
+        vault_path: &str,
+        password: Zeroizing<String>,
+    ) -> Result<(), SecureStorageError> {
+        storage.initialize(password.clone(), vault_path).await?;
+        self.keyring.set(ACCOUNT, &password)?;
+        self.keyring.delete(PENDING_ACCOUNT)?;
+        log::info!(
+            "Vault password enrolled in {} keyring",
+            self.keyring.backend()
+        );
+        Ok(())
+    }
+
+    /// Re-key the open vault with a new random password and store it. The
+    /// new password is kept as pending until the vault uses it, so a crash
+    /// in between never leaves the keyring without the working one.
+    pub async fn rotate(&self, storage: &SecureStorageManager) -> Result<(), SecureStorageError> {
+        let current = self
+            .keyring
+            .get(ACCOUNT)?
+            .ok_or("No vault password is enrolled")?;
+
+        let mut bytes = Zeroizing::new([0u8; 32]);
+        rand::rngs::OsRng.fill_bytes(bytes.as_mut());
+        let password = Zeroizing::new(BASE64.encode(bytes.as_ref()));
+
+        self.keyring.set(PENDING_ACCOUNT, &password)?;
+        if let Err(e) = storage.change_password(current, password.clone()).await {
+            if let Err(cleanup) = self.keyring.delete(PENDING_ACCOUNT) {
+                log::warn!("Failed to discard pending vault password: {}", cleanup);
+            }
+            return Err(e);
+        }
+        self.keyring.set(ACCOUNT, &password)?;
+        self.keyring.delete(PENDING_ACCOUNT)?;
+        log::info!("Vault password rotated");
+        Ok(())
+    }
+
+    /// Remove the stored password. The vault stays open until the app
+    /// exits and needs `initialize_secure_storage` afterwards.
+    pub fn forget(&self) -> Result<bool, SecureStorageError> {
+        let removed = self.keyring.delete(ACCOUNT)?;
+        self.keyring.delete(PENDING_ACCOUNT)?;
+        if removed {
+            log::info!(
+                "Vault password removed from {} keyring",
+                self.keyring.backend()
+            );
+        }
+        Ok(removed)
+    }
+}
+
+/// Open the vault from the keyring when the app starts, so the terminal can
+/// sign without anyone typing the vault password. Emits
+/// `secure-storage-unlocked` on success; otherwise the vault stays locked
+/// until `initialize_secure_storage`.
+pub async fn unlock_at_startup(app_handle: tauri::AppHandle) {
+    let storage = app_handle.state::<SecureStorageManager>();
+    let unlock = app_handle.state::<VaultUnlock>();
+
+    let result = match secure_storage::vault_path(&app_handle) {
+        Ok(vault_path) => unlock.unlock(&storage, &vault_path).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(true) => {
+            log::info!("Secure storage unlocked from keyring");
+            if let Err(e) = app_handle.emit("secure-storage-unlocked", ()) {
+                log::warn!("Failed to emit secure-storage-unlocked: {}", e);
+            }
+        }
+        Ok(false) => log::info!("No vault password enrolled, secure storage stays locked"),
+        Err(e) => log::error!("Failed to unlock secure storage from keyring: {}", e),
+    }
+}
@@ -1,3 +1,4 @@
+import { invoke } from "@tauri-apps/api/core";
 import {
   DatabaseAdapter,
   buildInsertQuery,
@@ -31,6 +32,13 @@ interface ProductRow {
   updated_at: string;
 }
 
+/** Result of the `search_products` command; only the id is used here */
+interface ProductSearchMatch {
+  id: number;
+  matchedBy: "barcode" | "text" | "fuzzy";
+  score: number;
+}
+
 function mapRowToProduct(row: ProductRow): Product {
   return {
     id: row.id,
@@ -97,14 +105,34 @@ export class ProductService {
     return row ? mapRowToProduct(row) : null;
   }
 
-  async search(query: string, limit: number = 20): Promise<Product[]> {
+  /**
+   * Ranked search through the backend's full-text index (accent-insensitive,
+   * word prefixes, barcode prefixes and typo tolerance)
+   */
+  async search(
+    query: string,
+    limit: number = 20,
+    categoryIds?: number[]
+  ): Promise<Product[]> {
+    const matches = await invoke<ProductSearchMatch[]>("search_products", {
+      query,
+      categoryIds,
+      limit,
+    });
+    if (matches.length === 0) {
+      return [];
+    }
+
+    const ids = matches.map((match) => match.id);
     const rows = await DatabaseAdapter.query<ProductRow>(
-      `SELECT * FROM products
-       WHERE is_active = 1 AND (name LIKE ? OR barcode LIKE ?)
-       ORDER BY name ASC LIMIT ?`,
-      [`%${query}%`, `%${query}%`, limit]
+      `SELECT * FROM products WHERE id IN (${ids.map(() => "?").join(", ")})`,
+      ids
     );
-    return rows.map(mapRowToProduct);
+    const byId = new Map(rows.map((row) => [row.id, row]));
+    return ids
+      .map((id) => byId.get(id))
+      .filter((row): row is ProductRow => row !== undefined)
+      .map(mapRowToProduct);
   }
 
   async create(input: CreateProductInput): Promise<Product> {
@@ -17,7 +17,8 @@ interface UseSalesReturn {
   voidSale: (
     saleId: number,
     userId: number,
-    reason?: string
+    reason?: string,
+    approvalToken?: string
   ) => Promise<Sale | null>;
   getSaleById: (id: number) => Promise<Sale | null>;
   getTodaySummary: (userId?: number) => Promise<{
@@ -91,12 +92,18 @@ export function useSales(): UseSalesReturn {
     async (
       saleId: number,
       userId: number,
-      reason?: string
+      reason?: string,
+      approvalToken?: string
     ): Promise<Sale | null> => {
       try {
         setLoading(true);
         setError(null);
-        const sale = await salesService.voidSale(saleId, userId, reason);
+        const sale = await salesService.voidSale(
+          saleId,
+          userId,
+          reason,
+          approvalToken
+        );
         return sale;
       } catch (err) {
         const message =
@@ -1,6 +1,15 @@
-import { DatabaseAdapter, insertIdOf } from "../../../infrastructure/database";
+import {
+  DatabaseAdapter,
+  insertIdOf,
+  moneyParam,
+} from "../../../infrastructure/database";
 import type { TransactionStatement } from "../../../infrastructure/database";
 import { logger } from "../../../infrastructure/logging";
+import {
+  calculateSaleTotals,
+  getSalesSummary,
+  voidSale as voidSaleCommand,
+} from "../../../infrastructure/tauri";
 import type { Sale, CreateSaleInput, SaleStatus } from "../entities/Sale";
 import type { SaleItem } from "../entities/SaleItem";
 
@@ -161,36 +170,35 @@ export class SalesService {
   }
 
   async create(input: CreateSaleInput): Promise<Sale> {
-    // Calculate totals
-    let subtotal = 0;
-    for (const item of input.items) {
-      subtotal += item.unitPrice * item.quantity - (item.discountAmount || 0);
-    }
-
-    const taxRate = 0.13; // TODO: Get from settings
-    const taxAmount = Math.round(subtotal * taxRate * 100) / 100;
-    const total =
-      Math.round((subtotal + taxAmount - (input.discountAmount || 0)) * 100) /
-      100;
+    // Priced in integer cents by the backend, with the configured tax rate
+    const totals = await calculateSaleTotals(
+      input.items.map((item) => ({
+        unitPrice: item.unitPrice,
+        quantity: item.quantity,
+        discountAmount: item.discountAmount ?? 0,
+      })),
+      input.discountAmount ?? 0
+    );
 
     // Generate transaction number (timestamp + random)
     const transactionNumber = `TXN-${Date.now()}-${Math.random().toString(36).substr(2, 9)}`;
 
     const statements: TransactionStatement[] = [];
 
-    // Statement 0: INSERT transaction (its lastInsertId = saleId)
+    // Statement 0: INSERT transaction (its lastInsertId = saleId).
+    // The *_cents columns are authoritative; the DECIMAL ones mirror them.
     statements.push({
-      sql: `INSERT INTO transactions (transaction_number, customer_id, user_id, session_id, subtotal, tax_amount, discount_amount, total, status, notes)
-         VALUES (?, ?, ?, ?, ?, ?, ?, ?, 'completed', ?)`,
+      sql: `INSERT INTO transactions (transaction_number, customer_id, user_id, session_id, subtotal, tax_amount, discount_amount, total, subtotal_cents, tax_amount_cents, discount_amount_cents, total_cents, status, notes)
+         VALUES (?, ?, ?, ?, 0, 0, 0, 0, ?, ?, ?, ?, 'completed', ?)`,
       params: [
         transactionNumber,
         input.customerId ?? null,
         input.userId,
         input.sessionId ?? null,
-        subtotal,
-        taxAmount,
-        input.discountAmount || 0,
-        total,
+        moneyParam(totals.subtotal),
+        moneyParam(totals.taxAmount),
+        moneyParam(totals.discountAmount),
+        moneyParam(totals.total),
         input.notes ?? null,
       ],
     });
@@ -198,12 +206,12 @@ export class SalesService {
     // Statements 1..N: INSERT payments (reference statement 0's insert id)
     for (const payment of input.payments) {
       statements.push({
-        sql: `INSERT INTO payments (transaction_id, payment_method, amount, reference_number, card_last_four, notes)
-           VALUES (?, ?, ?, ?, ?, ?)`,
+        sql: `INSERT INTO payments (transaction_id, payment_method, amount, amount_cents, reference_number, card_last_four, notes)
+           VALUES (?, ?, 0, ?, ?, ?, ?)`,
         params: [
           insertIdOf(0),
           payment.paymentMethod,
-          payment.amount,
+          moneyParam(payment.amount.toFixed(2)),
           payment.referenceNumber ?? null,
           payment.cardLastFour ?? null,
           payment.notes ?? null,
@@ -212,25 +220,23 @@ export class SalesService {
     }
 
     // Statements N+1..M: INSERT transaction_items (reference statement 0's insert id)
-    for (const item of input.items) {
-      const itemTotal =
-        item.unitPrice * item.quantity - (item.discountAmount || 0);
-
+    input.items.forEach((item, i) => {
+      const line = totals.lines[i];
       statements.push({
-        sql: `INSERT INTO transaction_items (transaction_id, product_id, quantity, unit_price, discount_amount, total_price)
-           VALUES (?, ?, ?, ?, ?, ?)`,
+        sql: `INSERT INTO transaction_items (transaction_id, product_id, quantity, unit_price, discount_amount, total_price, unit_price_cents, discount_amount_cents, total_price_cents)
+           VALUES (?, ?, ?, 0, 0, 0, ?, ?, ?)`,
         params: [
           insertIdOf(0),
           item.productId,
           item.quantity,
-          item.unitPrice,
-          item.discountAmount || 0,
-          itemTotal,
+          moneyParam(line.unitPrice),
+          moneyParam(line.discountAmount),
+          moneyParam(line.totalPrice),
         ],
       });
 
       // TODO: Implement stock management
-    }
+    });
 
     // Final statement: SELECT back the created sale
     statements.push({
@@ -250,13 +256,18 @@ export class SalesService {
     const row = lastResult.rows[0] as unknown as SaleRow;
     logger.info("Sale created", {
       saleId: row.id,
-      total,
+      total: totals.total,
       itemCount: input.items.length,
     });
     return mapRowToSale(row);
   }
 
-  async voidSale(id: number, userId: number, reason?: string): Promise<Sale> {
+  async voidSale(
+    id: number,
+    userId: number,
+    reason?: string,
+    approvalToken?: string
+  ): Promise<Sale> {
     // Validate before transaction (reads don't need atomicity)
     const sale = await this.findWithItems(id);
     if (!sale) {
@@ -267,13 +278,9 @@ export class SalesService {
       throw new Error("Only completed sales can be voided");
     }
 
-    await DatabaseAdapter.transactionBatch([
-      {
-        sql: `UPDATE transactions SET status = 'voided', notes = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?`,
-        params: [reason ? `ANULADO: ${reason}` : "ANULADO", id],
-      },
-      // TODO: Implement stock restoration when stock management is added
-    ]);
+    // Checks can_void or the supervisor's approval and audits both users
+    await voidSaleCommand(id, reason, approvalToken);
+    // TODO: Implement stock restoration when stock management is added
 
     logger.info("Sale voided", { saleId: id, voidedBy: userId, reason });
 
@@ -286,33 +293,12 @@ export class SalesService {
     totalAmount: number;
     averageTicket: number;
   }> {
-    let sql = `
-      SELECT
-        COUNT(*) as sales_count,
-        COALESCE(SUM(total), 0) as total_amount
-      FROM transactions
-      WHERE status = 'completed'
-        AND DATE(created_at) = DATE('now', 'localtime')
-    `;
-    const params: unknown[] = [];
-
-    if (userId) {
-      sql += " AND user_id = ?";
-      params.push(userId);
-    }
-
-    const result = await DatabaseAdapter.queryOne<{
-      sales_count: number;
-      total_amount: number;
-    }>(sql, params);
-
-    const salesCount = result?.sales_count ?? 0;
-    const totalAmount = result?.total_amount ?? 0;
-
+    // Summed from total_cents in the backend; numbers only for display
+    const summary = await getSalesSummary(userId);
     return {
-      salesCount,
-      totalAmount,
-      averageTicket: salesCount > 0 ? totalAmount / salesCount : 0,
+      salesCount: summary.salesCount,
+      totalAmount: Number(summary.totalAmount),
+      averageTicket: Number(summary.averageTicket),
     };
   }
 }
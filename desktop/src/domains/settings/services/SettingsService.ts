@@ -9,12 +9,6 @@ import {
 import type { CompanyInfo } from "../entities/CompanyInfo";
 import { EMPTY_COMPANY_INFO } from "../entities/CompanyInfo";
 
-interface SettingsRow {
-  key: string;
-  value: string;
-  updated_at: string;
-}
-
 type SettingsChangeListener = (settings: SystemSettings) => void;
 
 export class SettingsService {
@@ -26,9 +20,7 @@ export class SettingsService {
       return this.cache;
     }
 
-    const rows = await DatabaseAdapter.query<SettingsRow>(
-      "SELECT key, value FROM system_settings WHERE key LIKE 'settings.%'"
-    );
+    const rows = await DatabaseAdapter.settings("settings.");
 
     const settingsData: Record<string, unknown> = {};
 
@@ -127,9 +119,8 @@ export class SettingsService {
 
   // Company Info methods
   async getCompanyInfo(): Promise<CompanyInfo> {
-    const row = await DatabaseAdapter.queryOne<SettingsRow>(
-      "SELECT value FROM system_settings WHERE key = 'company_info'"
-    );
+    const rows = await DatabaseAdapter.settings("company_info");
+    const row = rows.find((r) => r.key === "company_info");
 
     if (row) {
       try {
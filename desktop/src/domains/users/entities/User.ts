@@ -3,7 +3,6 @@ import type { Role } from "./Role";
 export interface User {
   id: number;
   username: string;
-  pinHash?: string;
   fullName: string;
   role: Role;
   isActive: boolean;
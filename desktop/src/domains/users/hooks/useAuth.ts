@@ -1,7 +1,11 @@
-import { useState, useCallback } from "preact/hooks";
+import { useState, useCallback, useEffect } from "preact/hooks";
+import { listen } from "@tauri-apps/api/event";
 import type { User } from "../entities/User";
 import { userService } from "../services/UserService";
-import { auditService } from "../../audit/services/AuditService";
+import {
+  getCurrentSession,
+  logout as endSession,
+} from "../../../infrastructure/tauri";
 
 interface UseAuthReturn {
   currentUser: User | null;
@@ -17,6 +21,24 @@ export function useAuth(): UseAuthReturn {
   const [loading, setLoading] = useState(false);
   const [error, setError] = useState<string | null>(null);
 
+  // The session lives in Rust: pick it up after a reload or in a sale
+  // window, and drop the user when it idles out
+  useEffect(() => {
+    void getCurrentSession().then(async (session) => {
+      if (session) {
+        setCurrentUser(await userService.findById(session.user.id));
+      }
+    });
+
+    const unlisten = listen("session-expired", () => {
+      setCurrentUser(null);
+      setError("Sesión expirada");
+    });
+    return () => {
+      void unlisten.then((stop) => stop());
+    };
+  }, []);
+
   const login = useCallback(
     async (userId: number, pin: string): Promise<boolean> => {
       try {
@@ -28,8 +50,8 @@ export function useAuth(): UseAuthReturn {
         if (isValid) {
           const user = await userService.findById(userId);
           if (user) {
+            // login_with_pin already wrote the audit entry
             setCurrentUser(user);
-            await auditService.logLogin(userId);
             return true;
           }
         }
@@ -49,12 +71,11 @@ export function useAuth(): UseAuthReturn {
   );
 
   const logout = useCallback(() => {
-    if (currentUser) {
-      auditService.logLogout(currentUser.id);
-    }
+    // Ends the backend session and writes the audit entry
+    void endSession();
     setCurrentUser(null);
     setError(null);
-  }, [currentUser]);
+  }, []);
 
   return {
     currentUser,
@@ -4,14 +4,17 @@ import {
   buildUpdateQuery,
 } from "../../../infrastructure/database";
 import { logger } from "../../../infrastructure/logging";
-import { hashPin, verifyPin } from "../../../infrastructure/tauri";
+import { hashPin, loginWithPin } from "../../../infrastructure/tauri";
 import type { User, CreateUserInput, UpdateUserInput } from "../entities/User";
 import type { Role } from "../entities/Role";
 
+/** PIN hashes stay in the backend; they can't be read through SQL */
+const USER_COLUMNS = `id, username, full_name, role, is_active, permissions,
+  company_id, backend_user_id, phone_number, email, created_at, updated_at`;
+
 interface UserRow {
   id: number;
   username: string;
-  pin_hash: string | null;
   full_name: string;
   role: string;
   is_active: number;
@@ -28,7 +31,6 @@ function mapRowToUser(row: UserRow): User {
   return {
     id: row.id,
     username: row.username,
-    pinHash: row.pin_hash ?? undefined,
     fullName: row.full_name,
     role: row.role as Role,
     isActive: row.is_active === 1,
@@ -44,7 +46,7 @@ function mapRowToUser(row: UserRow): User {
 
 export class UserService {
   async findAll(activeOnly: boolean = true): Promise<User[]> {
-    let sql = "SELECT * FROM users";
+    let sql = `SELECT ${USER_COLUMNS} FROM users`;
     if (activeOnly) {
       sql += " WHERE is_active = 1";
     }
@@ -56,7 +58,7 @@ export class UserService {
 
   async findById(id: number): Promise<User | null> {
     const row = await DatabaseAdapter.queryOne<UserRow>(
-      "SELECT * FROM users WHERE id = ?",
+      `SELECT ${USER_COLUMNS} FROM users WHERE id = ?`,
       [id]
     );
     return row ? mapRowToUser(row) : null;
@@ -64,7 +66,7 @@ export class UserService {
 
   async findByUsername(username: string): Promise<User | null> {
     const row = await DatabaseAdapter.queryOne<UserRow>(
-      "SELECT * FROM users WHERE username = ?",
+      `SELECT ${USER_COLUMNS} FROM users WHERE username = ?`,
       [username]
     );
     return row ? mapRowToUser(row) : null;
@@ -130,32 +132,35 @@ export class UserService {
   }
 
   async authenticateByPin(userId: number, pin: string): Promise<boolean> {
-    const user = await this.findById(userId);
-    if (!user || !user.isActive || !user.pinHash) {
-      return false;
-    }
-
-    const isValid = await verifyPin(pin, user.pinHash);
-
-    if (isValid) {
+    try {
+      // Checks the PIN in Rust and upgrades legacy SHA-256 hashes
+      await loginWithPin(userId, pin);
       logger.info("User authenticated", { userId });
-    } else {
-      logger.warn("Authentication failed", { userId });
+      return true;
+    } catch (error) {
+      logger.warn("Authentication failed", { userId, error });
+      // Lockout and backoff messages are shown instead of "wrong PIN"
+      const message = (error as { message?: string })?.message;
+      if (message && message !== "Invalid PIN") {
+        throw new Error(message);
+      }
+      return false;
     }
-
-    return isValid;
   }
 
   async getCashiers(): Promise<User[]> {
     const rows = await DatabaseAdapter.query<UserRow>(
-      "SELECT * FROM users WHERE role IN ('cashier', 'supervisor') AND is_active = 1 ORDER BY full_name ASC"
+      `SELECT ${USER_COLUMNS} FROM users
+       WHERE role IN ('cashier', 'supervisor') AND is_active = 1
+       ORDER BY full_name ASC`
     );
     return rows.map(mapRowToUser);
   }
 
   async getAdmins(): Promise<User[]> {
     const rows = await DatabaseAdapter.query<UserRow>(
-      "SELECT * FROM users WHERE role = 'admin' AND is_active = 1 ORDER BY full_name ASC"
+      `SELECT ${USER_COLUMNS} FROM users
+       WHERE role = 'admin' AND is_active = 1 ORDER BY full_name ASC`
     );
     return rows.map(mapRowToUser);
   }
@@ -1,5 +1,4 @@
-import Database from "@tauri-apps/plugin-sql";
-import { invoke } from "@tauri-apps/api/core";
+import { Channel, invoke } from "@tauri-apps/api/core";
 import { logger } from "../logging/Logger";
 
 export type QueryResult = Record<string, unknown>[];
@@ -10,10 +9,61 @@ export interface DatabaseConfig {
 
 // --- Transaction batch types ---
 
+/**
+ * Explicitly typed parameter. Survives the IPC layer exactly, unlike plain
+ * JS numbers (money) or strings (binary data).
+ */
+export type TypedParam =
+  | { type: "blob"; base64: string }
+  | { type: "decimal"; value: string; scale?: number }
+  | { type: "datetime"; value: string }
+  | { type: "integer"; value: string }
+  | { type: "money"; value: string }
+  | { type: "real"; value: number }
+  | { type: "text"; value: string };
+
+export type SqlParam = string | number | boolean | null | TypedParam;
+
+/** "typed" returns query rows as TypedParam values instead of plain JSON */
+export type ResultEncoding = "plain" | "typed";
+
+export function blobParam(bytes: Uint8Array): TypedParam {
+  let binary = "";
+  bytes.forEach((b) => (binary += String.fromCharCode(b)));
+  return { type: "blob", base64: btoa(binary) };
+}
+
+/**
+ * Exact decimal for a DECIMAL column, which reads back with the same digits,
+ * e.g. "12.34". `scale` caps the decimal places; more is an error.
+ */
+export function decimalParam(value: string, scale?: number): TypedParam {
+  return scale === undefined
+    ? { type: "decimal", value }
+    : { type: "decimal", value, scale };
+}
+
+/**
+ * Exact amount with at most two decimals, e.g. "12.34", bound as integer
+ * cents. Write it to the *_cents columns; the DECIMAL ones mirror them.
+ */
+export function moneyParam(value: string): TypedParam {
+  return { type: "money", value };
+}
+
+export function datetimeParam(value: Date | string): TypedParam {
+  return {
+    type: "datetime",
+    value: value instanceof Date ? value.toISOString() : value,
+  };
+}
+
 export interface TransactionStatement {
   sql: string;
-  params: (string | number | boolean | null)[];
+  params: SqlParam[];
   query?: boolean;
+  /** Name later statements can reference with resultRef(alias) */
+  alias?: string;
 }
 
 export interface ExecuteStatementResult {
@@ -27,13 +77,66 @@ export interface QueryStatementResult {
   rows: Record<string, unknown>[];
 }
 
-export type StatementResult = ExecuteStatementResult | QueryStatementResult;
+/** Statement failed, was skipped or was undone by a savepoint rollback */
+export interface DiscardedStatementResult {
+  type: "discarded";
+}
+
+export type StatementResult =
+  | ExecuteStatementResult
+  | QueryStatementResult
+  | DiscardedStatementResult;
+
+/**
+ * Optional group of statements run under a named savepoint.
+ * With onError "rollback_savepoint" a failure only undoes this group and the
+ * rest of the batch still commits; "abort" (default) rolls back everything.
+ */
+export interface SavepointGroup {
+  savepoint: string;
+  onError?: "rollback_savepoint" | "abort";
+  statements: TransactionStatement[];
+}
+
+export type TransactionStep = TransactionStatement | SavepointGroup;
+
+/**
+ * Supervisor approval for a batch, from requestApproval. Discounts use the
+ * discount in cents as recordId, price overrides the product id and drawer
+ * openings the register session id.
+ */
+export interface ApprovalUse {
+  token: string;
+  action: "void_sale" | "refund" | "price_override" | "discount" | "open_drawer";
+  recordId: number;
+}
+
+export interface StatementOutcome {
+  index: number;
+  savepoint: string | null;
+  status: "ok" | "failed" | "rolledBack" | "skipped";
+  error: string | null;
+}
 
 export interface TransactionResult {
+  /** One entry per statement, savepoint groups flattened in order */
   results: StatementResult[];
+  outcomes: StatementOutcome[];
   lastInsertId: number;
 }
 
+// --- Read-only query types ---
+
+export interface QueryPage<T = Record<string, unknown>> {
+  rows: T[];
+  nextCursor: string | null;
+}
+
+export type QueryStreamEvent<T = Record<string, unknown>> =
+  | { event: "columns"; data: { columns: string[] } }
+  | { event: "rows"; data: { rows: T[] } }
+  | { event: "finished"; data: { totalRows: number } };
+
 /**
  * Placeholder: replaced at runtime with the lastInsertId from the most recent
  * execute statement within the same transaction.
@@ -49,18 +152,32 @@ export function insertIdOf(statementIndex: number): string {
 }
 
 /**
- * DatabaseAdapter - Thin facade over Tauri SQL plugin
+ * Returns a placeholder that references the result of an aliased statement
+ * within the same transaction. Without a path it resolves to the statement's
+ * lastInsertId; for query statements use a path like "rows[0].id".
+ * Unknown aliases are rejected before any statement runs.
+ */
+export function resultRef(alias: string, path?: string): string {
+  return path ? `$ref:${alias}.${path}` : `$ref:${alias}`;
+}
+
+/** A `system_settings` row; settings holding credentials never reach the webview */
+export interface SettingRow {
+  key: string;
+  value: string;
+}
+
+/**
+ * DatabaseAdapter - Thin facade over the backend's database commands
  *
  * This adapter provides a simple interface for database operations.
- * Domain services use this adapter instead of directly accessing the database.
+ * Domain services use this adapter instead of invoking commands directly.
+ * Reads and writes are checked against the window's session in Rust.
  * This allows for:
- * - Centralized connection management
  * - Query logging and error handling
  * - Easy testing with mock implementations
  */
 class DatabaseAdapterClass {
-  private db: Database | null = null;
-  private readonly dbPath = "sqlite:pos_database.db";
   private initPromise: Promise<void> | null = null;
 
   async initialize(): Promise<void> {
@@ -68,21 +185,24 @@ class DatabaseAdapterClass {
       return this.initPromise;
     }
 
-    this.initPromise = this._doInitialize();
+    this.initPromise = this._doInitialize().catch((error) => {
+      this.initPromise = null;
+      throw error;
+    });
     return this.initPromise;
   }
 
+  /** Migrations run in Rust at startup; this only checks the schema is current */
   private async _doInitialize(): Promise<void> {
     try {
-      this.db = await Database.load(this.dbPath);
-
-      // Configure SQLite PRAGMAs for better performance and concurrency
-      await this.configurePragmas();
-
-      // Log PRAGMA status for debugging
-      await this.logPragmaStatus();
-
-      logger.info("Database connection established");
+      const schema = await invoke<{
+        currentVersion: number;
+        latestVersion: number;
+      }>("get_schema_info");
+      logger.info("Database ready", {
+        schemaVersion: schema.currentVersion,
+        latestVersion: schema.latestVersion,
+      });
     } catch (error) {
       logger.error("Failed to initialize database", { error });
       throw error;
@@ -90,83 +210,26 @@ class DatabaseAdapterClass {
   }
 
   /**
-   * Configure SQLite PRAGMA settings for optimal performance
-   */
-  private async configurePragmas(): Promise<void> {
-    try {
-      // Enable Write-Ahead Logging for better concurrent access
-      await this.db!.execute("PRAGMA journal_mode = WAL");
-
-      // Reduce disk syncing while maintaining safety in WAL mode
-      await this.db!.execute("PRAGMA synchronous = NORMAL");
-
-      // Enable foreign key constraints for data integrity
-      await this.db!.execute("PRAGMA foreign_keys = ON");
-
-      // Cache size: 64MB for better query performance
-      await this.db!.execute("PRAGMA cache_size = -64000");
-
-      // Store temporary tables in memory
-      await this.db!.execute("PRAGMA temp_store = MEMORY");
-
-      logger.debug("SQLite PRAGMAs configured successfully");
-    } catch (error) {
-      logger.warn("Failed to configure PRAGMAs", { error });
-    }
-  }
-
-  /**
-   * Log current PRAGMA settings for debugging
-   */
-  private async logPragmaStatus(): Promise<void> {
-    try {
-      const journalMode = await this.db!.select<[{ journal_mode: string }]>(
-        "PRAGMA journal_mode"
-      );
-      const synchronous =
-        await this.db!.select<[{ synchronous: number }]>("PRAGMA synchronous");
-      const foreignKeys = await this.db!.select<[{ foreign_keys: number }]>(
-        "PRAGMA foreign_keys"
-      );
-      const cacheSize =
-        await this.db!.select<[{ cache_size: number }]>("PRAGMA cache_size");
-
-      logger.info("Database PRAGMA configuration", {
-        journal_mode: journalMode[0]?.journal_mode,
-        synchronous: synchronous[0]?.synchronous,
-        foreign_keys: foreignKeys[0]?.foreign_keys === 1 ? "ON" : "OFF",
-        cache_size: cacheSize[0]?.cache_size,
-      });
-    } catch (error) {
-      logger.warn("Failed to read PRAGMA status", { error });
-    }
-  }
-
-  async isConnected(): Promise<boolean> {
-    return this.db !== null;
-  }
-
-  /**
-   * Execute a SELECT query and return results
+   * Execute a SELECT query and return results.
+   * Needs a logged-in session; PIN hashes and setting values can't be read.
    */
   async query<T = Record<string, unknown>>(
     sql: string,
     params: unknown[] = []
   ): Promise<T[]> {
-    await this.ensureConnection();
-
     const operationStart = Date.now();
+    const rows: T[] = [];
 
     try {
-      const result = await this.db!.select<T[]>(sql, params);
+      await this.streamQuery<T>(sql, params, (chunk) => rows.push(...chunk));
       const duration = Date.now() - operationStart;
 
       logger.debug(`[QUERY] Completed (${duration}ms)`, {
         sql: sql.substring(0, 100),
-        rowCount: result.length,
+        rowCount: rows.length,
       });
 
-      return result;
+      return rows;
     } catch (error) {
       const duration = Date.now() - operationStart;
       logger.error(`[QUERY] Failed (${duration}ms)`, {
@@ -197,49 +260,43 @@ class DatabaseAdapterClass {
     sql: string,
     params: unknown[] = []
   ): Promise<{ rowsAffected: number; lastInsertId: number }> {
-    await this.ensureConnection();
-
-    const operationStart = Date.now();
+    const result = await this.transactionBatch([
+      { sql, params: params as SqlParam[] },
+    ]);
+    const first = result.results[0];
+
+    return {
+      rowsAffected: first?.type === "execute" ? first.rowsAffected : 0,
+      lastInsertId: result.lastInsertId,
+    };
+  }
 
-    logger.debug("[EXECUTE] Starting", {
-      sql: sql.substring(0, 100),
-      params,
+  /**
+   * Settings whose key starts with prefix, all when it's omitted.
+   * Works before login, unlike query().
+   */
+  async settings(prefix?: string): Promise<SettingRow[]> {
+    return invoke<SettingRow[]>("get_system_settings", {
+      prefix: prefix ?? null,
     });
-
-    try {
-      const result = await this.db!.execute(sql, params);
-      const duration = Date.now() - operationStart;
-
-      logger.debug(`[EXECUTE] Completed (${duration}ms)`, {
-        sql: sql.substring(0, 100),
-        rowsAffected: result.rowsAffected,
-      });
-
-      return {
-        rowsAffected: result.rowsAffected,
-        lastInsertId: result.lastInsertId ?? 0,
-      };
-    } catch (error) {
-      const duration = Date.now() - operationStart;
-      logger.error(`[EXECUTE] Failed (${duration}ms)`, {
-        sql,
-        params,
-        error,
-      });
-      throw error;
-    }
   }
 
   /**
    * Execute multiple statements in a single atomic transaction on the Rust side.
-   * Uses a dedicated rusqlite connection to avoid the "database is locked" bug
-   * caused by the sqlx pool dispatching statements to different connections.
+   * All writes go through the backend's single writer connection.
+   *
+   * Use resultRef(alias) as a param value to reference the result of an
+   * earlier aliased statement. LAST_INSERT_ID and insertIdOf(n) are still
+   * accepted but break silently when statements are reordered.
    *
-   * Use LAST_INSERT_ID or insertIdOf(n) as param values to reference
-   * lastInsertId from previous execute statements within the batch.
+   * Large discounts, prices off the product's tiers and cash movements are
+   * rejected unless the user may do them or an approval covers them.
+   * Approvals are spent only if the batch commits.
    */
   async transactionBatch(
-    statements: TransactionStatement[]
+    statements: TransactionStep[],
+    resultEncoding: ResultEncoding = "plain",
+    approvals: ApprovalUse[] = []
   ): Promise<TransactionResult> {
     const transactionStart = Date.now();
     logger.info("[TRANSACTION_BATCH] Starting", {
@@ -249,6 +306,8 @@ class DatabaseAdapterClass {
     try {
       const result = await invoke<TransactionResult>("execute_transaction", {
         statements,
+        resultEncoding,
+        approvals,
       });
 
       const duration = Date.now() - transactionStart;
@@ -266,20 +325,87 @@ class DatabaseAdapterClass {
   }
 
   /**
-   * Close the database connection
+   * Run a read-only SELECT on a dedicated Rust connection.
+   * Never takes the write lock, so reports don't block sales.
+   * Rows are paged in orderBy order, then by keyColumn (default "id"),
+   * which must be unique. The query must return those columns, can't have
+   * its own ORDER BY, and sort columns must not be null (use COALESCE).
+   * Pass the returned nextCursor back to fetch the following page.
    */
-  async close(): Promise<void> {
-    if (this.db) {
-      await this.db.close();
-      this.db = null;
-      this.initPromise = null;
-      logger.info("Database connection closed");
+  async readQuery<T = Record<string, unknown>>(
+    sql: string,
+    params: unknown[] = [],
+    options: {
+      limit?: number;
+      cursor?: string | null;
+      keyColumn?: string;
+      orderBy?: { column: string; descending?: boolean }[];
+      resultEncoding?: ResultEncoding;
+    } = {}
+  ): Promise<QueryPage<T>> {
+    const operationStart = Date.now();
+
+    try {
+      const page = await invoke<QueryPage<T>>("execute_read_query", {
+        sql,
+        params,
+        limit: options.limit ?? null,
+        cursor: options.cursor ?? null,
+        keyColumn: options.keyColumn ?? null,
+        orderBy: options.orderBy ?? null,
+        resultEncoding: options.resultEncoding ?? "plain",
+      });
+
+      const duration = Date.now() - operationStart;
+      logger.debug(`[READ_QUERY] Completed (${duration}ms)`, {
+        sql: sql.substring(0, 100),
+        rowCount: page.rows.length,
+        hasMore: page.nextCursor !== null,
+      });
+
+      return page;
+    } catch (error) {
+      const duration = Date.now() - operationStart;
+      logger.error(`[READ_QUERY] Failed (${duration}ms)`, {
+        sql,
+        params,
+        error,
+      });
+      throw error;
     }
   }
 
-  private async ensureConnection(): Promise<void> {
-    if (!this.db) {
-      await this.initialize();
+  /**
+   * Stream a large read-only result set in chunks.
+   * onRows is called once per chunk; resolves with the total row count.
+   */
+  async streamQuery<T = Record<string, unknown>>(
+    sql: string,
+    params: unknown[],
+    onRows: (rows: T[]) => void,
+    chunkSize?: number
+  ): Promise<number> {
+    let totalRows = 0;
+    const onEvent = new Channel<QueryStreamEvent<T>>();
+    onEvent.onmessage = (message) => {
+      if (message.event === "rows") {
+        onRows(message.data.rows);
+      } else if (message.event === "finished") {
+        totalRows = message.data.totalRows;
+      }
+    };
+
+    try {
+      await invoke("stream_read_query", {
+        sql,
+        params,
+        chunkSize: chunkSize ?? null,
+        onEvent,
+      });
+      return totalRows;
+    } catch (error) {
+      logger.error("[STREAM_QUERY] Failed", { sql, params, error });
+      throw error;
     }
   }
 }
@@ -4,6 +4,11 @@ export {
   buildUpdateQuery,
   LAST_INSERT_ID,
   insertIdOf,
+  resultRef,
+  blobParam,
+  decimalParam,
+  moneyParam,
+  datetimeParam,
 } from "./DatabaseAdapter";
 export type {
   QueryResult,
@@ -13,4 +18,15 @@ export type {
   StatementResult,
   ExecuteStatementResult,
   QueryStatementResult,
+  DiscardedStatementResult,
+  SavepointGroup,
+  TransactionStep,
+  ApprovalUse,
+  StatementOutcome,
+  QueryPage,
+  QueryStreamEvent,
+  TypedParam,
+  SqlParam,
+  ResultEncoding,
+  SettingRow,
 } from "./DatabaseAdapter";
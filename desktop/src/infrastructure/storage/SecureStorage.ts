@@ -2,7 +2,7 @@ import { invoke } from "@tauri-apps/api/core";
 import { logger } from "../logging/Logger";
 
 /**
- * SecureStorage - Facade for Tauri Stronghold secure storage
+ * SecureStorage - Facade for the backend's encrypted secure storage vault
  *
  * Provides a simple key-value interface for storing sensitive data
  * like certificates, tokens, and credentials.
@@ -28,8 +28,10 @@ class SecureStorageClass {
   async get(key: string): Promise<string | null> {
     this.ensureInitialized();
     try {
-      const value = await invoke<string | null>("secure_storage_get", { key });
-      return value;
+      const bytes = await invoke<number[] | null>("get_secret", { key });
+      return bytes === null
+        ? null
+        : new TextDecoder().decode(new Uint8Array(bytes));
     } catch (error) {
       logger.error("Failed to get from secure storage", { key, error });
       return null;
@@ -77,7 +79,7 @@ export const SecureStorage = new SecureStorageClass();
 // Storage keys constants
 export const STORAGE_KEYS = {
   VAULT_PASSWORD: "vault_password",
-  API_TOKEN: "api_token",
+  API_TOKEN: "sync_api_token",
   DTE_CERTIFICATE: "dte_certificate",
   DTE_CERTIFICATE_PASSWORD: "dte_cert_password",
   OFFLINE_PIN_HASH: "offline_pin_hash",
@@ -15,14 +15,204 @@ export interface LoginResult {
   error?: string;
 }
 
-export async function verifyPin(pin: string, hash: string): Promise<boolean> {
-  return invoke<boolean>("verify_pin", { pin, hash });
-}
-
+/**
+ * Argon2id hash of a PIN for creating or updating a user.
+ * Main window only, and needs can_manage_users.
+ */
 export async function hashPin(pin: string): Promise<string> {
   return invoke<string>("hash_pin", { pin });
 }
 
+export interface PinHashParams {
+  memoryKib: number;
+  iterations: number;
+  parallelism: number;
+}
+
+export interface PinHashBenchmark {
+  params: PinHashParams;
+  measuredMs: number;
+  targetMs: number;
+  applied: boolean;
+}
+
+/**
+ * Find Argon2 parameters that verify within targetMs on this machine.
+ * With apply they are saved and weaker hashes upgrade on next login.
+ */
+export async function benchmarkPinHash(
+  targetMs?: number,
+  apply = false
+): Promise<PinHashBenchmark> {
+  return invoke<PinHashBenchmark>("benchmark_pin_hash", { targetMs, apply });
+}
+
+export interface SessionUser {
+  id: number;
+  username: string;
+  fullName: string;
+  role: string;
+  permissions: Record<string, string[]>;
+}
+
+/** Checked by the backend before sensitive commands run */
+export type Permission =
+  | "can_sell"
+  | "can_refund"
+  | "can_void"
+  | "can_override_prices"
+  | "can_open_drawer"
+  | "can_view_reports"
+  | "can_manage_products"
+  | "can_manage_customers"
+  | "can_manage_users"
+  | "can_manage_settings"
+  | "can_manage_certificates"
+  | "can_manage_database"
+  | "can_close_app";
+
+export interface SessionInfo {
+  sessionId: string;
+  windowLabel: string;
+  user: SessionUser;
+  permissions: Permission[];
+  startedAt: string;
+  expiresAt: string;
+}
+
+export interface LoginUser {
+  id: number;
+  username: string;
+  fullName: string;
+  role: string;
+}
+
+/** Active users to pick from before anyone is logged in */
+export async function listLoginUsers(): Promise<LoginUser[]> {
+  return invoke<LoginUser[]>("list_login_users");
+}
+
+/** Log in and start a session for this window */
+export async function loginWithPin(
+  userId: number,
+  pin: string
+): Promise<SessionInfo> {
+  return invoke<SessionInfo>("login_with_pin", { userId, pin });
+}
+
+export async function logout(): Promise<void> {
+  return invoke<void>("logout");
+}
+
+export async function getCurrentSession(): Promise<SessionInfo | null> {
+  return invoke<SessionInfo | null>("get_current_session");
+}
+
+/** Keep this window's session from idling out */
+export async function touchSession(): Promise<SessionInfo> {
+  return invoke<SessionInfo>("touch_session");
+}
+
+export async function unlockPinLockout(
+  managerId: number,
+  managerPin: string,
+  userId?: number
+): Promise<void> {
+  return invoke<void>("unlock_pin_lockout", { managerId, managerPin, userId });
+}
+
+export type ApprovalAction =
+  | "void_sale"
+  | "refund"
+  | "price_override"
+  | "discount"
+  | "open_drawer";
+
+export interface ApprovalToken {
+  token: string;
+  action: ApprovalAction;
+  recordId: number;
+  approverId: number;
+  approverName: string;
+  expiresAt: string;
+}
+
+/**
+ * Have a supervisor approve one action on one record for the user logged in
+ * on this window. The token works once, within a minute.
+ */
+export async function requestApproval(
+  approverId: number,
+  approverPin: string,
+  action: ApprovalAction,
+  recordId: number
+): Promise<ApprovalToken> {
+  return invoke<ApprovalToken>("request_approval", {
+    approverId,
+    approverPin,
+    action,
+    recordId,
+  });
+}
+
+/** Void a completed sale; without can_void pass a void_sale approval token */
+export async function voidSale(
+  saleId: number,
+  reason?: string,
+  approvalToken?: string
+): Promise<void> {
+  return invoke<void>("void_sale", { saleId, reason, approvalToken });
+}
+
+/** Decimal string in cents precision, e.g. "12.34" */
+export type MoneyString = string;
+
+export interface SaleLineInput {
+  unitPrice: number | MoneyString;
+  quantity: number | string;
+  discountAmount?: number | MoneyString;
+}
+
+export interface SaleLineTotals {
+  unitPrice: MoneyString;
+  quantity: string;
+  discountAmount: MoneyString;
+  totalPrice: MoneyString;
+  /** This line's share of the sale-level discount */
+  allocatedDiscount: MoneyString;
+}
+
+export interface SaleTotals {
+  lines: SaleLineTotals[];
+  subtotal: MoneyString;
+  taxRateBps: number;
+  taxAmount: MoneyString;
+  discountAmount: MoneyString;
+  total: MoneyString;
+}
+
+/** Price a cart in integer cents with the configured tax rate */
+export async function calculateSaleTotals(
+  items: SaleLineInput[],
+  discountAmount?: number | MoneyString
+): Promise<SaleTotals> {
+  return invoke<SaleTotals>("calculate_sale_totals", {
+    items,
+    discountAmount,
+  });
+}
+
+export interface SalesSummary {
+  salesCount: number;
+  totalAmount: MoneyString;
+  averageTicket: MoneyString;
+}
+
+/** Today's completed sales; other users' figures need can_view_reports */
+export async function getSalesSummary(userId?: number): Promise<SalesSummary> {
+  return invoke<SalesSummary>("get_sales_summary", { userId });
+}
+
 // DTE Commands
 export interface SignDTEInput {
   dteType: string;
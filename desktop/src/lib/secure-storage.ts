@@ -9,6 +9,12 @@ export interface SecretMetadata {
   exists: boolean;
 }
 
+export interface VaultUnlockStatus {
+  /** "os" for the platform keyring, "file" for the POS_KEYRING=file fallback */
+  backend: "os" | "file";
+  enrolled: boolean;
+}
+
 export class SecureStorageService {
   private static instance: SecureStorageService;
   private initialized = false;
@@ -23,12 +29,13 @@ export class SecureStorageService {
   }
 
   /**
-   * Initialize secure storage with pre-hashed password from backend
-   * @param passwordHash - Pre-hashed password bytes from backend
+   * Open the vault, creating it on first use. Rejects with
+   * "Wrong vault password" if the password doesn't match.
+   * @param password - Vault password; the key is derived from it in Rust
    */
-  async initialize(passwordHash: number[]): Promise<void> {
+  async initialize(password: string): Promise<void> {
     try {
-      await invoke("initialize_secure_storage", { passwordHash });
+      await invoke("initialize_secure_storage", { password });
       this.initialized = true;
     } catch (error) {
       this.initialized = false;
@@ -36,6 +43,71 @@ export class SecureStorageService {
     }
   }
 
+  /**
+   * Re-encrypt the vault under a new password (main window only)
+   * @param currentPassword - Password the vault was opened with
+   * @param newPassword - At least 8 characters
+   */
+  async changeVaultPassword(
+    currentPassword: string,
+    newPassword: string
+  ): Promise<void> {
+    if (!this.initialized) {
+      throw new Error("Secure storage not initialized");
+    }
+
+    try {
+      await invoke("change_vault_password", { currentPassword, newPassword });
+    } catch (error) {
+      throw new Error(`Failed to change vault password: ${error}`);
+    }
+  }
+
+  /**
+   * Keyring backend and whether the vault opens by itself at startup
+   */
+  async getUnlockStatus(): Promise<VaultUnlockStatus> {
+    return await invoke("get_vault_unlock_status");
+  }
+
+  /**
+   * Store the vault password in the keyring so the vault opens at startup.
+   * Also opens the vault, creating it on first use (main window only).
+   * @param password - Vault password
+   */
+  async enrollVaultPassword(password: string): Promise<void> {
+    try {
+      await invoke("enroll_vault_password", { password });
+      this.initialized = true;
+    } catch (error) {
+      throw new Error(`Failed to enroll vault password: ${error}`);
+    }
+  }
+
+  /**
+   * Re-key the vault with a random password kept only in the keyring
+   * (main window only)
+   */
+  async rotateVaultPassword(): Promise<void> {
+    try {
+      await invoke("rotate_vault_password");
+    } catch (error) {
+      throw new Error(`Failed to rotate vault password: ${error}`);
+    }
+  }
+
+  /**
+   * Remove the stored vault password (main window only)
+   * @returns Whether a password was enrolled
+   */
+  async forgetVaultPassword(): Promise<boolean> {
+    try {
+      return await invoke("forget_vault_password");
+    } catch (error) {
+      throw new Error(`Failed to forget vault password: ${error}`);
+    }
+  }
+
   /**
    * Store a secret (private key, certificate, etc.)
    * @param key - Unique identifier for the secret
@@ -53,6 +125,26 @@ export class SecureStorageService {
     }
   }
 
+  /**
+   * Read a secret, if its policy lets this window and user see it.
+   * Certificate material, the DB key and the vault password never leave
+   * Rust; `sync_api_token` is readable from the main window with
+   * `can_manage_settings`.
+   * @param key - Secret identifier
+   * @returns The secret bytes, or null if it isn't stored
+   */
+  async getSecret(key: string): Promise<number[] | null> {
+    if (!this.initialized) {
+      throw new Error("Secure storage not initialized");
+    }
+
+    try {
+      return await invoke("get_secret", { key });
+    } catch (error) {
+      throw new Error(`Failed to read secret: ${error}`);
+    }
+  }
+
   /**
    * Check if a secret exists
    * @param key - Secret identifier
@@ -85,6 +177,21 @@ export class SecureStorageService {
     }
   }
 
+  /**
+   * List the keys of all stored secrets
+   */
+  async listSecretKeys(): Promise<string[]> {
+    if (!this.initialized) {
+      throw new Error("Secure storage not initialized");
+    }
+
+    try {
+      return await invoke("list_secret_keys");
+    } catch (error) {
+      throw new Error(`Failed to list secrets: ${error}`);
+    }
+  }
+
   /**
    * Get metadata for multiple secrets
    * @param keys - Array of secret identifiers
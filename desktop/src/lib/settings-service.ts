@@ -253,10 +253,7 @@ export class SettingsService {
       const settings = { ...DEFAULT_SETTINGS };
 
       // Load all settings from database
-      const dbSettings = await DatabaseAdapter.query<{
-        key: string;
-        value: string;
-      }>("SELECT key, value FROM system_settings");
+      const dbSettings = await DatabaseAdapter.settings();
 
       // Map database settings to app settings
       for (const appKey of Object.keys(SETTINGS_MAPPING) as Array<
@@ -13,8 +13,7 @@ import {
   setPerformanceTracking,
 } from "@/presentation/hooks/use-render-tracker";
 import { DatabaseAdapter } from "@/infrastructure/database";
-import { userService } from "@/domains/users/services/UserService";
-import { categoryService } from "@/domains/products/services/CategoryService";
+import { listLoginUsers } from "@/infrastructure/tauri";
 import { settingsService as dbSettingsService } from "@/domains/settings/services/SettingsService";
 import { settingsService } from "@/lib/settings-service";
 
@@ -196,14 +195,12 @@ export function AppStateProvider({ children }: AppStateProviderProps) {
       logger
         .info("Testing database operations...")
         .catch(globalThis.console.error);
-      const categories = await categoryService.findAll();
-      const users = await userService.findAll();
+      // Nobody is logged in yet, so only the session-free reads are used
+      const users = await listLoginUsers();
       await dbSettingsService.load(); // Load settings into cache
 
       logger
-        .info(
-          `Database ready: ${categories.length} categories, ${users.length} users`
-        )
+        .info(`Database ready: ${users.length} users`)
         .catch(globalThis.console.error);
 
       if (users.length === 0) {
@@ -11,7 +11,7 @@ vi.mock("../infrastructure/logging", () => ({
 // Mock DatabaseAdapter
 vi.mock("../infrastructure/database", () => ({
   DatabaseAdapter: {
-    query: vi.fn(),
+    settings: vi.fn(),
     execute: vi.fn(),
   },
 }));
@@ -58,7 +58,7 @@ describe("SettingsService", () => {
       const result = await settingsService.getSettings();
 
       expect(result).toBe(mockSettings);
-      expect(mockDatabaseAdapter.query).not.toHaveBeenCalled();
+      expect(mockDatabaseAdapter.settings).not.toHaveBeenCalled();
     });
 
     it("should load from database when cache is stale", async () => {
@@ -72,11 +72,11 @@ describe("SettingsService", () => {
         { key: "currency", value: "EUR" },
       ];
 
-      mockDatabaseAdapter.query.mockResolvedValue(mockDbSettings);
+      mockDatabaseAdapter.settings.mockResolvedValue(mockDbSettings);
 
       const result = await settingsService.getSettings();
 
-      expect(mockDatabaseAdapter.query).toHaveBeenCalled();
+      expect(mockDatabaseAdapter.settings).toHaveBeenCalled();
       expect(result.companyName).toBe("Test Company");
       expect(result.taxRate).toBe(0.15);
       expect(result.currency).toBe("EUR");
@@ -88,17 +88,17 @@ describe("SettingsService", () => {
         { key: "low_stock_alert", value: "5" },
       ];
 
-      mockDatabaseAdapter.query.mockResolvedValue(mockDbSettings);
+      mockDatabaseAdapter.settings.mockResolvedValue(mockDbSettings);
 
       const result = await settingsService.getSettings();
 
-      expect(mockDatabaseAdapter.query).toHaveBeenCalled();
+      expect(mockDatabaseAdapter.settings).toHaveBeenCalled();
       expect(result.companyName).toBe("Fresh Company");
       expect(result.lowStockAlert).toBe(5);
     });
 
     it("should return defaults when database fails", async () => {
-      mockDatabaseAdapter.query.mockRejectedValue(new Error("DB Error"));
+      mockDatabaseAdapter.settings.mockRejectedValue(new Error("DB Error"));
 
       const result = await settingsService.getSettings();
 
@@ -115,7 +115,7 @@ describe("SettingsService", () => {
         resolvePromise = resolve;
       });
 
-      mockDatabaseAdapter.query.mockReturnValue(delayedPromise);
+      mockDatabaseAdapter.settings.mockReturnValue(delayedPromise);
 
       // Start multiple concurrent loads
       const promise1 = settingsService.getSettings();
@@ -132,7 +132,7 @@ describe("SettingsService", () => {
       ]);
 
       // Should only call database once
-      expect(mockDatabaseAdapter.query).toHaveBeenCalledTimes(1);
+      expect(mockDatabaseAdapter.settings).toHaveBeenCalledTimes(1);
       expect(result1).toBe(result2);
       expect(result2).toBe(result3);
     });
@@ -152,12 +152,12 @@ describe("SettingsService", () => {
     it("should load settings if not cached", async () => {
       const mockDbSettings = [{ key: "tax_rate", value: "0.20" }];
 
-      mockDatabaseAdapter.query.mockResolvedValue(mockDbSettings);
+      mockDatabaseAdapter.settings.mockResolvedValue(mockDbSettings);
 
       const result = await settingsService.getSetting("taxRate");
 
       expect(result).toBe(0.2);
-      expect(mockDatabaseAdapter.query).toHaveBeenCalled();
+      expect(mockDatabaseAdapter.settings).toHaveBeenCalled();
     });
   });
 
@@ -347,12 +347,12 @@ describe("SettingsService", () => {
         { key: "company_name", value: "Refreshed Company" },
       ];
 
-      mockDatabaseAdapter.query.mockResolvedValue(mockDbSettings);
+      mockDatabaseAdapter.settings.mockResolvedValue(mockDbSettings);
 
       const result = await settingsService.refresh();
 
       expect(result.companyName).toBe("Refreshed Company");
-      expect(mockDatabaseAdapter.query).toHaveBeenCalled();
+      expect(mockDatabaseAdapter.settings).toHaveBeenCalled();
     });
 
     it("should report cache status correctly", () => {